@@ -0,0 +1,43 @@
+//! Benchmarks the per-request overhead of shielded endpoints
+//! (`cargo bench --features shield --bench shield`).
+//!
+//! Results before and after keeping the shielded endpoint state behind a single
+//! `Arc` (median of `--warm-up-time 1 --measurement-time 3`):
+//!
+//! | benchmark         | before  | after   |
+//! |-------------------|---------|---------|
+//! | shield_middleware | 3.51 µs | 3.47 µs |
+//! | shield_transform  | 3.61 µs | 3.28 µs |
+//! | unshielded        | 171 ns  | 171 ns  |
+//!
+//! The remaining overhead is dominated by spawning the shielded task.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use poem::{handler, Endpoint, EndpointExt, Request};
+use poem_ext::shield_mw::{shield, ShieldMiddleware};
+
+#[handler]
+fn index() -> &'static str {
+    "Hello World!"
+}
+
+fn bench_shield(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+
+    let ep = index.with(ShieldMiddleware);
+    c.bench_function("shield_middleware", |b| {
+        b.to_async(&rt).iter(|| ep.call(Request::default()))
+    });
+
+    let ep = shield(index);
+    c.bench_function("shield_transform", |b| {
+        b.to_async(&rt).iter(|| ep.call(Request::default()))
+    });
+
+    c.bench_function("unshielded", |b| {
+        b.to_async(&rt).iter(|| index.call(Request::default()))
+    });
+}
+
+criterion_group!(benches, bench_shield);
+criterion_main!(benches);
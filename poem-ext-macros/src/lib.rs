@@ -0,0 +1,208 @@
+//! Procedural macros for [poem-ext](https://docs.rs/poem-ext/). Use the
+//! re-exports from poem-ext instead of depending on this crate directly.
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    parenthesized, parse_macro_input, Attribute, Data, DeriveInput, Error, Expr, Fields, Ident,
+    ItemStruct, Lit, LitStr, Meta, Token, Type,
+};
+
+/// See `poem_ext::attr::static_string`.
+#[proc_macro_attribute]
+pub fn static_string(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let value = parse_macro_input!(attr as Expr);
+    let item = parse_macro_input!(item as ItemStruct);
+
+    if !matches!(item.fields, Fields::Unit) || !item.generics.params.is_empty() {
+        return Error::new_spanned(&item, "static_string can only be used on unit structs")
+            .into_compile_error()
+            .into();
+    }
+
+    let description = doc_comment(&item.attrs);
+    let description = description.trim();
+    let description = (!description.is_empty()).then(|| quote!(, description = #description));
+
+    let name = &item.ident;
+    quote! {
+        #item
+
+        ::poem_ext::__private::__static_string_impls!(#name, #value #description);
+    }
+    .into()
+}
+
+/// See `poem_ext::derive::Dto`.
+#[proc_macro_derive(Dto, attributes(dto))]
+pub fn dto(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_dto(input) {
+        Ok(output) => output.into(),
+        Err(err) => err.into_compile_error().into(),
+    }
+}
+
+/// A field of a dto that is computed from the model.
+struct ComputedField {
+    name: Ident,
+    ty: Type,
+    func: Expr,
+}
+
+fn expand_dto(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input,
+            "Dto can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new_spanned(
+            &input,
+            "Dto can only be derived for structs with named fields",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "Dto cannot be derived for generic structs",
+        ));
+    }
+
+    let mut name = None;
+    let mut rename_all = None;
+    let mut computed = Vec::new();
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("dto"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<Ident>()?);
+            } else if meta.path.is_ident("rename_all") {
+                rename_all = Some(meta.value()?.parse::<LitStr>()?);
+            } else if meta.path.is_ident("computed") {
+                let content;
+                parenthesized!(content in meta.input);
+                let name = content.parse()?;
+                content.parse::<Token![:]>()?;
+                let ty = content.parse()?;
+                content.parse::<Token![=]>()?;
+                let func = content.parse()?;
+                computed.push(ComputedField { name, ty, func });
+            } else {
+                return Err(meta.error("unsupported dto attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    let Some(name) = name else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "missing dto name, e.g. `#[dto(name = User)]`",
+        ));
+    };
+
+    let mut dto_fields = Vec::new();
+    let mut field_names = Vec::new();
+    for field in &fields.named {
+        let mut skip = false;
+        let mut rename = None;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("dto"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                } else if meta.path.is_ident("rename") {
+                    rename = Some(meta.value()?.parse::<LitStr>()?);
+                } else {
+                    return Err(meta.error("unsupported dto field attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        if skip {
+            continue;
+        }
+
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let docs = field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("doc"));
+        let rename = rename.map(|rename| quote!(#[oai(rename = #rename)]));
+        dto_fields.push(quote! {
+            #(#docs)*
+            #rename
+            pub #ident: #ty,
+        });
+        field_names.push(ident);
+    }
+
+    let computed_names = computed.iter().map(|field| &field.name).collect::<Vec<_>>();
+    let computed_vars = computed_names
+        .iter()
+        .map(|name| format_ident!("__computed_{}", name))
+        .collect::<Vec<_>>();
+    let computed_types = computed.iter().map(|field| &field.ty);
+    let computed_funcs = computed.iter().map(|field| &field.func);
+
+    let vis = &input.vis;
+    let model = &input.ident;
+    let description = doc_comment(&input.attrs);
+    let description = description.trim();
+    let doc = if description.is_empty() {
+        format!("Response type generated from [`{model}`].")
+    } else {
+        description.to_owned()
+    };
+    let rename_all = rename_all.map(|rename_all| quote!(#[oai(rename_all = #rename_all)]));
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(::std::fmt::Debug, ::std::clone::Clone, ::poem_ext::__private::poem_openapi::Object)]
+        #rename_all
+        #vis struct #name {
+            #(#dto_fields)*
+            #(pub #computed_names: #computed_types,)*
+        }
+
+        impl ::std::convert::From<#model> for #name {
+            fn from(model: #model) -> Self {
+                #(let #computed_vars = (#computed_funcs)(&model);)*
+                Self {
+                    #(#field_names: model.#field_names,)*
+                    #(#computed_names: #computed_vars,)*
+                }
+            }
+        }
+    })
+}
+
+/// Return the content of the doc comments in the given attributes.
+fn doc_comment(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) if nv.path.is_ident("doc") => match &nv.value {
+                Expr::Lit(lit) => match &lit.lit {
+                    Lit::Str(s) => Some(s.value()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| {
+            line.strip_prefix(' ')
+                .map(ToOwned::to_owned)
+                .unwrap_or(line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,344 @@
+//! Contains a middleware that logs requests and responses.
+//!
+//! For every request an event with the method, path, status, duration and
+//! [request id](crate::request_id) is emitted using the
+//! [`tracing`](https://docs.rs/tracing) crate (target `poem_ext::access_log`).
+//! Optionally, request and response bodies can be included up to a
+//! configurable size. Values in JSON bodies can be redacted using
+//! [JSON pointers](https://www.rfc-editor.org/rfc/rfc6901) in which `*` matches
+//! any object key or array index. Only JSON and text bodies are logged, other
+//! bodies (e.g. file uploads or event streams) are passed through untouched.
+//!
+//! To reduce the log volume of high traffic routes, only a fraction of the
+//! requests can be logged. Responses with a `5xx` status are always logged.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::access_log::AccessLogMiddleware;
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     AccessLogMiddleware::new()
+//!         .with_bodies(4096)
+//!         .with_redaction("/password")
+//!         .with_redaction("/users/*/token")
+//!         // log only every tenth request to the health endpoint
+//!         .with_path_sample_rate("/health", 0.1),
+//! );
+//! ```
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::{
+    request_id::RequestId,
+    utils::{format_body, is_loggable, parse_pointer, path_has_prefix},
+};
+use poem::{
+    http::header::CONTENT_TYPE, Body, Endpoint, IntoResponse, Middleware, Request, Response,
+};
+
+pub use crate::utils::REDACTED;
+
+/// A middleware that logs requests and responses.
+#[derive(Debug, Clone)]
+pub struct AccessLogMiddleware {
+    max_body_size: Option<usize>,
+    redactions: Vec<Vec<String>>,
+    sample_rate: f64,
+    path_sample_rates: Vec<(String, f64)>,
+}
+
+impl Default for AccessLogMiddleware {
+    fn default() -> Self {
+        Self {
+            max_body_size: None,
+            redactions: Vec::new(),
+            sample_rate: 1.0,
+            path_sample_rates: Vec::new(),
+        }
+    }
+}
+
+impl AccessLogMiddleware {
+    /// Create a new AccessLogMiddleware that logs all requests without their
+    /// bodies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Include request and response bodies in the log. Bodies that are larger
+    /// than `max_size` bytes are truncated.
+    pub fn with_bodies(self, max_size: usize) -> Self {
+        Self {
+            max_body_size: Some(max_size),
+            ..self
+        }
+    }
+
+    /// Replace the value at the given JSON pointer (e.g. `/password` or
+    /// `/users/*/token`) in logged JSON bodies with [`REDACTED`].
+    pub fn with_redaction(mut self, pointer: impl AsRef<str>) -> Self {
+        self.redactions.push(parse_pointer(pointer.as_ref()));
+        self
+    }
+
+    /// Log only the given fraction (between `0.0` and `1.0`) of all requests
+    /// (default: `1.0`).
+    pub fn with_sample_rate(self, rate: f64) -> Self {
+        Self {
+            sample_rate: check_rate(rate),
+            ..self
+        }
+    }
+
+    /// Log only the given fraction (between `0.0` and `1.0`) of requests whose
+    /// path starts with `prefix`. If multiple prefixes match a request, the
+    /// one that has been added first is used.
+    pub fn with_path_sample_rate(mut self, prefix: impl Into<String>, rate: f64) -> Self {
+        self.path_sample_rates
+            .push((prefix.into(), check_rate(rate)));
+        self
+    }
+}
+
+fn check_rate(rate: f64) -> f64 {
+    assert!(
+        (0.0..=1.0).contains(&rate),
+        "sample rate must be between 0 and 1"
+    );
+    rate
+}
+
+impl<E: Endpoint> Middleware<E> for AccessLogMiddleware {
+    type Output = AccessLogMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        AccessLogMwEndpoint {
+            inner: ep,
+            state: Arc::new(AccessLogState {
+                max_body_size: self.max_body_size,
+                redactions: self.redactions.clone(),
+                sampler: Sampler::new(self.sample_rate),
+                path_samplers: self
+                    .path_sample_rates
+                    .iter()
+                    .map(|(prefix, rate)| (prefix.clone(), Sampler::new(*rate)))
+                    .collect(),
+            }),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct AccessLogMwEndpoint<E> {
+    inner: E,
+    state: Arc<AccessLogState>,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for AccessLogMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let sampled = self.state.sample(req.uri().path());
+        let method = req.method().clone();
+        let path = req.uri().path().to_owned();
+        let request_id = RequestId::of(&req);
+
+        let mut request_body = None;
+        if let (true, Some(max_size)) = (sampled, self.state.max_body_size) {
+            if is_loggable(req.header(CONTENT_TYPE)) {
+                let body = req.take_body().into_bytes().await?;
+                request_body = Some(self.state.format_body(&body, max_size));
+                req.set_body(body);
+            }
+        }
+
+        let start = Instant::now();
+        let result = self.inner.call(req).await.map(IntoResponse::into_response);
+        let duration = start.elapsed();
+
+        let status = match &result {
+            Ok(resp) => resp.status(),
+            Err(err) => err.status(),
+        };
+        if !sampled && !status.is_server_error() {
+            return result;
+        }
+
+        let mut response_body = None;
+        let result = match result {
+            Ok(mut resp) => {
+                if let (true, Some(max_size)) = (sampled, self.state.max_body_size) {
+                    if is_loggable(
+                        resp.headers()
+                            .get(CONTENT_TYPE)
+                            .and_then(|x| x.to_str().ok()),
+                    ) {
+                        let body = resp.take_body().into_bytes().await?;
+                        response_body = Some(self.state.format_body(&body, max_size));
+                        resp.set_body(Body::from(body));
+                    }
+                }
+                Ok(resp)
+            }
+            Err(err) => Err(err),
+        };
+
+        tracing::info!(
+            target: "poem_ext::access_log",
+            %method,
+            path,
+            status = status.as_u16(),
+            duration_ms = duration.as_secs_f64() * 1000.0,
+            request_id = request_id.as_deref(),
+            request_body = request_body.as_deref(),
+            response_body = response_body.as_deref(),
+            "request handled",
+        );
+
+        result
+    }
+}
+
+#[derive(Debug)]
+struct AccessLogState {
+    max_body_size: Option<usize>,
+    redactions: Vec<Vec<String>>,
+    sampler: Sampler,
+    path_samplers: Vec<(String, Sampler)>,
+}
+
+impl AccessLogState {
+    fn sample(&self, path: &str) -> bool {
+        self.path_samplers
+            .iter()
+            .find(|(prefix, _)| path_has_prefix(path, prefix))
+            .map_or(&self.sampler, |(_, sampler)| sampler)
+            .sample()
+    }
+
+    fn format_body(&self, body: &[u8], max_size: usize) -> String {
+        format_body(body, &self.redactions, max_size)
+    }
+}
+
+/// Deterministically selects the given fraction of calls to
+/// [`sample`](Self::sample).
+#[derive(Debug)]
+struct Sampler {
+    rate: f64,
+    count: AtomicU64,
+}
+
+impl Sampler {
+    fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn sample(&self) -> bool {
+        let n = self.count.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.rate).floor() > (n * self.rate).floor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, test::TestClient, EndpointExt};
+    use serde_json::Value;
+
+    use super::*;
+
+    #[handler]
+    fn echo(body: String) -> poem::web::Json<Value> {
+        poem::web::Json(serde_json::from_str(&body).unwrap())
+    }
+
+    #[test]
+    fn format_body() {
+        let state = AccessLogMiddleware::new()
+            .with_redaction("/password")
+            .with_redaction("/users/*/token")
+            .with_redaction("/a~1b")
+            .transform(echo)
+            .state;
+
+        assert_eq!(
+            state.format_body(
+                br#"{"name":"foo","password":"bar","a/b":1,"users":[{"id":1,"token":"x"},{"id":2}]}"#,
+                1024
+            ),
+            r#"{"a/b":"[redacted]","name":"foo","password":"[redacted]","users":[{"id":1,"token":"[redacted]"},{"id":2}]}"#
+        );
+        assert_eq!(state.format_body(b"not json", 1024), "not json");
+        assert_eq!(
+            state.format_body("äöü".as_bytes(), 3),
+            "ä... (4 bytes truncated)"
+        );
+    }
+
+    #[test]
+    fn sampling() {
+        let state = AccessLogMiddleware::new()
+            .with_sample_rate(0.5)
+            .with_path_sample_rate("/health", 0.0)
+            .transform(echo)
+            .state;
+        assert_eq!(
+            (0..4).map(|_| state.sample("/test")).collect::<Vec<_>>(),
+            [false, true, false, true]
+        );
+        assert!((0..4).all(|_| !state.sample("/health/live")));
+    }
+
+    #[test]
+    fn loggable() {
+        assert!(is_loggable(Some("application/json; charset=utf-8")));
+        assert!(is_loggable(Some("application/problem+json")));
+        assert!(is_loggable(Some("text/plain")));
+        assert!(!is_loggable(Some("text/event-stream")));
+        assert!(!is_loggable(Some("application/octet-stream")));
+        assert!(!is_loggable(None));
+    }
+
+    #[tokio::test]
+    async fn bodies_are_preserved() {
+        let cli = TestClient::new(
+            echo.with(
+                AccessLogMiddleware::new()
+                    .with_bodies(4)
+                    .with_redaction("/password"),
+            ),
+        );
+        let resp = cli
+            .post("/")
+            .content_type("application/json")
+            .body(r#"{"password":"secret"}"#)
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text(r#"{"password":"secret"}"#).await;
+    }
+}
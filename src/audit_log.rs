@@ -0,0 +1,450 @@
+//! Contains a middleware that records an audit log of the actions performed by
+//! authenticated users.
+//!
+//! For every request an [`AuditEvent`] is created that contains the
+//! authenticated [`Identity`] of the caller, the operation id of the
+//! poem-openapi endpoint (set via `#[oai(operation_id = "...")]`), the ids of
+//! the affected resources (added by the endpoint using
+//! [`AuditResources::add`]), the response status and a timestamp. The event
+//! is then passed to an [`AuditSink`], which can e.g. write it to the log
+//! ([`TracingAuditSink`]), a database table ([`DbAuditSink`], requires the
+//! `sea-orm` feature) or any other destination using a custom function.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Request, Route};
+//! use poem_ext::{
+//!     audit_log::{AuditLogMiddleware, AuditResources},
+//!     identity::Identity,
+//! };
+//! use poem_openapi::{param::Path, payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/users/:id", method = "delete", operation_id = "delete_user")]
+//!     async fn delete_user(&self, req: &Request, id: Path<u64>) -> PlainText<&'static str> {
+//!         // usually done by the authorization checker
+//!         Identity::set(req, "admin");
+//!         AuditResources::add(req, "user", id.0);
+//!         PlainText("deleted")
+//!     }
+//! }
+//!
+//! # #[cfg(feature = "tracing")]
+//! # {
+//! use poem_ext::audit_log::TracingAuditSink;
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .with(AuditLogMiddleware::new(TracingAuditSink).only_unsafe_methods());
+//! # }
+//! ```
+//!
+//! Custom sinks can be implemented using async functions, e.g. to send events
+//! to a webhook:
+//! ```
+//! use poem_ext::audit_log::{AuditEvent, AuditLogMiddleware};
+//!
+//! let middleware = AuditLogMiddleware::new(|event: AuditEvent| async move {
+//!     // send the event to a webhook
+//! });
+//! ```
+
+use std::{
+    fmt::{Debug, Display},
+    future::Future,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use poem::{
+    http::{Method, StatusCode},
+    Endpoint, IntoResponse, Middleware, Request, Response,
+};
+use poem_openapi::OperationId;
+
+use crate::{client_ip::ClientIp, identity::Identity, request_id::RequestId};
+
+/// An entry of the audit log.
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    /// The time at which the request has been received.
+    pub timestamp: SystemTime,
+    /// The authenticated identity of the caller (see [`Identity`]).
+    pub identity: Option<String>,
+    /// The operation id of the endpoint.
+    pub operation: Option<&'static str>,
+    /// The method of the request.
+    pub method: Method,
+    /// The path of the request.
+    pub path: String,
+    /// The id of the request (see [`RequestId`]).
+    pub request_id: Option<RequestId>,
+    /// The ip address of the client (see [`ClientIp`]).
+    pub client_ip: Option<IpAddr>,
+    /// The resources affected by the request (see [`AuditResources`]).
+    pub resources: Vec<AuditResource>,
+    /// The status of the response.
+    pub status: StatusCode,
+}
+
+impl AuditEvent {
+    /// Return `true` if the request has been successful (i.e. the response
+    /// status is not a client or server error).
+    pub fn is_success(&self) -> bool {
+        !self.status.is_client_error() && !self.status.is_server_error()
+    }
+}
+
+/// A resource affected by a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditResource {
+    /// The kind of the resource (e.g. `user`).
+    pub kind: String,
+    /// The id of the resource.
+    pub id: String,
+}
+
+/// Shared list of resources affected by a request.
+#[derive(Debug, Clone, Default)]
+pub struct AuditResources(Arc<Mutex<Vec<AuditResource>>>);
+
+impl AuditResources {
+    /// Record that the request affects the resource of the given kind and id.
+    ///
+    /// This is a no-op if no [`AuditLogMiddleware`] is present.
+    pub fn add(req: &Request, kind: impl Into<String>, id: impl Display) {
+        if let Some(resources) = req.extensions().get::<Self>() {
+            resources.0.lock().unwrap().push(AuditResource {
+                kind: kind.into(),
+                id: id.to_string(),
+            });
+        }
+    }
+
+    fn take(&self) -> Vec<AuditResource> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+/// A destination for audit events.
+///
+/// This trait is implemented for async functions that take an
+/// [`AuditEvent`].
+#[poem::async_trait]
+pub trait AuditSink: Send + Sync + 'static {
+    /// Record an audit event.
+    async fn record(&self, event: AuditEvent);
+}
+
+#[poem::async_trait]
+impl<F, Fut> AuditSink for F
+where
+    F: Fn(AuditEvent) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    async fn record(&self, event: AuditEvent) {
+        self(event).await
+    }
+}
+
+/// An [`AuditSink`] that emits audit events using the
+/// [`tracing`](https://docs.rs/tracing) crate (target `poem_ext::audit_log`).
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingAuditSink;
+
+#[poem::async_trait]
+#[cfg(feature = "tracing")]
+impl AuditSink for TracingAuditSink {
+    async fn record(&self, event: AuditEvent) {
+        let resources = event
+            .resources
+            .iter()
+            .map(|resource| format!("{}:{}", resource.kind, resource.id))
+            .collect::<Vec<_>>()
+            .join(",");
+        tracing::info!(
+            target: "poem_ext::audit_log",
+            identity = event.identity,
+            operation = event.operation,
+            method = %event.method,
+            path = event.path,
+            request_id = event.request_id.as_deref(),
+            client_ip = event.client_ip.map(tracing::field::display),
+            resources,
+            status = event.status.as_u16(),
+            success = event.is_success(),
+            "audit event",
+        );
+    }
+}
+
+/// An [`AuditSink`] that inserts audit events into a database table.
+///
+/// #### Example
+/// ```no_run
+/// use poem_ext::audit_log::{AuditLogMiddleware, DbAuditSink};
+/// use sea_orm::Set;
+/// # mod audit_log {
+/// #     use sea_orm::entity::prelude::*;
+/// #     #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+/// #     #[sea_orm(table_name = "audit_log")]
+/// #     pub struct Model {
+/// #         #[sea_orm(primary_key)]
+/// #         pub id: i32,
+/// #         pub identity: Option<String>,
+/// #         pub operation: Option<String>,
+/// #         pub status: i16,
+/// #     }
+/// #     #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+/// #     pub enum Relation {}
+/// #     impl ActiveModelBehavior for ActiveModel {}
+/// # }
+///
+/// # let db_connection = todo!();
+/// let middleware = AuditLogMiddleware::new(DbAuditSink::new(db_connection, |event| {
+///     audit_log::ActiveModel {
+///         identity: Set(event.identity),
+///         operation: Set(event.operation.map(Into::into)),
+///         status: Set(event.status.as_u16() as _),
+///         ..Default::default()
+///     }
+/// }));
+/// ```
+#[cfg(feature = "sea-orm")]
+pub struct DbAuditSink<F> {
+    db: sea_orm::DatabaseConnection,
+    model_fn: F,
+}
+
+#[cfg(feature = "sea-orm")]
+impl<F> Debug for DbAuditSink<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DbAuditSink")
+            .field("db", &self.db)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "sea-orm")]
+impl<F, A> DbAuditSink<F>
+where
+    F: Fn(AuditEvent) -> A + Send + Sync + 'static,
+    A: sea_orm::ActiveModelTrait + sea_orm::ActiveModelBehavior + Send,
+    <A::Entity as sea_orm::EntityTrait>::Model: sea_orm::IntoActiveModel<A>,
+{
+    /// Create a new DbAuditSink that converts audit events into active models
+    /// using `model_fn` and inserts them using the given database connection.
+    pub fn new(db: sea_orm::DatabaseConnection, model_fn: F) -> Self {
+        Self { db, model_fn }
+    }
+}
+
+#[cfg(feature = "sea-orm")]
+#[poem::async_trait]
+impl<F, A> AuditSink for DbAuditSink<F>
+where
+    F: Fn(AuditEvent) -> A + Send + Sync + 'static,
+    A: sea_orm::ActiveModelTrait + sea_orm::ActiveModelBehavior + Send,
+    <A::Entity as sea_orm::EntityTrait>::Model: sea_orm::IntoActiveModel<A>,
+{
+    async fn record(&self, event: AuditEvent) {
+        #[allow(unused_variables)]
+        if let Err(err) = (self.model_fn)(event).insert(&self.db).await {
+            #[cfg(feature = "tracing")]
+            tracing::error!(error = %err, "failed to insert audit event");
+        }
+    }
+}
+
+/// A middleware that records an [`AuditEvent`] for each request.
+///
+/// The event is recorded after the endpoint has returned and before the
+/// response is sent to the client.
+#[derive(Clone)]
+pub struct AuditLogMiddleware {
+    sink: Arc<dyn AuditSink>,
+    methods: Option<Vec<Method>>,
+}
+
+impl Debug for AuditLogMiddleware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLogMiddleware")
+            .field("methods", &self.methods)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AuditLogMiddleware {
+    /// Create a new AuditLogMiddleware that passes audit events to the given
+    /// sink.
+    pub fn new(sink: impl AuditSink) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            methods: None,
+        }
+    }
+
+    /// Only record requests with one of the given methods.
+    pub fn with_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.methods.get_or_insert_with(Vec::new).extend(methods);
+        self
+    }
+
+    /// Only record requests with methods that are not
+    /// [safe](https://developer.mozilla.org/en-US/docs/Glossary/Safe/HTTP)
+    /// (i.e. `POST`, `PUT`, `PATCH` and `DELETE`).
+    pub fn only_unsafe_methods(self) -> Self {
+        self.with_methods([Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for AuditLogMiddleware {
+    type Output = AuditLogMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        AuditLogMwEndpoint {
+            inner: ep,
+            sink: self.sink.clone(),
+            methods: self.methods.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct AuditLogMwEndpoint<E> {
+    inner: E,
+    sink: Arc<dyn AuditSink>,
+    methods: Option<Vec<Method>>,
+}
+
+impl<E: Debug> Debug for AuditLogMwEndpoint<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLogMwEndpoint")
+            .field("inner", &self.inner)
+            .field("methods", &self.methods)
+            .finish_non_exhaustive()
+    }
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for AuditLogMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        if self
+            .methods
+            .as_ref()
+            .is_some_and(|methods| !methods.contains(req.method()))
+        {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        }
+
+        let timestamp = SystemTime::now();
+        let method = req.method().clone();
+        let path = req.uri().path().to_owned();
+        let request_id = RequestId::of(&req);
+        let client_ip = ClientIp::of(&req).map(|ip| ip.0);
+        let identity = Identity::attach(&mut req);
+        let resources = AuditResources::default();
+        req.extensions_mut().insert(resources.clone());
+
+        let result = self.inner.call(req).await.map(IntoResponse::into_response);
+
+        let (status, operation) = match &result {
+            Ok(resp) => (resp.status(), resp.data::<OperationId>()),
+            Err(err) => (err.status(), err.data::<OperationId>()),
+        };
+        let event = AuditEvent {
+            timestamp,
+            identity: identity.value(),
+            operation: operation.map(|op| op.0),
+            method,
+            path,
+            request_id,
+            client_ip,
+            resources: resources.take(),
+            status,
+        };
+        self.sink.record(event).await;
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, EndpointExt, Route};
+    use poem_openapi::{param::Path, payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/users/:id", method = "get")]
+        async fn get_user(&self, id: Path<u64>) -> PlainText<String> {
+            PlainText(id.0.to_string())
+        }
+
+        #[oai(path = "/users/:id", method = "delete", operation_id = "delete_user")]
+        async fn delete_user(&self, req: &Request, id: Path<u64>) -> PlainText<String> {
+            Identity::set(req, "admin");
+            AuditResources::add(req, "user", id.0);
+            PlainText(id.0.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn audit_log() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cli = TestClient::new(
+            Route::new()
+                .nest("/", OpenApiService::new(Api, "test", "1"))
+                .with(
+                    AuditLogMiddleware::new({
+                        let events = events.clone();
+                        move |event| {
+                            events.lock().unwrap().push(event);
+                            async {}
+                        }
+                    })
+                    .only_unsafe_methods(),
+                ),
+        );
+
+        cli.get("/users/1").send().await.assert_status_is_ok();
+        cli.delete("/users/42").send().await.assert_status_is_ok();
+        cli.delete("/users/foo")
+            .send()
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+
+        let events = std::mem::take(&mut *events.lock().unwrap());
+        assert_eq!(events.len(), 2);
+
+        assert_eq!(events[0].identity.as_deref(), Some("admin"));
+        assert_eq!(events[0].operation, Some("delete_user"));
+        assert_eq!(events[0].method, Method::DELETE);
+        assert_eq!(events[0].path, "/users/42");
+        assert_eq!(
+            events[0].resources,
+            [AuditResource {
+                kind: "user".into(),
+                id: "42".into()
+            }]
+        );
+        assert!(events[0].is_success());
+
+        assert_eq!(events[1].identity, None);
+        assert!(events[1].resources.is_empty());
+        assert_eq!(events[1].status, StatusCode::BAD_REQUEST);
+        assert!(!events[1].is_success());
+    }
+}
@@ -1,3 +1,19 @@
+use std::ops::Deref;
+
+use poem::{
+    http::header::COOKIE,
+    web::cookie::{CookieJar, CookieKey},
+    Request, RequestBody,
+};
+use poem_openapi::{
+    auth::ApiKey, registry::Registry, ApiExtractor, ApiExtractorType, ExtractParamOptions,
+};
+
+use crate::{
+    cookie::get_cookie,
+    responses::{internal_server_error, ErrorResponse},
+};
+
 /// Define a custom authorization dependency based on
 /// [`poem_openapi::auth::Bearer`] that uses a custom function to perform
 /// authorization.
@@ -7,6 +23,48 @@
 /// the authenticated user) and a function that taks a request and a bearer
 /// token to check authorization.
 ///
+/// Use `custom_auth!(Auth, checker, scheme = basic)` to use HTTP Basic
+/// authentication instead. In this case the checker receives the
+/// [`poem_openapi::auth::Basic`] credentials of the request.
+///
+/// For clients that cannot set headers (e.g. signed download links or
+/// `EventSource` clients), `custom_auth!(Auth, checker, query = "access_token")`
+/// reads the token from the given query parameter instead of the
+/// `Authorization` header. The checker still receives it as a
+/// [`Bearer`](poem_openapi::auth::Bearer) token, but the security scheme is
+/// documented as an `apiKey` in `query`.
+///
+/// Checkers that need access to application state (e.g. a database
+/// connection or a cache) can request additional extractors using
+/// `custom_auth!(Auth, checker, extract(Data<&Db>, ...))`. The extractors
+/// implement [`poem::FromRequest`] and are passed to the checker after the
+/// token. The `extract(...)` argument can be combined with the options above,
+/// e.g. `custom_auth!(Auth, checker, extract(Data<&Db>), scheme = basic)`.
+///
+/// Doc comments in front of the name of the dependency are used as the
+/// description of the security scheme, and
+/// `custom_auth!(Auth, checker, bearer_format = "JWT")` documents the format
+/// of bearer tokens.
+///
+/// APIs that issue tokens using OAuth2 can describe their flows using
+/// `scheme = oauth2 { ... }` (see the example below). The flows
+/// (`implicit`, `password`, `client_credentials` and `authorization_code`)
+/// contain the `authorization_url`, `token_url`, `refresh_url` and `scopes`
+/// that are included in the OpenAPI documentation. The checker still receives
+/// the bearer token of the `Authorization` header. Similarly,
+/// `scheme = openid_connect("https://.../.well-known/openid-configuration")`
+/// documents an OpenID Connect scheme with the given discovery URL.
+///
+/// With the `jwt` feature, `custom_auth!(Auth, checker, jwt = Claims)` verifies
+/// the bearer token using the [`JwtVerifier`](crate::jwt::JwtVerifier) (or,
+/// with the `jwks` feature, the [`JwksProvider`](crate::jwks::JwksProvider))
+/// from the application data and passes the decoded `Claims` (or the reason
+/// why the token has been rejected) to the checker instead of the raw token
+/// (see [`jwt`](crate::jwt)).
+///
+/// Endpoints that are public but behave differently for authenticated users
+/// can use [`OptionalAuth<Auth>`](crate::OptionalAuth) instead.
+///
 /// #### Example
 /// ```
 /// use poem::Request;
@@ -55,9 +113,528 @@
 ///     }
 /// }
 /// ```
+///
+/// #### Basic authentication
+/// ```
+/// use poem::Request;
+/// use poem_ext::{custom_auth, response};
+/// use poem_openapi::{auth::Basic, payload::PlainText, OpenApi};
+///
+/// struct AdminAuth(String);
+///
+/// response!(AuthResult = {
+///     /// The credentials are missing or invalid.
+///     Unauthorized(401, error),
+/// });
+///
+/// async fn admin_auth_check(
+///     _req: &Request,
+///     credentials: Option<Basic>,
+/// ) -> Result<String, AuthResult::raw::Response> {
+///     match credentials {
+///         Some(Basic { username, password }) if password == "secret" => Ok(username),
+///         _ => Err(AuthResult::raw::unauthorized()),
+///     }
+/// }
+///
+/// custom_auth!(AdminAuth, admin_auth_check, scheme = basic);
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/admin", method = "get")]
+///     async fn admin(&self, auth: AdminAuth) -> PlainText<String> {
+///         PlainText(auth.0)
+///     }
+/// }
+/// ```
+///
+/// #### OAuth2
+/// ```
+/// use poem::Request;
+/// use poem_ext::{custom_auth, response};
+/// use poem_openapi::{auth::Bearer, payload::PlainText, OpenApi};
+///
+/// struct UserAuth(String);
+///
+/// response!(AuthResult = {
+///     /// The access token is missing or invalid.
+///     Unauthorized(401, error),
+/// });
+///
+/// async fn user_auth_check(
+///     _req: &Request,
+///     token: Option<Bearer>,
+/// ) -> Result<String, AuthResult::raw::Response> {
+///     match token {
+///         Some(Bearer { token }) if token == "secret_token" => Ok("alice".into()),
+///         _ => Err(AuthResult::raw::unauthorized()),
+///     }
+/// }
+///
+/// custom_auth!(
+///     UserAuth,
+///     user_auth_check,
+///     scheme = oauth2 {
+///         authorization_code {
+///             authorization_url: "https://auth.example.com/authorize",
+///             token_url: "https://auth.example.com/token",
+///             scopes: {
+///                 "read": "Read your data",
+///                 "write": "Modify your data",
+///             },
+///         },
+///         client_credentials {
+///             token_url: "https://auth.example.com/token",
+///         },
+///     }
+/// );
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/me", method = "get")]
+///     async fn me(&self, auth: UserAuth) -> PlainText<String> {
+///         PlainText(auth.0)
+///     }
+/// }
+/// ```
+///
+/// #### Accessing application state
+/// ```
+/// use std::collections::HashMap;
+///
+/// use poem::{web::Data, EndpointExt, Request};
+/// use poem_ext::{custom_auth, response};
+/// use poem_openapi::{auth::Bearer, payload::PlainText, OpenApi, OpenApiService};
+///
+/// /// Stores the user name of each session token.
+/// #[derive(Clone)]
+/// struct Sessions(HashMap<String, String>);
+///
+/// struct UserAuth(String);
+///
+/// response!(AuthResult = {
+///     /// The user is unauthenticated.
+///     Unauthorized(401, error),
+/// });
+///
+/// async fn user_auth_check(
+///     _req: &Request,
+///     token: Option<Bearer>,
+///     Data(sessions): Data<&Sessions>,
+/// ) -> Result<String, AuthResult::raw::Response> {
+///     token
+///         .and_then(|Bearer { token }| sessions.0.get(&token).cloned())
+///         .ok_or_else(AuthResult::raw::unauthorized)
+/// }
+///
+/// custom_auth!(UserAuth, user_auth_check, extract(Data<&Sessions>));
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/me", method = "get")]
+///     async fn me(&self, auth: UserAuth) -> PlainText<String> {
+///         PlainText(auth.0)
+///     }
+/// }
+///
+/// let sessions = Sessions(HashMap::from([("secret_token".into(), "alice".into())]));
+/// let app = OpenApiService::new(Api, "test", "1").data(sessions);
+/// ```
 #[macro_export]
 macro_rules! custom_auth {
-    ($auth:path, $checker:expr) => {
+    (@options $head:tt $extract:tt $credentials:tt $meta:tt extract($($extractor:ty),+ $(,)?) $(, $($rest:tt)*)?) => {
+        $crate::custom_auth!(@options $head [$($extractor),+] $credentials $meta $($($rest)*)?);
+    };
+    (@options $head:tt $extract:tt $credentials:tt $meta:tt scheme = bearer $(, $($rest:tt)*)?) => {
+        $crate::custom_auth!(@options $head $extract (bearer) $meta $($($rest)*)?);
+    };
+    (@options $head:tt $extract:tt $credentials:tt $meta:tt scheme = basic $(, $($rest:tt)*)?) => {
+        $crate::custom_auth!(@options $head $extract (basic) $meta $($($rest)*)?);
+    };
+    (@options $head:tt $extract:tt $credentials:tt $meta:tt scheme = oauth2 $flows:tt $(, $($rest:tt)*)?) => {
+        $crate::custom_auth!(@options $head $extract (oauth2 $flows) $meta $($($rest)*)?);
+    };
+    (@options $head:tt $extract:tt $credentials:tt $meta:tt scheme = openid_connect($url:literal) $(, $($rest:tt)*)?) => {
+        $crate::custom_auth!(@options $head $extract (openid_connect $url) $meta $($($rest)*)?);
+    };
+    (@options $head:tt $extract:tt $credentials:tt $meta:tt jwt = $claims:ty $(, $($rest:tt)*)?) => {
+        $crate::custom_auth!(@options $head $extract (jwt $claims) $meta $($($rest)*)?);
+    };
+    (@options $head:tt $extract:tt $credentials:tt $meta:tt query = $name:literal $(, $($rest:tt)*)?) => {
+        $crate::custom_auth!(@options $head $extract (query $name) $meta $($($rest)*)?);
+    };
+    (@options $head:tt $extract:tt $credentials:tt [$($meta:tt)*] bearer_format = $format:literal $(, $($rest:tt)*)?) => {
+        $crate::custom_auth!(
+            @options $head $extract $credentials
+            [$($meta)* bearer_format: ::std::option::Option::Some($format),]
+            $($($rest)*)?
+        );
+    };
+    (@options [$auth:path, $checker:expr, $docs:tt] $extract:tt $credentials:tt $meta:tt) => {
+        $crate::custom_auth!(@build $credentials $auth, $checker, $extract, $docs, $meta);
+    };
+    (@build (bearer) $auth:path, $checker:expr, $extract:tt, $docs:tt, $meta:tt) => {
+        $crate::__custom_auth_token!(
+            $auth,
+            $checker,
+            $extract,
+            request => <::poem_openapi::auth::Bearer as ::poem_openapi::auth::BearerAuthorization>::from_request(request).ok(),
+            $crate::__custom_auth_scheme!($docs, $meta, ::poem_openapi::registry::MetaSecurityScheme {
+                ty: "http",
+                description: ::std::option::Option::None,
+                name: ::std::option::Option::None,
+                key_in: ::std::option::Option::None,
+                scheme: ::std::option::Option::Some("bearer"),
+                bearer_format: ::std::option::Option::None,
+                flows: ::std::option::Option::None,
+                openid_connect_url: ::std::option::Option::None,
+            })
+        );
+    };
+    (@build (oauth2 { $($flow:ident $fields:tt),* $(,)? }) $auth:path, $checker:expr, $extract:tt, $docs:tt, $meta:tt) => {
+        $crate::__custom_auth_token!(
+            $auth,
+            $checker,
+            $extract,
+            request => <::poem_openapi::auth::Bearer as ::poem_openapi::auth::BearerAuthorization>::from_request(request).ok(),
+            $crate::__custom_auth_scheme!($docs, $meta, ::poem_openapi::registry::MetaSecurityScheme {
+                ty: "oauth2",
+                description: ::std::option::Option::None,
+                name: ::std::option::Option::None,
+                key_in: ::std::option::Option::None,
+                scheme: ::std::option::Option::None,
+                bearer_format: ::std::option::Option::None,
+                flows: ::std::option::Option::Some({
+                    let mut flows = ::poem_openapi::registry::MetaOAuthFlows {
+                        implicit: ::std::option::Option::None,
+                        password: ::std::option::Option::None,
+                        client_credentials: ::std::option::Option::None,
+                        authorization_code: ::std::option::Option::None,
+                    };
+                    $(flows.$flow = ::std::option::Option::Some($crate::__custom_auth_oauth_flow!($fields));)*
+                    flows
+                }),
+                openid_connect_url: ::std::option::Option::None,
+            })
+        );
+    };
+    (@build (openid_connect $url:literal) $auth:path, $checker:expr, $extract:tt, $docs:tt, $meta:tt) => {
+        $crate::__custom_auth_token!(
+            $auth,
+            $checker,
+            $extract,
+            request => <::poem_openapi::auth::Bearer as ::poem_openapi::auth::BearerAuthorization>::from_request(request).ok(),
+            $crate::__custom_auth_scheme!($docs, $meta, ::poem_openapi::registry::MetaSecurityScheme {
+                ty: "openIdConnect",
+                description: ::std::option::Option::None,
+                name: ::std::option::Option::None,
+                key_in: ::std::option::Option::None,
+                scheme: ::std::option::Option::None,
+                bearer_format: ::std::option::Option::None,
+                flows: ::std::option::Option::None,
+                openid_connect_url: ::std::option::Option::Some($url),
+            })
+        );
+    };
+    (@build (jwt $claims:ty) $auth:path, $checker:expr, $extract:tt, $docs:tt, $meta:tt) => {
+        $crate::__custom_auth_extractor!(
+            $auth,
+            request => {
+                let claims = $crate::__private::jwt_claims::<$claims>(request).await?;
+                let output = $crate::__custom_auth_check!($checker, request, claims, $extract);
+                ::std::result::Result::Ok(Self(output))
+            },
+            <::poem_openapi::auth::Bearer as ::poem_openapi::auth::BearerAuthorization>::from_request(request).is_ok(),
+            $crate::__custom_auth_scheme!($docs, $meta, ::poem_openapi::registry::MetaSecurityScheme {
+                ty: "http",
+                description: ::std::option::Option::None,
+                name: ::std::option::Option::None,
+                key_in: ::std::option::Option::None,
+                scheme: ::std::option::Option::Some("bearer"),
+                bearer_format: ::std::option::Option::Some("JWT"),
+                flows: ::std::option::Option::None,
+                openid_connect_url: ::std::option::Option::None,
+            })
+        );
+    };
+    (@build (basic) $auth:path, $checker:expr, $extract:tt, $docs:tt, $meta:tt) => {
+        $crate::__custom_auth_extractor!(
+            $auth,
+            request => {
+                let credentials =
+                    <::poem_openapi::auth::Basic as ::poem_openapi::auth::BasicAuthorization>::from_request(request).ok();
+                let output = $crate::__custom_auth_check!($checker, request, credentials, $extract);
+                ::std::result::Result::Ok(Self(output))
+            },
+            <::poem_openapi::auth::Basic as ::poem_openapi::auth::BasicAuthorization>::from_request(request).is_ok(),
+            $crate::__custom_auth_scheme!($docs, $meta, ::poem_openapi::registry::MetaSecurityScheme {
+                ty: "http",
+                description: ::std::option::Option::None,
+                name: ::std::option::Option::None,
+                key_in: ::std::option::Option::None,
+                scheme: ::std::option::Option::Some("basic"),
+                bearer_format: ::std::option::Option::None,
+                flows: ::std::option::Option::None,
+                openid_connect_url: ::std::option::Option::None,
+            })
+        );
+    };
+    (@build (query $name:literal) $auth:path, $checker:expr, $extract:tt, $docs:tt, $meta:tt) => {
+        $crate::__custom_auth_token!(
+            $auth,
+            $checker,
+            $extract,
+            request => $crate::__private::api_key_from_request(
+                request,
+                $name,
+                $crate::__private::KeyLocation::Query,
+            )
+            .ok()
+            .flatten()
+            .map(|api_key| ::poem_openapi::auth::Bearer { token: api_key.key }),
+            $crate::__custom_auth_scheme!($docs, $meta, ::poem_openapi::registry::MetaSecurityScheme {
+                ty: "apiKey",
+                description: ::std::option::Option::None,
+                name: ::std::option::Option::Some($name),
+                key_in: ::std::option::Option::Some("query"),
+                scheme: ::std::option::Option::None,
+                bearer_format: ::std::option::Option::None,
+                flows: ::std::option::Option::None,
+                openid_connect_url: ::std::option::Option::None,
+            })
+        );
+    };
+    ($(#[doc = $doc:literal])* $auth:path, $checker:expr $(, $($options:tt)*)?) => {
+        $crate::custom_auth!(@options [$auth, $checker, [$($doc),*]] [] (bearer) [] $($($options)*)?);
+    };
+}
+
+/// Build an OAuth2 flow of a custom authorization dependency.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __custom_auth_oauth_flow {
+    ({ $($field:ident: $value:tt),* $(,)? }) => {{
+        let mut flow = ::poem_openapi::registry::MetaOAuthFlow {
+            authorization_url: ::std::option::Option::None,
+            token_url: ::std::option::Option::None,
+            refresh_url: ::std::option::Option::None,
+            scopes: ::std::vec::Vec::new(),
+        };
+        $($crate::__custom_auth_oauth_flow!(@field flow, $field: $value);)*
+        flow
+    }};
+    (@field $flow:ident, scopes: { $($scope:literal: $description:literal),* $(,)? }) => {
+        $flow.scopes = ::std::vec![$(::poem_openapi::registry::MetaOAuthScope {
+            name: $scope,
+            description: ::std::option::Option::Some($description),
+        }),*];
+    };
+    (@field $flow:ident, $field:ident: $url:literal) => {
+        $flow.$field = ::std::option::Option::Some($url);
+    };
+}
+
+/// Build the security scheme of a custom authorization dependency from the
+/// given doc comments, additional fields and base scheme.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __custom_auth_scheme {
+    ([], [$($field:ident: $value:expr,)*], $base:expr) => {
+        ::poem_openapi::registry::MetaSecurityScheme {
+            $($field: $value,)*
+            ..$base
+        }
+    };
+    ([$($doc:literal),+], [$($field:ident: $value:expr,)*], $base:expr) => {
+        ::poem_openapi::registry::MetaSecurityScheme {
+            description: ::std::option::Option::Some(::std::concat!($($doc, "\n"),+)),
+            $($field: $value,)*
+            ..$base
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __custom_auth_token {
+    ($auth:path, $checker:expr, $extract:tt, $request:ident => $token:expr, $scheme:expr) => {
+        #[::poem::async_trait]
+        impl $crate::__private::CustomAuth for $auth {
+            async fn authenticate(
+                $request: &::poem::Request,
+                token: ::std::option::Option<::poem_openapi::auth::Bearer>,
+            ) -> ::poem::Result<Self> {
+                let output = $crate::__custom_auth_check!($checker, $request, token, $extract);
+                ::std::result::Result::Ok(Self(output))
+            }
+        }
+
+        $crate::__custom_auth_extractor!(
+            $auth,
+            $request => {
+                let token = $token;
+                <Self as $crate::__private::CustomAuth>::authenticate($request, token).await
+            },
+            ($token).is_some(),
+            $scheme
+        );
+    };
+}
+
+/// Run the checker of a custom authorization dependency with the given
+/// credentials and the additional extractors.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __custom_auth_check {
+    ($checker:expr, $request:ident, $credentials:expr, [$($extractor:ty),*]) => {{
+        let checker = $checker;
+        checker(
+            $request,
+            $credentials,
+            $(<$extractor as ::poem::FromRequest>::from_request_without_body($request).await?,)*
+        )
+        .await?
+    }};
+}
+
+/// Define a custom authorization dependency based on
+/// [`poem_openapi::auth::ApiKey`] that uses a custom function to perform
+/// authorization.
+///
+/// This macro works like [`custom_auth!`](crate::custom_auth), but the checker
+/// receives the api key that is read from the given header, query parameter or
+/// cookie (`header = "..."`, `query = "..."` or `cookie = "..."`) instead of a
+/// bearer token. The security scheme is documented as an `apiKey` scheme with
+/// the respective name and location.
+///
+/// Browser-facing APIs can also read the credential from a signed or private
+/// (encrypted) cookie using `signed_cookie = "..."` or
+/// `private_cookie = "..."`. Cookies that cannot be verified or decrypted are
+/// treated as missing. This requires the
+/// [`CookieKey`](poem::web::cookie::CookieKey) that has been used to set the
+/// cookie to be attached to the endpoint using
+/// [`EndpointExt::data`](poem::EndpointExt::data). If the key is missing,
+/// requests are rejected with `500 Internal Server Error`.
+///
+/// Doc comments in front of the name of the dependency are used as the
+/// description of the security scheme.
+///
+/// Additional extractors can be passed to the checker using
+/// `custom_apikey_auth!(Auth, checker, extract(Data<&Db>), header = "...")`
+/// (see [`custom_auth!`](crate::custom_auth)).
+///
+/// #### Example
+/// ```
+/// use poem::Request;
+/// use poem_ext::{custom_apikey_auth, response};
+/// use poem_openapi::{auth::ApiKey, payload::PlainText, OpenApi};
+///
+/// /// Dependency used by endpoints which require a valid api key.
+/// struct ServiceAuth(String);
+///
+/// response!(AuthResult = {
+///     /// The api key is missing or invalid.
+///     Unauthorized(401, error),
+/// });
+///
+/// async fn service_auth_check(
+///     _req: &Request,
+///     api_key: Option<ApiKey>,
+/// ) -> Result<String, AuthResult::raw::Response> {
+///     match api_key {
+///         Some(ApiKey { key }) if key == "secret_key" => Ok("billing".into()),
+///         _ => Err(AuthResult::raw::unauthorized()),
+///     }
+/// }
+///
+/// custom_apikey_auth!(ServiceAuth, service_auth_check, header = "X-API-Key");
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/secret", method = "get")]
+///     async fn secret(&self, auth: ServiceAuth) -> PlainText<String> {
+///         // only executed if the `X-API-Key` header is set to `secret_key`
+///         PlainText(auth.0)
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! custom_apikey_auth {
+    ($(#[doc = $doc:literal])* $auth:path, $checker:expr, $key_in:ident = $name:literal) => {
+        $crate::custom_apikey_auth!($(#[doc = $doc])* $auth, $checker, extract(), $key_in = $name);
+    };
+    ($(#[doc = $doc:literal])* $auth:path, $checker:expr, extract($($extractor:ty),* $(,)?), $key_in:ident = $name:literal) => {
+        $crate::__custom_auth_extractor!(
+            $auth,
+            request => {
+                let api_key = $crate::__private::api_key_from_request(
+                    request,
+                    $name,
+                    $crate::__custom_auth_key_in!($key_in),
+                )?;
+                let output = $crate::__custom_auth_check!($checker, request, api_key, [$($extractor),*]);
+                ::std::result::Result::Ok(Self(output))
+            },
+            !::std::matches!(
+                $crate::__private::api_key_from_request(
+                    request,
+                    $name,
+                    $crate::__custom_auth_key_in!($key_in),
+                ),
+                ::std::result::Result::Ok(::std::option::Option::None)
+            ),
+            $crate::__custom_auth_scheme!([$($doc),*], [], ::poem_openapi::registry::MetaSecurityScheme {
+                ty: "apiKey",
+                description: ::std::option::Option::None,
+                name: ::std::option::Option::Some($name),
+                key_in: ::std::option::Option::Some($crate::__custom_auth_key_in!($key_in).key_in()),
+                scheme: ::std::option::Option::None,
+                bearer_format: ::std::option::Option::None,
+                flows: ::std::option::Option::None,
+                openid_connect_url: ::std::option::Option::None,
+            })
+        );
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __custom_auth_key_in {
+    (header) => {
+        $crate::__private::KeyLocation::Header
+    };
+    (query) => {
+        $crate::__private::KeyLocation::Query
+    };
+    (cookie) => {
+        $crate::__private::KeyLocation::Cookie
+    };
+    (signed_cookie) => {
+        $crate::__private::KeyLocation::SignedCookie
+    };
+    (private_cookie) => {
+        $crate::__private::KeyLocation::PrivateCookie
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __custom_auth_extractor {
+    ($auth:path, $request:ident => $extract:block, $has_credentials:expr, $scheme:expr) => {
+        impl $crate::__private::HasCredentials for $auth {
+            fn has_credentials($request: &::poem::Request) -> bool {
+                $has_credentials
+            }
+        }
+
         #[::poem::async_trait]
         impl<'a> ::poem_openapi::ApiExtractor<'a> for $auth {
             const TYPES: &'static [::poem_openapi::ApiExtractorType] =
@@ -67,31 +644,15 @@ macro_rules! custom_auth {
             type ParamRawType = ();
 
             async fn from_request(
-                request: &'a ::poem::Request,
+                $request: &'a ::poem::Request,
                 _body: &mut ::poem::RequestBody,
                 _param_opts: ::poem_openapi::ExtractParamOptions<Self::ParamType>,
             ) -> ::poem::Result<Self> {
-                let output =
-                    <::poem_openapi::auth::Bearer as ::poem_openapi::auth::BearerAuthorization>::from_request(request).ok();
-                let checker = $checker;
-                let output = checker(request, output).await?;
-                ::std::result::Result::Ok(Self(output))
+                $extract
             }
 
             fn register(registry: &mut ::poem_openapi::registry::Registry) {
-                registry.create_security_scheme(
-                    ::std::stringify!($auth),
-                    ::poem_openapi::registry::MetaSecurityScheme {
-                        ty: "http",
-                        description: ::std::option::Option::None,
-                        name: ::std::option::Option::None,
-                        key_in: ::std::option::Option::None,
-                        scheme: ::std::option::Option::Some("bearer"),
-                        bearer_format: ::std::option::Option::None,
-                        flows: ::std::option::Option::None,
-                        openid_connect_url: ::std::option::Option::None,
-                    },
-                );
+                registry.create_security_scheme(::std::stringify!($auth), $scheme);
             }
 
             fn security_schemes() -> ::std::vec::Vec<&'static str> {
@@ -101,11 +662,215 @@ macro_rules! custom_auth {
     };
 }
 
+/// The location of an api key (used by
+/// [`custom_apikey_auth!`](crate::custom_apikey_auth)).
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyLocation {
+    Header,
+    Query,
+    Cookie,
+    SignedCookie,
+    PrivateCookie,
+}
+
+impl KeyLocation {
+    /// Return the location of the api key in the security scheme.
+    pub const fn key_in(self) -> &'static str {
+        match self {
+            Self::Header => "header",
+            Self::Query => "query",
+            Self::Cookie | Self::SignedCookie | Self::PrivateCookie => "cookie",
+        }
+    }
+}
+
+/// Read an api key from the given location of a request (used by
+/// [`custom_apikey_auth!`](crate::custom_apikey_auth)).
+///
+/// Fails with `500 Internal Server Error` if a signed or private cookie should
+/// be read but no [`CookieKey`] has been attached to the endpoint.
+#[doc(hidden)]
+pub fn api_key_from_request(
+    request: &poem::Request,
+    name: &str,
+    location: KeyLocation,
+) -> Result<Option<ApiKey>, ErrorResponse> {
+    let key = match location {
+        KeyLocation::Header => request.header(name).map(ToOwned::to_owned),
+        KeyLocation::Query => request
+            .params::<Vec<(String, String)>>()
+            .ok()
+            .and_then(|params| {
+                params
+                    .into_iter()
+                    .find_map(|(key, value)| (key == name).then_some(value))
+            }),
+        KeyLocation::Cookie => get_cookie(request, name).map(ToOwned::to_owned),
+        KeyLocation::SignedCookie => cookie_jar(request)
+            .signed_with_key(cookie_key(request)?)
+            .get(name)
+            .map(|cookie| cookie.value_str().to_owned()),
+        KeyLocation::PrivateCookie => cookie_jar(request)
+            .private_with_key(cookie_key(request)?)
+            .get(name)
+            .map(|cookie| cookie.value_str().to_owned()),
+    };
+    Ok(key.map(|key| ApiKey { key }))
+}
+
+/// Return the key for signed and private cookies attached to the endpoint.
+fn cookie_key(request: &poem::Request) -> Result<&CookieKey, ErrorResponse> {
+    request.data::<CookieKey>().ok_or_else(|| {
+        internal_server_error("cookie key is missing (CookieKey not attached using `data`)")
+    })
+}
+
+/// Parse the cookies sent with the request.
+fn cookie_jar(request: &poem::Request) -> CookieJar {
+    let cookies = request
+        .headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join("; ");
+    cookies.parse().unwrap_or_default()
+}
+
+/// Implemented by the [`custom_auth!`](crate::custom_auth) macro to make the
+/// checker accessible independently of the `Authorization` header.
+#[doc(hidden)]
+#[poem::async_trait]
+pub trait CustomAuth: Sized {
+    /// Run the checker for the given request and bearer token.
+    async fn authenticate(
+        request: &poem::Request,
+        token: Option<poem_openapi::auth::Bearer>,
+    ) -> poem::Result<Self>;
+}
+
+/// Implemented by the [`custom_auth!`](crate::custom_auth) and
+/// [`custom_apikey_auth!`](crate::custom_apikey_auth) macros to check whether
+/// a request contains any credentials (used by [`OptionalAuth`]).
+#[doc(hidden)]
+pub trait HasCredentials {
+    /// Return whether the request contains credentials for this scheme.
+    fn has_credentials(request: &poem::Request) -> bool;
+}
+
+/// An authorization dependency that is optional.
+///
+/// Wraps a dependency defined using [`custom_auth!`](crate::custom_auth) or
+/// [`custom_apikey_auth!`](crate::custom_apikey_auth) and contains `None` if
+/// the request does not contain any credentials. If credentials are present,
+/// the checker is run as usual, i.e. invalid credentials are still rejected.
+/// The security scheme of the wrapped dependency is included in the OpenAPI
+/// documentation.
+///
+/// #### Example
+/// ```
+/// use poem::Request;
+/// use poem_ext::{custom_auth, response, OptionalAuth};
+/// use poem_openapi::{auth::Bearer, payload::PlainText, OpenApi};
+///
+/// struct UserAuth(String);
+///
+/// response!(AuthResult = {
+///     /// The user is unauthenticated.
+///     Unauthorized(401, error),
+///     /// The token is invalid.
+///     Forbidden(403, error),
+/// });
+///
+/// async fn user_auth_check(
+///     _req: &Request,
+///     token: Option<Bearer>,
+/// ) -> Result<String, AuthResult::raw::Response> {
+///     match token {
+///         Some(Bearer { token }) if token == "secret_token" => Ok("alice".into()),
+///         Some(_) => Err(AuthResult::raw::forbidden()),
+///         None => Err(AuthResult::raw::unauthorized()),
+///     }
+/// }
+///
+/// custom_auth!(UserAuth, user_auth_check);
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/greeting", method = "get")]
+///     async fn greeting(&self, auth: OptionalAuth<UserAuth>) -> PlainText<String> {
+///         match &*auth {
+///             Some(UserAuth(name)) => PlainText(format!("Hello, {name}!")),
+///             None => PlainText("Hello, stranger!".into()),
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionalAuth<A>(pub Option<A>);
+
+impl<A> Deref for OptionalAuth<A> {
+    type Target = Option<A>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[poem::async_trait]
+impl<'a, A> ApiExtractor<'a> for OptionalAuth<A>
+where
+    A: ApiExtractor<'a> + HasCredentials,
+{
+    const TYPES: &'static [ApiExtractorType] = A::TYPES;
+
+    type ParamType = A::ParamType;
+    type ParamRawType = A::ParamRawType;
+
+    fn register(registry: &mut Registry) {
+        A::register(registry);
+    }
+
+    fn security_schemes() -> Vec<&'static str> {
+        A::security_schemes()
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        body: &mut RequestBody,
+        param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        if !A::has_credentials(request) {
+            return Ok(Self(None));
+        }
+        A::from_request(request, body, param_opts)
+            .await
+            .map(Some)
+            .map(Self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use poem::Request;
-    use poem_openapi::{auth::Bearer, ApiExtractor};
+    use poem::{
+        http::StatusCode,
+        test::TestClient,
+        web::{
+            cookie::{Cookie, CookieJar, CookieKey},
+            Data, RealIp,
+        },
+        EndpointExt, Request,
+    };
+    use poem_openapi::{
+        auth::{Basic, Bearer},
+        registry::Registry,
+        ApiExtractor, OpenApi, OpenApiService,
+    };
 
+    use super::*;
     use crate::response;
 
     #[test]
@@ -162,4 +927,460 @@ mod tests {
     }
 
     custom_auth!(UserAuth, user_auth_check);
+
+    #[derive(Debug)]
+    struct HeaderAuth(());
+
+    #[derive(Debug)]
+    struct QueryAuth(());
+
+    #[derive(Debug)]
+    struct CookieAuth(());
+
+    async fn api_key_check(
+        _req: &Request,
+        api_key: Option<ApiKey>,
+    ) -> Result<(), UserAuthResult::raw::Response> {
+        match api_key {
+            Some(ApiKey { key }) if key == "secret_key" => Ok(()),
+            Some(_) => Err(UserAuthResult::raw::forbidden()),
+            None => Err(UserAuthResult::raw::unauthorized()),
+        }
+    }
+
+    custom_apikey_auth!(HeaderAuth, api_key_check, header = "X-API-Key");
+    custom_apikey_auth!(QueryAuth, api_key_check, query = "api_key");
+    custom_apikey_auth!(CookieAuth, api_key_check, cookie = "api_key");
+
+    async fn status<T: for<'a> ApiExtractor<'a>>(request: Request) -> u16 {
+        match T::from_request(&request, &mut Default::default(), Default::default()).await {
+            Ok(_) => 200,
+            Err(err) => err.into_response().status().into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apikey_auth() {
+        let request = || Request::builder();
+        assert_eq!(status::<HeaderAuth>(request().finish()).await, 401);
+        assert_eq!(
+            status::<HeaderAuth>(request().header("X-API-Key", "foo").finish()).await,
+            403
+        );
+        assert_eq!(
+            status::<HeaderAuth>(request().header("X-API-Key", "secret_key").finish()).await,
+            200
+        );
+        assert_eq!(
+            status::<QueryAuth>(request().uri_str("/?api_key=secret_key").finish()).await,
+            200
+        );
+        assert_eq!(
+            status::<QueryAuth>(request().header("X-API-Key", "secret_key").finish()).await,
+            401
+        );
+        assert_eq!(
+            status::<CookieAuth>(request().header("Cookie", "api_key=secret_key").finish()).await,
+            200
+        );
+    }
+
+    #[tokio::test]
+    async fn test_optional_auth() {
+        let check = |request: Request| async move {
+            OptionalAuth::<UserAuth>::from_request(
+                &request,
+                &mut Default::default(),
+                Default::default(),
+            )
+            .await
+            .map(|auth| auth.is_some())
+            .map_err(|err| u16::from(err.into_response().status()))
+        };
+        let request = |authorization: &str| {
+            Request::builder()
+                .header("Authorization", authorization)
+                .finish()
+        };
+        assert_eq!(check(Request::default()).await, Ok(false));
+        assert_eq!(check(request("Bearer secret_token")).await, Ok(true));
+        assert_eq!(check(request("Bearer foobar")).await, Err(403));
+
+        assert_eq!(
+            OptionalAuth::<UserAuth>::security_schemes(),
+            vec!["UserAuth"]
+        );
+        assert_eq!(
+            status::<OptionalAuth<HeaderAuth>>(Request::default()).await,
+            200
+        );
+    }
+
+    #[derive(Debug)]
+    struct StateAuth(String);
+
+    #[derive(Debug)]
+    struct StateApiKeyAuth(String);
+
+    #[derive(Clone)]
+    struct Sessions(Vec<(&'static str, &'static str)>);
+
+    async fn state_auth_check(
+        _req: &Request,
+        token: Option<Bearer>,
+        Data(sessions): Data<&Sessions>,
+        RealIp(_): RealIp,
+    ) -> Result<String, UserAuthResult::raw::Response> {
+        let token = token.ok_or_else(UserAuthResult::raw::unauthorized)?;
+        sessions
+            .0
+            .iter()
+            .find_map(|&(t, user)| (t == token.token).then(|| user.to_owned()))
+            .ok_or_else(UserAuthResult::raw::forbidden)
+    }
+
+    async fn state_api_key_check(
+        req: &Request,
+        api_key: Option<ApiKey>,
+        sessions: Data<&Sessions>,
+    ) -> Result<String, UserAuthResult::raw::Response> {
+        let token = api_key.map(|ApiKey { key }| Bearer { token: key });
+        state_auth_check(req, token, sessions, RealIp(None)).await
+    }
+
+    custom_auth!(
+        StateAuth,
+        state_auth_check,
+        extract(Data<&Sessions>, RealIp)
+    );
+    custom_apikey_auth!(
+        StateApiKeyAuth,
+        state_api_key_check,
+        extract(Data<&Sessions>),
+        header = "X-API-Key"
+    );
+
+    #[tokio::test]
+    async fn test_extract_state() {
+        let sessions = || Sessions(vec![("secret_token", "alice")]);
+        let request = |header: &str, value: &str| {
+            let mut request = Request::builder().header(header, value).finish();
+            request.extensions_mut().insert(sessions());
+            request
+        };
+        let auth = StateAuth::from_request(
+            &request("Authorization", "Bearer secret_token"),
+            &mut Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(auth.0, "alice");
+        assert_eq!(
+            status::<StateAuth>(request("Authorization", "Bearer foo")).await,
+            403
+        );
+        let auth = StateApiKeyAuth::from_request(
+            &request("X-API-Key", "secret_token"),
+            &mut Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(auth.0, "alice");
+        // missing data is reported by the extractor
+        assert_eq!(
+            status::<StateAuth>(
+                Request::builder()
+                    .header("Authorization", "Bearer secret_token")
+                    .finish()
+            )
+            .await,
+            500
+        );
+    }
+
+    #[derive(Debug)]
+    struct JwtAuth(User);
+
+    custom_auth!(
+        /// A session token.
+        ///
+        /// Obtained from `POST /login`.
+        JwtAuth,
+        user_auth_check,
+        bearer_format = "JWT",
+    );
+
+    #[derive(Debug)]
+    struct DocumentedApiKeyAuth(());
+
+    custom_apikey_auth!(
+        /// A service api key.
+        DocumentedApiKeyAuth,
+        api_key_check,
+        header = "X-API-Key"
+    );
+
+    #[test]
+    fn test_scheme_metadata() {
+        let mut registry = Registry::new();
+        JwtAuth::register(&mut registry);
+        UserAuth::register(&mut registry);
+        DocumentedApiKeyAuth::register(&mut registry);
+        let scheme = &registry.security_schemes["JwtAuth"];
+        assert_eq!(scheme.ty, "http");
+        assert_eq!(scheme.scheme, Some("bearer"));
+        assert_eq!(scheme.bearer_format, Some("JWT"));
+        assert_eq!(
+            scheme.description,
+            Some(" A session token.\n\n Obtained from `POST /login`.\n")
+        );
+        let scheme = &registry.security_schemes["UserAuth"];
+        assert_eq!(scheme.bearer_format, None);
+        assert_eq!(scheme.description, None);
+        assert_eq!(
+            registry.security_schemes["DocumentedApiKeyAuth"].description,
+            Some(" A service api key.\n")
+        );
+    }
+
+    #[derive(Debug)]
+    struct OAuthAuth(User);
+
+    custom_auth!(
+        OAuthAuth,
+        user_auth_check,
+        scheme = oauth2 {
+            authorization_code {
+                authorization_url: "https://example.com/authorize",
+                token_url: "https://example.com/token",
+                refresh_url: "https://example.com/refresh",
+                scopes: { "read": "Read access", "write": "Write access" },
+            },
+            client_credentials { token_url: "https://example.com/token" },
+        },
+    );
+
+    #[tokio::test]
+    async fn test_oauth2_scheme() {
+        let request = Request::builder()
+            .header("Authorization", "Bearer secret_token")
+            .finish();
+        assert_eq!(status::<OAuthAuth>(request).await, 200);
+
+        let mut registry = Registry::new();
+        OAuthAuth::register(&mut registry);
+        assert_eq!(
+            serde_json::to_value(&registry.security_schemes["OAuthAuth"]).unwrap(),
+            serde_json::json!({
+                "type": "oauth2",
+                "flows": {
+                    "authorizationCode": {
+                        "authorizationUrl": "https://example.com/authorize",
+                        "tokenUrl": "https://example.com/token",
+                        "refreshUrl": "https://example.com/refresh",
+                        "scopes": {"read": "Read access", "write": "Write access"},
+                    },
+                    "clientCredentials": {"tokenUrl": "https://example.com/token"},
+                },
+            })
+        );
+    }
+
+    #[derive(Debug)]
+    struct OidcAuth(User);
+
+    custom_auth!(
+        OidcAuth,
+        user_auth_check,
+        scheme = openid_connect("https://example.com/.well-known/openid-configuration")
+    );
+
+    #[tokio::test]
+    async fn test_openid_connect_scheme() {
+        let request = Request::builder()
+            .header("Authorization", "Bearer foo")
+            .finish();
+        assert_eq!(status::<OidcAuth>(request).await, 403);
+
+        let mut registry = Registry::new();
+        OidcAuth::register(&mut registry);
+        assert_eq!(
+            serde_json::to_value(&registry.security_schemes["OidcAuth"]).unwrap(),
+            serde_json::json!({
+                "type": "openIdConnect",
+                "openIdConnectUrl": "https://example.com/.well-known/openid-configuration",
+            })
+        );
+    }
+
+    #[derive(Debug)]
+    struct QueryTokenAuth(User);
+
+    custom_auth!(QueryTokenAuth, user_auth_check, query = "access_token");
+
+    #[tokio::test]
+    async fn test_query_token_auth() {
+        let request = |uri: &str| {
+            Request::builder()
+                .uri_str(uri)
+                .header("Authorization", "Bearer secret_token")
+                .finish()
+        };
+        assert_eq!(status::<QueryTokenAuth>(request("/")).await, 401);
+        assert_eq!(
+            status::<QueryTokenAuth>(request("/?access_token=foo")).await,
+            403
+        );
+        assert_eq!(
+            status::<QueryTokenAuth>(request("/?access_token=secret_token")).await,
+            200
+        );
+
+        let mut registry = Registry::new();
+        QueryTokenAuth::register(&mut registry);
+        let scheme = &registry.security_schemes["QueryTokenAuth"];
+        assert_eq!(scheme.ty, "apiKey");
+        assert_eq!(scheme.name, Some("access_token"));
+        assert_eq!(scheme.key_in, Some("query"));
+    }
+
+    #[derive(Debug)]
+    struct BasicAuth(());
+
+    async fn basic_auth_check(
+        _req: &Request,
+        credentials: Option<Basic>,
+    ) -> Result<(), UserAuthResult::raw::Response> {
+        match credentials {
+            Some(Basic { username, password }) if username == "admin" && password == "secret" => {
+                Ok(())
+            }
+            Some(_) => Err(UserAuthResult::raw::forbidden()),
+            None => Err(UserAuthResult::raw::unauthorized()),
+        }
+    }
+
+    custom_auth!(BasicAuth, basic_auth_check, scheme = basic);
+
+    #[tokio::test]
+    async fn test_basic_auth() {
+        let request = |authorization: &str| {
+            Request::builder()
+                .header("Authorization", authorization)
+                .finish()
+        };
+        // admin:secret
+        assert_eq!(
+            status::<BasicAuth>(request("Basic YWRtaW46c2VjcmV0")).await,
+            200
+        );
+        // admin:foo
+        assert_eq!(
+            status::<BasicAuth>(request("Basic YWRtaW46Zm9v")).await,
+            403
+        );
+        assert_eq!(
+            status::<BasicAuth>(request("Bearer secret_token")).await,
+            401
+        );
+
+        let mut registry = Registry::new();
+        BasicAuth::register(&mut registry);
+        let scheme = &registry.security_schemes["BasicAuth"];
+        assert_eq!(scheme.ty, "http");
+        assert_eq!(scheme.scheme, Some("basic"));
+    }
+
+    #[derive(Debug)]
+    struct SignedAuth(());
+
+    #[derive(Debug)]
+    struct PrivateAuth(());
+
+    custom_apikey_auth!(SignedAuth, api_key_check, signed_cookie = "session");
+    custom_apikey_auth!(PrivateAuth, api_key_check, private_cookie = "session");
+
+    struct CookieApi;
+
+    #[OpenApi]
+    impl CookieApi {
+        #[oai(path = "/signed", method = "get")]
+        async fn signed(&self, _auth: SignedAuth) {}
+
+        #[oai(path = "/private", method = "get")]
+        async fn private(&self, _auth: PrivateAuth) {}
+    }
+
+    #[tokio::test]
+    async fn test_cookie_auth() {
+        let key = CookieKey::generate();
+        let cli = TestClient::new(OpenApiService::new(CookieApi, "test", "1").data(key.clone()));
+
+        let jar = CookieJar::default();
+        jar.signed_with_key(&key)
+            .add(Cookie::new_with_str("session", "secret_key"));
+        let signed = jar.get("session").unwrap().value_str().to_owned();
+        jar.private_with_key(&key)
+            .add(Cookie::new_with_str("session", "secret_key"));
+        let private = jar.get("session").unwrap().value_str().to_owned();
+
+        let check = |path: &'static str, value: String| {
+            cli.get(path)
+                .header("Cookie", format!("session={value}"))
+                .send()
+        };
+        check("/signed", signed.clone()).await.assert_status_is_ok();
+        check("/private", private.clone())
+            .await
+            .assert_status_is_ok();
+        check("/signed", "secret_key".into())
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        check("/private", signed)
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        check("/signed", private)
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+
+        let mut registry = Registry::new();
+        PrivateAuth::register(&mut registry);
+        let scheme = &registry.security_schemes["PrivateAuth"];
+        assert_eq!(scheme.ty, "apiKey");
+        assert_eq!(scheme.name, Some("session"));
+        assert_eq!(scheme.key_in, Some("cookie"));
+    }
+
+    #[tokio::test]
+    async fn test_cookie_auth_without_key() {
+        let cli = TestClient::new(OpenApiService::new(CookieApi, "test", "1"));
+        for path in ["/signed", "/private"] {
+            cli.get(path)
+                .header("Cookie", "session=secret_key")
+                .send()
+                .await
+                .assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+            cli.get(path)
+                .send()
+                .await
+                .assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    #[test]
+    fn test_apikey_scheme() {
+        let mut registry = Registry::new();
+        HeaderAuth::register(&mut registry);
+        CookieAuth::register(&mut registry);
+        let scheme = &registry.security_schemes["HeaderAuth"];
+        assert_eq!(scheme.ty, "apiKey");
+        assert_eq!(scheme.name, Some("X-API-Key"));
+        assert_eq!(scheme.key_in, Some("header"));
+        assert_eq!(
+            registry.security_schemes["CookieAuth"].key_in,
+            Some("cookie")
+        );
+    }
 }
@@ -0,0 +1,237 @@
+//! Contains a middleware that captures request bodies for error reports.
+//!
+//! The [`RequestBodyCaptureMiddleware`] keeps a copy of JSON and text request
+//! bodies, so the hooks of the
+//! [`PanicHandlerMiddleware`](crate::panic_handler::PanicHandlerMiddleware)
+//! that are called for panics and `500 Internal Server Error` responses can
+//! include what the client actually sent (e.g. when forwarding errors to an
+//! alerting system). Values in JSON bodies can be redacted using
+//! [JSON pointers](https://www.rfc-editor.org/rfc/rfc6901) in which `*`
+//! matches any object key or array index, and bodies that are larger than a
+//! configurable size are truncated. Redaction and truncation only happen when
+//! an error is reported, so the overhead for successful requests is small.
+//!
+//! Bodies are read into memory before the endpoint is called, so this
+//! middleware should be combined with a
+//! [`BodyLimitMiddleware`](crate::body_limit::BodyLimitMiddleware) and not be
+//! used for endpoints that stream large uploads.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{body_capture::RequestBodyCaptureMiddleware, panic_handler::PanicHandler};
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "post")]
+//!     async fn test(&self, data: PlainText<String>) -> PlainText<String> {
+//!         data
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .with(
+//!         RequestBodyCaptureMiddleware::new(4096)
+//!             .with_redaction("/password")
+//!             .without_capture_for("/uploads"),
+//!     )
+//!     .with(PanicHandler::custom().with_hook(|info| {
+//!         // forward the panic and the request body to an alerting system
+//!         eprintln!("{} panicked: {:?}", info.path(), info.request_body);
+//!     }));
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use poem::{http::header::CONTENT_TYPE, Endpoint, IntoResponse, Middleware, Request, Response};
+
+use crate::{
+    utils::path_has_prefix,
+    utils::{format_body, is_loggable, parse_pointer},
+};
+
+/// A middleware that captures request bodies for error reports.
+#[derive(Debug, Clone)]
+pub struct RequestBodyCaptureMiddleware {
+    max_size: usize,
+    redactions: Vec<Vec<String>>,
+    excluded: Vec<String>,
+}
+
+impl RequestBodyCaptureMiddleware {
+    /// Create a new RequestBodyCaptureMiddleware. Captured bodies that are
+    /// larger than `max_size` bytes are truncated.
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            redactions: Vec::new(),
+            excluded: Vec::new(),
+        }
+    }
+
+    /// Replace the value at the given JSON pointer (e.g. `/password` or
+    /// `/users/*/token`) in captured JSON bodies with
+    /// [`REDACTED`](crate::access_log::REDACTED).
+    ///
+    /// # Panics
+    /// Panics if the pointer does not start with a `/`.
+    pub fn with_redaction(mut self, pointer: impl AsRef<str>) -> Self {
+        self.redactions.push(parse_pointer(pointer.as_ref()));
+        self
+    }
+
+    /// Never capture the bodies of requests whose path starts with the given
+    /// prefix.
+    pub fn without_capture_for(mut self, prefix: impl Into<String>) -> Self {
+        self.excluded.push(prefix.into());
+        self
+    }
+}
+
+/// Shared slot for the captured body of a request.
+///
+/// Error reporting middlewares attach an empty [`CapturedBody`] to the request
+/// before calling the endpoint, which the [`RequestBodyCaptureMiddleware`]
+/// fills with the request body, independent of the order of the middlewares.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedBody(Arc<Mutex<Option<Capture>>>);
+
+/// A captured body and the configuration used to format it.
+type Capture = (Bytes, Arc<RequestBodyCaptureMiddleware>);
+
+impl CapturedBody {
+    /// Attach a [`CapturedBody`] to a request (if not already present) and
+    /// return a handle to it that can be used to read the body after the
+    /// request has been passed to the endpoint.
+    pub fn attach(req: &mut Request) -> Self {
+        if let Some(slot) = req.extensions().get::<Self>() {
+            return slot.clone();
+        }
+        let slot = Self::default();
+        req.extensions_mut().insert(slot.clone());
+        slot
+    }
+
+    /// Return the redacted and truncated body of a request, if it has been
+    /// captured.
+    pub fn get(req: &Request) -> Option<String> {
+        req.extensions().get::<Self>().and_then(Self::value)
+    }
+
+    /// Return the redacted and truncated body, if it has been captured.
+    pub fn value(&self) -> Option<String> {
+        let captured = self.0.lock().unwrap();
+        let (body, config) = captured.as_ref()?;
+        Some(format_body(body, &config.redactions, config.max_size))
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RequestBodyCaptureMiddleware {
+    type Output = RequestBodyCaptureMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RequestBodyCaptureMwEndpoint {
+            inner: ep,
+            config: Arc::new(self.clone()),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct RequestBodyCaptureMwEndpoint<E> {
+    inner: E,
+    config: Arc<RequestBodyCaptureMiddleware>,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for RequestBodyCaptureMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let excluded = self
+            .config
+            .excluded
+            .iter()
+            .any(|prefix| path_has_prefix(req.uri().path(), prefix));
+        if !excluded && is_loggable(req.header(CONTENT_TYPE)) {
+            let body = req.take_body().into_bytes().await?;
+            let slot = CapturedBody::attach(&mut req);
+            *slot.0.lock().unwrap() = Some((body.clone(), self.config.clone()));
+            req.set_body(body);
+        }
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, test::TestClient, EndpointExt, Route};
+
+    use super::*;
+
+    #[handler]
+    fn captured(req: &Request, body: String) -> String {
+        format!("{body} {}", CapturedBody::get(req).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn capture() {
+        let cli = TestClient::new(
+            Route::new()
+                .at("/test", captured)
+                .at("/uploads", captured)
+                .with(
+                    RequestBodyCaptureMiddleware::new(64)
+                        .with_redaction("/password")
+                        .without_capture_for("/uploads"),
+                ),
+        );
+
+        let resp = cli
+            .post("/test")
+            .content_type("application/json")
+            .body(r#"{"user":"foo","password":"bar"}"#)
+            .send()
+            .await;
+        resp.assert_text(
+            r#"{"user":"foo","password":"bar"} {"password":"[redacted]","user":"foo"}"#,
+        )
+        .await;
+
+        let resp = cli
+            .post("/test")
+            .content_type("text/plain")
+            .body("x".repeat(72))
+            .send()
+            .await;
+        resp.assert_text(format!(
+            "{} {}... (8 bytes truncated)",
+            "x".repeat(72),
+            "x".repeat(64)
+        ))
+        .await;
+
+        let resp = cli
+            .post("/test")
+            .content_type("application/octet-stream")
+            .body("data")
+            .send()
+            .await;
+        resp.assert_text("data ").await;
+
+        let resp = cli
+            .post("/uploads")
+            .content_type("text/plain")
+            .body("data")
+            .send()
+            .await;
+        resp.assert_text("data ").await;
+    }
+}
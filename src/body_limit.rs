@@ -0,0 +1,208 @@
+//! Contains a middleware that limits the size of request bodies.
+//!
+//! Unlike poem's own limit, requests with bodies that are too large are
+//! rejected with a [`PayloadTooLarge`] response in the JSON error format of
+//! this crate which includes the configured limit:
+//!
+//! ```json
+//! {"error": "payload_too_large", "details": {"max": 1048576}}
+//! ```
+//!
+//! Bodies are read into memory before the endpoint is called, so this
+//! middleware should not be used for endpoints that stream large uploads.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     body_limit::{BodyLimitMiddleware, BodyLimited},
+//!     responses::Response,
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "post")]
+//!     async fn test(
+//!         &self,
+//!         data: PlainText<String>,
+//!     ) -> Response<PlainText<String>, BodyLimited> {
+//!         Ok(data.into())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     // allow 1 MiB by default, but 16 MiB for imports
+//!     BodyLimitMiddleware::new(1 << 20).with_path_limit("/imports", 16 << 20),
+//! );
+//! ```
+
+use poem::{
+    error::ReadBodyError, http::header::CONTENT_LENGTH, Endpoint, IntoResponse, Middleware,
+    Request, Response,
+};
+use poem_openapi::Object;
+
+use crate::{add_response_schemas, responses::PayloadTooLarge, utils::path_has_prefix};
+
+/// Details of a [`PayloadTooLarge`] response.
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct BodyLimitDetails {
+    /// The maximum size of the request body in bytes.
+    pub max: u64,
+}
+
+/// Marker type that adds the [`PayloadTooLarge`] response to the
+/// documentation of endpoints that return a
+/// [`Response<T, BodyLimited>`](crate::responses::Response).
+#[derive(Debug)]
+pub struct BodyLimited;
+add_response_schemas!(BodyLimited, PayloadTooLarge::raw::Response);
+
+/// A middleware that limits the size of request bodies.
+#[derive(Debug, Clone)]
+pub struct BodyLimitMiddleware {
+    max: Option<usize>,
+    path_limits: Vec<(String, Option<usize>)>,
+}
+
+impl BodyLimitMiddleware {
+    /// Create a new BodyLimitMiddleware that allows request bodies of at most
+    /// `max` bytes.
+    pub fn new(max: usize) -> Self {
+        Self {
+            max: Some(max),
+            path_limits: Vec::new(),
+        }
+    }
+
+    /// Use a different limit for requests whose path starts with the given
+    /// prefix.
+    ///
+    /// If multiple prefixes match a request, the longest one is used.
+    pub fn with_path_limit(mut self, prefix: impl Into<String>, max: usize) -> Self {
+        self.path_limits.push((prefix.into(), Some(max)));
+        self
+    }
+
+    /// Disable the limit for requests whose path starts with the given prefix.
+    pub fn without_limit_for(mut self, prefix: impl Into<String>) -> Self {
+        self.path_limits.push((prefix.into(), None));
+        self
+    }
+
+    fn limit_for(&self, req: &Request) -> Option<usize> {
+        self.path_limits
+            .iter()
+            .filter(|(prefix, _)| path_has_prefix(req.uri().path(), prefix))
+            .max_by_key(|(prefix, _)| prefix.trim_end_matches('/').len())
+            .map_or(self.max, |(_, max)| *max)
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for BodyLimitMiddleware {
+    type Output = BodyLimitMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        BodyLimitMwEndpoint {
+            inner: ep,
+            config: self.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct BodyLimitMwEndpoint<E> {
+    inner: E,
+    config: BodyLimitMiddleware,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for BodyLimitMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let Some(max) = self.config.limit_for(&req) else {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        };
+
+        let too_large = || {
+            PayloadTooLarge::raw::payload_too_large(BodyLimitDetails { max: max as u64 })
+                .into_response()
+        };
+        let content_length = req
+            .header(CONTENT_LENGTH)
+            .and_then(|x| x.parse::<usize>().ok());
+        if content_length.is_some_and(|len| len > max) {
+            return Ok(too_large());
+        }
+        let body = match req.take_body().into_bytes_limit(max).await {
+            Ok(body) => body,
+            Err(ReadBodyError::PayloadTooLarge) => return Ok(too_large()),
+            Err(err) => return Err(err.into()),
+        };
+
+        req.set_body(body);
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::StatusCode, test::TestClient, Body, EndpointExt, Route};
+
+    use super::*;
+
+    #[handler]
+    fn echo(body: String) -> String {
+        body
+    }
+
+    fn app() -> impl Endpoint {
+        Route::new()
+            .at("/small", echo)
+            .at("/large", echo)
+            .at("/unlimited", echo)
+            .with(
+                BodyLimitMiddleware::new(4)
+                    .with_path_limit("/large", 8)
+                    .without_limit_for("/unlimited"),
+            )
+    }
+
+    #[tokio::test]
+    async fn body_limit() {
+        let cli = TestClient::new(app());
+
+        let resp = cli.post("/small").body("1234").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("1234").await;
+
+        let resp = cli.post("/small").body("12345").send().await;
+        resp.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+        resp.assert_text(r#"{"details":{"max":4},"error":"payload_too_large"}"#)
+            .await;
+
+        // without content-length header
+        let resp = cli
+            .post("/small")
+            .body(Body::from_async_read(&b"12345"[..]))
+            .send()
+            .await;
+        resp.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+
+        let resp = cli.post("/large").body("12345678").send().await;
+        resp.assert_status_is_ok();
+        let resp = cli.post("/large").body("123456789").send().await;
+        resp.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+        resp.assert_text(r#"{"details":{"max":8},"error":"payload_too_large"}"#)
+            .await;
+
+        let resp = cli.post("/unlimited").body("123456789").send().await;
+        resp.assert_status_is_ok();
+    }
+}
@@ -0,0 +1,131 @@
+//! Contains a schema for the build metadata of a service and an API that
+//! exposes it.
+//!
+//! The [`build_info!`](crate::build_info!) macro creates a [`BuildInfo`] from
+//! environment variables that are read when the calling crate is compiled:
+//!
+//! - `version`: `CARGO_PKG_VERSION` (always set by cargo)
+//! - `git_sha`: `GIT_SHA`
+//! - `build_time`: `BUILD_TIME`
+//! - `rustc`: `RUSTC_VERSION`
+//!
+//! The optional variables can be set by the build environment (e.g. a CI
+//! pipeline) or by a build script using `cargo:rustc-env=GIT_SHA=...`.
+//! [`VersionApi`] exposes the build info at `GET /version`, so every service
+//! documents its build metadata the same way.
+//!
+//! #### Example
+//! ```
+//! use poem::Route;
+//! use poem_ext::{build_info, build_info::VersionApi};
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(
+//!     (Api, VersionApi::new(build_info!())),
+//!     "Test",
+//!     env!("CARGO_PKG_VERSION"),
+//! );
+//! let app = Route::new().nest("/", api_service);
+//! ```
+
+use poem_openapi::{payload::Json, Object, OpenApi};
+
+/// Metadata about the build of a service.
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct BuildInfo {
+    /// The version of the service.
+    pub version: String,
+    /// The hash of the git commit the service has been built from.
+    pub git_sha: Option<String>,
+    /// The time at which the service has been built.
+    pub build_time: Option<String>,
+    /// The version of the rust compiler used to build the service.
+    pub rustc: Option<String>,
+}
+
+impl BuildInfo {
+    /// Create a new BuildInfo that only contains the version of the service.
+    pub fn new(version: impl Into<String>) -> Self {
+        Self {
+            version: version.into(),
+            git_sha: None,
+            build_time: None,
+            rustc: None,
+        }
+    }
+}
+
+/// Create a [`BuildInfo`](crate::build_info::BuildInfo) from environment
+/// variables of the calling crate at compile time.
+///
+/// See the [module documentation](mod@crate::build_info) for the variables that
+/// are used.
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::build_info::BuildInfo {
+            version: ::std::env!("CARGO_PKG_VERSION").into(),
+            git_sha: ::std::option_env!("GIT_SHA").map(::std::convert::Into::into),
+            build_time: ::std::option_env!("BUILD_TIME").map(::std::convert::Into::into),
+            rustc: ::std::option_env!("RUSTC_VERSION").map(::std::convert::Into::into),
+        }
+    };
+}
+
+/// An API that exposes the [`BuildInfo`] of a service at `GET /version`.
+#[derive(Debug, Clone)]
+pub struct VersionApi(BuildInfo);
+
+impl VersionApi {
+    /// Create a new VersionApi that exposes the given build info (usually
+    /// created using the [`build_info!`](crate::build_info!) macro).
+    pub fn new(build: BuildInfo) -> Self {
+        Self(build)
+    }
+}
+
+#[OpenApi]
+impl VersionApi {
+    /// Return the version and build metadata of the service.
+    #[oai(path = "/version", method = "get", operation_id = "version")]
+    async fn version(&self) -> Json<BuildInfo> {
+        Json(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::test::TestClient;
+    use poem_openapi::OpenApiService;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn version() {
+        let build = build_info!();
+        assert_eq!(build.version, env!("CARGO_PKG_VERSION"));
+
+        let cli = TestClient::new(OpenApiService::new(
+            VersionApi::new(BuildInfo {
+                git_sha: Some("abc".into()),
+                ..BuildInfo::new("1.2.3")
+            }),
+            "test",
+            "1",
+        ));
+        let resp = cli.get("/version").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text(r#"{"build_time":null,"git_sha":"abc","rustc":null,"version":"1.2.3"}"#)
+            .await;
+    }
+}
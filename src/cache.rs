@@ -0,0 +1,671 @@
+//! Contains a middleware that caches responses of `GET` endpoints.
+//!
+//! Responses are cached in a [`CacheStore`] (e.g. the in-memory
+//! [`MemoryCache`]) using a key that consists of the path, the query string
+//! and the values of the configured vary headers. Whether and how long a
+//! response is cached is controlled by the `Cache-Control` header set by the
+//! handler (`max-age`/`s-maxage`, `no-store`, `no-cache` and `private` are
+//! supported). Responses without a `Cache-Control` header are only cached if a
+//! [default ttl](ResponseCacheMiddleware::with_default_ttl) is configured.
+//!
+//! Responses to requests with an `Authorization` or `Cookie` header are only
+//! cached if that header has been added as a
+//! [vary header](ResponseCacheMiddleware::with_vary) or if the response
+//! explicitly allows shared caching using `public` or `s-maxage` (see
+//! [RFC 9111, section 3.5](https://www.rfc-editor.org/rfc/rfc9111#section-3.5)).
+//! Responses whose `Vary` header contains headers that are not part of the
+//! cache key are not cached. Other headers that affect the response must be
+//! added as vary headers, otherwise clients may receive responses meant for
+//! other clients.
+//!
+//! Mutating endpoints can invalidate cached responses using the
+//! [`ResponseCache`] that is attached to each request.
+//!
+//! #### Example
+//! ```
+//! use std::time::Duration;
+//!
+//! use poem::{http::header::CACHE_CONTROL, web::Data, EndpointExt, Route};
+//! use poem_ext::cache::{MemoryCache, ResponseCache, ResponseCacheMiddleware};
+//! use poem_openapi::{
+//!     param::Path,
+//!     payload::{self, PlainText},
+//!     OpenApi, OpenApiService,
+//! };
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/users/:id", method = "get")]
+//!     async fn get_user(&self, id: Path<u64>) -> payload::Response<PlainText<String>> {
+//!         payload::Response::new(PlainText(format!("user {}", id.0)))
+//!             .header(CACHE_CONTROL, "max-age=60")
+//!     }
+//!
+//!     #[oai(path = "/users/:id", method = "put")]
+//!     async fn update_user(
+//!         &self,
+//!         id: Path<u64>,
+//!         cache: Data<&ResponseCache>,
+//!     ) -> PlainText<&'static str> {
+//!         // update the user
+//!         cache.invalidate(&format!("/users/{}", id.0)).await;
+//!         PlainText("updated")
+//!     }
+//! }
+//!
+//! let cache = ResponseCache::new(MemoryCache::new(1000));
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     ResponseCacheMiddleware::new(cache)
+//!         .with_vary(poem::http::header::ACCEPT_LANGUAGE)
+//!         .with_default_ttl(Duration::from_secs(5)),
+//! );
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use poem::{
+    http::{
+        header::{AUTHORIZATION, CACHE_CONTROL, COOKIE, SET_COOKIE, VARY},
+        HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
+    },
+    Body, Endpoint, IntoResponse, Middleware, Request, Response,
+};
+
+use crate::utils::path_has_prefix;
+
+/// Name of the header that is added to responses of cacheable requests and
+/// indicates whether the response has been served from the cache (`HIT`) or
+/// not (`MISS`).
+pub const CACHE_STATUS_HEADER: &str = "x-cache";
+
+/// The key of a cached response.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// The path of the request.
+    pub path: String,
+    /// The query string of the request.
+    pub query: String,
+    /// The names and values of the configured vary headers.
+    pub vary: Vec<(String, String)>,
+}
+
+impl Display for CacheKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.path)?;
+        if !self.query.is_empty() {
+            write!(f, "?{}", self.query)?;
+        }
+        for (name, value) in &self.vary {
+            write!(f, "\n{name}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+impl CacheKey {
+    /// Create the key of a request that includes the values of the given vary
+    /// headers.
+    pub(crate) fn from_request(req: &Request, vary: &[HeaderName]) -> Self {
+        Self {
+            path: req.uri().path().into(),
+            query: req.uri().query().unwrap_or_default().into(),
+            vary: vary
+                .iter()
+                .map(|name| {
+                    let value = req
+                        .headers()
+                        .get_all(name)
+                        .iter()
+                        .filter_map(|value| value.to_str().ok())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    (name.as_str().into(), value)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A cached response.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    /// The status code of the response.
+    pub status: StatusCode,
+    /// The headers of the response.
+    pub headers: HeaderMap,
+    /// The body of the response.
+    pub body: Vec<u8>,
+}
+
+impl IntoResponse for CachedResponse {
+    fn into_response(self) -> Response {
+        let mut resp = Response::builder()
+            .status(self.status)
+            .body(Body::from_vec(self.body));
+        resp.headers_mut().extend(self.headers);
+        resp
+    }
+}
+
+/// A storage backend for cached responses (e.g. [`MemoryCache`] or a Redis
+/// based implementation).
+#[poem::async_trait]
+pub trait CacheStore: Send + Sync + 'static {
+    /// Return the cached response for the given key if it has not expired yet.
+    async fn get(&self, key: &CacheKey) -> Option<CachedResponse>;
+
+    /// Cache a response for the given duration.
+    async fn set(&self, key: CacheKey, response: CachedResponse, ttl: Duration);
+
+    /// Remove all cached responses whose path is equal to `path` or a sub path
+    /// of it.
+    async fn invalidate(&self, path: &str);
+}
+
+/// An in-memory [`CacheStore`] that evicts the least recently used response if
+/// its capacity is exceeded.
+#[derive(Debug)]
+pub struct MemoryCache {
+    capacity: usize,
+    inner: Mutex<MemoryCacheInner>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryCacheInner {
+    entries: HashMap<CacheKey, MemoryCacheEntry>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct MemoryCacheEntry {
+    response: CachedResponse,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+impl MemoryCache {
+    /// Create a new MemoryCache that holds at most `capacity` responses.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Default::default(),
+        }
+    }
+}
+
+#[poem::async_trait]
+impl CacheStore for MemoryCache {
+    async fn get(&self, key: &CacheKey) -> Option<CachedResponse> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.entries.get_mut(key)?;
+        if entry.expires_at <= Instant::now() {
+            inner.entries.remove(key);
+            return None;
+        }
+        entry.last_used = tick;
+        Some(entry.response.clone())
+    }
+
+    async fn set(&self, key: CacheKey, response: CachedResponse, ttl: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let now = Instant::now();
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(&key) {
+            inner.entries.retain(|_, entry| entry.expires_at > now);
+        }
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(&key) {
+            if let Some(lru) = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            {
+                inner.entries.remove(&lru);
+            }
+        }
+        let last_used = inner.tick;
+        inner.entries.insert(
+            key,
+            MemoryCacheEntry {
+                response,
+                expires_at: now + ttl,
+                last_used,
+            },
+        );
+    }
+
+    async fn invalidate(&self, path: &str) {
+        self.inner
+            .lock()
+            .unwrap()
+            .entries
+            .retain(|key, _| !path_has_prefix(&key.path, path));
+    }
+}
+
+/// Handle to a [`CacheStore`] that is shared between the
+/// [`ResponseCacheMiddleware`] and the endpoints.
+///
+/// The middleware attaches the cache to each request, so endpoints can access
+/// it using [`Data<&ResponseCache>`](poem::web::Data).
+#[derive(Clone)]
+pub struct ResponseCache(Arc<dyn CacheStore>);
+
+impl Debug for ResponseCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseCache").finish_non_exhaustive()
+    }
+}
+
+impl ResponseCache {
+    /// Create a new ResponseCache using the given store.
+    pub fn new(store: impl CacheStore) -> Self {
+        Self(Arc::new(store))
+    }
+
+    /// Remove all cached responses whose path is equal to `path` or a sub path
+    /// of it.
+    pub async fn invalidate(&self, path: &str) {
+        self.0.invalidate(path).await;
+    }
+}
+
+/// A middleware that caches responses of `GET` endpoints.
+#[derive(Debug, Clone)]
+pub struct ResponseCacheMiddleware {
+    cache: ResponseCache,
+    vary: Vec<HeaderName>,
+    default_ttl: Option<Duration>,
+}
+
+impl ResponseCacheMiddleware {
+    /// Create a new ResponseCacheMiddleware.
+    pub fn new(cache: ResponseCache) -> Self {
+        Self {
+            cache,
+            vary: Vec::new(),
+            default_ttl: None,
+        }
+    }
+
+    /// Include the value of the given request header in the cache key (e.g.
+    /// `Accept-Language` or `Authorization`).
+    pub fn with_vary(mut self, header: HeaderName) -> Self {
+        self.vary.push(header);
+        self
+    }
+
+    /// Cache successful responses without a `Cache-Control` header for the
+    /// given duration.
+    pub fn with_default_ttl(self, ttl: Duration) -> Self {
+        Self {
+            default_ttl: Some(ttl),
+            ..self
+        }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for ResponseCacheMiddleware {
+    type Output = ResponseCacheMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ResponseCacheMwEndpoint {
+            inner: ep,
+            config: self.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ResponseCacheMwEndpoint<E> {
+    inner: E,
+    config: ResponseCacheMiddleware,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for ResponseCacheMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let cache = self.config.cache.clone();
+        req.extensions_mut().insert(cache.clone());
+        if req.method() != Method::GET {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        }
+
+        let key = CacheKey::from_request(&req, &self.config.vary);
+        let credentials = [AUTHORIZATION, COOKIE]
+            .iter()
+            .any(|name| req.headers().contains_key(name) && !self.config.vary.contains(name));
+
+        let bypass = cache_control(req.headers()).any(|d| d == "no-cache" || d == "no-store");
+        if !bypass {
+            if let Some(cached) = cache.0.get(&key).await {
+                let mut resp = cached.into_response();
+                resp.headers_mut()
+                    .insert(CACHE_STATUS_HEADER, HeaderValue::from_static("HIT"));
+                return Ok(resp);
+            }
+        }
+
+        let mut resp = self.inner.call(req).await?.into_response();
+        if let Some(ttl) = self.ttl(&resp, credentials) {
+            let (parts, body) = resp.into_parts();
+            let body = body.into_vec().await?;
+            cache
+                .0
+                .set(
+                    key,
+                    CachedResponse {
+                        status: parts.status,
+                        headers: parts.headers.clone(),
+                        body: body.clone(),
+                    },
+                    ttl,
+                )
+                .await;
+            resp = Response::from_parts(parts, Body::from_vec(body));
+        }
+        resp.headers_mut()
+            .insert(CACHE_STATUS_HEADER, HeaderValue::from_static("MISS"));
+        Ok(resp)
+    }
+}
+
+impl<E> ResponseCacheMwEndpoint<E> {
+    /// Return how long the response may be cached. `credentials` indicates
+    /// whether the request contains credentials that are not part of the cache
+    /// key.
+    fn ttl(&self, resp: &Response, credentials: bool) -> Option<Duration> {
+        if resp.status() != StatusCode::OK || resp.headers().contains_key(SET_COOKIE) {
+            return None;
+        }
+        if !self.varies_by_key(resp.headers()) {
+            return None;
+        }
+        if !resp.headers().contains_key(CACHE_CONTROL) {
+            return self.config.default_ttl.filter(|_| !credentials);
+        }
+        let mut max_age = None;
+        let mut s_maxage = None;
+        let mut public = false;
+        for directive in cache_control(resp.headers()) {
+            match directive.split_once('=') {
+                Some(("max-age", value)) => max_age = value.trim_matches('"').parse().ok(),
+                Some(("s-maxage", value)) => s_maxage = value.trim_matches('"').parse().ok(),
+                None if directive == "public" => public = true,
+                None if matches!(directive.as_str(), "no-store" | "no-cache" | "private") => {
+                    return None
+                }
+                _ => {}
+            }
+        }
+        if credentials && !public && s_maxage.is_none() {
+            return None;
+        }
+        s_maxage
+            .or(max_age)
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Return whether all headers listed in the `Vary` header of the response
+    /// are part of the cache key.
+    fn varies_by_key(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get_all(VARY)
+            .iter()
+            .map(|value| value.to_str().ok())
+            .all(|value| {
+                value.is_some_and(|value| {
+                    value.split(',').map(str::trim).all(|name| {
+                        name.is_empty()
+                            || self
+                                .config
+                                .vary
+                                .iter()
+                                .any(|vary| vary.as_str().eq_ignore_ascii_case(name))
+                    })
+                })
+            })
+    }
+}
+
+/// Return the lowercase directives of all `Cache-Control` headers.
+fn cache_control(headers: &HeaderMap) -> impl Iterator<Item = String> + '_ {
+    headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|directive| directive.trim().to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use poem::{
+        handler,
+        http::header::ACCEPT_LANGUAGE,
+        test::TestClient,
+        web::{Data, Query},
+        EndpointExt, Route,
+    };
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    #[handler]
+    fn counter(
+        counter: Data<&Arc<Counter>>,
+        Query(cc): Query<HashMap<String, String>>,
+    ) -> Response {
+        let n = counter.0 .0.fetch_add(1, Ordering::Relaxed);
+        let mut resp = Response::builder().body(n.to_string());
+        if let Some(cc) = cc.get("cc") {
+            resp.headers_mut()
+                .insert(CACHE_CONTROL, HeaderValue::from_str(cc).unwrap());
+        }
+        if let Some(vary) = cc.get("vary") {
+            resp.headers_mut()
+                .insert(VARY, HeaderValue::from_str(vary).unwrap());
+        }
+        resp
+    }
+
+    #[handler]
+    async fn invalidate_foo(cache: Data<&ResponseCache>) -> &'static str {
+        cache.invalidate("/foo").await;
+        "ok"
+    }
+
+    fn app() -> impl Endpoint {
+        Route::new()
+            .at("/foo", poem::get(counter).put(invalidate_foo))
+            .at("/foo/bar", counter)
+            .at("/baz", counter)
+            .with(
+                ResponseCacheMiddleware::new(ResponseCache::new(MemoryCache::new(10)))
+                    .with_vary(ACCEPT_LANGUAGE),
+            )
+            .data(Arc::new(Counter::default()))
+    }
+
+    async fn get_text<E: Endpoint>(cli: &TestClient<E>, uri: &str) -> (String, String) {
+        let resp = cli.get(uri).send().await;
+        resp.assert_status_is_ok();
+        let status = resp.0.headers()[CACHE_STATUS_HEADER]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        (resp.0.into_body().into_string().await.unwrap(), status)
+    }
+
+    #[tokio::test]
+    async fn cache_control() {
+        let cli = TestClient::new(app());
+        let fetch = |uri| get_text(&cli, uri);
+
+        // no cache-control and no default ttl
+        assert_eq!(fetch("/foo").await, ("0".into(), "MISS".into()));
+        assert_eq!(fetch("/foo").await, ("1".into(), "MISS".into()));
+
+        assert_eq!(
+            fetch("/foo?cc=max-age=60").await,
+            ("2".into(), "MISS".into())
+        );
+        assert_eq!(
+            fetch("/foo?cc=max-age=60").await,
+            ("2".into(), "HIT".into())
+        );
+
+        assert_eq!(fetch("/foo?cc=no-store").await, ("3".into(), "MISS".into()));
+        assert_eq!(fetch("/foo?cc=no-store").await, ("4".into(), "MISS".into()));
+
+        assert_eq!(
+            fetch("/foo?cc=private,max-age=60").await,
+            ("5".into(), "MISS".into())
+        );
+        assert_eq!(
+            fetch("/foo?cc=private,max-age=60").await,
+            ("6".into(), "MISS".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn vary() {
+        let cli = &TestClient::new(app());
+        let fetch = |lang| async move {
+            let resp = cli
+                .get("/baz?cc=max-age=60")
+                .header(ACCEPT_LANGUAGE, lang)
+                .send()
+                .await;
+            resp.0.into_body().into_string().await.unwrap()
+        };
+        assert_eq!(fetch("en").await, "0");
+        assert_eq!(fetch("de").await, "1");
+        assert_eq!(fetch("en").await, "0");
+    }
+
+    #[tokio::test]
+    async fn credentials() {
+        let cli = &TestClient::new(app());
+        let fetch = |uri, name: HeaderName| async move {
+            let resp = cli.get(uri).header(name, "secret").send().await;
+            resp.0.into_body().into_string().await.unwrap()
+        };
+
+        // private responses are not shared between clients
+        assert_eq!(fetch("/foo?cc=max-age=60", AUTHORIZATION).await, "0");
+        assert_eq!(fetch("/foo?cc=max-age=60", AUTHORIZATION).await, "1");
+        assert_eq!(fetch("/foo/bar?cc=max-age=60", COOKIE).await, "2");
+        assert_eq!(fetch("/foo/bar?cc=max-age=60", COOKIE).await, "3");
+
+        // explicitly shared responses
+        assert_eq!(fetch("/foo?cc=public,max-age=60", AUTHORIZATION).await, "4");
+        assert_eq!(fetch("/foo?cc=public,max-age=60", AUTHORIZATION).await, "4");
+        assert_eq!(fetch("/foo?cc=s-maxage=60", COOKIE).await, "5");
+        assert_eq!(fetch("/foo?cc=s-maxage=60", COOKIE).await, "5");
+
+        // credentials that are part of the cache key
+        let cli = &TestClient::new(
+            Route::new()
+                .at("/foo", counter)
+                .with(
+                    ResponseCacheMiddleware::new(ResponseCache::new(MemoryCache::new(10)))
+                        .with_vary(AUTHORIZATION),
+                )
+                .data(Arc::new(Counter::default())),
+        );
+        let fetch = |token| async move {
+            let resp = cli
+                .get("/foo?cc=max-age=60")
+                .header(AUTHORIZATION, token)
+                .send()
+                .await;
+            resp.0.into_body().into_string().await.unwrap()
+        };
+        assert_eq!(fetch("a").await, "0");
+        assert_eq!(fetch("b").await, "1");
+        assert_eq!(fetch("a").await, "0");
+    }
+
+    #[tokio::test]
+    async fn response_vary() {
+        let cli = TestClient::new(app());
+        let fetch = |uri| get_text(&cli, uri);
+
+        let uri = "/foo?cc=max-age=60&vary=Accept-Language";
+        assert_eq!(fetch(uri).await, ("0".into(), "MISS".into()));
+        assert_eq!(fetch(uri).await, ("0".into(), "HIT".into()));
+
+        let uri = "/foo?cc=max-age=60&vary=Accept-Language,Accept-Encoding";
+        assert_eq!(fetch(uri).await, ("1".into(), "MISS".into()));
+        assert_eq!(fetch(uri).await, ("2".into(), "MISS".into()));
+
+        let uri = "/foo?cc=max-age=60&vary=*";
+        assert_eq!(fetch(uri).await, ("3".into(), "MISS".into()));
+        assert_eq!(fetch(uri).await, ("4".into(), "MISS".into()));
+    }
+
+    #[tokio::test]
+    async fn invalidate() {
+        let cli = TestClient::new(app());
+        let fetch = |uri| get_text(&cli, uri);
+
+        assert_eq!(fetch("/foo?cc=max-age=60").await.0, "0");
+        assert_eq!(fetch("/foo/bar?cc=max-age=60").await.0, "1");
+        assert_eq!(fetch("/baz?cc=max-age=60").await.0, "2");
+
+        cli.put("/foo").send().await.assert_status_is_ok();
+
+        assert_eq!(fetch("/foo?cc=max-age=60").await.0, "3");
+        assert_eq!(fetch("/foo/bar?cc=max-age=60").await.0, "4");
+        assert_eq!(fetch("/baz?cc=max-age=60").await.0, "2");
+    }
+
+    #[tokio::test]
+    async fn lru() {
+        let cache = MemoryCache::new(2);
+        let key = |path: &str| CacheKey {
+            path: path.into(),
+            query: String::new(),
+            vary: Vec::new(),
+        };
+        let resp = || CachedResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        };
+        let ttl = Duration::from_secs(60);
+
+        cache.set(key("/a"), resp(), ttl).await;
+        cache.set(key("/b"), resp(), ttl).await;
+        assert!(cache.get(&key("/a")).await.is_some());
+        cache.set(key("/c"), resp(), ttl).await;
+        assert!(cache.get(&key("/a")).await.is_some());
+        assert!(cache.get(&key("/b")).await.is_none());
+        assert!(cache.get(&key("/c")).await.is_some());
+
+        cache.set(key("/d"), resp(), Duration::ZERO).await;
+        assert!(cache.get(&key("/d")).await.is_none());
+    }
+}
@@ -0,0 +1,418 @@
+//! Contains an extractor for the ip address of the client that has sent a
+//! request.
+//!
+//! If the application runs behind reverse proxies, the address of the peer is
+//! the one of the last proxy instead of the client. The [`ClientIpMiddleware`]
+//! resolves the real address of the client from the `Forwarded`,
+//! `X-Forwarded-For` or `X-Real-IP` headers, but only trusts them if the
+//! request has been received from one of the configured trusted proxies.
+//! Otherwise clients could spoof their address by setting these headers
+//! themselves.
+//!
+//! The resolved address is used by other components like the
+//! [`RateLimitMiddleware`](crate::rate_limit::RateLimitMiddleware) and the
+//! [`AuditLogMiddleware`](crate::audit_log::AuditLogMiddleware). Without the
+//! middleware, [`ClientIp`] falls back to the address of the peer.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::client_ip::{ClientIp, ClientIpMiddleware};
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/ip", method = "get")]
+//!     async fn ip(&self, ip: ClientIp) -> PlainText<String> {
+//!         PlainText(ip.to_string())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     ClientIpMiddleware::new()
+//!         .with_trusted_proxy("10.0.0.0/8")
+//!         .with_trusted_proxy("fd00::/8"),
+//! );
+//! ```
+
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv6Addr},
+    ops::Deref,
+};
+
+use poem::{
+    error::ResponseError,
+    http::{HeaderMap, StatusCode},
+    Endpoint, FromRequest, IntoResponse, Middleware, Request, RequestBody, Response,
+};
+
+/// The ip address of the client that has sent a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    /// Return the ip address of the client that has sent a request.
+    ///
+    /// If no [`ClientIpMiddleware`] is present, the address of the peer is
+    /// used instead.
+    pub fn of(req: &Request) -> Option<Self> {
+        req.extensions()
+            .get::<Self>()
+            .copied()
+            .or_else(|| peer_ip(req).map(Self))
+    }
+}
+
+impl Deref for ClientIp {
+    type Target = IpAddr;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for ClientIp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for ClientIp {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        Self::of(req).ok_or_else(|| MissingClientIp.into())
+    }
+}
+
+/// Error returned by the [`ClientIp`] extractor if the request has not been
+/// received over an ip connection (e.g. over a unix socket). Results in
+/// `500 Internal Server Error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingClientIp;
+
+impl Display for MissingClientIp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the ip address of the client is unknown")
+    }
+}
+
+impl std::error::Error for MissingClientIp {}
+
+impl ResponseError for MissingClientIp {
+    fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// A network in CIDR notation (e.g. `10.0.0.0/8`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IpNetwork {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    fn parse(network: &str) -> Option<Self> {
+        let (addr, prefix_len) = match network.split_once('/') {
+            Some((addr, prefix_len)) => (addr.parse().ok()?, Some(prefix_len.parse().ok()?)),
+            None => (network.parse().ok()?, None),
+        };
+        let max_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix_len = prefix_len.unwrap_or(max_len);
+        (prefix_len <= max_len).then_some(Self { addr, prefix_len })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, canonical(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => prefix_eq(
+                u32::from(net).into(),
+                u32::from(ip).into(),
+                32,
+                self.prefix_len,
+            ),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(u128::from(net), u128::from(ip), 128, self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Compare the first `prefix_len` of `bits` bits of two addresses.
+fn prefix_eq(a: u128, b: u128, bits: u8, prefix_len: u8) -> bool {
+    let shift = bits - prefix_len;
+    shift >= bits || (a >> shift) == (b >> shift)
+}
+
+/// Convert ipv4-mapped ipv6 addresses to ipv4.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4),
+        ip => ip,
+    }
+}
+
+fn peer_ip(req: &Request) -> Option<IpAddr> {
+    req.remote_addr()
+        .as_socket_addr()
+        .map(|addr| canonical(addr.ip()))
+}
+
+/// A middleware that resolves the ip address of the client from the headers
+/// set by trusted reverse proxies.
+#[derive(Debug, Clone, Default)]
+pub struct ClientIpMiddleware {
+    trusted: Vec<IpNetwork>,
+}
+
+impl ClientIpMiddleware {
+    /// Create a new ClientIpMiddleware without any trusted proxies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust the proxies in the given network (e.g. `10.0.0.0/8`) or with the
+    /// given address (e.g. `10.1.2.3`).
+    ///
+    /// # Panics
+    /// Panics if the network is invalid.
+    pub fn with_trusted_proxy(mut self, network: &str) -> Self {
+        let network = IpNetwork::parse(network)
+            .unwrap_or_else(|| panic!("invalid trusted proxy network: {network}"));
+        self.trusted.push(network);
+        self
+    }
+
+    /// Trust all proxies in the loopback and private networks.
+    pub fn with_private_networks(self) -> Self {
+        [
+            "127.0.0.0/8",
+            "10.0.0.0/8",
+            "172.16.0.0/12",
+            "192.168.0.0/16",
+            "::1/128",
+            "fc00::/7",
+        ]
+        .into_iter()
+        .fold(self, Self::with_trusted_proxy)
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.iter().any(|network| network.contains(ip))
+    }
+
+    /// Resolve the address of the client from the address of the peer and the
+    /// headers of the request.
+    ///
+    /// The forwarded addresses are walked from right to left, skipping
+    /// trusted proxies, so that addresses prepended by the client itself are
+    /// ignored.
+    fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+
+        let mut chain = forwarded_chain(headers).into_iter().rev();
+        let mut client = peer;
+        loop {
+            match chain.next() {
+                Some(Some(ip)) if self.is_trusted(ip) => client = ip,
+                Some(Some(ip)) => return ip,
+                // an obfuscated or invalid address, so the last trusted
+                // proxy is the best guess
+                Some(None) => return client,
+                None => break,
+            }
+        }
+
+        if client == peer {
+            if let Some(ip) = headers
+                .get("x-real-ip")
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_node)
+            {
+                return ip;
+            }
+        }
+        client
+    }
+}
+
+/// Return the forwarded addresses of a request (from the client to the last
+/// proxy) from the `Forwarded` or, if not present, the `X-Forwarded-For`
+/// headers.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+    };
+
+    let forwarded = values("forwarded")
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.eq_ignore_ascii_case("for")
+                    .then(|| parse_node(value.trim().trim_matches('"')))
+            })
+        })
+        .collect::<Vec<_>>();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    values("x-forwarded-for").map(parse_node).collect()
+}
+
+/// Parse an address with an optional port (e.g. `192.0.2.1:1234` or
+/// `[2001:db8::1]:1234`).
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(canonical(ip));
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        let (ip, _) = rest.split_once(']')?;
+        return ip.parse::<Ipv6Addr>().ok().map(|ip| canonical(ip.into()));
+    }
+    let (ip, _) = node.rsplit_once(':')?;
+    ip.parse::<IpAddr>().ok().map(canonical)
+}
+
+impl<E: Endpoint> Middleware<E> for ClientIpMiddleware {
+    type Output = ClientIpMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ClientIpMwEndpoint {
+            inner: ep,
+            config: self.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ClientIpMwEndpoint<E> {
+    inner: E,
+    config: ClientIpMiddleware,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for ClientIpMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        if let Some(peer) = peer_ip(&req) {
+            let ip = self.config.resolve(peer, req.headers());
+            req.extensions_mut().insert(ClientIp(ip));
+        }
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::HeaderValue, test::TestClient};
+
+    use super::*;
+
+    fn resolve(peer: &str, headers: &[(&'static str, &'static str)]) -> String {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)))
+            .collect();
+        ClientIpMiddleware::new()
+            .with_trusted_proxy("10.0.0.0/8")
+            .with_trusted_proxy("fd00::1")
+            .resolve(peer.parse().unwrap(), &headers)
+            .to_string()
+    }
+
+    #[test]
+    fn network() {
+        let network = IpNetwork::parse("10.0.0.0/8").unwrap();
+        assert!(network.contains("10.1.2.3".parse().unwrap()));
+        assert!(network.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!network.contains("11.0.0.1".parse().unwrap()));
+        assert!(!network.contains("::1".parse().unwrap()));
+        assert!(IpNetwork::parse("0.0.0.0/0")
+            .unwrap()
+            .contains("1.2.3.4".parse().unwrap()));
+        assert!(IpNetwork::parse("10.0.0.0/33").is_none());
+        assert!(IpNetwork::parse("foo").is_none());
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        assert_eq!(
+            resolve("1.2.3.4", &[("x-forwarded-for", "5.6.7.8")]),
+            "1.2.3.4"
+        );
+    }
+
+    #[test]
+    fn x_forwarded_for() {
+        let xff = |value| resolve("10.0.0.1", &[("x-forwarded-for", value)]);
+        assert_eq!(xff("9.9.9.9, 1.2.3.4, 10.0.0.2"), "1.2.3.4");
+        assert_eq!(xff("10.0.0.3"), "10.0.0.3");
+        assert_eq!(xff("unknown, 10.0.0.2"), "10.0.0.2");
+    }
+
+    #[test]
+    fn forwarded() {
+        let headers = [
+            (
+                "forwarded",
+                r#"for="[2001:db8::1]:4711";proto=https, for=10.0.0.2"#,
+            ),
+            ("x-forwarded-for", "5.6.7.8"),
+        ];
+        assert_eq!(resolve("10.0.0.1", &headers), "2001:db8::1");
+    }
+
+    #[test]
+    fn x_real_ip() {
+        assert_eq!(resolve("10.0.0.1", &[("x-real-ip", "1.2.3.4")]), "1.2.3.4");
+        assert_eq!(resolve("10.0.0.1", &[]), "10.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn extractor() {
+        #[handler]
+        fn ip(ip: ClientIp) -> String {
+            ip.to_string()
+        }
+
+        let cli = TestClient::new(ip);
+        let resp = cli
+            .get("/")
+            .data(ClientIp("1.2.3.4".parse().unwrap()))
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text("1.2.3.4").await;
+
+        // the test client does not use an ip connection
+        cli.get("/")
+            .send()
+            .await
+            .assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_node("1.2.3.4:80"), Some("1.2.3.4".parse().unwrap()));
+        assert_eq!(parse_node("[::1]:80"), Some("::1".parse().unwrap()));
+        assert_eq!(parse_node("::1"), Some("::1".parse().unwrap()));
+        assert_eq!(parse_node("_hidden"), None);
+    }
+}
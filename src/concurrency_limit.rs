@@ -0,0 +1,243 @@
+//! Contains a middleware that limits the number of requests that are processed
+//! concurrently.
+//!
+//! Requests that exceed the limit are rejected with a [`ServiceUnavailable`]
+//! response and a `Retry-After` header instead of piling up, which e.g.
+//! protects the database pool used by the
+//! [`DbTransactionMiddleware`](crate::db::DbTransactionMiddleware) from
+//! exhaustion.
+//!
+//! #### Example
+//! ```
+//! use std::time::Duration;
+//!
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     add_response_schemas,
+//!     concurrency_limit::ConcurrencyLimitMiddleware,
+//!     responses::{Response, ServiceUnavailable},
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! /// Marker type used to document the 503 response.
+//! struct Overloaded;
+//! add_response_schemas!(Overloaded, ServiceUnavailable::raw::Response);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> Response<PlainText<&'static str>, Overloaded> {
+//!         Ok(PlainText("Hello World!").into())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     // process at most 100 requests at once, but only 10 exports
+//!     ConcurrencyLimitMiddleware::new(100)
+//!         .with_group("/exports", 10)
+//!         .with_max_wait(Duration::from_millis(100)),
+//! );
+//! ```
+
+use std::{sync::Arc, time::Duration};
+
+use poem::{http::header::RETRY_AFTER, Endpoint, IntoResponse, Middleware, Request, Response};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{responses::ServiceUnavailable, utils::path_has_prefix};
+
+/// A middleware that limits the number of requests that are processed
+/// concurrently.
+///
+/// Clones of this middleware share their state, so a single instance can be
+/// used to apply a common limit to multiple endpoints.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimitMiddleware {
+    global: Option<Arc<Semaphore>>,
+    groups: Vec<(String, Arc<Semaphore>)>,
+    max_wait: Option<Duration>,
+    retry_after: Duration,
+}
+
+impl ConcurrencyLimitMiddleware {
+    /// Create a new ConcurrencyLimitMiddleware that processes at most `max`
+    /// requests concurrently.
+    pub fn new(max: usize) -> Self {
+        Self {
+            global: Some(Arc::new(Semaphore::new(max))),
+            ..Self::unlimited()
+        }
+    }
+
+    /// Create a new ConcurrencyLimitMiddleware without a global limit, which
+    /// only limits the groups added using [`with_group`](Self::with_group).
+    pub fn unlimited() -> Self {
+        Self {
+            global: None,
+            groups: Vec::new(),
+            max_wait: None,
+            retry_after: Duration::from_secs(1),
+        }
+    }
+
+    /// Process at most `max` requests whose path starts with the given prefix
+    /// concurrently.
+    ///
+    /// These requests still count towards the global limit. If multiple
+    /// prefixes match a request, the longest one is used.
+    pub fn with_group(mut self, prefix: impl Into<String>, max: usize) -> Self {
+        self.groups
+            .push((prefix.into(), Arc::new(Semaphore::new(max))));
+        self
+    }
+
+    /// Wait up to the given duration for a request to be processed before
+    /// rejecting it. By default requests are rejected immediately.
+    pub fn with_max_wait(self, max_wait: Duration) -> Self {
+        Self {
+            max_wait: Some(max_wait),
+            ..self
+        }
+    }
+
+    /// Set the value of the `Retry-After` header of rejected requests
+    /// (default: one second).
+    pub fn with_retry_after(self, retry_after: Duration) -> Self {
+        Self {
+            retry_after,
+            ..self
+        }
+    }
+
+    fn group(&self, req: &Request) -> Option<&Arc<Semaphore>> {
+        self.groups
+            .iter()
+            .filter(|(prefix, _)| path_has_prefix(req.uri().path(), prefix))
+            .max_by_key(|(prefix, _)| prefix.trim_end_matches('/').len())
+            .map(|(_, semaphore)| semaphore)
+    }
+
+    async fn acquire(&self, semaphore: &Arc<Semaphore>) -> Option<OwnedSemaphorePermit> {
+        match self.max_wait {
+            Some(max_wait) => tokio::time::timeout(max_wait, semaphore.clone().acquire_owned())
+                .await
+                .ok()?
+                .ok(),
+            None => semaphore.clone().try_acquire_owned().ok(),
+        }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for ConcurrencyLimitMiddleware {
+    type Output = ConcurrencyLimitMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ConcurrencyLimitMwEndpoint {
+            inner: ep,
+            config: self.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ConcurrencyLimitMwEndpoint<E> {
+    inner: E,
+    config: ConcurrencyLimitMiddleware,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for ConcurrencyLimitMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let mut permits = Vec::with_capacity(2);
+        for semaphore in [self.config.group(&req), self.config.global.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            let Some(permit) = self.config.acquire(semaphore).await else {
+                let mut resp = ServiceUnavailable::raw::service_unavailable().into_response();
+                resp.headers_mut()
+                    .insert(RETRY_AFTER, self.config.retry_after.as_secs().max(1).into());
+                return Ok(resp);
+            };
+            permits.push(permit);
+        }
+
+        let result = self.inner.call(req).await.map(IntoResponse::into_response);
+        drop(permits);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::StatusCode, test::TestClient, EndpointExt, Route};
+
+    use super::*;
+
+    #[handler]
+    async fn slow() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        "done"
+    }
+
+    #[tokio::test]
+    async fn concurrency_limit() {
+        let mw = ConcurrencyLimitMiddleware::new(2)
+            .with_group("/group", 1)
+            .with_retry_after(Duration::from_secs(5));
+        let cli = Arc::new(TestClient::new(
+            Route::new().at("/", slow).at("/group", slow).with(mw),
+        ));
+
+        let first = tokio::spawn({
+            let cli = cli.clone();
+            async move { cli.get("/group").send().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // the group is full
+        let resp = cli.get("/group").send().await;
+        resp.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        resp.assert_header(RETRY_AFTER, "5");
+        resp.assert_text(r#"{"error":"service_unavailable"}"#).await;
+
+        // the global limit is not reached yet
+        let second = tokio::spawn({
+            let cli = cli.clone();
+            async move { cli.get("/").send().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // the global limit is reached
+        cli.get("/")
+            .send()
+            .await
+            .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+
+        first.await.unwrap().assert_status_is_ok();
+        second.await.unwrap().assert_status_is_ok();
+        cli.get("/").send().await.assert_status_is_ok();
+    }
+
+    #[tokio::test]
+    async fn max_wait() {
+        let cli = Arc::new(TestClient::new(slow.with(
+            ConcurrencyLimitMiddleware::new(1).with_max_wait(Duration::from_secs(5)),
+        )));
+
+        let first = tokio::spawn({
+            let cli = cli.clone();
+            async move { cli.get("/").send().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        cli.get("/").send().await.assert_status_is_ok();
+        first.await.unwrap().assert_status_is_ok();
+    }
+}
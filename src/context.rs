@@ -0,0 +1,195 @@
+//! Contains an extractor that bundles cross-cutting information about a
+//! request.
+//!
+//! The [`RequestContext`] collects the [request id](crate::request_id), the
+//! [ip address of the client](crate::client_ip), the
+//! [authenticated identity](crate::identity), the [`Tenant`] and the
+//! [locale](crate::locale) of a request into a single value, so handlers and
+//! logging or auditing code don't have to look up each of them separately.
+//! Every field is optional and only populated if the corresponding middleware
+//! or configuration is present:
+//!
+//! - `request_id`: [`RequestIdMiddleware`](crate::request_id::RequestIdMiddleware)
+//! - `client_ip`: [`ClientIpMiddleware`](crate::client_ip::ClientIpMiddleware)
+//!   (or the address of the peer)
+//! - `identity`: a middleware that attaches an [`Identity`] and an
+//!   authorization checker that sets it
+//! - `tenant`: an application middleware that inserts a [`Tenant`] extension
+//! - `locale`: [`SupportedLocales`](crate::locale::SupportedLocales) attached
+//!   to the endpoint
+//!
+//! The context is a snapshot taken when it is extracted. As the identity is
+//! usually recorded by an authorization checker, middlewares should call
+//! [`RequestContext::of`] after the request has been passed to the endpoint.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     client_ip::ClientIpMiddleware, context::RequestContext, request_id::RequestIdMiddleware,
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self, ctx: RequestContext) -> PlainText<String> {
+//!         let fields = ctx
+//!             .fields()
+//!             .map(|(name, value)| format!("{name}={value}"))
+//!             .collect::<Vec<_>>();
+//!         PlainText(fields.join(" "))
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .with(ClientIpMiddleware::new())
+//!     .with(RequestIdMiddleware::new());
+//! ```
+
+use std::{fmt::Display, net::IpAddr, ops::Deref};
+
+use poem::{FromRequest, Request, RequestBody};
+
+use crate::{
+    client_ip::ClientIp,
+    identity::Identity,
+    locale::{LanguageTag, Locale},
+    request_id::RequestId,
+};
+
+/// The tenant a request belongs to in a multi-tenant application.
+///
+/// This crate does not resolve tenants itself. Insert a [`Tenant`] into the
+/// extensions of the request (e.g. in a middleware that looks up the tenant
+/// by the host of the request) to make it available to the
+/// [`RequestContext`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tenant(pub String);
+
+impl Tenant {
+    /// Return the tenant of a request, if it has been resolved.
+    pub fn of(req: &Request) -> Option<Self> {
+        req.extensions().get::<Self>().cloned()
+    }
+}
+
+impl Deref for Tenant {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for Tenant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Cross-cutting information about a request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestContext {
+    /// The id of the request (see [`RequestId`]).
+    pub request_id: Option<RequestId>,
+    /// The ip address of the client (see [`ClientIp`]).
+    pub client_ip: Option<IpAddr>,
+    /// The authenticated identity of the caller (see [`Identity`]).
+    pub identity: Option<String>,
+    /// The tenant of the request (see [`Tenant`]).
+    pub tenant: Option<Tenant>,
+    /// The locale that best matches the `Accept-Language` header of the
+    /// request (see [`Locale`]).
+    pub locale: Option<LanguageTag>,
+}
+
+impl RequestContext {
+    /// Return the context of a request.
+    pub fn of(req: &Request) -> Self {
+        Self {
+            request_id: RequestId::of(req),
+            client_ip: ClientIp::of(req).map(|ip| ip.0),
+            identity: Identity::get(req),
+            tenant: Tenant::of(req),
+            locale: Locale::of(req).map(|locale| locale.0),
+        }
+    }
+
+    /// Return the names and values of all fields that are present (e.g. for
+    /// adding them to log events).
+    pub fn fields(&self) -> impl Iterator<Item = (&'static str, String)> {
+        [
+            (
+                "request_id",
+                self.request_id.as_ref().map(ToString::to_string),
+            ),
+            (
+                "client_ip",
+                self.client_ip.as_ref().map(ToString::to_string),
+            ),
+            ("identity", self.identity.clone()),
+            ("tenant", self.tenant.as_ref().map(ToString::to_string)),
+            ("locale", self.locale.as_ref().map(ToString::to_string)),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+    }
+}
+
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for RequestContext {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        Ok(Self::of(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::header::ACCEPT_LANGUAGE, test::TestClient, Endpoint, EndpointExt};
+
+    use super::*;
+    use crate::{
+        locale::SupportedLocales,
+        request_id::{RequestIdMiddleware, REQUEST_ID_HEADER},
+    };
+
+    #[handler]
+    fn context(ctx: RequestContext) -> String {
+        ctx.fields()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[tokio::test]
+    async fn extractor() {
+        let resp = TestClient::new(context).get("/").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("").await;
+
+        let cli = TestClient::new(
+            context
+                .data(SupportedLocales::new(["en", "de"]))
+                .around(|ep, mut req| async move {
+                    req.extensions_mut().insert(Tenant("acme".into()));
+                    Identity::attach(&mut req);
+                    Identity::set(&req, "user");
+                    ep.call(req).await
+                })
+                .with(RequestIdMiddleware::new().trust_header(true)),
+        );
+        let resp = cli
+            .get("/")
+            .header(REQUEST_ID_HEADER, "abc")
+            .header(ACCEPT_LANGUAGE, "de-DE")
+            .send()
+            .await;
+        resp.assert_text("request_id=abc identity=user tenant=acme locale=de")
+            .await;
+    }
+}
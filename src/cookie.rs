@@ -0,0 +1,401 @@
+//! Contains typed cookie parameters and a builder for `Set-Cookie` headers.
+//!
+//! The [`CookieParam`] extractor parses the value of a cookie into any type
+//! that can be used as a parameter (e.g. `String`, `u64` or `Option<T>` for
+//! optional cookies) and documents it as a cookie parameter in the OpenAPI
+//! spec. The name of the cookie is the name of the parameter, which can be
+//! changed using `#[oai(name = "...")]`. Invalid or missing (required)
+//! cookies result in `400 Bad Request`.
+//!
+//! The [`SetCookie`] builder creates `Set-Cookie` header values with safe
+//! defaults (`Path=/`, `SameSite=Lax`, `Secure` and `HttpOnly`). It can be
+//! used as a header of an [`ApiResponse`](poem_openapi::ApiResponse) (via
+//! `#[oai(header = "Set-Cookie")]`), so the header is documented as well, or
+//! appended to any response using [`SetCookie::apply`].
+//!
+//! #### Example
+//! ```
+//! use std::time::Duration;
+//!
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::cookie::{CookieParam, SameSite, SetCookie};
+//! use poem_openapi::{payload::PlainText, ApiResponse, OpenApi, OpenApiService};
+//!
+//! #[derive(ApiResponse)]
+//! enum LoginResponse {
+//!     #[oai(status = 200)]
+//!     Ok(
+//!         PlainText<&'static str>,
+//!         #[oai(header = "Set-Cookie")] SetCookie,
+//!     ),
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/login", method = "post")]
+//!     async fn login(&self) -> LoginResponse {
+//!         let cookie = SetCookie::new("session", "secret")
+//!             .with_same_site(SameSite::Strict)
+//!             .with_max_age(Duration::from_secs(3600));
+//!         LoginResponse::Ok(PlainText("Welcome!"), cookie)
+//!     }
+//!
+//!     #[oai(path = "/me", method = "get")]
+//!     async fn me(&self, session: CookieParam<Option<String>>) -> PlainText<&'static str> {
+//!         match session.0 {
+//!             Some(_) => PlainText("logged in"),
+//!             None => PlainText("not logged in"),
+//!         }
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service);
+//! ```
+
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+
+use poem::{
+    http::{
+        header::{COOKIE, SET_COOKIE},
+        HeaderValue,
+    },
+    Request, RequestBody, Response,
+};
+use poem_openapi::{
+    error::ParseParamError,
+    registry::{MetaParamIn, MetaSchemaRef, Registry},
+    types::{ParseFromParameter, ToHeader, Type},
+    ApiExtractor, ApiExtractorType, ExtractParamOptions,
+};
+
+/// Extractor for a typed cookie parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CookieParam<T>(pub T);
+
+impl<T> Deref for CookieParam<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CookieParam<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[poem::async_trait]
+impl<'a, T: ParseFromParameter> ApiExtractor<'a> for CookieParam<T> {
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::Parameter];
+    const PARAM_IS_REQUIRED: bool = T::IS_REQUIRED;
+
+    type ParamType = T;
+    type ParamRawType = T::RawValueType;
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+
+    fn param_in() -> Option<MetaParamIn> {
+        Some(MetaParamIn::Cookie)
+    }
+
+    fn param_schema_ref() -> Option<MetaSchemaRef> {
+        Some(T::schema_ref())
+    }
+
+    fn param_raw_type(&self) -> Option<&Self::ParamRawType> {
+        self.0.as_raw_value()
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        _body: &mut RequestBody,
+        param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let value = get_cookie(request, param_opts.name);
+        if let (None, Some(default_value)) = (value, &param_opts.default_value) {
+            return Ok(Self(default_value()));
+        }
+        T::parse_from_parameters(value).map(Self).map_err(|err| {
+            ParseParamError {
+                name: param_opts.name,
+                reason: err.into_message(),
+            }
+            .into()
+        })
+    }
+}
+
+/// Return the value of the cookie with the given name.
+pub(crate) fn get_cookie<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+    req.headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find_map(|(key, value)| (key == name).then_some(value))
+}
+
+/// The value of the `SameSite` attribute of a cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SameSite {
+    /// Only send the cookie with requests from the same site.
+    Strict,
+    /// Also send the cookie when navigating to the site from other sites.
+    Lax,
+    /// Send the cookie with all requests. Requires the `Secure` attribute,
+    /// which is therefore always set.
+    None,
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        })
+    }
+}
+
+/// Builder for the value of a `Set-Cookie` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    same_site: SameSite,
+    secure: bool,
+    http_only: bool,
+}
+
+impl SetCookie {
+    /// Create a new session cookie with the attributes `Path=/`,
+    /// `SameSite=Lax`, `Secure` and `HttpOnly`.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            path: Some("/".into()),
+            domain: None,
+            max_age: None,
+            same_site: SameSite::Lax,
+            secure: true,
+            http_only: true,
+        }
+    }
+
+    /// Create a cookie that instructs the client to delete the cookie with
+    /// the given name. The path and domain have to match the ones of the
+    /// original cookie.
+    pub fn removal(name: impl Into<String>) -> Self {
+        Self::new(name, "").with_max_age(Duration::ZERO)
+    }
+
+    /// Set the `Path` attribute (default: `/`).
+    pub fn with_path(self, path: impl Into<String>) -> Self {
+        Self {
+            path: Some(path.into()),
+            ..self
+        }
+    }
+
+    /// Set the `Domain` attribute (default: none, i.e. the cookie is only
+    /// sent to the host that has set it).
+    pub fn with_domain(self, domain: impl Into<String>) -> Self {
+        Self {
+            domain: Some(domain.into()),
+            ..self
+        }
+    }
+
+    /// Set the `Max-Age` attribute (default: none, i.e. the cookie is deleted
+    /// when the browser is closed).
+    pub fn with_max_age(self, max_age: Duration) -> Self {
+        Self {
+            max_age: Some(max_age),
+            ..self
+        }
+    }
+
+    /// Set the `SameSite` attribute (default: [`SameSite::Lax`]).
+    pub fn with_same_site(self, same_site: SameSite) -> Self {
+        Self { same_site, ..self }
+    }
+
+    /// Control whether the `Secure` attribute is set (default: `true`). This
+    /// should only be disabled during development.
+    pub fn with_secure(self, secure: bool) -> Self {
+        Self { secure, ..self }
+    }
+
+    /// Control whether the `HttpOnly` attribute is set (default: `true`).
+    /// Disable it only for cookies that have to be read by scripts.
+    pub fn with_http_only(self, http_only: bool) -> Self {
+        Self { http_only, ..self }
+    }
+
+    /// Append the `Set-Cookie` header to a response.
+    pub fn apply(&self, resp: &mut Response) {
+        if let Some(value) = self.to_header() {
+            resp.headers_mut().append(SET_COOKIE, value);
+        }
+    }
+}
+
+impl Display for SetCookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        write!(f, "; SameSite={}", self.same_site)?;
+        if self.secure || self.same_site == SameSite::None {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        Ok(())
+    }
+}
+
+impl Type for SetCookie {
+    const IS_REQUIRED: bool = true;
+
+    type RawValueType = Self;
+
+    type RawElementValueType = Self;
+
+    fn name() -> Cow<'static, str> {
+        "string".into()
+    }
+
+    fn schema_ref() -> MetaSchemaRef {
+        String::schema_ref()
+    }
+
+    fn as_raw_value(&self) -> Option<&Self::RawValueType> {
+        Some(self)
+    }
+
+    fn raw_element_iter<'a>(
+        &'a self,
+    ) -> Box<dyn Iterator<Item = &'a Self::RawElementValueType> + 'a> {
+        Box::new(self.as_raw_value().into_iter())
+    }
+}
+
+impl ToHeader for SetCookie {
+    fn to_header(&self) -> Option<HeaderValue> {
+        self.to_string().parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{http::StatusCode, test::TestClient};
+    use poem_openapi::{payload::PlainText, ApiResponse, OpenApi, OpenApiService};
+
+    use super::*;
+
+    #[derive(ApiResponse)]
+    enum LoginResponse {
+        #[oai(status = 200)]
+        Ok(PlainText<String>, #[oai(header = "Set-Cookie")] SetCookie),
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test", method = "get")]
+        async fn test(
+            &self,
+            #[oai(name = "user_id")] user_id: CookieParam<u64>,
+            theme: CookieParam<Option<String>>,
+        ) -> LoginResponse {
+            LoginResponse::Ok(
+                PlainText(format!("{} {}", user_id.0, theme.0.unwrap_or_default())),
+                SetCookie::new("seen", "1")
+                    .with_max_age(Duration::from_secs(60))
+                    .with_http_only(false),
+            )
+        }
+    }
+
+    #[test]
+    fn set_cookie() {
+        assert_eq!(
+            SetCookie::new("session", "abc").to_string(),
+            "session=abc; Path=/; SameSite=Lax; Secure; HttpOnly"
+        );
+        assert_eq!(
+            SetCookie::removal("session")
+                .with_domain("example.com")
+                .with_secure(false)
+                .to_string(),
+            "session=; Path=/; Domain=example.com; Max-Age=0; SameSite=Lax; HttpOnly"
+        );
+        assert_eq!(
+            SetCookie::new("a", "b")
+                .with_same_site(SameSite::None)
+                .with_secure(false)
+                .with_http_only(false)
+                .to_string(),
+            "a=b; Path=/; SameSite=None; Secure"
+        );
+    }
+
+    #[tokio::test]
+    async fn cookies() {
+        let service = OpenApiService::new(Api, "test", "1");
+        let spec = serde_json::from_str::<serde_json::Value>(&service.spec()).unwrap();
+        let operation = &spec["paths"]["/test"]["get"];
+        assert_eq!(operation["parameters"][0]["in"], "cookie");
+        assert_eq!(operation["parameters"][0]["required"], true);
+        assert_eq!(operation["parameters"][1]["name"], "theme");
+        assert_eq!(operation["parameters"][1]["required"], false);
+        assert!(operation["responses"]["200"]["headers"]
+            .get("SET-COOKIE")
+            .is_some());
+
+        let cli = TestClient::new(service);
+        let resp = cli
+            .get("/test")
+            .header(COOKIE, "theme=dark; user_id=42")
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_header(
+            SET_COOKIE,
+            "seen=1; Path=/; Max-Age=60; SameSite=Lax; Secure",
+        );
+        resp.assert_text("42 dark").await;
+
+        let resp = cli.get("/test").header(COOKIE, "user_id=x").send().await;
+        resp.assert_status(StatusCode::BAD_REQUEST);
+        let resp = cli.get("/test").send().await;
+        resp.assert_status(StatusCode::BAD_REQUEST);
+    }
+}
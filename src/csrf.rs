@@ -0,0 +1,332 @@
+//! Contains a middleware that protects cookie authenticated apis against
+//! cross-site request forgery.
+//!
+//! The middleware implements the
+//! [double submit cookie](https://cheatsheetseries.owasp.org/cheatsheets/Cross-Site_Request_Forgery_Prevention_Cheat_Sheet.html#alternative-using-a-double-submit-cookie)
+//! pattern: a random token is issued to the client in a cookie, which
+//! the client has to read and send back in a custom header (`X-CSRF-Token` by
+//! default) with every request that uses an unsafe method (i.e. `POST`, `PUT`,
+//! `PATCH`, `DELETE`, ...). Requests with a missing or mismatching token are
+//! rejected with an [`InvalidCsrfToken`] response.
+//!
+//! A new token is issued automatically if a request does not contain one.
+//! Endpoints can access the token of the current request using
+//! [`CsrfToken::of`], e.g. to embed it in a response.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     add_response_schemas,
+//!     csrf::CsrfMiddleware,
+//!     responses::{InvalidCsrfToken, Response},
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! /// Marker type used to document the 403 response.
+//! struct Csrf;
+//! add_response_schemas!(Csrf, InvalidCsrfToken::raw::Response);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "post")]
+//!     async fn test(&self) -> Response<PlainText<&'static str>, Csrf> {
+//!         Ok(PlainText("Hello World!").into())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     // only check requests that are authenticated using the session cookie
+//!     CsrfMiddleware::new().with_auth_cookie("session"),
+//! );
+//! ```
+
+use std::{fmt::Display, ops::Deref};
+
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response};
+use uuid::Uuid;
+
+use crate::{
+    cookie::{get_cookie, SameSite, SetCookie},
+    responses::InvalidCsrfToken,
+};
+
+/// Default name of the cookie that contains the CSRF token.
+pub const CSRF_COOKIE: &str = "csrf_token";
+
+/// Default name of the header that contains the CSRF token.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// The CSRF token of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsrfToken(pub String);
+
+impl CsrfToken {
+    /// Generate a new random token.
+    pub fn generate() -> Self {
+        Self(format!(
+            "{}{}",
+            Uuid::new_v4().simple(),
+            Uuid::new_v4().simple()
+        ))
+    }
+
+    /// Return the CSRF token of a request.
+    ///
+    /// This is either the token sent by the client or the one that has been
+    /// issued by the [`CsrfMiddleware`] for this request.
+    pub fn of(req: &Request) -> Option<Self> {
+        req.extensions().get::<Self>().cloned()
+    }
+}
+
+impl Deref for CsrfToken {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for CsrfToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A middleware that protects against cross-site request forgery.
+#[derive(Debug, Clone)]
+pub struct CsrfMiddleware {
+    cookie_name: String,
+    header_name: String,
+    auth_cookie: Option<String>,
+    secure: bool,
+}
+
+impl Default for CsrfMiddleware {
+    fn default() -> Self {
+        Self {
+            cookie_name: CSRF_COOKIE.into(),
+            header_name: CSRF_HEADER.into(),
+            auth_cookie: None,
+            secure: true,
+        }
+    }
+}
+
+impl CsrfMiddleware {
+    /// Create a new CsrfMiddleware.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the name of the cookie that contains the CSRF token (default:
+    /// [`CSRF_COOKIE`]).
+    pub fn with_cookie_name(self, name: impl Into<String>) -> Self {
+        Self {
+            cookie_name: name.into(),
+            ..self
+        }
+    }
+
+    /// Set the name of the header that contains the CSRF token (default:
+    /// [`CSRF_HEADER`]).
+    pub fn with_header_name(self, name: impl Into<String>) -> Self {
+        Self {
+            header_name: name.into(),
+            ..self
+        }
+    }
+
+    /// Only check requests that contain the given cookie (e.g. the session
+    /// cookie). Requests that are authenticated by other means (e.g. a bearer
+    /// token) are not vulnerable to cross-site request forgery.
+    pub fn with_auth_cookie(self, name: impl Into<String>) -> Self {
+        Self {
+            auth_cookie: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Control whether the `Secure` attribute is set on the token cookie
+    /// (default: `true`). This should only be disabled during development.
+    pub fn with_secure(self, secure: bool) -> Self {
+        Self { secure, ..self }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for CsrfMiddleware {
+    type Output = CsrfMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        CsrfMwEndpoint {
+            inner: ep,
+            config: self.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct CsrfMwEndpoint<E> {
+    inner: E,
+    config: CsrfMiddleware,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for CsrfMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let config = &self.config;
+        let token = get_cookie(&req, &config.cookie_name)
+            .filter(|token| !token.is_empty())
+            .map(|token| CsrfToken(token.into()));
+
+        let check = !req.method().is_safe()
+            && config
+                .auth_cookie
+                .as_ref()
+                .map_or(true, |name| get_cookie(&req, name).is_some());
+        if check {
+            let valid = match (&token, req.header(&config.header_name)) {
+                (Some(token), Some(header)) => {
+                    constant_time_eq(token.as_bytes(), header.as_bytes())
+                }
+                _ => false,
+            };
+            if !valid {
+                return Ok(InvalidCsrfToken::raw::invalid_csrf_token().into_response());
+            }
+        }
+
+        let issued = token.is_none().then(CsrfToken::generate);
+        if let Some(token) = token.as_ref().or(issued.as_ref()) {
+            req.extensions_mut().insert(token.clone());
+        }
+
+        // errors are converted, so the token is also issued with error responses
+        let mut resp = self
+            .inner
+            .call(req)
+            .await
+            .map(IntoResponse::into_response)
+            .unwrap_or_else(|err| err.into_response());
+        if let Some(token) = issued {
+            // the token has to be readable by scripts to be submitted in a header
+            SetCookie::new(&config.cookie_name, token.0)
+                .with_same_site(SameSite::Strict)
+                .with_secure(config.secure)
+                .with_http_only(false)
+                .apply(&mut resp);
+        }
+        Ok(resp)
+    }
+}
+
+/// Compare two byte strings in constant time (with respect to their contents).
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{
+        handler,
+        http::{
+            header::{COOKIE, SET_COOKIE},
+            StatusCode,
+        },
+        test::TestClient,
+        EndpointExt,
+    };
+
+    use super::*;
+
+    #[handler]
+    fn index(req: &Request) -> String {
+        CsrfToken::of(req).unwrap().0
+    }
+
+    #[tokio::test]
+    async fn csrf() {
+        let cli = TestClient::new(index.with(CsrfMiddleware::new()));
+
+        // safe requests without a token get a new one
+        let resp = cli.get("/").send().await;
+        resp.assert_status_is_ok();
+        let cookie = resp.0.headers().get(SET_COOKIE).unwrap().to_str().unwrap();
+        let token = cookie
+            .strip_prefix("csrf_token=")
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_owned();
+        assert!(cookie.ends_with("; Path=/; SameSite=Strict; Secure"));
+        assert_eq!(token.len(), 64);
+        resp.assert_text(&token).await;
+
+        // unsafe requests without a valid token are rejected
+        let resp = cli.post("/").send().await;
+        resp.assert_status(StatusCode::FORBIDDEN);
+        resp.assert_text(r#"{"error":"invalid_csrf_token"}"#).await;
+
+        let resp = cli
+            .post("/")
+            .header(COOKIE, format!("csrf_token={token}"))
+            .header(CSRF_HEADER, "foo")
+            .send()
+            .await;
+        resp.assert_status(StatusCode::FORBIDDEN);
+
+        // unsafe requests with a valid token are allowed
+        let resp = cli
+            .post("/")
+            .header(COOKIE, format!("foo=bar; csrf_token={token}"))
+            .header(CSRF_HEADER, &token)
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_header_is_not_exist(SET_COOKIE);
+        resp.assert_text(&token).await;
+    }
+
+    #[tokio::test]
+    async fn auth_cookie() {
+        let cli = TestClient::new(index.with(CsrfMiddleware::new().with_auth_cookie("session")));
+
+        cli.post("/").send().await.assert_status_is_ok();
+        cli.post("/")
+            .header(COOKIE, "session=foo")
+            .send()
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn error() {
+        #[handler]
+        async fn fail() -> poem::Result<()> {
+            Err(poem::Error::from_status(StatusCode::NOT_FOUND))
+        }
+
+        let cli = TestClient::new(fail.with(CsrfMiddleware::new()));
+        let resp = cli.get("/").send().await;
+        resp.assert_status(StatusCode::NOT_FOUND);
+        assert!(resp.0.headers()[SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .starts_with("csrf_token="));
+    }
+
+    #[test]
+    fn eq() {
+        assert!(constant_time_eq(b"foo", b"foo"));
+        assert!(!constant_time_eq(b"foo", b"bar"));
+        assert!(!constant_time_eq(b"foo", b"fooo"));
+    }
+}
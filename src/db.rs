@@ -1,11 +1,29 @@
 //! Contains a middleware that automatically creates and manages a
-//! [`sea_orm::DatabaseTransaction`](sea_orm::DatabaseTransaction) for each
-//! incoming request. The transaction is automatically
-//! [`commit()`](sea_orm::DatabaseTransaction::commit)ed if the endpoint returns
-//! a successful response or
+//! [`sea_orm::DatabaseTransaction`] for each incoming request. The transaction
+//! is automatically [`commit()`](sea_orm::DatabaseTransaction::commit)ed if the
+//! endpoint returns a successful response or
 //! [`rollback()`](sea_orm::DatabaseTransaction::rollback)ed in case of an
 //! error.
 //!
+//! If [dry runs](DbTransactionMiddleware::with_dry_run) are enabled, clients
+//! can set the `X-Dry-Run: true` header or the `dry_run=true` query parameter
+//! to force the transaction to be rolled back while still receiving the
+//! normal response of the endpoint, e.g. to validate destructive operations.
+//! Endpoints that support dry runs should take a [`DryRun`] parameter (named
+//! `X-Dry-Run` using `#[oai(name = "X-Dry-Run")]`) to document the header and
+//! to skip side effects outside of the database (e.g. sending emails).
+//!
+//! If a [`Readiness`] is [attached](DbTransactionMiddleware::with_readiness),
+//! consecutive failures to begin a transaction mark the `database` component
+//! as not ready, so the [readiness endpoint](crate::health::HealthApi) tells
+//! load balancers to stop sending traffic to the instance. The next
+//! successfully started transaction marks it as ready again.
+//!
+//! Endpoints can either access the transaction using `Data<&DbTxn>` or the
+//! [`Txn`] extractor, which implements [`ConnectionTrait`] and
+//! [`TransactionTrait`] itself and can therefore be passed directly to queries
+//! and generic repository functions.
+//!
 //! #### Example
 //! ```no_run
 //! use poem::{web::Data, EndpointExt, Route};
@@ -31,25 +49,287 @@
 //!     .with(DbTransactionMiddleware::new(db_connection));
 //! ```
 
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use poem::{async_trait, Endpoint, IntoResponse, Middleware, Response};
-use sea_orm::{DatabaseConnection, DatabaseTransaction, TransactionTrait};
+use futures_util::Future;
+use poem::{
+    async_trait, http::HeaderValue, Endpoint, FromRequest, IntoResponse, Middleware, Request,
+    RequestBody, Response,
+};
+use poem_openapi::{
+    registry::{MetaParamIn, MetaSchema, MetaSchemaRef},
+    ApiExtractor, ApiExtractorType, ExtractParamOptions,
+};
+use sea_orm::{
+    AccessMode, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbBackend, DbErr,
+    ExecResult, IsolationLevel, QueryResult, Statement, TransactionError, TransactionTrait,
+};
 
-use crate::responses::internal_server_error;
+use crate::{health::Readiness, responses::internal_server_error};
 
 /// Param type to use in endpoints that need a database transaction.
 pub type DbTxn = Arc<DatabaseTransaction>;
 
+/// The name of the header that requests a dry run.
+pub const DRY_RUN_HEADER: &str = "x-dry-run";
+
+/// The name of the query parameter that requests a dry run.
+pub const DRY_RUN_QUERY: &str = "dry_run";
+
+/// The name of the component that is reported to the [`Readiness`].
+pub const READINESS_COMPONENT: &str = "database";
+
+/// Whether the request is a dry run whose database transaction is always
+/// rolled back (see [`DbTransactionMiddleware::with_dry_run`]).
+///
+/// Use this type as a parameter of endpoints that support dry runs to
+/// document the `X-Dry-Run` header. Outside of a [`DbTransactionMiddleware`]
+/// with enabled dry runs, this is always `false`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DryRun(pub bool);
+
+impl DryRun {
+    /// Return whether a request is a dry run.
+    pub fn of(req: &Request) -> Self {
+        req.extensions().get::<Self>().copied().unwrap_or_default()
+    }
+
+    /// Check whether a request asks for a dry run using the [`DRY_RUN_HEADER`]
+    /// or the [`DRY_RUN_QUERY`] parameter.
+    fn requested(req: &Request) -> bool {
+        let enabled = |value: &str| value.eq_ignore_ascii_case("true") || value == "1";
+        req.header(DRY_RUN_HEADER).is_some_and(enabled)
+            || req.params::<Vec<(String, String)>>().is_ok_and(|params| {
+                params
+                    .iter()
+                    .any(|(key, value)| key == DRY_RUN_QUERY && enabled(value))
+            })
+    }
+}
+
+#[poem::async_trait]
+impl<'a> ApiExtractor<'a> for DryRun {
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::Parameter];
+
+    type ParamType = Self;
+    type ParamRawType = Self;
+
+    fn param_in() -> Option<MetaParamIn> {
+        Some(MetaParamIn::Header)
+    }
+
+    fn param_schema_ref() -> Option<MetaSchemaRef> {
+        Some(MetaSchemaRef::Inline(Box::new(MetaSchema {
+            description: Some(
+                "Set to `true` to roll back all changes to the database while still returning \
+                 the normal response. Alternatively, the `dry_run=true` query parameter can be \
+                 used.",
+            ),
+            default: Some(false.into()),
+            ..MetaSchema::new("boolean")
+        })))
+    }
+
+    fn param_raw_type(&self) -> Option<&Self::ParamRawType> {
+        Some(self)
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        _body: &mut RequestBody,
+        _param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        Ok(Self::of(request))
+    }
+}
+
+/// Extractor for the database transaction of the request that implements
+/// [`ConnectionTrait`] and [`TransactionTrait`] by delegating to the
+/// transaction.
+///
+/// Like `Data<&DbTxn>`, this extractor requires the
+/// [`DbTransactionMiddleware`] and must not outlive the endpoint.
+///
+/// #### Example
+/// ```no_run
+/// use poem_ext::db::Txn;
+/// use poem_openapi::{payload::PlainText, OpenApi};
+/// use sea_orm::{ConnectionTrait, DbErr, Statement};
+///
+/// async fn count_users(db: &impl ConnectionTrait) -> Result<usize, DbErr> {
+///     let stmt = Statement::from_string(db.get_database_backend(), "select * from users");
+///     Ok(db.query_all(stmt).await?.len())
+/// }
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/users/count", method = "get")]
+///     async fn count(&self, txn: Txn) -> PlainText<String> {
+///         PlainText(count_users(&txn).await.unwrap().to_string())
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Txn(pub DbTxn);
+
+impl Deref for Txn {
+    type Target = DatabaseTransaction;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for Txn {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        let txn = req.extensions().get::<DbTxn>().ok_or_else(|| {
+            internal_server_error("db transaction is missing (DbTransactionMiddleware not used)")
+        })?;
+        Ok(Self(txn.clone()))
+    }
+}
+
+#[poem::async_trait]
+impl ConnectionTrait for Txn {
+    fn get_database_backend(&self) -> DbBackend {
+        self.0.get_database_backend()
+    }
+
+    async fn execute(&self, stmt: Statement) -> Result<ExecResult, DbErr> {
+        self.0.execute(stmt).await
+    }
+
+    async fn execute_unprepared(&self, sql: &str) -> Result<ExecResult, DbErr> {
+        self.0.execute_unprepared(sql).await
+    }
+
+    async fn query_one(&self, stmt: Statement) -> Result<Option<QueryResult>, DbErr> {
+        self.0.query_one(stmt).await
+    }
+
+    async fn query_all(&self, stmt: Statement) -> Result<Vec<QueryResult>, DbErr> {
+        self.0.query_all(stmt).await
+    }
+
+    fn support_returning(&self) -> bool {
+        self.0.support_returning()
+    }
+
+    fn is_mock_connection(&self) -> bool {
+        self.0.is_mock_connection()
+    }
+}
+
+#[poem::async_trait]
+impl TransactionTrait for Txn {
+    async fn begin(&self) -> Result<DatabaseTransaction, DbErr> {
+        self.0.begin().await
+    }
+
+    async fn begin_with_config(
+        &self,
+        isolation_level: Option<IsolationLevel>,
+        access_mode: Option<AccessMode>,
+    ) -> Result<DatabaseTransaction, DbErr> {
+        self.0.begin_with_config(isolation_level, access_mode).await
+    }
+
+    async fn transaction<F, T, E>(&self, callback: F) -> Result<T, TransactionError<E>>
+    where
+        F: for<'c> FnOnce(
+                &'c DatabaseTransaction,
+            ) -> Pin<Box<dyn Future<Output = Result<T, E>> + Send + 'c>>
+            + Send,
+        T: Send,
+        E: std::error::Error + Send,
+    {
+        self.0.transaction(callback).await
+    }
+
+    async fn transaction_with_config<F, T, E>(
+        &self,
+        callback: F,
+        isolation_level: Option<IsolationLevel>,
+        access_mode: Option<AccessMode>,
+    ) -> Result<T, TransactionError<E>>
+    where
+        F: for<'c> FnOnce(
+                &'c DatabaseTransaction,
+            ) -> Pin<Box<dyn Future<Output = Result<T, E>> + Send + 'c>>
+            + Send,
+        T: Send,
+        E: std::error::Error + Send,
+    {
+        self.0
+            .transaction_with_config(callback, isolation_level, access_mode)
+            .await
+    }
+}
+
+/// Response data that is set by the [`DbTransactionMiddleware`] if the
+/// transaction has been committed successfully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionCommitted;
+
+/// Response data that is set by the [`DbTransactionMiddleware`] with
+/// statistics about the transaction of the request (e.g. for the
+/// [`SlowRequestLogMiddleware`](crate::slow_log::SlowRequestLogMiddleware)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionStats {
+    /// The time between the start of the transaction and the end of the
+    /// commit or rollback.
+    pub duration: Duration,
+    /// The time it took to commit or roll back the transaction.
+    pub finish_duration: Duration,
+    /// Whether the transaction has been committed.
+    pub committed: bool,
+}
+
 /// A function that checks if a response is successful.
 pub type CheckFn = Arc<dyn Fn(&Response) -> bool + Send + Sync>;
 
+/// Tracks consecutive failures to begin a transaction and reports them to a
+/// [`Readiness`].
+#[derive(Debug)]
+struct DbHealth {
+    readiness: Readiness,
+    threshold: u32,
+    failures: AtomicU32,
+}
+
+impl DbHealth {
+    fn record_success(&self) {
+        if self.failures.swap(0, Ordering::Relaxed) >= self.threshold {
+            self.readiness.set_ready(READINESS_COMPONENT);
+        }
+    }
+
+    fn record_failure(&self) {
+        if self.failures.fetch_add(1, Ordering::Relaxed) + 1 >= self.threshold {
+            self.readiness.set_unready(READINESS_COMPONENT);
+        }
+    }
+}
+
 /// A middleware for automatically creating and managing
-/// [`sea_orm::DatabaseTransaction`](sea_orm::DatabaseTransaction)s for incoming
-/// requests.
+/// [`sea_orm::DatabaseTransaction`]s for incoming requests.
 pub struct DbTransactionMiddleware {
     db: DatabaseConnection,
     check_fn: Option<CheckFn>,
+    dry_run: bool,
+    health: Option<Arc<DbHealth>>,
 }
 
 impl Debug for DbTransactionMiddleware {
@@ -63,7 +343,12 @@ impl Debug for DbTransactionMiddleware {
 impl DbTransactionMiddleware {
     /// Create a new DbTransactionMiddleware.
     pub fn new(db: DatabaseConnection) -> Self {
-        Self { db, check_fn: None }
+        Self {
+            db,
+            check_fn: None,
+            dry_run: false,
+            health: None,
+        }
     }
 
     /// Use a custom function to check if a response is successful.
@@ -88,8 +373,36 @@ impl DbTransactionMiddleware {
         F: Fn(&Response) -> bool + Send + Sync + 'static,
     {
         Self {
-            db: self.db,
             check_fn: Some(Arc::new(check_fn)),
+            ..self
+        }
+    }
+
+    /// Allow clients to request a dry run using the [`DRY_RUN_HEADER`] or the
+    /// [`DRY_RUN_QUERY`] parameter. The transaction of a dry run is always
+    /// rolled back, and the response contains the `X-Dry-Run: true` header.
+    pub fn with_dry_run(self) -> Self {
+        Self {
+            dry_run: true,
+            ..self
+        }
+    }
+
+    /// Mark the [`READINESS_COMPONENT`] as not ready after `threshold`
+    /// consecutive failures to begin a transaction, and as ready again after
+    /// the next success.
+    ///
+    /// # Panics
+    /// Panics if `threshold` is zero.
+    pub fn with_readiness(self, readiness: Readiness, threshold: u32) -> Self {
+        assert!(threshold > 0, "readiness threshold must be positive");
+        Self {
+            health: Some(Arc::new(DbHealth {
+                readiness,
+                threshold,
+                failures: AtomicU32::new(0),
+            })),
+            ..self
         }
     }
 }
@@ -102,6 +415,8 @@ impl<E: Endpoint> Middleware<E> for DbTransactionMiddleware {
             inner: ep,
             db: self.db.clone(),
             check_fn: self.check_fn.clone(),
+            dry_run: self.dry_run,
+            health: self.health.clone(),
         }
     }
 }
@@ -111,6 +426,8 @@ pub struct DbTransactionMwEndpoint<E> {
     inner: E,
     db: DatabaseConnection,
     check_fn: Option<CheckFn>,
+    dry_run: bool,
+    health: Option<Arc<DbHealth>>,
 }
 
 impl<E: Debug> Debug for DbTransactionMwEndpoint<E> {
@@ -118,6 +435,7 @@ impl<E: Debug> Debug for DbTransactionMwEndpoint<E> {
         f.debug_struct("DbTransactionMwEndpoint")
             .field("inner", &self.inner)
             .field("db", &self.db)
+            .field("dry_run", &self.dry_run)
             .finish_non_exhaustive()
     }
 }
@@ -127,7 +445,17 @@ impl<E: Endpoint> Endpoint for DbTransactionMwEndpoint<E> {
     type Output = Response;
 
     async fn call(&self, mut req: poem::Request) -> Result<Self::Output, poem::Error> {
-        let txn = Arc::new(self.db.begin().await.map_err(internal_server_error)?);
+        let dry_run = self.dry_run && DryRun::requested(&req);
+        req.extensions_mut().insert(DryRun(dry_run));
+        let start = Instant::now();
+        let txn = self.db.begin().await;
+        if let Some(health) = &self.health {
+            match txn {
+                Ok(_) => health.record_success(),
+                Err(_) => health.record_failure(),
+            }
+        }
+        let txn = Arc::new(txn.map_err(internal_server_error)?);
         req.extensions_mut().insert(txn.clone());
         let result = self.inner.call(req).await;
         let txn = Arc::try_unwrap(txn).map_err(|_| {
@@ -135,15 +463,28 @@ impl<E: Endpoint> Endpoint for DbTransactionMwEndpoint<E> {
         })?;
         match result {
             Ok(resp) => {
-                let resp = resp.into_response();
-                if self.check_fn.as_ref().map_or_else(
-                    || !resp.status().is_server_error() && !resp.status().is_client_error(),
-                    |check_fn| check_fn(&resp),
-                ) {
+                let mut resp = resp.into_response();
+                let finish_start = Instant::now();
+                let committed = !dry_run
+                    && self.check_fn.as_ref().map_or_else(
+                        || !resp.status().is_server_error() && !resp.status().is_client_error(),
+                        |check_fn| check_fn(&resp),
+                    );
+                if committed {
                     txn.commit().await.map_err(internal_server_error)?;
+                    resp.set_data(TransactionCommitted);
                 } else {
                     txn.rollback().await.map_err(internal_server_error)?;
                 }
+                if dry_run {
+                    resp.headers_mut()
+                        .insert(DRY_RUN_HEADER, HeaderValue::from_static("true"));
+                }
+                resp.set_data(TransactionStats {
+                    duration: start.elapsed(),
+                    finish_duration: finish_start.elapsed(),
+                    committed,
+                });
                 Ok(resp)
             }
             Err(err) => {
@@ -153,3 +494,122 @@ impl<E: Endpoint> Endpoint for DbTransactionMwEndpoint<E> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::StatusCode, test::TestClient, EndpointExt};
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+
+    #[handler]
+    fn requested(req: &Request) -> String {
+        DryRun::requested(req).to_string()
+    }
+
+    #[tokio::test]
+    async fn dry_run_requested() {
+        let cli = TestClient::new(requested);
+        let check = |uri: &'static str, header: Option<&'static str>| {
+            let cli = &cli;
+            async move {
+                let mut req = cli.get(uri);
+                if let Some(header) = header {
+                    req = req.header(DRY_RUN_HEADER, header);
+                }
+                req.send().await.0.into_body().into_string().await.unwrap()
+            }
+        };
+
+        assert_eq!(check("/", None).await, "false");
+        assert_eq!(check("/", Some("true")).await, "true");
+        assert_eq!(check("/", Some("TRUE")).await, "true");
+        assert_eq!(check("/", Some("1")).await, "true");
+        assert_eq!(check("/", Some("false")).await, "false");
+        assert_eq!(check("/?dry_run=true", None).await, "true");
+        assert_eq!(check("/?dry_run=false", None).await, "false");
+        assert_eq!(check("/?dry_run=1&foo=bar", None).await, "true");
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test", method = "delete")]
+        async fn test(&self, #[oai(name = "X-Dry-Run")] dry_run: DryRun) -> PlainText<String> {
+            PlainText(dry_run.0.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn extractor() {
+        // dry runs are only enabled by the middleware
+        let resp = TestClient::new(OpenApiService::new(Api, "test", "1"))
+            .delete("/test")
+            .header(DRY_RUN_HEADER, "true")
+            .send()
+            .await;
+        resp.assert_text("false").await;
+
+        let spec: serde_json::Value =
+            serde_json::from_str(&OpenApiService::new(Api, "test", "1").spec()).unwrap();
+        let param = &spec["paths"]["/test"]["delete"]["parameters"][0];
+        assert_eq!(param["name"], "X-Dry-Run");
+        assert_eq!(param["in"], "header");
+        assert_eq!(param["schema"]["type"], "boolean");
+    }
+
+    #[handler]
+    fn txn(_txn: Txn) {}
+
+    #[tokio::test]
+    async fn txn_extractor() {
+        fn connection<C: ConnectionTrait + TransactionTrait>() {}
+        connection::<Txn>();
+
+        // the transaction is only available inside of the middleware
+        TestClient::new(txn)
+            .get("/")
+            .send()
+            .await
+            .assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn readiness() {
+        let readiness = Readiness::new();
+        let cli = TestClient::new(
+            OpenApiService::new(Api, "test", "1").with(
+                DbTransactionMiddleware::new(DatabaseConnection::Disconnected)
+                    .with_readiness(readiness.clone(), 2),
+            ),
+        );
+
+        cli.delete("/test")
+            .send()
+            .await
+            .assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(readiness.is_ready());
+        cli.delete("/test").send().await;
+        assert_eq!(readiness.failing(), [READINESS_COMPONENT]);
+    }
+
+    #[test]
+    fn readiness_recovery() {
+        let readiness = Readiness::new();
+        let health = DbHealth {
+            readiness: readiness.clone(),
+            threshold: 2,
+            failures: AtomicU32::new(0),
+        };
+        health.record_failure();
+        health.record_success();
+        health.record_failure();
+        assert!(readiness.is_ready());
+        health.record_failure();
+        health.record_failure();
+        assert!(!readiness.is_ready());
+        health.record_success();
+        assert!(readiness.is_ready());
+    }
+}
@@ -0,0 +1,416 @@
+//! Contains an API with admin endpoints for debugging a running service.
+//!
+//! The [`DebugApi`] exposes the following endpoints, which are guarded by an
+//! authorization dependency created using the
+//! [`custom_auth!`](crate::custom_auth!) macro:
+//!
+//! - `GET /build`: the [`BuildInfo`] of the service
+//! - `GET /config`: a snapshot of the configuration of the service, with
+//!   secrets replaced by [`REDACTED`](crate::access_log::REDACTED)
+//! - `GET /feature-flags`: whether the configured feature flags are enabled
+//!   for the caller (evaluated using the attached [`FeatureFlags`])
+//! - `GET /errors`: the ids of the most recent internal server errors, which
+//!   are recorded by [`RecentErrors`] (a [`RequestEventSink`])
+//!
+//! The api should be documented in a separate OpenAPI spec that is not
+//! published with the public api. [`DebugApi::into_endpoint`] creates an
+//! endpoint that serves the api together with its own spec at
+//! `/openapi.json` and is supposed to be nested under `/admin`.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Request, Route};
+//! use poem_ext::{
+//!     build_info,
+//!     custom_auth,
+//!     debug_api::{DebugApi, RecentErrors},
+//!     request_events::RequestEventMiddleware,
+//! };
+//! use poem_openapi::{auth::Bearer, payload::PlainText, OpenApi, OpenApiService};
+//! use serde_json::json;
+//!
+//! struct AdminAuth(());
+//!
+//! async fn admin_auth_check(_req: &Request, token: Option<Bearer>) -> poem::Result<()> {
+//!     match token {
+//!         Some(Bearer { token }) if token == "admin_token" => Ok(()),
+//!         _ => Err(poem::Error::from_status(poem::http::StatusCode::UNAUTHORIZED)),
+//!     }
+//! }
+//!
+//! custom_auth!(AdminAuth, admin_auth_check);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! let errors = RecentErrors::new(100);
+//! let debug_api = DebugApi::<AdminAuth>::new(build_info!())
+//!     .with_config(json!({"database": {"url": "postgres://...", "pool_size": 8}}))
+//!     .with_redaction("/database/url")
+//!     .with_feature_flag("new_checkout")
+//!     .with_recent_errors(errors.clone());
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .nest("/admin", debug_api.into_endpoint())
+//!     .with(RequestEventMiddleware::new(errors));
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
+};
+
+use poem::{Request, Route};
+use poem_openapi::{payload::Json, ApiExtractor, Object, OpenApi, OpenApiService};
+use serde_json::Value;
+
+use crate::{
+    build_info::BuildInfo,
+    feature_flag::FeatureFlags,
+    request_events::{RequestEvent, RequestEventSink},
+    utils::{parse_pointer, redact},
+};
+
+/// An API with admin endpoints for debugging a running service.
+///
+/// All endpoints require the authorization dependency `A`, which should be
+/// created using the [`custom_auth!`](crate::custom_auth!) macro.
+pub struct DebugApi<A> {
+    build: BuildInfo,
+    config: Value,
+    redactions: Vec<Vec<String>>,
+    feature_flags: Vec<String>,
+    errors: RecentErrors,
+    _auth: PhantomData<fn() -> A>,
+}
+
+impl<A> Debug for DebugApi<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DebugApi")
+            .field("build", &self.build)
+            .field("feature_flags", &self.feature_flags)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> DebugApi<A> {
+    /// Create a new DebugApi that exposes the given build info.
+    pub fn new(build: BuildInfo) -> Self {
+        Self {
+            build,
+            config: Value::Null,
+            redactions: Vec::new(),
+            feature_flags: Vec::new(),
+            errors: RecentErrors::new(0),
+            _auth: PhantomData,
+        }
+    }
+
+    /// Expose a snapshot of the configuration of the service (e.g. created
+    /// using [`serde_json::to_value`]).
+    pub fn with_config(self, config: Value) -> Self {
+        Self { config, ..self }
+    }
+
+    /// Replace the value at the given JSON pointer (wildcards allowed, e.g.
+    /// `/databases/*/password`) in the configuration with
+    /// [`REDACTED`](crate::access_log::REDACTED).
+    pub fn with_redaction(mut self, pointer: impl AsRef<str>) -> Self {
+        self.redactions.push(parse_pointer(pointer.as_ref()));
+        self
+    }
+
+    /// Report whether the feature flag with the given name is enabled.
+    pub fn with_feature_flag(mut self, name: impl Into<String>) -> Self {
+        self.feature_flags.push(name.into());
+        self
+    }
+
+    /// Expose the errors recorded by the given [`RecentErrors`].
+    pub fn with_recent_errors(self, errors: RecentErrors) -> Self {
+        Self { errors, ..self }
+    }
+
+    /// Return the redacted configuration.
+    fn config(&self) -> Value {
+        let mut config = self.config.clone();
+        for pointer in &self.redactions {
+            redact(&mut config, pointer);
+        }
+        config
+    }
+}
+
+impl<A> DebugApi<A>
+where
+    A: for<'a> ApiExtractor<'a> + Send + Sync + 'static,
+{
+    /// Create an endpoint that serves the api and its OpenAPI spec (at
+    /// `/openapi.json`). The endpoint should be nested under `/admin`.
+    pub fn into_endpoint(self) -> Route {
+        let service = OpenApiService::new(self, "Debug API", env!("CARGO_PKG_VERSION"));
+        Route::new()
+            .at("/openapi.json", service.spec_endpoint())
+            .nest("/", service)
+    }
+}
+
+#[OpenApi]
+impl<A> DebugApi<A>
+where
+    A: for<'a> ApiExtractor<'a> + Send + Sync + 'static,
+{
+    /// Return the build info of the service.
+    #[oai(path = "/build", method = "get", operation_id = "debug_build")]
+    async fn build(&self, _auth: A) -> Json<BuildInfo> {
+        Json(self.build.clone())
+    }
+
+    /// Return the redacted configuration of the service.
+    #[oai(path = "/config", method = "get", operation_id = "debug_config")]
+    async fn config_snapshot(&self, _auth: A) -> Json<Value> {
+        Json(self.config())
+    }
+
+    /// Return whether the feature flags are enabled for the caller.
+    #[oai(
+        path = "/feature-flags",
+        method = "get",
+        operation_id = "debug_feature_flags"
+    )]
+    async fn feature_flags(&self, req: &Request, _auth: A) -> Json<Vec<FeatureFlagState>> {
+        let provider = req.data::<FeatureFlags>();
+        let mut flags = Vec::with_capacity(self.feature_flags.len());
+        for name in &self.feature_flags {
+            let enabled = match provider {
+                Some(provider) => provider.is_enabled(name, req).await,
+                None => false,
+            };
+            flags.push(FeatureFlagState {
+                name: name.clone(),
+                enabled,
+            });
+        }
+        Json(flags)
+    }
+
+    /// Return the most recent internal server errors (newest first).
+    #[oai(path = "/errors", method = "get", operation_id = "debug_errors")]
+    async fn errors(&self, _auth: A) -> Json<Vec<RecentError>> {
+        Json(self.errors.get())
+    }
+}
+
+/// The state of a feature flag.
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct FeatureFlagState {
+    /// The name of the feature flag.
+    pub name: String,
+    /// Whether the feature flag is enabled.
+    pub enabled: bool,
+}
+
+/// An internal server error recorded by [`RecentErrors`].
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct RecentError {
+    /// The id of the error, which is also returned to the client and logged.
+    pub error_id: String,
+    /// The time at which the request has been received (unix timestamp).
+    pub timestamp: u64,
+    /// The method of the request.
+    pub method: String,
+    /// The path of the request.
+    pub path: String,
+    /// The operation id of the endpoint.
+    pub operation: Option<String>,
+    /// The status of the response.
+    pub status: u16,
+    /// The id of the request.
+    pub request_id: Option<String>,
+}
+
+/// A [`RequestEventSink`] that keeps the most recent internal server errors.
+#[derive(Debug, Clone)]
+pub struct RecentErrors {
+    errors: Arc<Mutex<VecDeque<RecentError>>>,
+    capacity: usize,
+}
+
+impl RecentErrors {
+    /// Create a new RecentErrors that keeps at most `capacity` errors.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            errors: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Return the recorded errors (newest first).
+    pub fn get(&self) -> Vec<RecentError> {
+        self.errors.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+impl RequestEventSink for RecentErrors {
+    fn emit(&self, event: RequestEvent) {
+        let Some(error_id) = event.error_id else {
+            return;
+        };
+        if self.capacity == 0 {
+            return;
+        }
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() == self.capacity {
+            errors.pop_front();
+        }
+        errors.push_back(RecentError {
+            error_id,
+            timestamp: event
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            method: event.method.to_string(),
+            path: event.path,
+            operation: event.operation.map(Into::into),
+            status: event.status.as_u16(),
+            request_id: event.context.request_id.map(|id| id.to_string()),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use poem::{http::StatusCode, test::TestClient, EndpointExt};
+    use poem_openapi::{auth::Bearer, payload::PlainText};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        custom_auth,
+        request_events::RequestEventMiddleware,
+        responses::{internal_server_error, Response},
+    };
+
+    struct AdminAuth(());
+
+    async fn admin_auth_check(_req: &Request, token: Option<Bearer>) -> poem::Result<()> {
+        match token {
+            Some(Bearer { token }) if token == "admin" => Ok(()),
+            _ => Err(poem::Error::from_status(StatusCode::UNAUTHORIZED)),
+        }
+    }
+
+    custom_auth!(AdminAuth, admin_auth_check);
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/error", method = "get", operation_id = "error")]
+        async fn error(&self) -> Response<PlainText<&'static str>> {
+            Err(internal_server_error("failed"))
+        }
+    }
+
+    #[tokio::test]
+    async fn debug_api() {
+        let errors = RecentErrors::new(2);
+        let debug_api = DebugApi::<AdminAuth>::new(BuildInfo::new("1.2.3"))
+            .with_config(json!({"db": {"url": "secret", "pool": 8}}))
+            .with_redaction("/db/url")
+            .with_feature_flag("enabled")
+            .with_feature_flag("disabled")
+            .with_recent_errors(errors.clone());
+        let cli = TestClient::new(
+            Route::new()
+                .nest("/", OpenApiService::new(Api, "test", "1"))
+                .nest("/admin", debug_api.into_endpoint())
+                .data(FeatureFlags::new(HashSet::from(["enabled".to_owned()])))
+                .with(RequestEventMiddleware::new(errors)),
+        );
+
+        cli.get("/admin/build")
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+
+        let resp = cli
+            .get("/admin/build")
+            .header("Authorization", "Bearer admin")
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text(r#"{"build_time":null,"git_sha":null,"rustc":null,"version":"1.2.3"}"#)
+            .await;
+
+        let resp = cli
+            .get("/admin/config")
+            .header("Authorization", "Bearer admin")
+            .send()
+            .await;
+        resp.assert_text(r#"{"db":{"pool":8,"url":"[redacted]"}}"#)
+            .await;
+
+        let resp = cli
+            .get("/admin/feature-flags")
+            .header("Authorization", "Bearer admin")
+            .send()
+            .await;
+        resp.assert_text(
+            r#"[{"enabled":true,"name":"enabled"},{"enabled":false,"name":"disabled"}]"#,
+        )
+        .await;
+
+        let mut error_ids = Vec::new();
+        for _ in 0..3 {
+            let resp = cli.get("/error").send().await;
+            let body = resp.json().await;
+            error_ids.push(body.value().object().get("error_id").string().to_owned());
+        }
+        let resp = cli
+            .get("/admin/errors")
+            .header("Authorization", "Bearer admin")
+            .send()
+            .await;
+        let body = resp.json().await;
+        let recent = body.value().array();
+        recent.assert_len(2);
+        recent
+            .get(0)
+            .object()
+            .get("error_id")
+            .assert_string(&error_ids[2]);
+        recent
+            .get(1)
+            .object()
+            .get("error_id")
+            .assert_string(&error_ids[1]);
+        recent
+            .get(0)
+            .object()
+            .get("operation")
+            .assert_string("error");
+
+        let resp = cli.get("/admin/openapi.json").send().await;
+        resp.assert_status_is_ok();
+        let spec = resp.json().await;
+        let paths = spec.value().object().get("paths").object();
+        paths.get("/build").object();
+        assert!(paths.get_opt("/error").is_none());
+    }
+}
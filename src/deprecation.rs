@@ -0,0 +1,283 @@
+//! Contains a middleware that announces the deprecation of single operations.
+//!
+//! Responses of deprecated operations contain a `Deprecation` header and
+//! optionally a `Sunset` header with the date after which the operation will
+//! no longer be available and a `Link` header pointing to further information.
+//! [`DeprecationMiddleware::spec`] marks the same operations as deprecated in
+//! the OpenAPI document.
+//!
+//! Operations are identified by their operation id, so the deprecated
+//! operations must set one explicitly (e.g.
+//! `#[oai(path = "/users", method = "get", operation_id = "list_users")]`). To
+//! deprecate a whole api version, use
+//! [`ApiVersion::deprecated`](crate::versioning::ApiVersion::deprecated)
+//! instead.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::deprecation::{Deprecation, DeprecationMiddleware};
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/users", method = "get", operation_id = "list_users")]
+//!     async fn list_users(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! let deprecations = DeprecationMiddleware::new().deprecate(
+//!     "list_users",
+//!     Deprecation::new()
+//!         .with_sunset("Sat, 01 Nov 2025 00:00:00 GMT")
+//!         .with_link("https://example.com/changelog"),
+//! );
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let spec = deprecations.spec_endpoint(&api_service);
+//! let app = Route::new()
+//!     .at("/openapi.json", spec)
+//!     .nest("/", api_service)
+//!     .with(deprecations);
+//! ```
+
+use std::{collections::HashMap, sync::Arc};
+
+use poem::{
+    endpoint::make_sync, http::HeaderValue, Endpoint, IntoResponse, Middleware, Request, Response,
+};
+use poem_openapi::{OpenApi, OpenApiService, OperationId, Webhook};
+use serde_json::Value;
+
+/// Name of the extension that contains the sunset date of a deprecated
+/// operation in the OpenAPI document.
+pub const SUNSET_EXTENSION: &str = "x-sunset";
+
+/// Deprecation metadata of an operation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Deprecation {
+    sunset: Option<String>,
+    link: Option<String>,
+}
+
+impl Deprecation {
+    /// Create a new Deprecation without a sunset date.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the date (in the HTTP date format, e.g.
+    /// `Sat, 01 Nov 2025 00:00:00 GMT`) after which the operation will no
+    /// longer be available.
+    pub fn with_sunset(self, date: impl Into<String>) -> Self {
+        Self {
+            sunset: Some(date.into()),
+            ..self
+        }
+    }
+
+    /// Set the url of a document with further information about the
+    /// deprecation (e.g. a migration guide).
+    pub fn with_link(self, url: impl Into<String>) -> Self {
+        Self {
+            link: Some(url.into()),
+            ..self
+        }
+    }
+
+    fn add_headers(&self, resp: &mut Response) {
+        let headers = resp.headers_mut();
+        headers.insert("deprecation", HeaderValue::from_static("true"));
+        if let Some(sunset) = self.sunset.as_deref().and_then(|x| x.parse().ok()) {
+            headers.insert("sunset", sunset);
+        }
+        if let Some(link) = self
+            .link
+            .as_deref()
+            .and_then(|x| format!("<{x}>; rel=\"deprecation\"").parse().ok())
+        {
+            headers.append("link", link);
+        }
+    }
+}
+
+/// A middleware that adds deprecation headers to the responses of deprecated
+/// operations.
+#[derive(Debug, Clone, Default)]
+pub struct DeprecationMiddleware {
+    operations: Arc<HashMap<String, Deprecation>>,
+}
+
+impl DeprecationMiddleware {
+    /// Create a new DeprecationMiddleware without any deprecated operations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark the operation with the given operation id as deprecated.
+    pub fn deprecate(mut self, operation_id: impl Into<String>, deprecation: Deprecation) -> Self {
+        Arc::make_mut(&mut self.operations).insert(operation_id.into(), deprecation);
+        self
+    }
+
+    /// Mark the deprecated operations in an OpenAPI document in JSON format
+    /// as deprecated and add their sunset dates (see [`SUNSET_EXTENSION`]).
+    pub fn spec(&self, spec: &str) -> String {
+        let Ok(mut spec) = serde_json::from_str::<Value>(spec) else {
+            return spec.into();
+        };
+        self.apply(&mut spec);
+        spec.to_string()
+    }
+
+    /// Mark the deprecated operations in a parsed OpenAPI document as
+    /// deprecated, e.g. in a hook of a
+    /// [`SpecProcessor`](crate::spec::SpecProcessor).
+    pub fn apply(&self, spec: &mut Value) {
+        let Some(paths) = spec.get_mut("paths").and_then(Value::as_object_mut) else {
+            return;
+        };
+        for operation in paths
+            .values_mut()
+            .filter_map(Value::as_object_mut)
+            .flat_map(|path| path.values_mut())
+            .filter_map(Value::as_object_mut)
+        {
+            let Some(deprecation) = operation
+                .get("operationId")
+                .and_then(Value::as_str)
+                .and_then(|id| self.operations.get(id))
+            else {
+                continue;
+            };
+            operation.insert("deprecated".into(), true.into());
+            if let Some(sunset) = &deprecation.sunset {
+                operation.insert(SUNSET_EXTENSION.into(), sunset.as_str().into());
+            }
+        }
+    }
+
+    /// Create an endpoint that serves the OpenAPI document of the given
+    /// service in JSON format including the deprecation metadata (see
+    /// [`spec`](Self::spec)).
+    pub fn spec_endpoint<T, W>(
+        &self,
+        service: &OpenApiService<T, W>,
+    ) -> impl Endpoint<Output = Response>
+    where
+        T: OpenApi,
+        W: Webhook,
+    {
+        let spec = self.spec(&service.spec());
+        make_sync(move |_| {
+            Response::builder()
+                .content_type("application/json; charset=utf-8")
+                .body(spec.clone())
+        })
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for DeprecationMiddleware {
+    type Output = DeprecationMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        DeprecationMwEndpoint {
+            inner: ep,
+            operations: self.operations.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct DeprecationMwEndpoint<E> {
+    inner: E,
+    operations: Arc<HashMap<String, Deprecation>>,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for DeprecationMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let mut resp = self.inner.call(req).await?.into_response();
+        if let Some(deprecation) = resp
+            .data::<OperationId>()
+            .and_then(|id| self.operations.get(id.0))
+        {
+            deprecation.add_headers(&mut resp);
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, EndpointExt, Route};
+    use poem_openapi::payload::PlainText;
+
+    use super::*;
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/old", method = "get", operation_id = "old")]
+        async fn old(&self) -> PlainText<&'static str> {
+            PlainText("old")
+        }
+
+        #[oai(path = "/new", method = "get", operation_id = "new")]
+        async fn current(&self) -> PlainText<&'static str> {
+            PlainText("new")
+        }
+    }
+
+    fn deprecations() -> DeprecationMiddleware {
+        DeprecationMiddleware::new().deprecate(
+            "old",
+            Deprecation::new()
+                .with_sunset("Sat, 01 Nov 2025 00:00:00 GMT")
+                .with_link("https://example.com/changelog"),
+        )
+    }
+
+    #[tokio::test]
+    async fn headers() {
+        let cli = TestClient::new(
+            Route::new()
+                .nest("/", OpenApiService::new(Api, "test", "1"))
+                .with(deprecations()),
+        );
+
+        let resp = cli.get("/old").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_header("deprecation", "true");
+        resp.assert_header("sunset", "Sat, 01 Nov 2025 00:00:00 GMT");
+        resp.assert_header(
+            "link",
+            "<https://example.com/changelog>; rel=\"deprecation\"",
+        );
+
+        let resp = cli.get("/new").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_header_is_not_exist("deprecation");
+        resp.assert_header_is_not_exist("sunset");
+    }
+
+    #[test]
+    fn spec() {
+        let spec = deprecations().spec(&OpenApiService::new(Api, "test", "1").spec());
+        let spec: Value = serde_json::from_str(&spec).unwrap();
+        assert_eq!(spec["paths"]["/old"]["get"]["deprecated"], true);
+        assert_eq!(
+            spec["paths"]["/old"]["get"][SUNSET_EXTENSION],
+            "Sat, 01 Nov 2025 00:00:00 GMT"
+        );
+        assert!(spec["paths"]["/new"]["get"].get("deprecated").is_none());
+        assert!(spec["paths"]["/new"]["get"].get(SUNSET_EXTENSION).is_none());
+    }
+}
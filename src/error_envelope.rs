@@ -0,0 +1,240 @@
+//! Contains a middleware that converts unhandled errors into the JSON error
+//! format of this crate.
+//!
+//! Errors that are returned by middlewares or by poem itself (e.g. failed
+//! authorization checks of other layers, errors while reading the request
+//! body or `404 Not Found` for unknown paths) are usually turned into
+//! `text/plain` responses. The [`JsonErrorMiddleware`] converts them into the
+//! same format that is used by the responses of this crate, so clients of a
+//! JSON API never have to handle plain text errors:
+//!
+//! - `400 Bad Request` becomes `422 Unprocessable Content` with the error
+//!   message as `reason` (like in [`Response`](crate::responses::Response)):
+//!   `{"error": "unprocessable_content", "reason": "..."}`
+//! - `500 Internal Server Error` is logged with a new error id (see
+//!   [`internal_server_error`]):
+//!   `{"error": "internal_server_error", "error_id": "..."}`
+//! - all other statuses contain the snake case reason phrase of the status
+//!   (like the responses defined using [`response!`](crate::response!)):
+//!   `{"error": "method_not_allowed"}`
+//!
+//! The headers of the original error (e.g. `Allow` or `WWW-Authenticate`) are
+//! preserved. Errors that already have a JSON body (e.g. the error responses
+//! of this crate) and successful responses are passed through unchanged.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::error_envelope::JsonErrorMiddleware;
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .with(JsonErrorMiddleware::new());
+//! ```
+
+use poem::{
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        StatusCode,
+    },
+    Endpoint, IntoResponse, Middleware, Request, Response,
+};
+use serde_json::json;
+
+use crate::responses::internal_server_error;
+
+/// A middleware that converts unhandled errors into the JSON error format of
+/// this crate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonErrorMiddleware;
+
+impl JsonErrorMiddleware {
+    /// Create a new JsonErrorMiddleware.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for JsonErrorMiddleware {
+    type Output = JsonErrorMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        JsonErrorMwEndpoint { inner: ep }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct JsonErrorMwEndpoint<E> {
+    inner: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for JsonErrorMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        match self.inner.call(req).await {
+            Ok(resp) => Ok(resp.into_response()),
+            Err(err) => Ok(convert_error(err)),
+        }
+    }
+}
+
+/// Convert an error into a response in the JSON error format.
+fn convert_error(err: poem::Error) -> Response {
+    let status = err.status();
+    let message = err.to_string();
+    let resp = err.into_response();
+    let is_json = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.contains("json"));
+    if is_json || !(status.is_client_error() || status.is_server_error()) {
+        return resp;
+    }
+
+    let mut converted = match status {
+        StatusCode::BAD_REQUEST => Response::builder()
+            .status(StatusCode::UNPROCESSABLE_ENTITY)
+            .content_type("application/json; charset=utf-8")
+            .body(json!({"error": "unprocessable_content", "reason": message}).to_string()),
+        StatusCode::INTERNAL_SERVER_ERROR => internal_server_error(message).into_response(),
+        _ => Response::builder()
+            .status(status)
+            .content_type("application/json; charset=utf-8")
+            .body(json!({"error": error_code(status)}).to_string()),
+    };
+    for (name, value) in resp.headers() {
+        if name != CONTENT_TYPE && name != CONTENT_LENGTH {
+            converted.headers_mut().append(name, value.clone());
+        }
+    }
+    converted
+}
+
+/// Return the snake case reason phrase of a status (e.g. `not_found`).
+fn error_code(status: StatusCode) -> String {
+    let Some(reason) = status.canonical_reason() else {
+        return "error".into();
+    };
+    reason
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(|word| word.replace('\'', "").to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{
+        get, handler, http::header::WWW_AUTHENTICATE, test::TestClient, EndpointExt, Route,
+    };
+
+    use super::*;
+    use crate::responses::TooManyRequests;
+
+    #[handler]
+    fn ok() -> &'static str {
+        "ok"
+    }
+
+    /// Return an endpoint that always fails with the given error.
+    fn fail(err: fn() -> poem::Error) -> impl Endpoint {
+        ok.around(move |_, _| async move { Err::<Response, _>(err()) })
+    }
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(error_code(StatusCode::NOT_FOUND), "not_found");
+        assert_eq!(error_code(StatusCode::IM_A_TEAPOT), "im_a_teapot");
+        assert_eq!(
+            error_code(StatusCode::NON_AUTHORITATIVE_INFORMATION),
+            "non_authoritative_information"
+        );
+        assert_eq!(error_code(StatusCode::from_u16(599).unwrap()), "error");
+    }
+
+    #[tokio::test]
+    async fn json_errors() {
+        let cli = TestClient::new(
+            Route::new()
+                .at("/ok", get(ok))
+                .at(
+                    "/bad_request",
+                    fail(|| poem::Error::from_string("invalid body", StatusCode::BAD_REQUEST)),
+                )
+                .at(
+                    "/internal",
+                    fail(|| {
+                        poem::Error::from_string(
+                            "connection refused",
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    }),
+                )
+                .at(
+                    "/too_many_requests",
+                    fail(|| TooManyRequests::raw::too_many_requests().into()),
+                )
+                .at(
+                    "/unauthorized",
+                    fail(|| {
+                        poem::Error::from_response(
+                            Response::builder()
+                                .status(StatusCode::UNAUTHORIZED)
+                                .header(WWW_AUTHENTICATE, "Bearer")
+                                .body("unauthorized"),
+                        )
+                    }),
+                )
+                .with(JsonErrorMiddleware::new()),
+        );
+
+        cli.get("/ok").send().await.assert_text("ok").await;
+
+        let resp = cli.get("/bad_request").send().await;
+        resp.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        resp.assert_content_type("application/json; charset=utf-8");
+        resp.assert_text(r#"{"error":"unprocessable_content","reason":"invalid body"}"#)
+            .await;
+
+        let resp = cli.get("/internal").send().await;
+        resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = resp.json().await;
+        let body = body.value().object();
+        body.get("error").assert_string("internal_server_error");
+        body.get("error_id").string();
+
+        let resp = cli.get("/unauthorized").send().await;
+        resp.assert_status(StatusCode::UNAUTHORIZED);
+        resp.assert_header(WWW_AUTHENTICATE, "Bearer");
+        resp.assert_text(r#"{"error":"unauthorized"}"#).await;
+
+        let resp = cli.get("/too_many_requests").send().await;
+        resp.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        resp.assert_text(r#"{"error":"too_many_requests"}"#).await;
+
+        let resp = cli.get("/missing").send().await;
+        resp.assert_status(StatusCode::NOT_FOUND);
+        resp.assert_text(r#"{"error":"not_found"}"#).await;
+
+        let resp = cli.post("/ok").send().await;
+        resp.assert_status(StatusCode::METHOD_NOT_ALLOWED);
+        resp.assert_text(r#"{"error":"method_not_allowed"}"#).await;
+    }
+}
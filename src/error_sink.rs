@@ -0,0 +1,156 @@
+//! Contains the sink that receives the diagnostics of internal server errors.
+//!
+//! [`internal_server_error`](crate::responses::internal_server_error) and the
+//! [`PanicHandler`](crate::panic_handler::PanicHandler) report the underlying
+//! error (together with the error id that is included in the response) to the
+//! global [`ErrorSink`]. By default, errors are logged using the
+//! [`tracing`](https://docs.rs/tracing) crate ([`TracingErrorSink`]) if the
+//! `tracing` feature is enabled, or written to stderr ([`StderrErrorSink`])
+//! otherwise. Applications that use a different logging library (e.g. `log`
+//! or `slog`) can install their own sink at startup using [`set_error_sink`].
+//!
+//! #### Example
+//! ```
+//! use std::fmt::Display;
+//!
+//! use poem_ext::error_sink::set_error_sink;
+//!
+//! set_error_sink(|error_id: &str, error: &dyn Display| {
+//!     eprintln!("internal server error {error_id}: {error}");
+//! });
+//! ```
+
+use std::{
+    fmt::Display,
+    sync::{Arc, RwLock},
+};
+
+use crate::panic_handler::PanicInfo;
+
+static SINK: RwLock<Option<Arc<dyn ErrorSink>>> = RwLock::new(None);
+
+/// A receiver of internal server error diagnostics.
+pub trait ErrorSink: Send + Sync + 'static {
+    /// Report an error that has resulted in an internal server error response
+    /// with the given error id.
+    fn internal_error(&self, error_id: &str, error: &dyn Display);
+
+    /// Report a panic that has been caught by the
+    /// [`PanicHandler`](crate::panic_handler::PanicHandler).
+    ///
+    /// By default, the panic message is reported using
+    /// [`internal_error`](Self::internal_error).
+    fn panic(&self, info: &PanicInfo) {
+        let message = info.message().unwrap_or("Box<dyn Any>");
+        self.internal_error(
+            &info.error_id,
+            &format_args!(
+                "endpoint panicked: {message} ({} {})",
+                info.method,
+                info.path()
+            ),
+        );
+    }
+}
+
+impl<F> ErrorSink for F
+where
+    F: Fn(&str, &dyn Display) + Send + Sync + 'static,
+{
+    fn internal_error(&self, error_id: &str, error: &dyn Display) {
+        self(error_id, error)
+    }
+}
+
+/// An [`ErrorSink`] that logs errors using the
+/// [`tracing`](https://docs.rs/tracing) crate.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingErrorSink;
+
+#[cfg(feature = "tracing")]
+impl ErrorSink for TracingErrorSink {
+    fn internal_error(&self, error_id: &str, error: &dyn Display) {
+        tracing::error!(error_id, "{error}");
+    }
+
+    fn panic(&self, info: &PanicInfo) {
+        tracing::error!(
+            error_id = info.error_id,
+            method = %info.method,
+            path = info.path(),
+            request_id = info.request_id.as_deref(),
+            identity = info.identity,
+            message = info.message().unwrap_or("Box<dyn Any>"),
+            backtrace = info.backtrace.as_deref().map(tracing::field::display),
+            "endpoint panicked"
+        );
+    }
+}
+
+/// An [`ErrorSink`] that writes errors to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrErrorSink;
+
+impl ErrorSink for StderrErrorSink {
+    fn internal_error(&self, error_id: &str, error: &dyn Display) {
+        eprintln!("internal server error (error_id={error_id}): {error}");
+    }
+}
+
+/// Replace the global [`ErrorSink`].
+///
+/// This should be called once at application startup, before any requests are
+/// handled.
+pub fn set_error_sink(sink: impl ErrorSink) {
+    *SINK.write().unwrap() = Some(Arc::new(sink));
+}
+
+/// Return the global [`ErrorSink`].
+pub fn error_sink() -> Arc<dyn ErrorSink> {
+    if let Some(sink) = &*SINK.read().unwrap() {
+        return sink.clone();
+    }
+    #[cfg(feature = "tracing")]
+    return Arc::new(TracingErrorSink);
+    #[cfg(not(feature = "tracing"))]
+    return Arc::new(StderrErrorSink);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{panic_handler::PanicHandler, responses::internal_server_error};
+
+    #[test]
+    fn custom_sink() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        set_error_sink({
+            let reported = reported.clone();
+            move |error_id: &str, error: &dyn Display| {
+                reported
+                    .lock()
+                    .unwrap()
+                    .push((error_id.to_owned(), error.to_string()));
+            }
+        });
+
+        let error = uuid::Uuid::new_v4().to_string();
+        internal_server_error(&error);
+        assert!(reported
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(error_id, x)| *x == error && !error_id.is_empty()));
+
+        // panics caught by the CatchPanic middleware
+        poem::middleware::PanicHandler::get_response(&PanicHandler, Box::new(error.clone()));
+        assert!(reported.lock().unwrap().iter().any(|(error_id, x)| *x
+            == format!("endpoint panicked: {error}")
+            && !error_id.is_empty()));
+
+        *SINK.write().unwrap() = None;
+    }
+}
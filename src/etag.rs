@@ -0,0 +1,485 @@
+//! Support for conditional requests using `ETag`, `If-None-Match` and
+//! `If-Match` headers.
+//!
+//! Endpoints can either return a [`Cached`] response with an [`ETag`] derived
+//! from e.g. the version of the resource, or the [`ETagMiddleware`] can be
+//! used to compute the `ETag` from the response body. In both cases a
+//! `304 Not Modified` response is returned if the `If-None-Match` header of
+//! the request matches the `ETag`.
+//!
+//! #### Example
+//! ```
+//! use poem_ext::{
+//!     etag::{Cached, ETag},
+//!     responses::Response,
+//! };
+//! use poem_openapi::{param::Header, payload::Json, Object, OpenApi};
+//!
+//! #[derive(Debug, Object)]
+//! struct User {
+//!     name: String,
+//!     version: u64,
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/user", method = "get")]
+//!     async fn get_user(
+//!         &self,
+//!         #[oai(name = "If-None-Match")] if_none_match: Header<Option<String>>,
+//!     ) -> Response<Cached<Json<User>>> {
+//!         let user = User {
+//!             name: "test".into(),
+//!             version: 42,
+//!         };
+//!         let etag = ETag::from_version(user.version);
+//!         Ok(Cached::new(if_none_match.as_deref(), etag, Json(user)).into())
+//!     }
+//! }
+//! ```
+//!
+//! For optimistic concurrency control, write endpoints can compare the
+//! `If-Match` header with the current version of the resource using
+//! [`ETag::check_if_match`]. The [`RequireIfMatchMiddleware`] can be used to
+//! reject unsafe requests without an `If-Match` header early.
+//!
+//! #### Example
+//! ```
+//! use poem_ext::{etag::ETag, response, responses::Precondition};
+//! use poem_openapi::{param::Header, payload::Json, Object, OpenApi};
+//!
+//! #[derive(Debug, Object)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/user", method = "put")]
+//!     async fn update_user(
+//!         &self,
+//!         #[oai(name = "If-Match")] if_match: Header<Option<String>>,
+//!         data: Json<User>,
+//!     ) -> Result<UpdateUser::raw::Response, Precondition::raw::Response> {
+//!         let current_version = 42;
+//!         ETag::from_version(current_version).check_if_match(if_match.as_deref())?;
+//!         // update the user
+//!         Ok(UpdateUser::raw::ok())
+//!     }
+//! }
+//!
+//! response!(UpdateUser = {
+//!     /// User has been updated
+//!     Ok(200),
+//! });
+//! ```
+
+use std::fmt::Display;
+
+use poem::{
+    http::{
+        header::{ETAG, IF_MATCH, IF_NONE_MATCH},
+        Method, StatusCode,
+    },
+    Endpoint, IntoResponse, Middleware, Request, Response,
+};
+use poem_openapi::{
+    registry::{MetaHeader, MetaResponse, MetaResponses, Registry},
+    types::Type,
+    ApiResponse,
+};
+use sha2::{Digest, Sha256};
+
+use crate::responses::Precondition;
+
+/// A strong entity tag that identifies a specific version of a resource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ETag(String);
+
+impl ETag {
+    /// Create an ETag from a version identifier of the resource (e.g. a
+    /// revision number or a timestamp of the last modification).
+    pub fn from_version(version: impl Display) -> Self {
+        Self(format!("\"{version}\""))
+    }
+
+    /// Create an ETag from the hash of the given content.
+    pub fn from_content(content: impl AsRef<[u8]>) -> Self {
+        let hash = Sha256::digest(content.as_ref());
+        Self::from_version(
+            hash[..16]
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<String>(),
+        )
+    }
+
+    /// Return the value of the `ETag` header.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Check whether the value of an `If-None-Match` header matches this
+    /// ETag.
+    ///
+    /// As recommended for `If-None-Match`, the weak comparison function is
+    /// used, i.e. weak ETags (`W/"..."`) match their strong counterparts.
+    pub fn matches(&self, if_none_match: &str) -> bool {
+        let if_none_match = if_none_match.trim();
+        if_none_match == "*"
+            || if_none_match
+                .split(',')
+                .map(|tag| strip_weak(tag.trim()))
+                .any(|tag| tag == strip_weak(&self.0))
+    }
+
+    /// Check the value of an `If-Match` header against this ETag, which should
+    /// identify the current version of the resource.
+    ///
+    /// Returns a `412 Precondition Failed` response if the header does not
+    /// match and a `428 Precondition Required` response if it is missing. As
+    /// required for `If-Match`, the strong comparison function is used, i.e.
+    /// weak ETags never match.
+    pub fn check_if_match(
+        &self,
+        if_match: Option<&str>,
+    ) -> Result<(), Precondition::raw::Response> {
+        let Some(if_match) = if_match else {
+            return Err(Precondition::raw::precondition_required());
+        };
+        let if_match = if_match.trim();
+        if if_match == "*"
+            || (!self.0.starts_with("W/") && if_match.split(',').any(|tag| tag.trim() == self.0))
+        {
+            Ok(())
+        } else {
+            Err(Precondition::raw::precondition_failed())
+        }
+    }
+}
+
+impl Display for ETag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+fn strip_weak(etag: &str) -> &str {
+    etag.strip_prefix("W/").unwrap_or(etag)
+}
+
+/// Response wrapper that adds an `ETag` header to the response or returns
+/// `304 Not Modified` if the client already has the current version of the
+/// resource.
+///
+/// Both the `ETag` header and the `304` response are added to the OpenAPI
+/// documentation of the endpoint.
+#[derive(Debug)]
+pub enum Cached<T> {
+    /// The client does not have the current version of the resource.
+    Modified {
+        /// The current ETag of the resource.
+        etag: ETag,
+        /// The response to return.
+        value: T,
+    },
+    /// The client already has the current version of the resource.
+    NotModified {
+        /// The current ETag of the resource.
+        etag: ETag,
+    },
+}
+
+impl<T> Cached<T> {
+    /// Create a new response, which is `304 Not Modified` if the value of the
+    /// `If-None-Match` header matches the given `etag`.
+    pub fn new(if_none_match: Option<&str>, etag: ETag, value: T) -> Self {
+        Self::new_with(if_none_match, etag, || value)
+    }
+
+    /// Create a new response like [`Cached::new`], but only construct the
+    /// actual response if it is needed.
+    pub fn new_with(if_none_match: Option<&str>, etag: ETag, value: impl FnOnce() -> T) -> Self {
+        match if_none_match {
+            Some(if_none_match) if etag.matches(if_none_match) => Self::NotModified { etag },
+            _ => Self::Modified {
+                etag,
+                value: value(),
+            },
+        }
+    }
+}
+
+fn etag_header() -> MetaHeader {
+    MetaHeader {
+        name: "ETag".into(),
+        description: Some("The entity tag of the current version of the resource.".into()),
+        required: true,
+        deprecated: false,
+        schema: String::schema_ref(),
+    }
+}
+
+impl<T: ApiResponse> ApiResponse for Cached<T> {
+    fn meta() -> MetaResponses {
+        let mut responses = T::meta().responses;
+        for response in &mut responses {
+            response.headers.push(etag_header());
+        }
+        responses.push(MetaResponse {
+            description: "Not Modified",
+            status: Some(304),
+            content: vec![],
+            headers: vec![etag_header()],
+        });
+        MetaResponses { responses }
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for Cached<T> {
+    fn into_response(self) -> Response {
+        let (mut resp, etag) = match self {
+            Cached::Modified { etag, value } => (value.into_response(), etag),
+            Cached::NotModified { etag } => (StatusCode::NOT_MODIFIED.into_response(), etag),
+        };
+        if let Ok(value) = etag.as_str().parse() {
+            resp.headers_mut().insert(ETAG, value);
+        }
+        resp
+    }
+}
+
+/// A middleware that adds an `ETag` header to successful responses of `GET`
+/// and `HEAD` requests and answers conditional requests with
+/// `304 Not Modified`.
+///
+/// If the response does not already contain an `ETag` header, it is computed
+/// from the hash of the response body. Note that this requires the whole body
+/// to be buffered, so this middleware should not be used for endpoints with
+/// streaming responses.
+///
+/// #### Example
+/// ```
+/// use poem::{EndpointExt, Route};
+/// use poem_ext::etag::ETagMiddleware;
+///
+/// # let api_service: Route = Route::new();
+/// let app = Route::new()
+///     .nest("/", api_service)
+///     .with(ETagMiddleware::new());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ETagMiddleware;
+
+impl ETagMiddleware {
+    /// Create a new ETagMiddleware.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for ETagMiddleware {
+    type Output = ETagMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ETagMwEndpoint { inner: ep }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ETagMwEndpoint<E> {
+    inner: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for ETagMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let conditional = matches!(*req.method(), Method::GET | Method::HEAD);
+        let if_none_match = req.header(IF_NONE_MATCH).map(ToOwned::to_owned);
+        let mut resp = self.inner.call(req).await?.into_response();
+        if !conditional || resp.status() != StatusCode::OK {
+            return Ok(resp);
+        }
+
+        let etag = match resp.headers().get(ETAG).and_then(|v| v.to_str().ok()) {
+            Some(etag) => ETag(etag.to_owned()),
+            None => {
+                let body = resp.take_body().into_bytes().await?;
+                let etag = ETag::from_content(&body);
+                resp.set_body(body);
+                if let Ok(value) = etag.as_str().parse() {
+                    resp.headers_mut().insert(ETAG, value);
+                }
+                etag
+            }
+        };
+
+        match if_none_match {
+            Some(if_none_match) if etag.matches(&if_none_match) => {
+                Ok(Cached::<()>::NotModified { etag }.into_response())
+            }
+            _ => Ok(resp),
+        }
+    }
+}
+
+/// A middleware that rejects `PUT`, `PATCH` and `DELETE` requests without an
+/// `If-Match` header with `428 Precondition Required`.
+///
+/// The header still has to be checked against the current version of the
+/// resource in the endpoint (see [`ETag::check_if_match`]).
+///
+/// #### Example
+/// ```
+/// use poem::{EndpointExt, Route};
+/// use poem_ext::etag::RequireIfMatchMiddleware;
+///
+/// # let api_service: Route = Route::new();
+/// let app = Route::new()
+///     .nest("/", api_service)
+///     .with(RequireIfMatchMiddleware::new());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RequireIfMatchMiddleware;
+
+impl RequireIfMatchMiddleware {
+    /// Create a new RequireIfMatchMiddleware.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RequireIfMatchMiddleware {
+    type Output = RequireIfMatchMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RequireIfMatchMwEndpoint { inner: ep }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct RequireIfMatchMwEndpoint<E> {
+    inner: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for RequireIfMatchMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        if matches!(*req.method(), Method::PUT | Method::PATCH | Method::DELETE)
+            && req.header(IF_MATCH).is_none()
+        {
+            return Ok(Precondition::raw::precondition_required().into_response());
+        }
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, test::TestClient, EndpointExt};
+
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let etag = ETag::from_version(42);
+        assert_eq!(etag.as_str(), r#""42""#);
+        assert!(etag.matches(r#""42""#));
+        assert!(etag.matches(r#"W/"42""#));
+        assert!(etag.matches(r#""1", "42""#));
+        assert!(etag.matches("*"));
+        assert!(!etag.matches(r#""1""#));
+        assert!(!etag.matches("42"));
+    }
+
+    #[handler]
+    fn index() -> &'static str {
+        "Hello World!"
+    }
+
+    #[tokio::test]
+    async fn middleware() {
+        let cli = TestClient::new(index.with(ETagMiddleware::new()));
+        let etag = ETag::from_content("Hello World!");
+
+        let resp = cli.get("/").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_header(ETAG, etag.as_str());
+        resp.assert_text("Hello World!").await;
+
+        let resp = cli
+            .get("/")
+            .header(IF_NONE_MATCH, etag.as_str())
+            .send()
+            .await;
+        resp.assert_status(StatusCode::NOT_MODIFIED);
+        resp.assert_header(ETAG, etag.as_str());
+        resp.assert_text("").await;
+
+        let resp = cli.get("/").header(IF_NONE_MATCH, r#""foo""#).send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("Hello World!").await;
+
+        let resp = cli
+            .post("/")
+            .header(IF_NONE_MATCH, etag.as_str())
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_header_is_not_exist(ETAG);
+    }
+
+    #[test]
+    fn check_if_match() {
+        let etag = ETag::from_version(42);
+        assert!(etag.check_if_match(Some(r#""42""#)).is_ok());
+        assert!(etag.check_if_match(Some(r#""1", "42""#)).is_ok());
+        assert!(etag.check_if_match(Some("*")).is_ok());
+        assert!(matches!(
+            etag.check_if_match(Some(r#"W/"42""#)),
+            Err(Precondition::raw::Response::PreconditionFailed(_))
+        ));
+        assert!(matches!(
+            etag.check_if_match(None),
+            Err(Precondition::raw::Response::PreconditionRequired(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn require_if_match() {
+        let cli = TestClient::new(index.with(RequireIfMatchMiddleware::new()));
+
+        let resp = cli.put("/").send().await;
+        resp.assert_status(StatusCode::PRECONDITION_REQUIRED);
+        resp.assert_text(r#"{"error":"precondition_required"}"#)
+            .await;
+
+        let resp = cli.put("/").header(IF_MATCH, r#""42""#).send().await;
+        resp.assert_status_is_ok();
+
+        let resp = cli.get("/").send().await;
+        resp.assert_status_is_ok();
+    }
+
+    #[test]
+    fn cached_meta() {
+        let responses = Cached::<()>::meta().responses;
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].headers[0].name, "ETag");
+        assert_eq!(responses[1].status, Some(304));
+    }
+}
@@ -0,0 +1,204 @@
+//! Contains an extractor that guards endpoints behind feature flags.
+//!
+//! Whether a feature flag is enabled is decided by a [`FeatureFlagProvider`]
+//! that has to be attached to the endpoint using
+//! [`data`](poem::EndpointExt::data) (wrapped in [`FeatureFlags`]). The
+//! provider gets access to the request, so flags can be enabled only for some
+//! callers (e.g. based on the [`Identity`](crate::identity::Identity)). If no
+//! provider is present, all flags are disabled.
+//!
+//! #### Example
+//! ```
+//! use std::collections::HashSet;
+//!
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     add_response_schemas,
+//!     feature_flag::{FeatureFlag, FeatureFlags, FeatureGate},
+//!     responses::{FeatureDisabled, Response},
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct NewCheckout;
+//! impl FeatureFlag for NewCheckout {
+//!     const NAME: &'static str = "new_checkout";
+//! }
+//!
+//! /// Marker type used to document the 404 response.
+//! struct Gated;
+//! add_response_schemas!(Gated, FeatureDisabled::raw::Response);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/checkout", method = "post")]
+//!     async fn checkout(
+//!         &self,
+//!         _gate: FeatureGate<NewCheckout>,
+//!     ) -> Response<PlainText<&'static str>, Gated> {
+//!         Ok(PlainText("Hello World!").into())
+//!     }
+//! }
+//!
+//! let enabled: HashSet<String> = ["new_checkout".into()].into();
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .data(FeatureFlags::new(enabled));
+//! ```
+
+use std::{collections::HashSet, fmt::Debug, marker::PhantomData, sync::Arc};
+
+use poem::{FromRequest, Request, RequestBody};
+
+use crate::responses::FeatureDisabled;
+
+/// A feature flag that can be used with [`FeatureGate`].
+pub trait FeatureFlag: Send + Sync + 'static {
+    /// The name of the feature flag.
+    const NAME: &'static str;
+
+    /// Respond with `403 Forbidden` instead of `404 Not Found` if the feature
+    /// flag is disabled (default: `false`).
+    const FORBIDDEN: bool = false;
+}
+
+/// Decides whether feature flags are enabled.
+#[poem::async_trait]
+pub trait FeatureFlagProvider: Send + Sync + 'static {
+    /// Return `true` if the feature flag with the given name is enabled for
+    /// the given request.
+    async fn is_enabled(&self, flag: &str, req: &Request) -> bool;
+}
+
+/// Enables all feature flags contained in the set.
+#[poem::async_trait]
+impl FeatureFlagProvider for HashSet<String> {
+    async fn is_enabled(&self, flag: &str, _req: &Request) -> bool {
+        self.contains(flag)
+    }
+}
+
+/// Request data that contains the [`FeatureFlagProvider`] used by
+/// [`FeatureGate`].
+#[derive(Clone)]
+pub struct FeatureFlags(Arc<dyn FeatureFlagProvider>);
+
+impl Debug for FeatureFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FeatureFlags").finish_non_exhaustive()
+    }
+}
+
+impl FeatureFlags {
+    /// Create a new FeatureFlags instance that uses the given provider.
+    pub fn new(provider: impl FeatureFlagProvider) -> Self {
+        Self(Arc::new(provider))
+    }
+
+    /// Return `true` if the feature flag with the given name is enabled for
+    /// the given request.
+    pub async fn is_enabled(&self, flag: &str, req: &Request) -> bool {
+        self.0.is_enabled(flag, req).await
+    }
+}
+
+/// Extractor that rejects requests with a [`FeatureDisabled`] response if the
+/// feature flag `F` is disabled for the caller.
+pub struct FeatureGate<F>(PhantomData<F>);
+
+impl<F> Debug for FeatureGate<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FeatureGate").finish()
+    }
+}
+
+#[poem::async_trait]
+impl<'a, F: FeatureFlag> FromRequest<'a> for FeatureGate<F> {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        let enabled = match req.data::<FeatureFlags>() {
+            Some(flags) => flags.is_enabled(F::NAME, req).await,
+            None => false,
+        };
+        match (enabled, F::FORBIDDEN) {
+            (true, _) => Ok(Self(PhantomData)),
+            (false, false) => Err(FeatureDisabled::raw::not_found().into()),
+            (false, true) => Err(FeatureDisabled::raw::forbidden().into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{http::StatusCode, test::TestClient, EndpointExt, Route};
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+
+    struct Enabled;
+    impl FeatureFlag for Enabled {
+        const NAME: &'static str = "enabled";
+    }
+
+    struct Disabled;
+    impl FeatureFlag for Disabled {
+        const NAME: &'static str = "disabled";
+    }
+
+    struct Forbidden;
+    impl FeatureFlag for Forbidden {
+        const NAME: &'static str = "forbidden";
+        const FORBIDDEN: bool = true;
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/enabled", method = "get")]
+        async fn enabled(&self, _gate: FeatureGate<Enabled>) -> PlainText<&'static str> {
+            PlainText("ok")
+        }
+
+        #[oai(path = "/disabled", method = "get")]
+        async fn disabled(&self, _gate: FeatureGate<Disabled>) -> PlainText<&'static str> {
+            PlainText("ok")
+        }
+
+        #[oai(path = "/forbidden", method = "get")]
+        async fn forbidden(&self, _gate: FeatureGate<Forbidden>) -> PlainText<&'static str> {
+            PlainText("ok")
+        }
+    }
+
+    #[tokio::test]
+    async fn feature_gate() {
+        let cli = TestClient::new(
+            Route::new()
+                .nest("/", OpenApiService::new(Api, "test", "1"))
+                .data(FeatureFlags::new(HashSet::from(["enabled".to_owned()]))),
+        );
+
+        let resp = cli.get("/enabled").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("ok").await;
+
+        let resp = cli.get("/disabled").send().await;
+        resp.assert_status(StatusCode::NOT_FOUND);
+        resp.assert_text(r#"{"error":"not_found"}"#).await;
+
+        let resp = cli.get("/forbidden").send().await;
+        resp.assert_status(StatusCode::FORBIDDEN);
+        resp.assert_text(r#"{"error":"forbidden"}"#).await;
+    }
+
+    #[tokio::test]
+    async fn missing_provider() {
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+        cli.get("/enabled")
+            .send()
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+    }
+}
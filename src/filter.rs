@@ -0,0 +1,474 @@
+//! Contains an extractor for filter query parameters.
+//!
+//! Filters are specified as query parameters of the form
+//! `filter[<field>][<operator>]=<value>` (e.g. `?filter[age][gte]=18`). If the
+//! operator is omitted (e.g. `?filter[status]=active`), `eq` is used. The
+//! following operators are supported:
+//!
+//! | Operator   | Meaning                                    |
+//! |------------|--------------------------------------------|
+//! | `eq`       | equal to the value                         |
+//! | `ne`       | not equal to the value                     |
+//! | `gt`       | greater than the value                     |
+//! | `gte`      | greater than or equal to the value         |
+//! | `lt`       | less than the value                        |
+//! | `lte`      | less than or equal to the value            |
+//! | `in`       | equal to one of the comma separated values |
+//! | `contains` | contains the value as a substring          |
+//!
+//! #### Example
+//! ```
+//! use poem_ext::{
+//!     filter::{Filter, FilterField, FilterOp},
+//!     responses::Response,
+//!     static_enum,
+//! };
+//! use poem_openapi::{payload::Json, OpenApi};
+//!
+//! static_enum!(UserField {
+//!     Status = "status",
+//!     Age = "age",
+//! });
+//!
+//! impl FilterField for UserField {
+//!     fn operators(&self) -> &'static [FilterOp] {
+//!         match self {
+//!             Self::Status => &[FilterOp::Eq, FilterOp::Ne, FilterOp::In],
+//!             Self::Age => &[FilterOp::Eq, FilterOp::Gt, FilterOp::Gte, FilterOp::Lt, FilterOp::Lte],
+//!         }
+//!     }
+//!
+//!     fn validate_value(&self, value: &str) -> Result<(), String> {
+//!         match self {
+//!             Self::Status => Ok(()),
+//!             Self::Age => value.parse::<u32>().map(|_| ()).map_err(|err| err.to_string()),
+//!         }
+//!     }
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     /// Return a list of users.
+//!     ///
+//!     /// Use e.g. `?filter[status]=active&filter[age][gte]=18` to filter the
+//!     /// users.
+//!     #[oai(path = "/users", method = "get")]
+//!     async fn list_users(&self, filter: Filter<UserField>) -> Response<Json<Vec<String>>> {
+//!         for expr in &filter.0 {
+//!             // ...
+//!         }
+//!         todo!()
+//!     }
+//! }
+//! ```
+
+use std::fmt::Display;
+
+use poem::{Request, RequestBody};
+use poem_openapi::{
+    error::ParseParamError,
+    registry::{MetaParamIn, MetaSchema, MetaSchemaRef, Registry},
+    types::{ParseFromParameter, ParseResult, Type},
+    ApiExtractor, ApiExtractorType, ExtractParamOptions,
+};
+
+/// A filter operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FilterOp {
+    /// Equal to the value (`eq`).
+    Eq,
+    /// Not equal to the value (`ne`).
+    Ne,
+    /// Greater than the value (`gt`).
+    Gt,
+    /// Greater than or equal to the value (`gte`).
+    Gte,
+    /// Less than the value (`lt`).
+    Lt,
+    /// Less than or equal to the value (`lte`).
+    Lte,
+    /// Equal to one of the comma separated values (`in`).
+    In,
+    /// Contains the value as a substring (`contains`).
+    Contains,
+}
+
+impl FilterOp {
+    /// All filter operators.
+    pub const ALL: &'static [Self] = &[
+        Self::Eq,
+        Self::Ne,
+        Self::Gt,
+        Self::Gte,
+        Self::Lt,
+        Self::Lte,
+        Self::In,
+        Self::Contains,
+    ];
+
+    /// Return the name of this operator as used in query parameters.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Gt => "gt",
+            Self::Gte => "gte",
+            Self::Lt => "lt",
+            Self::Lte => "lte",
+            Self::In => "in",
+            Self::Contains => "contains",
+        }
+    }
+}
+
+impl Display for FilterOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Trait for types that define the fields a [`Filter`] can refer to.
+///
+/// This is usually implemented for an enum created using e.g. the
+/// [`static_enum!`](crate::static_enum!) macro or the
+/// [`Enum`](derive@poem_openapi::Enum) derive macro.
+pub trait FilterField: Type + ParseFromParameter {
+    /// Return the operators that are allowed for this field (default: all
+    /// operators).
+    fn operators(&self) -> &'static [FilterOp] {
+        FilterOp::ALL
+    }
+
+    /// Check whether a value is valid for this field (default: all values
+    /// are valid). For the `in` operator, this function is called for each of
+    /// the comma separated values.
+    #[allow(unused_variables)]
+    fn validate_value(&self, value: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A single filter expression, e.g. `filter[age][gte]=18`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterExpr<F> {
+    /// The field to filter by.
+    pub field: F,
+    /// The filter operator.
+    pub op: FilterOp,
+    /// The raw value of the filter. For the `in` operator, this contains a
+    /// comma separated list of values (see [`FilterExpr::values`]).
+    pub value: String,
+}
+
+impl<F> FilterExpr<F> {
+    /// Return the individual values of this filter, i.e. the comma separated
+    /// values for the `in` operator and the value itself for all other
+    /// operators.
+    pub fn values(&self) -> Vec<&str> {
+        match self.op {
+            FilterOp::In => self.value.split(',').collect(),
+            _ => vec![&self.value],
+        }
+    }
+
+    /// Parse the value of this filter.
+    pub fn parse_value<T: ParseFromParameter>(&self) -> ParseResult<T> {
+        T::parse_from_parameter(&self.value)
+    }
+}
+
+/// Extractor for filter query parameters of the form
+/// `filter[<field>][<operator>]=<value>` (see the [module level
+/// documentation](self)).
+///
+/// The name of the parameter is taken from the name of the endpoint argument.
+/// Unknown fields, operators that are not allowed for a field and invalid
+/// values result in a `400 Bad Request` response (or a
+/// `422 Unprocessable Content` response if the endpoint uses the
+/// [`Response`](crate::responses::Response) type).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter<F>(pub Vec<FilterExpr<F>>);
+
+impl<F> Default for Filter<F> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<F: FilterField> Filter<F> {
+    fn parse<'a>(
+        name: &str,
+        params: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self, String> {
+        let mut exprs = Vec::new();
+        for (key, value) in params {
+            let Some(key) = key.strip_prefix(name).and_then(|key| key.strip_prefix('[')) else {
+                continue;
+            };
+            let (field_name, op_name) = match key.split_once("][") {
+                Some((field, op)) => (field, op.strip_suffix(']')),
+                None => (key.strip_suffix(']').unwrap_or(key), Some("eq")),
+            };
+            let field = F::parse_from_parameter(field_name).map_err(|err| {
+                format!(
+                    "invalid filter field {field_name:?}: {}",
+                    err.into_message()
+                )
+            })?;
+            let op = op_name
+                .and_then(|op| FilterOp::ALL.iter().find(|o| o.as_str() == op))
+                .filter(|op| field.operators().contains(op))
+                .copied()
+                .ok_or_else(|| {
+                    format!(
+                        "invalid filter operator {:?} for field {field_name:?}, expected one of \
+                         {:?}",
+                        op_name.unwrap_or_default(),
+                        field
+                            .operators()
+                            .iter()
+                            .map(FilterOp::as_str)
+                            .collect::<Vec<_>>()
+                    )
+                })?;
+            let expr = FilterExpr {
+                field,
+                op,
+                value: value.to_owned(),
+            };
+            for value in expr.values() {
+                expr.field.validate_value(value).map_err(|err| {
+                    format!("invalid value {value:?} for filter field {field_name:?}: {err}")
+                })?;
+            }
+            exprs.push(expr);
+        }
+        Ok(Self(exprs))
+    }
+
+    /// Convert this filter into a sea-orm [`Condition`](sea_orm::Condition)
+    /// that requires all filter expressions to match.
+    ///
+    /// The `column` function maps each field and value to the column to
+    /// filter by and the value converted into a sea-orm
+    /// [`Value`](sea_orm::Value). For the `in` operator, it is called once for
+    /// each of the comma separated values. As values have already been
+    /// checked using [`FilterField::validate_value`], the conversion may
+    /// assume that the value is valid.
+    ///
+    /// #### Example
+    /// ```no_run
+    /// # use poem_ext::{filter::{Filter, FilterField}, static_enum};
+    /// # use sea_orm::{entity::prelude::*, EntityTrait, QueryFilter};
+    /// # mod user {
+    /// #     use sea_orm::entity::prelude::*;
+    /// #     #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    /// #     #[sea_orm(table_name = "user")]
+    /// #     pub struct Model {
+    /// #         #[sea_orm(primary_key)]
+    /// #         pub id: i32,
+    /// #         pub name: String,
+    /// #     }
+    /// #     #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    /// #     pub enum Relation {}
+    /// #     impl ActiveModelBehavior for ActiveModel {}
+    /// # }
+    /// static_enum!(UserField {
+    ///     Id = "id",
+    ///     Name = "name",
+    /// });
+    ///
+    /// impl FilterField for UserField {
+    ///     fn validate_value(&self, value: &str) -> Result<(), String> {
+    ///         match self {
+    ///             Self::Id => value.parse::<i32>().map(|_| ()).map_err(|e| e.to_string()),
+    ///             Self::Name => Ok(()),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # let filter: Filter<UserField> = todo!();
+    /// let query = user::Entity::find().filter(filter.condition(|field, value| match field {
+    ///     UserField::Id => (user::Column::Id, value.parse::<i32>().unwrap_or_default().into()),
+    ///     UserField::Name => (user::Column::Name, value.into()),
+    /// }));
+    /// ```
+    #[cfg(feature = "sea-orm")]
+    pub fn condition<C>(
+        &self,
+        column: impl Fn(&F, &str) -> (C, sea_orm::Value),
+    ) -> sea_orm::Condition
+    where
+        C: sea_orm::ColumnTrait,
+    {
+        self.0
+            .iter()
+            .fold(sea_orm::Condition::all(), |condition, expr| {
+                if expr.op == FilterOp::In {
+                    let mut values = expr.values().into_iter().map(|v| column(&expr.field, v));
+                    let Some((col, first)) = values.next() else {
+                        return condition;
+                    };
+                    return condition
+                        .add(col.is_in(std::iter::once(first).chain(values.map(|(_, v)| v))));
+                }
+                let (col, value) = column(&expr.field, &expr.value);
+                condition.add(match expr.op {
+                    FilterOp::Eq => col.eq(value),
+                    FilterOp::Ne => col.ne(value),
+                    FilterOp::Gt => col.gt(value),
+                    FilterOp::Gte => col.gte(value),
+                    FilterOp::Lt => col.lt(value),
+                    FilterOp::Lte => col.lte(value),
+                    FilterOp::Contains => col.contains(&expr.value),
+                    FilterOp::In => unreachable!(),
+                })
+            })
+    }
+}
+
+#[poem::async_trait]
+impl<'a, F: FilterField> ApiExtractor<'a> for Filter<F> {
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::Parameter];
+
+    type ParamType = Self;
+    type ParamRawType = Self;
+
+    fn register(registry: &mut Registry) {
+        F::register(registry);
+    }
+
+    fn param_in() -> Option<MetaParamIn> {
+        Some(MetaParamIn::Query)
+    }
+
+    fn param_schema_ref() -> Option<MetaSchemaRef> {
+        Some(MetaSchemaRef::Inline(Box::new(MetaSchema {
+            description: Some(
+                "Filter expressions of the form `filter[<field>][<operator>]=<value>`. If the \
+                 operator is omitted, `eq` is used. Supported operators: `eq`, `ne`, `gt`, \
+                 `gte`, `lt`, `lte`, `in` (comma separated values), `contains`.",
+            ),
+            additional_properties: Some(Box::new(String::schema_ref())),
+            ..MetaSchema::new("object")
+        })))
+    }
+
+    fn param_raw_type(&self) -> Option<&Self::ParamRawType> {
+        Some(self)
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        _body: &mut RequestBody,
+        param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let params = request.params::<Vec<(String, String)>>()?;
+        Self::parse(
+            param_opts.name,
+            params.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+        )
+        .map_err(|reason| {
+            ParseParamError {
+                name: param_opts.name,
+                reason,
+            }
+            .into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, Route};
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+    use crate::{responses::Response, static_enum};
+
+    static_enum!(Field {
+        Status = "status",
+        Age = "age",
+    });
+
+    impl FilterField for Field {
+        fn operators(&self) -> &'static [FilterOp] {
+            match self {
+                Self::Status => &[FilterOp::Eq, FilterOp::In],
+                Self::Age => &[FilterOp::Eq, FilterOp::Gte],
+            }
+        }
+
+        fn validate_value(&self, value: &str) -> Result<(), String> {
+            match self {
+                Self::Status => Ok(()),
+                Self::Age => value.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()),
+            }
+        }
+    }
+
+    fn parse(query: &[(&str, &str)]) -> Result<Vec<FilterExpr<Field>>, String> {
+        Filter::parse("filter", query.iter().copied()).map(|filter| filter.0)
+    }
+
+    #[test]
+    fn parse_filter() {
+        assert_eq!(
+            parse(&[
+                ("filter[status]", "active"),
+                ("filter[age][gte]", "18"),
+                ("other", "x"),
+                ("filter[status][in]", "a,b"),
+            ])
+            .unwrap(),
+            [
+                FilterExpr {
+                    field: Field::Status,
+                    op: FilterOp::Eq,
+                    value: "active".into()
+                },
+                FilterExpr {
+                    field: Field::Age,
+                    op: FilterOp::Gte,
+                    value: "18".into()
+                },
+                FilterExpr {
+                    field: Field::Status,
+                    op: FilterOp::In,
+                    value: "a,b".into()
+                },
+            ]
+        );
+        assert!(parse(&[("filter[name]", "x")]).is_err());
+        assert!(parse(&[("filter[status][gte]", "x")]).is_err());
+        assert!(parse(&[("filter[age][foo]", "1")]).is_err());
+        assert!(parse(&[("filter[age]", "x")]).is_err());
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test", method = "get")]
+        async fn test(&self, filter: Filter<Field>) -> Response<PlainText<String>> {
+            Ok(PlainText(filter.0.len().to_string()).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn extractor() {
+        let cli = TestClient::new(Route::new().nest("/", OpenApiService::new(Api, "test", "1")));
+
+        let resp = cli
+            .get("/test?filter[status]=active&filter[age][gte]=18")
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text("2").await;
+
+        let resp = cli.get("/test?filter[age][gte]=x").send().await;
+        resp.assert_status(poem::http::StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -0,0 +1,170 @@
+//! Contains liveness and readiness endpoints for load balancers and container
+//! orchestrators.
+//!
+//! [`Readiness`] is a shared flag that components of the application (e.g.
+//! the [`DbTransactionMiddleware`](crate::db::DbTransactionMiddleware) via
+//! [`with_readiness`](crate::db::DbTransactionMiddleware::with_readiness))
+//! use to report that they are currently unable to serve requests. The
+//! application is ready iff no component has reported a failure.
+//!
+//! [`HealthApi`] exposes `GET /health/live`, which always succeeds while the
+//! server is running, and `GET /health/ready`, which responds with
+//! `503 Service Unavailable` and the list of failing components if the
+//! application is not ready.
+//!
+//! #### Example
+//! ```
+//! use poem::Route;
+//! use poem_ext::health::{HealthApi, Readiness};
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! let readiness = Readiness::new();
+//! // e.g. while a cache is being warmed up
+//! readiness.set_unready("cache");
+//!
+//! let api_service = OpenApiService::new((Api, HealthApi::new(readiness)), "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service);
+//! ```
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use poem_openapi::{
+    payload::{Json, PlainText},
+    ApiResponse, Object, OpenApi,
+};
+
+/// Shared flag that tracks whether the application is ready to serve
+/// requests.
+#[derive(Debug, Clone, Default)]
+pub struct Readiness(Arc<Mutex<BTreeSet<String>>>);
+
+impl Readiness {
+    /// Create a new Readiness, which is initially ready.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report that the given component is unable to serve requests.
+    pub fn set_unready(&self, component: impl Into<String>) {
+        self.0.lock().unwrap().insert(component.into());
+    }
+
+    /// Report that the given component has recovered.
+    pub fn set_ready(&self, component: &str) {
+        self.0.lock().unwrap().remove(component);
+    }
+
+    /// Return `true` if no component has reported a failure.
+    pub fn is_ready(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    /// Return the names of the components that are unable to serve requests.
+    pub fn failing(&self) -> Vec<String> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// The readiness of the application.
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct ReadinessStatus {
+    /// Whether the application is ready to serve requests.
+    pub ready: bool,
+    /// The components that are unable to serve requests.
+    pub failing: Vec<String>,
+}
+
+/// Response of the readiness endpoint.
+#[derive(Debug, ApiResponse)]
+pub enum ReadinessResponse {
+    /// The application is ready to serve requests.
+    #[oai(status = 200)]
+    Ready(Json<ReadinessStatus>),
+    /// The application is not ready to serve requests.
+    #[oai(status = 503)]
+    NotReady(Json<ReadinessStatus>),
+}
+
+/// An API that exposes liveness and readiness endpoints.
+#[derive(Debug, Clone)]
+pub struct HealthApi(Readiness);
+
+impl HealthApi {
+    /// Create a new HealthApi that reports the given readiness.
+    pub fn new(readiness: Readiness) -> Self {
+        Self(readiness)
+    }
+}
+
+#[OpenApi]
+impl HealthApi {
+    /// Check whether the server is running.
+    #[oai(path = "/health/live", method = "get", operation_id = "health_live")]
+    async fn live(&self) -> PlainText<&'static str> {
+        PlainText("ok")
+    }
+
+    /// Check whether the application is ready to serve requests.
+    #[oai(path = "/health/ready", method = "get", operation_id = "health_ready")]
+    async fn ready(&self) -> ReadinessResponse {
+        let failing = self.0.failing();
+        let status = ReadinessStatus {
+            ready: failing.is_empty(),
+            failing,
+        };
+        if status.ready {
+            ReadinessResponse::Ready(Json(status))
+        } else {
+            ReadinessResponse::NotReady(Json(status))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{http::StatusCode, test::TestClient};
+    use poem_openapi::OpenApiService;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn health() {
+        let readiness = Readiness::new();
+        let cli = TestClient::new(OpenApiService::new(
+            HealthApi::new(readiness.clone()),
+            "test",
+            "1",
+        ));
+
+        cli.get("/health/live").send().await.assert_text("ok").await;
+        let resp = cli.get("/health/ready").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text(r#"{"failing":[],"ready":true}"#).await;
+
+        readiness.set_unready("database");
+        readiness.set_unready("cache");
+        assert!(!readiness.is_ready());
+        let resp = cli.get("/health/ready").send().await;
+        resp.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        resp.assert_text(r#"{"failing":["cache","database"],"ready":false}"#)
+            .await;
+
+        readiness.set_ready("database");
+        readiness.set_ready("cache");
+        assert!(readiness.is_ready());
+        cli.get("/health/ready").send().await.assert_status_is_ok();
+    }
+}
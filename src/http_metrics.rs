@@ -0,0 +1,266 @@
+//! Contains a middleware that records HTTP metrics and an endpoint that
+//! exposes them in the Prometheus text format.
+//!
+//! #### Metrics
+//! The following metrics are recorded using the
+//! [`metrics`](https://docs.rs/metrics) crate:
+//! - [`REQUESTS_METRIC`] (counter): number of handled requests, labeled by
+//!   `operation`, `method` and `status`
+//! - [`DURATION_METRIC`] (histogram): time it took to handle a request in
+//!   seconds, labeled by `operation`, `method` and `status`
+//! - [`IN_FLIGHT_METRIC`] (gauge): number of requests that are currently being
+//!   handled, labeled by `method`
+//!
+//! The `operation` label contains the operation id of the poem-openapi
+//! endpoint (set via `#[oai(operation_id = "...")]`) instead of the raw
+//! request path to keep the cardinality of the metrics low. Requests to
+//! endpoints without an operation id are labeled with [`UNKNOWN_OPERATION`].
+//!
+//! #### Example
+//! ```
+//! use poem::{get, EndpointExt, Route};
+//! use poem_ext::http_metrics::{
+//!     install_prometheus_recorder, HttpMetricsMiddleware, PrometheusEndpoint,
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get", operation_id = "test")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! let handle = install_prometheus_recorder().unwrap();
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service.with(HttpMetricsMiddleware::new()))
+//!     .at("/metrics", get(PrometheusEndpoint::new(handle)));
+//! ```
+
+use std::{fmt::Debug, time::Instant};
+
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response};
+use poem_openapi::OperationId;
+
+/// Name of the counter that counts handled requests.
+pub const REQUESTS_METRIC: &str = "http_requests_total";
+
+/// Name of the histogram that records the time it took to handle a request.
+pub const DURATION_METRIC: &str = "http_request_duration_seconds";
+
+/// Name of the gauge that counts requests that are currently being handled.
+pub const IN_FLIGHT_METRIC: &str = "http_requests_in_flight";
+
+/// Value of the `operation` label for requests to endpoints without an
+/// operation id.
+pub const UNKNOWN_OPERATION: &str = "unknown";
+
+/// Default buckets (in seconds) of the [`DURATION_METRIC`] histogram used by
+/// [`install_prometheus_recorder`].
+pub const DEFAULT_DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A middleware that records the number, duration and status of requests.
+///
+/// See the [module level documentation](self) for a list of recorded
+/// metrics.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HttpMetricsMiddleware;
+
+impl HttpMetricsMiddleware {
+    /// Create a new HttpMetricsMiddleware.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for HttpMetricsMiddleware {
+    type Output = HttpMetricsMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        HttpMetricsMwEndpoint { inner: ep }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct HttpMetricsMwEndpoint<E> {
+    inner: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for HttpMetricsMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let method = req.method().to_string();
+        let _in_flight = InFlightGuard::new(method.clone());
+        let start = Instant::now();
+
+        let result = self.inner.call(req).await.map(IntoResponse::into_response);
+
+        let (status, operation) = match &result {
+            Ok(resp) => (resp.status(), resp.data::<OperationId>()),
+            Err(err) => (err.status(), err.data::<OperationId>()),
+        };
+        let labels = [
+            ("operation", operation.map_or(UNKNOWN_OPERATION, |op| op.0)),
+            ("method", method.as_str()),
+            ("status", status.as_str()),
+        ]
+        .map(|(key, value)| (key, value.to_owned()));
+        metrics::counter!(REQUESTS_METRIC, &labels).increment(1);
+        metrics::histogram!(DURATION_METRIC, &labels).record(start.elapsed());
+
+        result
+    }
+}
+
+/// Keeps the in-flight gauge incremented while the request is being handled,
+/// even if the future is dropped before completion.
+struct InFlightGuard(String);
+
+impl InFlightGuard {
+    fn new(method: String) -> Self {
+        metrics::gauge!(IN_FLIGHT_METRIC, "method" => method.clone()).increment(1.0);
+        Self(method)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        metrics::gauge!(IN_FLIGHT_METRIC, "method" => self.0.clone()).decrement(1.0);
+    }
+}
+
+/// Install a Prometheus recorder as the global recorder and return a handle
+/// that can be passed to [`PrometheusEndpoint`].
+///
+/// The [`DURATION_METRIC`] histogram uses the [`DEFAULT_DURATION_BUCKETS`].
+/// Use [`PrometheusBuilder`](metrics_exporter_prometheus::PrometheusBuilder)
+/// directly for more control over the recorder.
+#[cfg(feature = "prometheus")]
+pub fn install_prometheus_recorder(
+) -> Result<metrics_exporter_prometheus::PrometheusHandle, metrics_exporter_prometheus::BuildError>
+{
+    metrics_exporter_prometheus::PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            metrics_exporter_prometheus::Matcher::Full(DURATION_METRIC.into()),
+            DEFAULT_DURATION_BUCKETS,
+        )?
+        .install_recorder()
+}
+
+/// An endpoint that renders all metrics of a Prometheus recorder in the
+/// Prometheus text exposition format.
+#[cfg(feature = "prometheus")]
+#[derive(Clone)]
+pub struct PrometheusEndpoint {
+    handle: metrics_exporter_prometheus::PrometheusHandle,
+}
+
+#[cfg(feature = "prometheus")]
+impl Debug for PrometheusEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PrometheusEndpoint").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl PrometheusEndpoint {
+    /// Create a new PrometheusEndpoint that renders the metrics of the
+    /// recorder the given handle belongs to.
+    pub fn new(handle: metrics_exporter_prometheus::PrometheusHandle) -> Self {
+        Self { handle }
+    }
+}
+
+#[cfg(feature = "prometheus")]
+#[poem::async_trait]
+impl Endpoint for PrometheusEndpoint {
+    type Output = Response;
+
+    async fn call(&self, _req: Request) -> poem::Result<Self::Output> {
+        Ok(Response::builder()
+            .content_type("text/plain; version=0.0.4")
+            .body(self.handle.render()))
+    }
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod tests {
+    use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+    use poem::{get, test::TestClient, EndpointExt, Route};
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test", method = "get", operation_id = "test")]
+        async fn test(&self) -> PlainText<&'static str> {
+            PlainText("ok")
+        }
+
+        #[oai(path = "/other/:id", method = "get")]
+        async fn other(&self, id: poem_openapi::param::Path<i32>) -> PlainText<String> {
+            PlainText(id.0.to_string())
+        }
+    }
+
+    #[test]
+    fn metrics() {
+        let recorder = PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Full(DURATION_METRIC.into()), &[1.0])
+            .unwrap()
+            .build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    let cli = TestClient::new(
+                        Route::new()
+                            .nest(
+                                "/",
+                                OpenApiService::new(Api, "test", "1")
+                                    .with(HttpMetricsMiddleware::new()),
+                            )
+                            .at("/metrics", get(PrometheusEndpoint::new(handle))),
+                    );
+
+                    for _ in 0..2 {
+                        cli.get("/test").send().await.assert_status_is_ok();
+                    }
+                    cli.get("/other/1").send().await.assert_status_is_ok();
+                    cli.get("/other/foo")
+                        .send()
+                        .await
+                        .assert_status(poem::http::StatusCode::BAD_REQUEST);
+
+                    let resp = cli.get("/metrics").send().await;
+                    resp.assert_status_is_ok();
+                    resp.assert_content_type("text/plain; version=0.0.4");
+                    let text = resp.0.into_body().into_string().await.unwrap();
+                    for line in [
+                        r#"http_requests_total{operation="test",method="GET",status="200"} 2"#,
+                        r#"http_requests_total{operation="unknown",method="GET",status="200"} 1"#,
+                        r#"http_requests_total{operation="unknown",method="GET",status="400"} 1"#,
+                        r#"http_request_duration_seconds_count{operation="test",method="GET",status="200"} 2"#,
+                        r#"http_requests_in_flight{method="GET"} 0"#,
+                    ] {
+                        assert!(text.lines().any(|l| l == line), "{line} not in {text}");
+                    }
+                });
+        });
+    }
+}
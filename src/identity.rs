@@ -0,0 +1,72 @@
+//! Contains the [`Identity`] type that makes the authenticated identity of a
+//! request available to middlewares (e.g. for logging).
+//!
+//! Authorization dependencies like the ones created by the
+//! [`custom_auth!`](crate::custom_auth!) macro only get a shared reference to
+//! the request and can therefore not add extensions to it. Instead,
+//! middlewares that are interested in the identity attach an empty
+//! [`Identity`] to the request before calling the endpoint, which the
+//! authorization checker can then fill using [`Identity::set`].
+//!
+//! #### Example
+//! ```
+//! use poem::Request;
+//! use poem_ext::identity::Identity;
+//! use poem_openapi::auth::Bearer;
+//!
+//! struct User {
+//!     id: u64,
+//! }
+//!
+//! async fn user_auth_check(req: &Request, token: Option<Bearer>) -> Result<User, poem::Error> {
+//!     let user = User { id: 42 }; // look up the user
+//!     Identity::set(req, user.id);
+//!     Ok(user)
+//! }
+//! ```
+
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use poem::Request;
+
+/// Shared slot for the authenticated identity of a request.
+#[derive(Debug, Clone, Default)]
+pub struct Identity(Arc<Mutex<Option<String>>>);
+
+impl Identity {
+    /// Record the authenticated identity of a request.
+    ///
+    /// This is a no-op if no middleware has attached an [`Identity`] to the
+    /// request.
+    pub fn set(req: &Request, identity: impl Display) {
+        if let Some(slot) = req.extensions().get::<Self>() {
+            *slot.0.lock().unwrap() = Some(identity.to_string());
+        }
+    }
+
+    /// Return the authenticated identity of a request, if it has been
+    /// recorded.
+    pub fn get(req: &Request) -> Option<String> {
+        req.extensions().get::<Self>().and_then(Self::value)
+    }
+
+    /// Attach an [`Identity`] to a request (if not already present) and return
+    /// a handle to it that can be used to read the identity after the request
+    /// has been passed to the endpoint.
+    pub fn attach(req: &mut Request) -> Self {
+        if let Some(slot) = req.extensions().get::<Self>() {
+            return slot.clone();
+        }
+        let slot = Self::default();
+        req.extensions_mut().insert(slot.clone());
+        slot
+    }
+
+    /// Return the recorded identity.
+    pub fn value(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,267 @@
+//! Contains a middleware that dispatches background jobs only after the
+//! database transaction of a request has been committed.
+//!
+//! Jobs that are enqueued using [`JobQueue::enqueue`] during a request are
+//! buffered and passed to a [`JobExecutor`] after the
+//! [`DbTransactionMiddleware`](crate::db::DbTransactionMiddleware) has
+//! committed the transaction. If the transaction is rolled back (or has not
+//! been committed for any other reason), the jobs are discarded. This
+//! prevents jobs that reference rows which have never been written.
+//!
+//! The [`JobQueueMiddleware`] has to wrap the
+//! [`DbTransactionMiddleware`](crate::db::DbTransactionMiddleware), i.e. it
+//! has to be added after it. The jobs are dispatched before the response is
+//! returned, so the executor should only hand them off (e.g. to a channel or
+//! a message queue) instead of running them.
+//!
+//! #### Example
+//! ```no_run
+//! use poem::{EndpointExt, Request, Route};
+//! use poem_ext::{
+//!     db::DbTransactionMiddleware,
+//!     jobs::{JobQueue, JobQueueMiddleware},
+//!     responses::Response,
+//! };
+//! use poem_openapi::{param::Path, payload::PlainText, OpenApi, OpenApiService};
+//!
+//! enum Job {
+//!     SendWelcomeEmail { user_id: u64 },
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/users/:id", method = "post")]
+//!     async fn create_user(
+//!         &self,
+//!         req: &Request,
+//!         id: Path<u64>,
+//!     ) -> Response<PlainText<&'static str>> {
+//!         // insert the user into the database
+//!         JobQueue::enqueue(req, Job::SendWelcomeEmail { user_id: id.0 })?;
+//!         Ok(PlainText("created").into())
+//!     }
+//! }
+//!
+//! # let db_connection = todo!();
+//! let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .with(DbTransactionMiddleware::new(db_connection))
+//!     .with(JobQueueMiddleware::new(move |job: Job| {
+//!         let _ = sender.send(job);
+//!         async {}
+//!     }));
+//! # drop(receiver);
+//! ```
+
+use std::{
+    fmt::{Debug, Display},
+    future::Future,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+use poem::{
+    error::ResponseError, http::StatusCode, Endpoint, IntoResponse, Middleware, Request, Response,
+};
+
+use crate::db::TransactionCommitted;
+
+/// Dispatches jobs of type `J` after the transaction has been committed.
+///
+/// This trait is implemented for async functions that take a job.
+#[poem::async_trait]
+pub trait JobExecutor<J>: Send + Sync + 'static {
+    /// Dispatch a job.
+    async fn execute(&self, job: J);
+}
+
+#[poem::async_trait]
+impl<F, Fut, J> JobExecutor<J> for F
+where
+    F: Fn(J) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send,
+    J: Send + 'static,
+{
+    async fn execute(&self, job: J) {
+        self(job).await
+    }
+}
+
+/// Buffer for the jobs of type `J` that have been enqueued during a request.
+pub struct JobQueue<J>(Arc<Mutex<Vec<J>>>);
+
+impl<J> Clone for JobQueue<J> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<J> Default for JobQueue<J> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<J> Debug for JobQueue<J> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobQueue")
+            .field("len", &self.0.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl<J: Send + 'static> JobQueue<J> {
+    /// Enqueue a job that is dispatched after the transaction of the request
+    /// has been committed.
+    ///
+    /// Fails if no [`JobQueueMiddleware`] for jobs of type `J` is present.
+    pub fn enqueue(req: &Request, job: J) -> Result<(), MissingJobQueue> {
+        let queue = req
+            .extensions()
+            .get::<Self>()
+            .ok_or(MissingJobQueue(std::any::type_name::<J>()))?;
+        queue.0.lock().unwrap().push(job);
+        Ok(())
+    }
+
+    fn take(&self) -> Vec<J> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+/// Error returned by [`JobQueue::enqueue`] if no [`JobQueueMiddleware`] is
+/// present. Results in `500 Internal Server Error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingJobQueue(&'static str);
+
+impl Display for MissingJobQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no job queue for jobs of type {}", self.0)
+    }
+}
+
+impl std::error::Error for MissingJobQueue {}
+
+impl ResponseError for MissingJobQueue {
+    fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// A middleware that dispatches the jobs enqueued during a request after the
+/// database transaction has been committed.
+pub struct JobQueueMiddleware<J> {
+    executor: Arc<dyn JobExecutor<J>>,
+}
+
+impl<J> Debug for JobQueueMiddleware<J> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobQueueMiddleware").finish_non_exhaustive()
+    }
+}
+
+impl<J> JobQueueMiddleware<J> {
+    /// Create a new JobQueueMiddleware that passes the jobs to the given
+    /// executor.
+    pub fn new(executor: impl JobExecutor<J>) -> Self {
+        Self {
+            executor: Arc::new(executor),
+        }
+    }
+}
+
+impl<E: Endpoint, J: Send + 'static> Middleware<E> for JobQueueMiddleware<J> {
+    type Output = JobQueueMwEndpoint<E, J>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        JobQueueMwEndpoint {
+            inner: ep,
+            executor: self.executor.clone(),
+            _job: PhantomData,
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct JobQueueMwEndpoint<E, J> {
+    inner: E,
+    executor: Arc<dyn JobExecutor<J>>,
+    _job: PhantomData<fn() -> J>,
+}
+
+impl<E: Debug, J> Debug for JobQueueMwEndpoint<E, J> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobQueueMwEndpoint")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[poem::async_trait]
+impl<E: Endpoint, J: Send + 'static> Endpoint for JobQueueMwEndpoint<E, J> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let queue = JobQueue::<J>::default();
+        req.extensions_mut().insert(queue.clone());
+
+        let resp = self.inner.call(req).await?.into_response();
+        let jobs = queue.take();
+        if resp.data::<TransactionCommitted>().is_some() {
+            for job in jobs {
+                self.executor.execute(job).await;
+            }
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, test::TestClient, EndpointExt};
+
+    use super::*;
+
+    #[handler]
+    async fn index(req: &Request) -> poem::Result<Response> {
+        JobQueue::enqueue(req, 1)?;
+        JobQueue::enqueue(req, 2)?;
+        let mut resp = Response::builder().finish();
+        if req.uri().query() == Some("commit") {
+            // usually done by the `DbTransactionMiddleware`
+            resp.set_data(TransactionCommitted);
+        }
+        Ok(resp)
+    }
+
+    #[tokio::test]
+    async fn job_queue() {
+        let jobs = Arc::new(Mutex::new(Vec::new()));
+        let cli = TestClient::new(index.with(JobQueueMiddleware::new({
+            let jobs = jobs.clone();
+            move |job: i32| {
+                jobs.lock().unwrap().push(job);
+                async {}
+            }
+        })));
+
+        cli.get("/").send().await.assert_status_is_ok();
+        assert!(jobs.lock().unwrap().is_empty());
+
+        cli.get("/?commit").send().await.assert_status_is_ok();
+        assert_eq!(*jobs.lock().unwrap(), [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn missing_middleware() {
+        let cli = TestClient::new(index);
+        cli.get("/?commit")
+            .send()
+            .await
+            .assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
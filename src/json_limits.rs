@@ -0,0 +1,340 @@
+//! Contains a middleware that rejects JSON request bodies with a pathological
+//! structure before they are deserialized.
+//!
+//! The body of every request with a JSON content type is scanned once without
+//! building any intermediate representation. Bodies that exceed the maximum
+//! size are rejected with `413 Payload Too Large`, bodies that exceed the
+//! maximum nesting depth, array length or string length with
+//! `422 Unprocessable Content` (see [`JsonLimitExceeded`]). The response
+//! names the offending limit:
+//!
+//! ```json
+//! {"error": "json_limit_exceeded", "details": {"limit": "max_depth", "max": 64}}
+//! ```
+//!
+//! Syntax errors are not reported by this middleware but left to the
+//! deserializer of the endpoint.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     add_response_schemas,
+//!     json_limits::JsonLimitsMiddleware,
+//!     responses::{JsonLimitExceeded, Response},
+//! };
+//! use poem_openapi::{payload::Json, OpenApi, OpenApiService};
+//!
+//! /// Marker type used to document the 413 and 422 responses.
+//! struct Limits;
+//! add_response_schemas!(Limits, JsonLimitExceeded::raw::Response);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "post")]
+//!     async fn test(&self, data: Json<Vec<String>>) -> Response<Json<usize>, Limits> {
+//!         Ok(Json(data.0.len()).into())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     JsonLimitsMiddleware::new()
+//!         .with_max_body_size(1 << 20)
+//!         .with_max_depth(16)
+//!         .with_max_array_length(1000)
+//!         .with_max_string_length(4096),
+//! );
+//! ```
+
+use poem::{
+    http::header::{CONTENT_LENGTH, CONTENT_TYPE},
+    Endpoint, IntoResponse, Middleware, Request, Response,
+};
+use poem_openapi::{Enum, Object};
+
+use crate::responses::JsonLimitExceeded;
+
+/// A limit enforced by the [`JsonLimitsMiddleware`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[oai(rename_all = "snake_case")]
+pub enum JsonLimit {
+    /// Maximum size of the request body in bytes.
+    MaxBodySize,
+    /// Maximum nesting depth of arrays and objects.
+    MaxDepth,
+    /// Maximum number of elements in an array.
+    MaxArrayLength,
+    /// Maximum length of a string (including object keys) in bytes.
+    MaxStringLength,
+}
+
+/// Details of a [`JsonLimitExceeded`] response.
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct JsonLimitDetails {
+    /// The limit that has been exceeded.
+    pub limit: JsonLimit,
+    /// The configured value of the limit.
+    pub max: u64,
+}
+
+/// A middleware that enforces structural limits on JSON request bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsonLimitsMiddleware {
+    max_body_size: Option<usize>,
+    max_depth: Option<usize>,
+    max_array_length: Option<usize>,
+    max_string_length: Option<usize>,
+}
+
+impl Default for JsonLimitsMiddleware {
+    fn default() -> Self {
+        Self {
+            max_body_size: None,
+            max_depth: Some(64),
+            max_array_length: None,
+            max_string_length: None,
+        }
+    }
+}
+
+impl JsonLimitsMiddleware {
+    /// Create a new JsonLimitsMiddleware that only limits the nesting depth
+    /// (to `64`).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum size of the request body in bytes.
+    pub fn with_max_body_size(self, max: usize) -> Self {
+        Self {
+            max_body_size: Some(max),
+            ..self
+        }
+    }
+
+    /// Set the maximum nesting depth of arrays and objects (default: `64`).
+    pub fn with_max_depth(self, max: usize) -> Self {
+        Self {
+            max_depth: Some(max),
+            ..self
+        }
+    }
+
+    /// Set the maximum number of elements in an array.
+    pub fn with_max_array_length(self, max: usize) -> Self {
+        Self {
+            max_array_length: Some(max),
+            ..self
+        }
+    }
+
+    /// Set the maximum length of a string (including object keys) in bytes.
+    /// Escape sequences count as multiple bytes.
+    pub fn with_max_string_length(self, max: usize) -> Self {
+        Self {
+            max_string_length: Some(max),
+            ..self
+        }
+    }
+
+    /// Check the structure of a JSON document.
+    fn check(&self, data: &[u8]) -> Result<(), JsonLimitDetails> {
+        let exceeded = |limit, max: usize| JsonLimitDetails {
+            limit,
+            max: max as u64,
+        };
+        // number of elements and whether the current element has already been
+        // counted for each array (`Some`) or object (`None`) on the stack
+        let mut stack: Vec<Option<(usize, bool)>> = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let c = data[i];
+            if !matches!(c, b']' | b'}' | b',' | b':') && !c.is_ascii_whitespace() {
+                if let Some(Some((len, counted @ false))) = stack.last_mut() {
+                    *len += 1;
+                    *counted = true;
+                    if let Some(max) = self.max_array_length.filter(|&max| *len > max) {
+                        return Err(exceeded(JsonLimit::MaxArrayLength, max));
+                    }
+                }
+            }
+            match c {
+                b'"' => {
+                    let start = i + 1;
+                    i = start;
+                    while i < data.len() && data[i] != b'"' {
+                        i += if data[i] == b'\\' { 2 } else { 1 };
+                    }
+                    if let Some(max) = self.max_string_length.filter(|&max| i - start > max) {
+                        return Err(exceeded(JsonLimit::MaxStringLength, max));
+                    }
+                }
+                b'[' | b'{' => {
+                    stack.push((c == b'[').then_some((0, false)));
+                    if let Some(max) = self.max_depth.filter(|&max| stack.len() > max) {
+                        return Err(exceeded(JsonLimit::MaxDepth, max));
+                    }
+                }
+                b']' | b'}' => {
+                    stack.pop();
+                }
+                b',' => {
+                    if let Some(Some((_, counted))) = stack.last_mut() {
+                        *counted = false;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for JsonLimitsMiddleware {
+    type Output = JsonLimitsMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        JsonLimitsMwEndpoint {
+            inner: ep,
+            limits: *self,
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct JsonLimitsMwEndpoint<E> {
+    inner: E,
+    limits: JsonLimitsMiddleware,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for JsonLimitsMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let is_json = req
+            .header(CONTENT_TYPE)
+            .and_then(|content_type| content_type.split(';').next())
+            .is_some_and(|essence| essence.trim().to_ascii_lowercase().ends_with("json"));
+        if !is_json {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        }
+
+        let too_large = |max: usize| {
+            JsonLimitExceeded::raw::payload_too_large(JsonLimitDetails {
+                limit: JsonLimit::MaxBodySize,
+                max: max as u64,
+            })
+            .into_response()
+        };
+        let body = req.take_body();
+        let body = match self.limits.max_body_size {
+            Some(max) => {
+                let content_length = req
+                    .header(CONTENT_LENGTH)
+                    .and_then(|x| x.parse::<usize>().ok());
+                if content_length.is_some_and(|len| len > max) {
+                    return Ok(too_large(max));
+                }
+                match body.into_bytes_limit(max).await {
+                    Ok(body) => body,
+                    Err(poem::error::ReadBodyError::PayloadTooLarge) => return Ok(too_large(max)),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            None => body.into_bytes().await?,
+        };
+
+        if let Err(details) = self.limits.check(&body) {
+            return Ok(JsonLimitExceeded::raw::json_limit_exceeded(details).into_response());
+        }
+
+        req.set_body(body);
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::StatusCode, test::TestClient, EndpointExt};
+
+    use super::*;
+
+    #[test]
+    fn check() {
+        let limits = JsonLimitsMiddleware::new()
+            .with_max_depth(3)
+            .with_max_array_length(3)
+            .with_max_string_length(5);
+        let check = |data: &str| limits.check(data.as_bytes()).map_err(|err| err.limit);
+
+        assert_eq!(check(r#"{"a": [1, {"b": "xyz"}], "c": null}"#), Ok(()));
+        assert_eq!(check("[[[]]]"), Ok(()));
+        assert_eq!(check("[[[[]]]]"), Err(JsonLimit::MaxDepth));
+        assert_eq!(check(r#"[{"a": 1}, [1, 2, 3], "x"]"#), Ok(()));
+        assert_eq!(check("[1, 2, 3, 4]"), Err(JsonLimit::MaxArrayLength));
+        assert_eq!(check("[[], [], [], []]"), Err(JsonLimit::MaxArrayLength));
+        assert_eq!(check("[]"), Ok(()));
+        assert_eq!(check(r#"["ab\"cd"]"#), Err(JsonLimit::MaxStringLength));
+        assert_eq!(check(r#"{"abcdef": 1}"#), Err(JsonLimit::MaxStringLength));
+        assert_eq!(check(r#"["[[[[", "]]]]"]"#), Ok(()));
+    }
+
+    #[handler]
+    fn index(body: String) -> String {
+        body
+    }
+
+    #[tokio::test]
+    async fn middleware() {
+        let cli = TestClient::new(
+            index.with(
+                JsonLimitsMiddleware::new()
+                    .with_max_body_size(16)
+                    .with_max_depth(2),
+            ),
+        );
+
+        let resp = cli
+            .post("/")
+            .content_type("application/json")
+            .body("[[1]]")
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text("[[1]]").await;
+
+        let resp = cli
+            .post("/")
+            .content_type("application/json")
+            .body("[[[1]]]")
+            .send()
+            .await;
+        resp.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        resp.assert_text(
+            r#"{"details":{"limit":"max_depth","max":2},"error":"json_limit_exceeded"}"#,
+        )
+        .await;
+
+        let resp = cli
+            .post("/")
+            .content_type("application/json")
+            .body("[1, 2, 3, 4, 5, 6, 7, 8, 9]")
+            .send()
+            .await;
+        resp.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+        resp.assert_text(
+            r#"{"details":{"limit":"max_body_size","max":16},"error":"payload_too_large"}"#,
+        )
+        .await;
+
+        // other content types are ignored
+        let resp = cli.post("/").body("[[[1]]]").send().await;
+        resp.assert_status_is_ok();
+    }
+}
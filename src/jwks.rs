@@ -0,0 +1,665 @@
+//! Contains a provider that fetches the keys for JWT verification from a JSON
+//! Web Key Set (JWKS).
+//!
+//! A [`JwksProvider`] loads the keys from the `jwks_uri` of an identity
+//! provider (e.g. Auth0, Keycloak or Microsoft Entra ID) or using a custom
+//! fetch function, caches them and reloads them when they are older than the
+//! configured maximum age or when a token references a key id (`kid`) that is
+//! not known yet. Reloads are coalesced, so concurrent requests trigger at
+//! most one fetch, and are rate limited using a minimum refresh interval and
+//! an exponential backoff after failed fetches. If a reload fails, the error
+//! is reported to the [error sink](crate::error_sink) and the previously
+//! loaded keys are used until the next successful reload.
+//!
+//! RSA (`RSA`), elliptic curve (`EC` with `P-256` or `P-384`), Ed25519 (`OKP`)
+//! and symmetric (`oct`) keys are supported. Keys that are not meant for
+//! signatures (`"use": "enc"`) or whose algorithm is not supported are
+//! ignored. A key without an `alg` parameter can be used with all algorithms
+//! of its key type (see [`JwtAlgorithm`]).
+//!
+//! If a [`JwksProvider`] is attached to the endpoint using
+//! [`data`](poem::EndpointExt::data), authorization dependencies defined using
+//! `custom_auth!(Auth, checker, jwt = Claims)` use it instead of a
+//! [`JwtVerifier`]. It can also be passed to the checker using
+//! `extract(Data<&JwksProvider>)`.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Request, Route};
+//! use poem_ext::{custom_auth, jwks::JwksProvider, jwt::JwtError, response};
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Claims {
+//!     sub: String,
+//! }
+//!
+//! struct UserAuth(String);
+//!
+//! response!(AuthResult = {
+//!     /// The access token is missing or invalid.
+//!     Unauthorized(401, error),
+//! });
+//!
+//! async fn user_auth_check(
+//!     _req: &Request,
+//!     claims: Option<Result<Claims, JwtError>>,
+//! ) -> Result<String, AuthResult::raw::Response> {
+//!     match claims {
+//!         Some(Ok(claims)) => Ok(claims.sub),
+//!         Some(Err(_)) | None => Err(AuthResult::raw::unauthorized()),
+//!     }
+//! }
+//!
+//! custom_auth!(UserAuth, user_auth_check, jwt = Claims);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/me", method = "get")]
+//!     async fn me(&self, auth: UserAuth) -> PlainText<String> {
+//!         PlainText(auth.0)
+//!     }
+//! }
+//!
+//! let provider = JwksProvider::from_url("https://auth.example.com/.well-known/jwks.json")
+//!     .with_issuer("https://auth.example.com/")
+//!     .with_audience("my-api");
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).data(provider);
+//! ```
+
+use std::{
+    fmt::{Debug, Display},
+    future::Future,
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use jsonwebtoken::{
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, PublicKeyUse},
+    DecodingKey,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use crate::{
+    error_sink::error_sink,
+    jwt::{JwtAlgorithm, JwtError, JwtKey, JwtVerifier},
+    responses::new_error_id,
+};
+
+/// Default maximum age of the cached keys.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Default minimum interval between two fetches.
+pub const DEFAULT_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default maximum delay between two fetches after failures.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Default timeout for fetching the keys from a URL.
+pub const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Initial delay between two fetches after a failure, which is doubled after
+/// each consecutive failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+type FetchFn = dyn Fn() -> BoxFuture<'static, Result<String, String>> + Send + Sync;
+
+/// Fetches and caches the keys of a JSON Web Key Set and verifies JWTs using
+/// them.
+///
+/// Clones of a JwksProvider share the same cache.
+#[derive(Clone)]
+pub struct JwksProvider {
+    fetch: Arc<FetchFn>,
+    verifier: JwtVerifier,
+    max_age: Duration,
+    min_refresh_interval: Duration,
+    max_backoff: Duration,
+    state: Arc<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The verifier for the most recently loaded keys and the time at which
+    /// they have been loaded.
+    cache: RwLock<Option<(Instant, Arc<JwtVerifier>)>>,
+    /// Held while the keys are being reloaded.
+    refresh: Mutex<RefreshState>,
+}
+
+#[derive(Default)]
+struct RefreshState {
+    last_attempt: Option<Instant>,
+    failures: u32,
+}
+
+impl Debug for JwksProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwksProvider")
+            .field("max_age", &self.max_age)
+            .field("min_refresh_interval", &self.min_refresh_interval)
+            .field("max_backoff", &self.max_backoff)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JwksProvider {
+    /// Create a new JwksProvider that uses the given function to fetch the
+    /// JSON Web Key Set.
+    pub fn new<F, Fut, E>(fetch: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, E>> + Send + 'static,
+        E: Display,
+    {
+        Self {
+            fetch: Arc::new(move || {
+                fetch()
+                    .map(|result| result.map_err(|err| err.to_string()))
+                    .boxed()
+            }),
+            verifier: JwtVerifier::from_keys([]),
+            max_age: DEFAULT_MAX_AGE,
+            min_refresh_interval: DEFAULT_MIN_REFRESH_INTERVAL,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            state: Default::default(),
+        }
+    }
+
+    /// Create a new JwksProvider that fetches the JSON Web Key Set from the
+    /// given URL (e.g. the `jwks_uri` of an OpenID Connect provider).
+    pub fn from_url(url: impl Into<String>) -> Self {
+        Self::from_url_with_client(reqwest::Client::new(), url)
+    }
+
+    /// Create a new JwksProvider that fetches the JSON Web Key Set from the
+    /// given URL using the given HTTP client (e.g. to configure a proxy).
+    ///
+    /// Each request times out after [`DEFAULT_FETCH_TIMEOUT`].
+    pub fn from_url_with_client(client: reqwest::Client, url: impl Into<String>) -> Self {
+        let url = Arc::<str>::from(url.into());
+        Self::new(move || {
+            let request = client.get(&*url).timeout(DEFAULT_FETCH_TIMEOUT);
+            async move { request.send().await?.error_for_status()?.text().await }
+        })
+    }
+
+    /// Add an accepted audience (see
+    /// [`JwtVerifier::with_audience`](crate::jwt::JwtVerifier::with_audience)).
+    pub fn with_audience(self, audience: impl Into<String>) -> Self {
+        Self {
+            verifier: self.verifier.with_audience(audience),
+            ..self
+        }
+    }
+
+    /// Add a trusted issuer (see
+    /// [`JwtVerifier::with_issuer`](crate::jwt::JwtVerifier::with_issuer)).
+    pub fn with_issuer(self, issuer: impl Into<String>) -> Self {
+        Self {
+            verifier: self.verifier.with_issuer(issuer),
+            ..self
+        }
+    }
+
+    /// Set the tolerance for the `exp` and `nbf` claims (see
+    /// [`JwtVerifier::with_leeway`](crate::jwt::JwtVerifier::with_leeway)).
+    pub fn with_leeway(self, leeway: Duration) -> Self {
+        Self {
+            verifier: self.verifier.with_leeway(leeway),
+            ..self
+        }
+    }
+
+    /// Set whether tokens without an `exp` claim are rejected (see
+    /// [`JwtVerifier::with_exp_required`](crate::jwt::JwtVerifier::with_exp_required)).
+    pub fn with_exp_required(self, exp_required: bool) -> Self {
+        Self {
+            verifier: self.verifier.with_exp_required(exp_required),
+            ..self
+        }
+    }
+
+    /// Set the maximum age of the cached keys after which they are reloaded
+    /// (default: [`DEFAULT_MAX_AGE`]).
+    pub fn with_max_age(self, max_age: Duration) -> Self {
+        Self { max_age, ..self }
+    }
+
+    /// Set the minimum interval between two fetches, which limits how often
+    /// tokens with unknown key ids can trigger a reload (default:
+    /// [`DEFAULT_MIN_REFRESH_INTERVAL`]).
+    pub fn with_min_refresh_interval(self, min_refresh_interval: Duration) -> Self {
+        Self {
+            min_refresh_interval,
+            ..self
+        }
+    }
+
+    /// Set the maximum delay between two fetches after consecutive failures
+    /// (default: [`DEFAULT_MAX_BACKOFF`]).
+    pub fn with_max_backoff(self, max_backoff: Duration) -> Self {
+        Self {
+            max_backoff,
+            ..self
+        }
+    }
+
+    /// Verify a token using the cached keys and return its claims.
+    ///
+    /// The keys are reloaded if they have expired or if the token references
+    /// an unknown key. Returns [`JwtError::KeysUnavailable`] if no keys could
+    /// be loaded yet.
+    pub async fn verify<C: DeserializeOwned>(&self, token: &str) -> Result<C, JwtError> {
+        let verifier = match self.cached() {
+            Some((loaded_at, verifier)) if loaded_at.elapsed() < self.max_age => verifier,
+            _ => self.refresh(None).await.ok_or(JwtError::KeysUnavailable)?,
+        };
+        match verifier.verify(token) {
+            Err(JwtError::UnknownKey) => match self.refresh(Some(&verifier)).await {
+                Some(reloaded) if !Arc::ptr_eq(&reloaded, &verifier) => reloaded.verify(token),
+                _ => Err(JwtError::UnknownKey),
+            },
+            result => result,
+        }
+    }
+
+    fn cached(&self) -> Option<(Instant, Arc<JwtVerifier>)> {
+        self.state
+            .cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Reload the keys unless another task has already done so or the last
+    /// attempt has been too recent, and return the current verifier.
+    ///
+    /// `outdated` is the verifier that caused the reload because it does not
+    /// know the key of a token. If it is `None`, the keys are only reloaded if
+    /// they have expired.
+    async fn refresh(&self, outdated: Option<&Arc<JwtVerifier>>) -> Option<Arc<JwtVerifier>> {
+        let mut refresh = self.state.refresh.lock().await;
+
+        let cached = self.cached();
+        if let Some((loaded_at, verifier)) = &cached {
+            let reloaded = match outdated {
+                Some(outdated) => !Arc::ptr_eq(outdated, verifier),
+                None => loaded_at.elapsed() < self.max_age,
+            };
+            if reloaded {
+                return Some(verifier.clone());
+            }
+        }
+        let cached = cached.map(|(_, verifier)| verifier);
+
+        if refresh
+            .last_attempt
+            .is_some_and(|last| last.elapsed() < self.retry_delay(refresh.failures))
+        {
+            return cached;
+        }
+        refresh.last_attempt = Some(Instant::now());
+
+        match (self.fetch)().await.and_then(|jwks| parse_jwks(&jwks)) {
+            Ok(keys) => {
+                refresh.failures = 0;
+                let verifier = Arc::new(self.verifier.clone().with_keys(keys));
+                *self
+                    .state
+                    .cache
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner) =
+                    Some((Instant::now(), verifier.clone()));
+                Some(verifier)
+            }
+            Err(err) => {
+                refresh.failures = refresh.failures.saturating_add(1);
+                error_sink().internal_error(
+                    &new_error_id(),
+                    &format_args!(
+                        "failed to load jwks ({} consecutive failures): {err}",
+                        refresh.failures
+                    ),
+                );
+                cached
+            }
+        }
+    }
+
+    /// Return the minimum delay between the last and the next fetch.
+    fn retry_delay(&self, failures: u32) -> Duration {
+        let backoff = match failures {
+            0 => Duration::ZERO,
+            n => INITIAL_BACKOFF
+                .saturating_mul(1 << (n - 1).min(31))
+                .min(self.max_backoff),
+        };
+        backoff.max(self.min_refresh_interval)
+    }
+}
+
+/// Parse a JSON Web Key Set and return all supported keys.
+fn parse_jwks(jwks: &str) -> Result<Vec<JwtKey>, String> {
+    let jwks = serde_json::from_str::<Value>(jwks).map_err(|err| err.to_string())?;
+    let keys = jwks
+        .get("keys")
+        .and_then(Value::as_array)
+        .ok_or("invalid jwks (keys are missing)")?;
+    Ok(keys.iter().flat_map(parse_jwk).collect())
+}
+
+/// Convert a JSON Web Key into [`JwtKey`]s. A key without an `alg` parameter
+/// can be used with any algorithm that supports its key type.
+fn parse_jwk(jwk: &Value) -> Vec<JwtKey> {
+    let Ok(jwk) = serde_json::from_value::<Jwk>(jwk.clone()) else {
+        return Vec::new();
+    };
+    if jwk
+        .common
+        .public_key_use
+        .as_ref()
+        .is_some_and(|u| *u != PublicKeyUse::Signature)
+    {
+        return Vec::new();
+    }
+    let Ok(key) = DecodingKey::from_jwk(&jwk) else {
+        return Vec::new();
+    };
+
+    use JwtAlgorithm::*;
+    let algorithms: &[JwtAlgorithm] = match &jwk.algorithm {
+        AlgorithmParameters::OctetKey(_) => &[HS256, HS384, HS512],
+        AlgorithmParameters::RSA(_) => &[RS256, RS384, RS512, PS256, PS384, PS512],
+        AlgorithmParameters::EllipticCurve(params) => match params.curve {
+            EllipticCurve::P256 => &[ES256],
+            EllipticCurve::P384 => &[ES384],
+            _ => &[],
+        },
+        AlgorithmParameters::OctetKeyPair(params) => match params.curve {
+            EllipticCurve::Ed25519 => &[EdDSA],
+            _ => &[],
+        },
+    };
+    let alg = jwk.common.key_algorithm.map(|alg| alg.to_string());
+    algorithms
+        .iter()
+        .filter(|algorithm| alg.as_ref().map_or(true, |alg| algorithm.name() == alg))
+        .map(|&algorithm| {
+            let key = JwtKey::from_decoding_key(algorithm, key.clone());
+            match &jwk.common.key_id {
+                Some(kid) => key.with_kid(kid),
+                None => key,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::jwt::tests::{
+        EC_PRIVATE_KEY, EC_PUBLIC_KEY, ED_PRIVATE_KEY, ED_PUBLIC_KEY, RSA_PRIVATE_KEY,
+        RSA_PUBLIC_KEY,
+    };
+
+    // public keys of the test keys in `jwt::tests`
+    const RSA_N: &str = "sRTjIIpFKcUxsEXDMdv80-bKQuwHI4dwC0_F5BW31sAoej488Mfmi_J5M3XupCKpYYTLqSCIU0YdWAIQ_hZeRmyfSu4prhFRVSJ6gFi5tPZRoPZNmGozH5FMAT9ViFxJOBxuiN4Z5nrhvBId1GJNDXNEd2exuH786AZWfERJmV4sZRKfCbBMog95DN-MUTF_s835tKWBRK_QSRl2QoI3cC0lQ43F_PMHeF47U9umov9UfES412tacOK8cwQfFwkDBE-CtC64vQqvkwUkxa8ym_CiCIrkyUcFI8Ew-kZguA2oY8VHFeztxetg0AMGUFgwnlN4Ocf7JmTKF_iJ_bkjNQ";
+    const RSA_E: &str = "AQAB";
+    const EC_X: &str = "dxH-lq_YDBr9SzBWghjWiDJVbpiPAlg6JZZ7L7PMnys";
+    const EC_Y: &str = "H9C7CDKvbGf4DB52hjAY0JiuyloFojDd1YFiGPVV-oA";
+    const ED_X: &str = "91X-opUYHAiup0QeW4EYAJtSkayKORhcxJb50_c9SVU";
+
+    struct Jwks {
+        keys: std::sync::Mutex<Vec<Value>>,
+        fetches: AtomicUsize,
+    }
+
+    impl Jwks {
+        fn provider(keys: Vec<Value>) -> (Arc<Self>, JwksProvider) {
+            let jwks = Arc::new(Self {
+                keys: std::sync::Mutex::new(keys),
+                fetches: AtomicUsize::new(0),
+            });
+            let provider = JwksProvider::new({
+                let jwks = jwks.clone();
+                move || {
+                    let jwks = jwks.clone();
+                    async move {
+                        jwks.fetches.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        let keys = jwks.keys.lock().unwrap().clone();
+                        match keys.is_empty() {
+                            true => Err("unavailable"),
+                            false => Ok(json!({ "keys": keys }).to_string()),
+                        }
+                    }
+                }
+            })
+            .with_exp_required(false);
+            (jwks, provider)
+        }
+
+        fn fetches(&self) -> usize {
+            self.fetches.load(Ordering::SeqCst)
+        }
+    }
+
+    fn jwk(kid: &str, secret: &[u8]) -> Value {
+        json!({"kty": "oct", "kid": kid, "alg": "HS256", "k": URL_SAFE_NO_PAD.encode(secret)})
+    }
+
+    #[tokio::test]
+    async fn rotation() {
+        let (jwks, provider) = Jwks::provider(vec![jwk("1", b"old")]);
+        let provider = provider.with_min_refresh_interval(Duration::ZERO);
+        let claims = json!({"sub": "alice"});
+        let old = JwtKey::hs256(b"old").with_kid("1").encode(&claims).unwrap();
+        let new = JwtKey::hs256(b"new").with_kid("2").encode(&claims).unwrap();
+
+        assert_eq!(provider.verify(&old).await, Ok(claims.clone()));
+        assert_eq!(provider.verify(&old).await, Ok(claims.clone()));
+        assert_eq!(jwks.fetches(), 1);
+
+        // unknown kid triggers a reload
+        assert_eq!(
+            provider.verify::<Value>(&new).await,
+            Err(JwtError::UnknownKey)
+        );
+        assert_eq!(jwks.fetches(), 2);
+        jwks.keys.lock().unwrap().push(jwk("2", b"new"));
+        assert_eq!(provider.verify(&new).await, Ok(claims.clone()));
+        assert_eq!(provider.verify(&old).await, Ok(claims.clone()));
+        assert_eq!(jwks.fetches(), 3);
+
+        // wrong signature for a known kid does not trigger a reload
+        let forged = JwtKey::hs256(b"forged")
+            .with_kid("2")
+            .encode(&claims)
+            .unwrap();
+        assert_eq!(
+            provider.verify::<Value>(&forged).await,
+            Err(JwtError::InvalidSignature)
+        );
+        assert_eq!(jwks.fetches(), 3);
+    }
+
+    #[tokio::test]
+    async fn single_flight() {
+        let (jwks, provider) = Jwks::provider(vec![jwk("1", b"secret")]);
+        let claims = json!({"sub": "alice"});
+        let token = JwtKey::hs256(b"secret")
+            .with_kid("1")
+            .encode(&claims)
+            .unwrap();
+        let unknown = JwtKey::hs256(b"secret")
+            .with_kid("2")
+            .encode(&claims)
+            .unwrap();
+
+        let results =
+            futures_util::future::join_all((0..8).map(|_| provider.verify::<Value>(&token))).await;
+        assert!(results
+            .into_iter()
+            .all(|result| result == Ok(claims.clone())));
+        assert_eq!(jwks.fetches(), 1);
+
+        // reloads are rate limited
+        for _ in 0..3 {
+            assert_eq!(
+                provider.verify::<Value>(&unknown).await,
+                Err(JwtError::UnknownKey)
+            );
+        }
+        assert_eq!(jwks.fetches(), 1);
+    }
+
+    #[tokio::test]
+    async fn unavailable() {
+        let (jwks, provider) = Jwks::provider(Vec::new());
+        let provider = provider
+            .with_min_refresh_interval(Duration::ZERO)
+            .with_max_age(Duration::ZERO);
+        let claims = json!({"sub": "alice"});
+        let token = JwtKey::hs256(b"secret")
+            .with_kid("1")
+            .encode(&claims)
+            .unwrap();
+
+        assert_eq!(
+            provider.verify::<Value>(&token).await,
+            Err(JwtError::KeysUnavailable)
+        );
+        // backoff after the failure
+        assert_eq!(
+            provider.verify::<Value>(&token).await,
+            Err(JwtError::KeysUnavailable)
+        );
+        assert_eq!(jwks.fetches(), 1);
+        assert_eq!(provider.retry_delay(1), Duration::from_secs(1));
+        assert_eq!(provider.retry_delay(4), Duration::from_secs(8));
+        assert_eq!(provider.retry_delay(100), DEFAULT_MAX_BACKOFF);
+    }
+
+    #[test]
+    fn jwks_parsing() {
+        let keys = parse_jwks(
+            &json!({"keys": [
+                {"kty": "oct", "kid": "a", "k": "c2VjcmV0"},
+                {"kty": "oct", "kid": "b", "alg": "HS384", "k": "c2VjcmV0"},
+                {"kty": "oct", "kid": "c", "use": "enc", "k": "c2VjcmV0"},
+                {"kty": "RSA", "kid": "d", "alg": "RS256", "n": RSA_N, "e": RSA_E},
+                {"kty": "RSA", "kid": "e", "use": "sig", "n": RSA_N, "e": RSA_E},
+                {"kty": "RSA", "kid": "f", "alg": "RSA-OAEP", "n": RSA_N, "e": RSA_E},
+                {"kty": "EC", "kid": "g", "crv": "P-256", "x": EC_X, "y": EC_Y},
+                {"kty": "EC", "kid": "h", "alg": "ES384", "crv": "P-256", "x": EC_X, "y": EC_Y},
+                {"kty": "OKP", "kid": "i", "crv": "Ed25519", "x": ED_X},
+                {"kty": "foo", "kid": "j"},
+            ]})
+            .to_string(),
+        )
+        .unwrap();
+        let keys = keys
+            .iter()
+            .map(|key| format!("{}:{}", key.kid().unwrap(), key.algorithm()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                "a:HS256", "a:HS384", "a:HS512", "b:HS384", "d:RS256", "e:RS256", "e:RS384",
+                "e:RS512", "e:PS256", "e:PS384", "e:PS512", "g:ES256", "i:EdDSA",
+            ]
+        );
+        assert!(parse_jwks("{}").is_err());
+    }
+
+    /// Serve the given JSON Web Key Set at `/jwks.json` and return its URL.
+    async fn serve_jwks(jwks: Value) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/jwks.json", listener.local_addr().unwrap());
+        let jwks = jwks.to_string();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf).await.unwrap() {
+                        0 => break,
+                        n => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let (status, body) = match request.starts_with(b"GET /jwks.json ") {
+                    true => ("200 OK", jwks.as_str()),
+                    false => ("404 Not Found", ""),
+                };
+                let response = format!(
+                    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn from_url() {
+        let url = serve_jwks(json!({"keys": [
+            {"kty": "RSA", "kid": "rsa", "use": "sig", "n": RSA_N, "e": RSA_E},
+            {"kty": "EC", "kid": "ec", "alg": "ES256", "crv": "P-256", "x": EC_X, "y": EC_Y},
+            {"kty": "OKP", "kid": "ed", "crv": "Ed25519", "x": ED_X},
+        ]}))
+        .await;
+        let provider = JwksProvider::from_url(&url).with_exp_required(false);
+        let claims = json!({"sub": "alice"});
+
+        for (algorithm, kid, public, private) in [
+            (JwtAlgorithm::RS256, "rsa", RSA_PUBLIC_KEY, RSA_PRIVATE_KEY),
+            (JwtAlgorithm::PS384, "rsa", RSA_PUBLIC_KEY, RSA_PRIVATE_KEY),
+            (JwtAlgorithm::ES256, "ec", EC_PUBLIC_KEY, EC_PRIVATE_KEY),
+            (JwtAlgorithm::EdDSA, "ed", ED_PUBLIC_KEY, ED_PRIVATE_KEY),
+        ] {
+            let key = JwtKey::from_public_pem(algorithm, public)
+                .unwrap()
+                .with_private_pem(private)
+                .unwrap();
+            let token = key.with_kid(kid).encode(&claims).unwrap();
+            assert_eq!(provider.verify(&token).await, Ok(claims.clone()));
+        }
+
+        // the algorithm of the token must be supported by the key
+        let key = JwtKey::from_public_pem(JwtAlgorithm::ES256, EC_PUBLIC_KEY)
+            .unwrap()
+            .with_private_pem(EC_PRIVATE_KEY)
+            .unwrap()
+            .with_kid("rsa");
+        assert_eq!(
+            provider
+                .verify::<Value>(&key.encode(&claims).unwrap())
+                .await,
+            Err(JwtError::UnknownKey)
+        );
+
+        let provider = JwksProvider::from_url(format!("{url}/missing"));
+        let token = JwtKey::hs256(b"secret").encode(&claims).unwrap();
+        assert_eq!(
+            provider.verify::<Value>(&token).await,
+            Err(JwtError::KeysUnavailable)
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Support for conditional requests using `Last-Modified` and
+//! `If-Modified-Since` headers.
+//!
+//! Endpoints that return resources with a modification timestamp (e.g. the
+//! `updated_at` column of a sea-orm model) can return a [`ModifiedSince`]
+//! response, which adds a `Last-Modified` header to the response or returns
+//! `304 Not Modified` if the resource has not been modified since the time in
+//! the `If-Modified-Since` header of the request. Any timestamp that can be
+//! converted into a [`SystemTime`] can be used, including
+//! `chrono::DateTime<Tz>` and `time::OffsetDateTime`. For collections,
+//! [`LastModified::latest`] returns the most recent timestamp of all items.
+//!
+//! As HTTP dates only have a precision of one second, timestamps are truncated
+//! to whole seconds. If the client also sends an `If-None-Match` header, the
+//! [`ETag`](crate::etag) based validation should take precedence.
+//!
+//! #### Example
+//! ```
+//! use std::time::SystemTime;
+//!
+//! use poem_ext::{
+//!     last_modified::{LastModified, ModifiedSince},
+//!     responses::Response,
+//! };
+//! use poem_openapi::{param::Header, payload::Json, Object, OpenApi};
+//!
+//! #[derive(Debug, Object)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/user", method = "get")]
+//!     async fn get_user(
+//!         &self,
+//!         #[oai(name = "If-Modified-Since")] if_modified_since: Header<Option<String>>,
+//!     ) -> Response<ModifiedSince<Json<User>>> {
+//!         // load the user from the database
+//!         let (user, updated_at) = (User { name: "test".into() }, SystemTime::now());
+//!         let last_modified = LastModified::new(updated_at);
+//!         Ok(ModifiedSince::new(if_modified_since.as_deref(), last_modified, Json(user)).into())
+//!     }
+//! }
+//! ```
+
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use poem::{
+    http::{header::LAST_MODIFIED, HeaderValue, StatusCode},
+    web::headers::{self, Header, HeaderMapExt},
+    IntoResponse, Response,
+};
+use poem_openapi::{
+    registry::{MetaHeader, MetaResponse, MetaResponses, Registry},
+    types::Type,
+    ApiResponse,
+};
+
+/// The time of the last modification of a resource, truncated to whole
+/// seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LastModified(SystemTime);
+
+impl LastModified {
+    /// Create a LastModified from a timestamp (e.g. the `updated_at` column of
+    /// a model).
+    pub fn new(time: impl Into<SystemTime>) -> Self {
+        let time = time.into();
+        Self(match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => UNIX_EPOCH + Duration::from_secs(duration.as_secs()),
+            Err(_) => UNIX_EPOCH,
+        })
+    }
+
+    /// Return the most recent of the given timestamps (e.g. of all items of a
+    /// collection), or `None` if there are no timestamps.
+    pub fn latest<I>(times: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: Into<SystemTime>,
+    {
+        times.into_iter().map(Self::new).max()
+    }
+
+    /// Return the time of the last modification.
+    pub fn time(&self) -> SystemTime {
+        self.0
+    }
+
+    /// Check whether the resource has been modified since the time in the
+    /// value of an `If-Modified-Since` header. Invalid dates are ignored, i.e.
+    /// the resource is considered to be modified.
+    pub fn is_modified_since(&self, if_modified_since: &str) -> bool {
+        HeaderValue::from_str(if_modified_since)
+            .ok()
+            .and_then(|value| headers::IfModifiedSince::decode(&mut [value].iter()).ok())
+            .map_or(true, |since| since.is_modified(self.0))
+    }
+}
+
+impl Display for LastModified {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut headers = poem::http::HeaderMap::new();
+        headers.typed_insert(headers::LastModified::from(self.0));
+        let value = headers.get(LAST_MODIFIED).and_then(|x| x.to_str().ok());
+        f.write_str(value.unwrap_or_default())
+    }
+}
+
+/// Response wrapper that adds a `Last-Modified` header to the response or
+/// returns `304 Not Modified` if the resource has not been modified since the
+/// client has last retrieved it.
+///
+/// Both the `Last-Modified` header and the `304` response are added to the
+/// OpenAPI documentation of the endpoint.
+#[derive(Debug)]
+pub enum ModifiedSince<T> {
+    /// The resource has been modified.
+    Modified {
+        /// The time of the last modification of the resource.
+        last_modified: LastModified,
+        /// The response to return.
+        value: T,
+    },
+    /// The resource has not been modified.
+    NotModified {
+        /// The time of the last modification of the resource.
+        last_modified: LastModified,
+    },
+}
+
+impl<T> ModifiedSince<T> {
+    /// Create a new response, which is `304 Not Modified` if the resource has
+    /// not been modified since the time in the `If-Modified-Since` header.
+    pub fn new(if_modified_since: Option<&str>, last_modified: LastModified, value: T) -> Self {
+        Self::new_with(if_modified_since, last_modified, || value)
+    }
+
+    /// Create a new response like [`ModifiedSince::new`], but only construct
+    /// the actual response if it is needed.
+    pub fn new_with(
+        if_modified_since: Option<&str>,
+        last_modified: LastModified,
+        value: impl FnOnce() -> T,
+    ) -> Self {
+        match if_modified_since {
+            Some(since) if !last_modified.is_modified_since(since) => {
+                Self::NotModified { last_modified }
+            }
+            _ => Self::Modified {
+                last_modified,
+                value: value(),
+            },
+        }
+    }
+}
+
+fn last_modified_header() -> MetaHeader {
+    MetaHeader {
+        name: "Last-Modified".into(),
+        description: Some("The time of the last modification of the resource.".into()),
+        required: true,
+        deprecated: false,
+        schema: String::schema_ref(),
+    }
+}
+
+impl<T: ApiResponse> ApiResponse for ModifiedSince<T> {
+    fn meta() -> MetaResponses {
+        let mut responses = T::meta().responses;
+        for response in &mut responses {
+            response.headers.push(last_modified_header());
+        }
+        responses.push(MetaResponse {
+            description: "Not Modified",
+            status: Some(304),
+            content: vec![],
+            headers: vec![last_modified_header()],
+        });
+        MetaResponses { responses }
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for ModifiedSince<T> {
+    fn into_response(self) -> Response {
+        let (mut resp, last_modified) = match self {
+            ModifiedSince::Modified {
+                last_modified,
+                value,
+            } => (value.into_response(), last_modified),
+            ModifiedSince::NotModified { last_modified } => {
+                (StatusCode::NOT_MODIFIED.into_response(), last_modified)
+            }
+        };
+        resp.headers_mut()
+            .typed_insert(headers::LastModified::from(last_modified.0));
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{http::header::IF_MODIFIED_SINCE, test::TestClient};
+    use poem_openapi::{param::Header, payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+
+    fn time(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn last_modified() {
+        let last_modified = LastModified::new(time(784111777) + Duration::from_millis(500));
+        assert_eq!(last_modified.time(), time(784111777));
+        assert_eq!(last_modified.to_string(), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert!(!last_modified.is_modified_since("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert!(!last_modified.is_modified_since("Mon, 07 Nov 1994 08:49:37 GMT"));
+        assert!(last_modified.is_modified_since("Sat, 05 Nov 1994 08:49:37 GMT"));
+        assert!(last_modified.is_modified_since("garbage"));
+        assert_eq!(
+            LastModified::latest([time(3), time(5), time(1)]),
+            Some(LastModified::new(time(5)))
+        );
+        assert_eq!(LastModified::latest(Vec::<SystemTime>::new()), None);
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test", method = "get")]
+        async fn test(
+            &self,
+            #[oai(name = "If-Modified-Since")] if_modified_since: Header<Option<String>>,
+        ) -> ModifiedSince<PlainText<&'static str>> {
+            ModifiedSince::new(
+                if_modified_since.as_deref(),
+                LastModified::new(time(784111777)),
+                PlainText("test"),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn response() {
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+
+        let resp = cli.get("/test").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_header(LAST_MODIFIED, "Sun, 06 Nov 1994 08:49:37 GMT");
+        resp.assert_text("test").await;
+
+        let resp = cli
+            .get("/test")
+            .header(IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:37 GMT")
+            .send()
+            .await;
+        resp.assert_status(StatusCode::NOT_MODIFIED);
+        resp.assert_header(LAST_MODIFIED, "Sun, 06 Nov 1994 08:49:37 GMT");
+        resp.assert_text("").await;
+
+        let responses = ModifiedSince::<PlainText<String>>::meta().responses;
+        assert_eq!(responses[0].headers[0].name, "Last-Modified");
+        assert_eq!(responses[1].status, Some(304));
+    }
+}
@@ -4,12 +4,204 @@
 #![warn(missing_docs, missing_debug_implementations)]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+#[cfg(feature = "tracing")]
+pub mod access_log;
+pub mod audit_log;
 mod auth;
+pub mod body_capture;
+pub mod body_limit;
+pub mod build_info;
+pub mod cache;
+pub mod client_ip;
+pub mod concurrency_limit;
+pub mod context;
+pub mod cookie;
+pub mod csrf;
 #[cfg(feature = "sea-orm")]
 pub mod db;
+pub mod debug_api;
+pub mod deprecation;
+pub mod error_envelope;
+pub mod error_sink;
+pub mod etag;
+pub mod feature_flag;
+pub mod filter;
+pub mod health;
+#[cfg(feature = "metrics")]
+pub mod http_metrics;
+pub mod identity;
+#[cfg(feature = "sea-orm")]
+pub mod jobs;
+pub mod json_limits;
+#[cfg(feature = "jwks")]
+pub mod jwks;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+pub mod last_modified;
+pub mod lifecycle;
+pub mod links;
+pub mod load_shed;
+pub mod locale;
+pub mod ndjson;
+pub mod negotiate;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod pagination;
 pub mod panic_handler;
 pub mod patch_value;
+pub mod quota;
+pub mod range;
+pub mod rate_limit;
+pub mod replay;
+pub mod request_events;
+pub mod request_id;
 pub mod responses;
+pub mod roles;
+pub mod scopes;
+#[cfg(feature = "sentry")]
+pub mod sentry;
+pub mod server_timing;
 #[cfg(feature = "shield")]
 pub mod shield_mw;
+pub mod single_flight;
+#[cfg(feature = "tracing")]
+pub mod slow_log;
+#[cfg(feature = "sea-orm")]
+pub mod soft_delete;
+pub mod sort;
+pub mod spec;
+mod static_enum;
+mod static_number;
 mod static_string;
+pub mod strict_query;
+mod tagged_union;
+#[cfg(feature = "test")]
+pub mod test;
+pub mod timeout;
+pub mod upload;
+mod utils;
+pub mod validation;
+pub mod versioning;
+pub mod webhook_signing;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+pub use auth::OptionalAuth;
+
+/// Attribute macros.
+pub mod attr {
+    /// Turn a unit struct into an OpenApi type that always evaluates to a
+    /// static string that is set at compile time.
+    ///
+    /// This is the attribute form of the
+    /// [`static_string!`](crate::static_string) macro. The value can be any
+    /// constant expression of type `&'static str` (e.g. a named `const`) and
+    /// doc comments on the struct are used as the schema description.
+    /// Attributes and derives on the struct are kept as they are, [`Default`]
+    /// is implemented automatically.
+    ///
+    /// #### Example
+    /// ```
+    /// use poem_ext::attr::static_string;
+    /// use poem_openapi::{
+    ///     registry::MetaSchemaRef,
+    ///     types::{ParseFromJSON, ToJSON, Type},
+    ///     Object,
+    /// };
+    ///
+    /// const CONFLICT: &str = "conflict";
+    ///
+    /// #[static_string("not found")]
+    /// #[derive(Debug)]
+    /// pub struct NotFoundError;
+    ///
+    /// /// The resource already exists.
+    /// #[static_string(CONFLICT)]
+    /// #[derive(Debug, Clone, Copy)]
+    /// pub struct ConflictError;
+    ///
+    /// #[derive(Debug, Object)]
+    /// struct NotFoundDetails {
+    ///     error: NotFoundError,
+    /// }
+    ///
+    /// let response = NotFoundDetails {
+    ///     error: Default::default(),
+    /// };
+    /// assert_eq!(response.to_json_string(), r#"{"error":"not found"}"#);
+    /// assert!(NotFoundDetails::parse_from_json_string(r#"{"error":"conflict"}"#).is_err());
+    ///
+    /// let MetaSchemaRef::Inline(schema) = ConflictError::schema_ref() else {
+    ///     unreachable!()
+    /// };
+    /// assert_eq!(schema.description, Some("The resource already exists."));
+    /// assert_eq!(schema.enum_items, ["conflict"]);
+    /// ```
+    pub use poem_ext_macros::static_string;
+}
+
+/// Derive macros.
+pub mod derive {
+    /// Generate a response type (an [`Object`](derive@poem_openapi::Object))
+    /// from a model struct (e.g. a sea-orm entity `Model`) together with a
+    /// [`From`] implementation that converts the model into the response type.
+    ///
+    /// The name of the generated struct must be specified using
+    /// `#[dto(name = ...)]`. All fields of the model (including their doc
+    /// comments) are copied, except for fields marked with `#[dto(skip)]`.
+    /// The following attributes are supported:
+    ///
+    /// - `#[dto(name = Name)]`: the name of the generated struct (required)
+    /// - `#[dto(rename_all = "camelCase")]`: rename all fields in the schema
+    /// - `#[dto(computed(field: Type = func))]`: add a field whose value is
+    ///   computed by calling `func` with a reference to the model
+    /// - `#[dto(skip)]` on a field: do not include the field
+    /// - `#[dto(rename = "name")]` on a field: rename the field in the schema
+    ///
+    /// #### Example
+    /// ```
+    /// use poem_ext::derive::Dto;
+    /// use poem_openapi::types::ToJSON;
+    ///
+    /// /// A user of the application.
+    /// #[derive(Debug, Clone, PartialEq, Eq, Dto)]
+    /// #[dto(name = User, computed(display_name: String = display_name))]
+    /// pub struct Model {
+    ///     pub id: i32,
+    ///     /// The login name of the user.
+    ///     pub name: String,
+    ///     #[dto(rename = "admin")]
+    ///     pub is_admin: bool,
+    ///     #[dto(skip)]
+    ///     pub password_hash: String,
+    /// }
+    ///
+    /// fn display_name(user: &Model) -> String {
+    ///     format!("{} (#{})", user.name, user.id)
+    /// }
+    ///
+    /// let model = Model {
+    ///     id: 1,
+    ///     name: "alice".into(),
+    ///     is_admin: true,
+    ///     password_hash: "secret".into(),
+    /// };
+    /// assert_eq!(
+    ///     User::from(model).to_json_string(),
+    ///     r#"{"admin":true,"display_name":"alice (#1)","id":1,"name":"alice"}"#
+    /// );
+    /// ```
+    pub use poem_ext_macros::Dto;
+}
+
+#[doc(hidden)]
+pub mod __private {
+    pub use poem_openapi;
+
+    pub use crate::__static_string_impls;
+    pub use crate::auth::{api_key_from_request, CustomAuth, HasCredentials, KeyLocation};
+    #[cfg(feature = "jwt")]
+    pub use crate::jwt::jwt_claims;
+    #[cfg(feature = "serde")]
+    pub use serde;
+}
@@ -0,0 +1,302 @@
+//! Contains hooks that run when the application starts up and shuts down.
+//!
+//! [`Lifecycle`] runs startup hooks (e.g. warming caches or running
+//! migrations) before the server accepts connections and shutdown hooks (e.g.
+//! flushing buffers or closing database pools) after it has stopped. Before the
+//! shutdown hooks are run, [`Lifecycle`] waits for all
+//! [shielded](crate::shield_mw) handlers to complete, so they can still use
+//! the resources that are released by the hooks.
+//!
+//! Startup hooks are run in the order in which they have been added and abort
+//! the startup if they fail. Shutdown hooks are run in reverse order, and
+//! failures are logged without preventing the remaining hooks from running.
+//!
+//! #### Example
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use poem::{listener::TcpListener, Route, Server};
+//! use poem_ext::lifecycle::Lifecycle;
+//!
+//! # async fn run(api_service: poem_openapi::OpenApiService<(), ()>) -> std::io::Result<()> {
+//! # let shutdown_signal = async {};
+//! let app = Route::new().nest("/", api_service);
+//! Lifecycle::new()
+//!     .on_startup("warm cache", || async {
+//!         // load frequently used data
+//!         Ok::<_, std::io::Error>(())
+//!     })
+//!     .on_shutdown("close database pool", || async {
+//!         // close connections
+//!         Ok::<_, std::io::Error>(())
+//!     })
+//!     .with_shutdown_timeout(Duration::from_secs(5))
+//!     .run(
+//!         Server::new(TcpListener::bind("127.0.0.1:3000")),
+//!         app,
+//!         shutdown_signal,
+//!     )
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    convert::Infallible,
+    fmt::{Debug, Display},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
+
+use poem::{listener::Listener, IntoEndpoint, Server};
+
+#[cfg(feature = "shield")]
+use crate::shield_mw::ShieldTracker;
+
+/// Error returned by a failed lifecycle hook.
+pub type HookError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A hook that runs when the application starts up or shuts down.
+///
+/// This trait is implemented for async functions that return a [`Result`].
+#[poem::async_trait]
+pub trait LifecycleHook: Send + Sync + 'static {
+    /// Run the hook.
+    async fn run(&self) -> Result<(), HookError>;
+}
+
+#[poem::async_trait]
+impl<F, Fut, E> LifecycleHook for F
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), E>> + Send,
+    E: Into<HookError>,
+{
+    async fn run(&self) -> Result<(), HookError> {
+        self().await.map_err(Into::into)
+    }
+}
+
+/// Error returned by [`Lifecycle::startup`] if a startup hook has failed.
+#[derive(Debug)]
+pub struct StartupError {
+    /// The name of the hook that has failed.
+    pub hook: String,
+    /// The error returned by the hook.
+    pub error: HookError,
+}
+
+impl Display for StartupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "startup hook `{}` failed: {}", self.hook, self.error)
+    }
+}
+
+impl std::error::Error for StartupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+type NamedHook = (String, Arc<dyn LifecycleHook>);
+
+/// Startup and shutdown hooks of an application.
+#[derive(Clone, Default)]
+pub struct Lifecycle {
+    startup: Vec<NamedHook>,
+    shutdown: Vec<NamedHook>,
+    shutdown_timeout: Option<Duration>,
+    #[cfg(feature = "shield")]
+    tracker: Option<ShieldTracker>,
+}
+
+impl Debug for Lifecycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn names(hooks: &[NamedHook]) -> Vec<&str> {
+            hooks.iter().map(|(name, _)| name.as_str()).collect()
+        }
+        f.debug_struct("Lifecycle")
+            .field("startup", &names(&self.startup))
+            .field("shutdown", &names(&self.shutdown))
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Lifecycle {
+    /// Create a new Lifecycle without any hooks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a hook that runs before the server accepts connections.
+    pub fn on_startup(mut self, name: impl Into<String>, hook: impl LifecycleHook) -> Self {
+        self.startup.push((name.into(), Arc::new(hook)));
+        self
+    }
+
+    /// Add a hook that runs after the server has stopped.
+    pub fn on_shutdown(mut self, name: impl Into<String>, hook: impl LifecycleHook) -> Self {
+        self.shutdown.push((name.into(), Arc::new(hook)));
+        self
+    }
+
+    /// Set the maximum duration to wait for running requests to complete
+    /// after the shutdown signal has been received (see
+    /// [`Server::run_with_graceful_shutdown`]).
+    pub fn with_shutdown_timeout(self, timeout: Duration) -> Self {
+        Self {
+            shutdown_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Wait for the shielded handlers that are registered with the given
+    /// [`ShieldTracker`] instead of the [global](ShieldTracker::global) one
+    /// before running the shutdown hooks.
+    #[cfg(feature = "shield")]
+    pub fn with_shield_tracker(self, tracker: ShieldTracker) -> Self {
+        Self {
+            tracker: Some(tracker),
+            ..self
+        }
+    }
+
+    /// Run the startup hooks.
+    pub async fn startup(&self) -> Result<(), StartupError> {
+        for (name, hook) in &self.startup {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(hook = name, "running startup hook");
+            hook.run().await.map_err(|error| StartupError {
+                hook: name.clone(),
+                error,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Wait for all shielded handlers to complete and run the shutdown hooks.
+    pub async fn shutdown(&self) {
+        #[cfg(feature = "shield")]
+        self.tracker
+            .as_ref()
+            .unwrap_or_else(|| ShieldTracker::global())
+            .wait_idle()
+            .await;
+
+        #[allow(unused_variables)]
+        for (name, hook) in self.shutdown.iter().rev() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(hook = name, "running shutdown hook");
+            if let Err(err) = hook.run().await {
+                #[cfg(feature = "tracing")]
+                tracing::error!(hook = name, error = %err, "shutdown hook failed");
+            }
+        }
+    }
+
+    /// Run the startup hooks, the server until the shutdown signal has been
+    /// received and the shutdown hooks.
+    ///
+    /// The server is not started if a startup hook fails.
+    pub async fn run<L, E>(
+        &self,
+        server: Server<L, Infallible>,
+        ep: E,
+        signal: impl Future<Output = ()>,
+    ) -> std::io::Result<()>
+    where
+        L: Listener + 'static,
+        E: IntoEndpoint,
+        E::Endpoint: 'static,
+    {
+        self.startup().await.map_err(std::io::Error::other)?;
+        let result = server
+            .run_with_graceful_shutdown(ep, signal, self.shutdown_timeout)
+            .await;
+        self.shutdown().await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn record(
+        log: &Arc<Mutex<Vec<&'static str>>>,
+        name: &'static str,
+        fail: bool,
+    ) -> impl LifecycleHook {
+        let log = log.clone();
+        move || {
+            let log = log.clone();
+            async move {
+                log.lock().unwrap().push(name);
+                if fail {
+                    Err("failed")
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn startup() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let lifecycle = Lifecycle::new()
+            .on_startup("a", record(&log, "a", false))
+            .on_startup("b", record(&log, "b", true))
+            .on_startup("c", record(&log, "c", false));
+
+        let err = lifecycle.startup().await.unwrap_err();
+        assert_eq!(err.hook, "b");
+        assert_eq!(err.to_string(), "startup hook `b` failed: failed");
+        assert_eq!(*log.lock().unwrap(), ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let lifecycle = Lifecycle::new()
+            .on_shutdown("a", record(&log, "a", false))
+            .on_shutdown("b", record(&log, "b", true))
+            .on_shutdown("c", record(&log, "c", false));
+
+        lifecycle.shutdown().await;
+        assert_eq!(*log.lock().unwrap(), ["c", "b", "a"]);
+    }
+
+    #[cfg(feature = "shield")]
+    #[tokio::test]
+    async fn shutdown_waits_for_shielded_handlers() {
+        use poem::{handler, test::TestClient, EndpointExt};
+
+        use crate::shield_mw::ShieldMiddleware;
+
+        #[handler]
+        async fn slow() {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let tracker = ShieldTracker::new();
+        let app = slow.with(ShieldMiddleware::custom().with_tracker(tracker.clone()));
+        let lifecycle = Lifecycle::new()
+            .with_shield_tracker(tracker.clone())
+            .on_shutdown("a", record(&log, "a", false));
+
+        let request = tokio::spawn(async move { TestClient::new(app).get("/").send().await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        request.abort();
+        assert_eq!(tracker.in_flight(), 1);
+
+        lifecycle.shutdown().await;
+        assert_eq!(tracker.in_flight(), 0);
+        assert_eq!(*log.lock().unwrap(), ["a"]);
+    }
+}
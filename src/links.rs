@@ -0,0 +1,474 @@
+//! Contains utilities for building hypermedia links.
+//!
+//! A [`LinkRegistry`] is created from the OpenAPI document of an
+//! [`OpenApiService`] at startup and maps the operation ids of the endpoints
+//! (set via `#[oai(operation_id = "...")]`) to their paths. Endpoints use it
+//! to generate [`Links`] (e.g. `self`, `next` and `prev`) from operation ids
+//! and parameters instead of formatting urls by hand. Parameters that appear
+//! in the path of the operation are substituted, all other parameters are
+//! appended to the query string.
+//!
+//! Use [`LinkRegistry::require`] to check at startup that all operation ids
+//! used for links exist. Building a link with an unknown operation id or
+//! without all path parameters fails with a [`LinkError`], which results in
+//! `500 Internal Server Error`.
+//!
+//! #### Example
+//! ```
+//! use poem::{web::Data, EndpointExt, Route};
+//! use poem_ext::{
+//!     links::{LinkRegistry, Links},
+//!     responses::Response,
+//! };
+//! use poem_openapi::{param::Path, payload::Json, Object, OpenApi, OpenApiService};
+//!
+//! #[derive(Debug, Object)]
+//! struct User {
+//!     id: u64,
+//!     #[oai(rename = "_links")]
+//!     links: Links,
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/users/:id", method = "get", operation_id = "get_user")]
+//!     async fn get_user(
+//!         &self,
+//!         links: Data<&LinkRegistry>,
+//!         id: Path<u64>,
+//!     ) -> Response<Json<User>> {
+//!         let links = links
+//!             .links()
+//!             .self_("get_user", [("id", id.0)])
+//!             .link("posts", "list_posts", [("user_id", id.0), ("limit", 20)])
+//!             .build()?;
+//!         Ok(Json(User { id: id.0, links }).into())
+//!     }
+//!
+//!     #[oai(path = "/posts", method = "get", operation_id = "list_posts")]
+//!     async fn list_posts(&self) -> Json<Vec<String>> {
+//!         Json(Vec::new())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let links = LinkRegistry::new(&api_service)
+//!     .with_prefix("/api")
+//!     .require(["get_user", "list_posts"]);
+//! let app = Route::new().nest("/api", api_service).data(links);
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt::{Display, Write},
+    sync::Arc,
+};
+
+use poem::{error::ResponseError, http::StatusCode};
+use poem_openapi::{
+    registry::{MetaSchemaRef, Registry},
+    types::{ParseFromJSON, ParseResult, ToJSON, Type},
+    Object, OpenApi, OpenApiService, Webhook,
+};
+use serde_json::Value;
+
+/// A hypermedia link.
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct Link {
+    /// The url of the linked resource.
+    pub href: String,
+}
+
+/// Hypermedia links keyed by their relation (e.g. `self`, `next` or `prev`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Links(pub BTreeMap<String, Link>);
+
+impl Links {
+    /// Return the link with the given relation.
+    pub fn get(&self, rel: &str) -> Option<&Link> {
+        self.0.get(rel)
+    }
+}
+
+impl Type for Links {
+    const IS_REQUIRED: bool = true;
+
+    type RawValueType = Self;
+
+    type RawElementValueType = Link;
+
+    fn name() -> Cow<'static, str> {
+        "Links".into()
+    }
+
+    fn schema_ref() -> MetaSchemaRef {
+        BTreeMap::<String, Link>::schema_ref()
+    }
+
+    fn register(registry: &mut Registry) {
+        BTreeMap::<String, Link>::register(registry);
+    }
+
+    fn as_raw_value(&self) -> Option<&Self::RawValueType> {
+        Some(self)
+    }
+
+    fn raw_element_iter<'a>(
+        &'a self,
+    ) -> Box<dyn Iterator<Item = &'a Self::RawElementValueType> + 'a> {
+        Box::new(self.0.values())
+    }
+}
+
+impl ParseFromJSON for Links {
+    fn parse_from_json(value: Option<Value>) -> ParseResult<Self> {
+        BTreeMap::parse_from_json(value)
+            .map(Self)
+            .map_err(|err| err.propagate())
+    }
+}
+
+impl ToJSON for Links {
+    fn to_json(&self) -> Option<Value> {
+        self.0.to_json()
+    }
+}
+
+/// Error returned if a link cannot be built. Results in
+/// `500 Internal Server Error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    /// No operation with the given id exists.
+    UnknownOperation(String),
+    /// A path parameter of the operation has not been provided.
+    MissingParameter {
+        /// The id of the operation.
+        operation_id: String,
+        /// The name of the missing path parameter.
+        param: String,
+    },
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownOperation(operation_id) => {
+                write!(f, "unknown operation `{operation_id}`")
+            }
+            Self::MissingParameter {
+                operation_id,
+                param,
+            } => write!(
+                f,
+                "missing path parameter `{param}` for operation `{operation_id}`"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+impl ResponseError for LinkError {
+    fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Maps operation ids to paths to build links.
+///
+/// Has to be attached to the endpoint using
+/// [`data`](poem::EndpointExt::data) to be accessible in endpoints.
+#[derive(Debug, Clone)]
+pub struct LinkRegistry {
+    prefix: String,
+    paths: Arc<HashMap<String, String>>,
+}
+
+impl LinkRegistry {
+    /// Create a new LinkRegistry that contains all operations of the given
+    /// service that have an operation id.
+    pub fn new<T, W>(service: &OpenApiService<T, W>) -> Self
+    where
+        T: OpenApi,
+        W: Webhook,
+    {
+        Self::from_spec(&service.spec())
+    }
+
+    /// Create a new LinkRegistry from an OpenAPI document in JSON format.
+    pub fn from_spec(spec: &str) -> Self {
+        let spec = serde_json::from_str::<Value>(spec).unwrap_or_default();
+        let paths = spec
+            .get("paths")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .flat_map(|(path, operations)| {
+                operations
+                    .as_object()
+                    .into_iter()
+                    .flat_map(|operations| operations.values())
+                    .filter_map(|operation| operation.get("operationId")?.as_str())
+                    .map(|operation_id| (operation_id.to_owned(), path.clone()))
+            })
+            .collect();
+        Self {
+            prefix: String::new(),
+            paths: Arc::new(paths),
+        }
+    }
+
+    /// Set the path prefix of all links, i.e. the path at which the service
+    /// is nested (e.g. `/api`).
+    pub fn with_prefix(self, prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into().trim_end_matches('/').into(),
+            ..self
+        }
+    }
+
+    /// Check that operations with the given ids exist.
+    ///
+    /// # Panics
+    /// Panics if one of the operations does not exist.
+    pub fn require<'a>(self, operation_ids: impl IntoIterator<Item = &'a str>) -> Self {
+        for operation_id in operation_ids {
+            assert!(
+                self.paths.contains_key(operation_id),
+                "{}",
+                LinkError::UnknownOperation(operation_id.into())
+            );
+        }
+        self
+    }
+
+    /// Build the url of the operation with the given id. Parameters that
+    /// appear in the path of the operation are substituted, all other
+    /// parameters are appended to the query string.
+    pub fn url<'a, V: Display>(
+        &self,
+        operation_id: &str,
+        params: impl IntoIterator<Item = (&'a str, V)>,
+    ) -> Result<String, LinkError> {
+        let path = self
+            .paths
+            .get(operation_id)
+            .ok_or_else(|| LinkError::UnknownOperation(operation_id.into()))?;
+        let mut params = params
+            .into_iter()
+            .map(|(name, value)| (name, value.to_string()))
+            .collect::<Vec<_>>();
+
+        let mut url = self.prefix.clone();
+        let mut rest = path.as_str();
+        while let Some((before, after)) = rest.split_once('{') {
+            let (param, after) = after.split_once('}').unwrap_or((after, ""));
+            let idx = params
+                .iter()
+                .position(|(name, _)| *name == param)
+                .ok_or_else(|| LinkError::MissingParameter {
+                    operation_id: operation_id.into(),
+                    param: param.into(),
+                })?;
+            url.push_str(before);
+            percent_encode(&mut url, &params.remove(idx).1);
+            rest = after;
+        }
+        url.push_str(rest);
+
+        for (i, (name, value)) in params.iter().enumerate() {
+            url.push(if i == 0 { '?' } else { '&' });
+            percent_encode(&mut url, name);
+            url.push('=');
+            percent_encode(&mut url, value);
+        }
+        Ok(url)
+    }
+
+    /// Create a new builder for [`Links`].
+    pub fn links(&self) -> LinksBuilder<'_> {
+        LinksBuilder {
+            registry: self,
+            links: Ok(Links::default()),
+        }
+    }
+}
+
+/// Builder for [`Links`] (see [`LinkRegistry::links`]).
+#[derive(Debug)]
+pub struct LinksBuilder<'r> {
+    registry: &'r LinkRegistry,
+    links: Result<Links, LinkError>,
+}
+
+impl LinksBuilder<'_> {
+    /// Add a link with the given relation to the operation with the given id
+    /// (see [`LinkRegistry::url`]).
+    pub fn link<'a, V: Display>(
+        mut self,
+        rel: impl Into<String>,
+        operation_id: &str,
+        params: impl IntoIterator<Item = (&'a str, V)>,
+    ) -> Self {
+        if let Ok(links) = &mut self.links {
+            match self.registry.url(operation_id, params) {
+                Ok(href) => {
+                    links.0.insert(rel.into(), Link { href });
+                }
+                Err(err) => self.links = Err(err),
+            }
+        }
+        self
+    }
+
+    /// Add a `self` link.
+    pub fn self_<'a, V: Display>(
+        self,
+        operation_id: &str,
+        params: impl IntoIterator<Item = (&'a str, V)>,
+    ) -> Self {
+        self.link("self", operation_id, params)
+    }
+
+    /// Add a `next` link.
+    pub fn next<'a, V: Display>(
+        self,
+        operation_id: &str,
+        params: impl IntoIterator<Item = (&'a str, V)>,
+    ) -> Self {
+        self.link("next", operation_id, params)
+    }
+
+    /// Add a `prev` link.
+    pub fn prev<'a, V: Display>(
+        self,
+        operation_id: &str,
+        params: impl IntoIterator<Item = (&'a str, V)>,
+    ) -> Self {
+        self.link("prev", operation_id, params)
+    }
+
+    /// Return the links or the first error that occurred.
+    pub fn build(self) -> Result<Links, LinkError> {
+        self.links
+    }
+}
+
+/// Append `value` to `out`, percent encoding all characters that are not
+/// unreserved.
+fn percent_encode(out: &mut String, value: &str) {
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            let _ = write!(out, "%{b:02X}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, web::Data, EndpointExt};
+    use poem_openapi::{param::Path, payload::Json};
+
+    use super::*;
+
+    #[derive(Debug, Object)]
+    struct User {
+        id: u64,
+        #[oai(rename = "_links")]
+        links: Links,
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/users/:id", method = "get", operation_id = "get_user")]
+        async fn get_user(
+            &self,
+            links: Data<&LinkRegistry>,
+            id: Path<u64>,
+        ) -> poem::Result<Json<User>> {
+            let links = links
+                .links()
+                .self_("get_user", [("id", id.0)])
+                .next("get_user", [("id", id.0 + 1)])
+                .link("posts", "list_posts", [("user_id", id.0), ("limit", 10)])
+                .build()?;
+            Ok(Json(User { id: id.0, links }))
+        }
+
+        #[oai(
+            path = "/users/:user_id/posts",
+            method = "get",
+            operation_id = "list_posts"
+        )]
+        async fn list_posts(&self, _user_id: Path<u64>) -> Json<Vec<String>> {
+            Json(Vec::new())
+        }
+
+        #[oai(path = "/other", method = "get")]
+        async fn other(&self) {}
+    }
+
+    #[test]
+    fn url() {
+        let links = LinkRegistry::new(&OpenApiService::new(Api, "test", "1"))
+            .with_prefix("/api/")
+            .require(["get_user", "list_posts"]);
+
+        assert_eq!(links.url("get_user", [("id", 7)]).unwrap(), "/api/users/7");
+        assert_eq!(
+            links
+                .url("list_posts", [("q", "a b&c"), ("user_id", "x/y")])
+                .unwrap(),
+            "/api/users/x%2Fy/posts?q=a%20b%26c"
+        );
+        assert_eq!(
+            links.url("foo", [("id", 7)]),
+            Err(LinkError::UnknownOperation("foo".into()))
+        );
+        assert_eq!(
+            links.url("get_user", [("foo", 7)]),
+            Err(LinkError::MissingParameter {
+                operation_id: "get_user".into(),
+                param: "id".into()
+            })
+        );
+        assert!(links
+            .links()
+            .self_("get_user", [("id", 1)])
+            .link("foo", "foo", [("id", 1)])
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    #[should_panic = "unknown operation `foo`"]
+    fn require() {
+        LinkRegistry::new(&OpenApiService::new(Api, "test", "1")).require(["foo"]);
+    }
+
+    #[tokio::test]
+    async fn links() {
+        let service = OpenApiService::new(Api, "test", "1");
+        let links = LinkRegistry::new(&service);
+        let cli = TestClient::new(service.data(links));
+
+        let resp = cli.get("/users/42").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_json(serde_json::json!({
+            "id": 42,
+            "_links": {
+                "self": {"href": "/users/42"},
+                "next": {"href": "/users/43"},
+                "posts": {"href": "/users/42/posts?limit=10"},
+            },
+        }))
+        .await;
+    }
+}
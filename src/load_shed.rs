@@ -0,0 +1,326 @@
+//! Contains a middleware that sheds load if the latency of the service
+//! exceeds a target.
+//!
+//! The middleware records the latency of all requests within a rolling window
+//! and computes its 95th percentile. If it exceeds the configured target, a
+//! fraction of the requests, which grows with the ratio between the measured
+//! and the target latency, is rejected with a [`ServiceUnavailable`] response
+//! and a `Retry-After` header. Unlike the
+//! [`ConcurrencyLimitMiddleware`](crate::concurrency_limit::ConcurrencyLimitMiddleware),
+//! this does not require knowing the capacity of the service in advance.
+//!
+//! #### Example
+//! ```
+//! use std::time::Duration;
+//!
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     add_response_schemas,
+//!     load_shed::LoadShedMiddleware,
+//!     responses::{Response, ServiceUnavailable},
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! /// Marker type used to document the 503 response.
+//! struct Overloaded;
+//! add_response_schemas!(Overloaded, ServiceUnavailable::raw::Response);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> Response<PlainText<&'static str>, Overloaded> {
+//!         Ok(PlainText("Hello World!").into())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     // keep the p95 latency below 250ms
+//!     LoadShedMiddleware::new(Duration::from_millis(250)).with_window(Duration::from_secs(30)),
+//! );
+//! ```
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use poem::{http::header::RETRY_AFTER, Endpoint, IntoResponse, Middleware, Request, Response};
+
+use crate::responses::ServiceUnavailable;
+
+/// Maximum number of latency samples that are kept in the window.
+const MAX_SAMPLES: usize = 10_000;
+
+/// Minimum time between two updates of the shed fraction.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A middleware that rejects a fraction of the requests if the p95 latency
+/// exceeds a target.
+///
+/// Clones of this middleware share their state, so a single instance can be
+/// used to protect multiple endpoints.
+#[derive(Debug, Clone)]
+pub struct LoadShedMiddleware {
+    config: LoadShedConfig,
+    state: Arc<LoadShedState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LoadShedConfig {
+    target: Duration,
+    window: Duration,
+    min_samples: usize,
+    max_shed: f64,
+    retry_after: Duration,
+}
+
+impl LoadShedMiddleware {
+    /// Create a new LoadShedMiddleware that starts shedding load if the p95
+    /// latency exceeds `target`.
+    pub fn new(target: Duration) -> Self {
+        Self {
+            config: LoadShedConfig {
+                target,
+                window: Duration::from_secs(10),
+                min_samples: 100,
+                max_shed: 0.9,
+                retry_after: Duration::from_secs(1),
+            },
+            state: Default::default(),
+        }
+    }
+
+    /// Set the duration of the rolling window in which latencies are recorded
+    /// (default: ten seconds).
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.config.window = window;
+        self
+    }
+
+    /// Set the minimum number of requests in the window that are required
+    /// before any load is shed (default: 100).
+    pub fn with_min_samples(mut self, min_samples: usize) -> Self {
+        self.config.min_samples = min_samples;
+        self
+    }
+
+    /// Set the maximum fraction of requests that are rejected (default: 0.9).
+    ///
+    /// Some requests have to be let through to detect that the latency has
+    /// recovered.
+    pub fn with_max_shed(mut self, max_shed: f64) -> Self {
+        self.config.max_shed = max_shed.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the value of the `Retry-After` header of rejected requests
+    /// (default: one second).
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.config.retry_after = retry_after;
+        self
+    }
+
+    /// Return the fraction of requests that is currently rejected.
+    pub fn shed_fraction(&self) -> f64 {
+        f64::from_bits(self.state.fraction.load(Ordering::Relaxed))
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for LoadShedMiddleware {
+    type Output = LoadShedMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        LoadShedMwEndpoint {
+            inner: ep,
+            config: self.config,
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct LoadShedMwEndpoint<E> {
+    inner: E,
+    config: LoadShedConfig,
+    state: Arc<LoadShedState>,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for LoadShedMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        if self.state.shed() {
+            let mut resp = ServiceUnavailable::raw::service_unavailable().into_response();
+            resp.headers_mut()
+                .insert(RETRY_AFTER, self.config.retry_after.as_secs().max(1).into());
+            return Ok(resp);
+        }
+
+        let start = Instant::now();
+        let result = self.inner.call(req).await.map(IntoResponse::into_response);
+        self.state.record(&self.config, start, Instant::now());
+        result
+    }
+}
+
+#[derive(Debug, Default)]
+struct LoadShedState {
+    /// The fraction of requests to reject, stored as the bits of an `f64`.
+    fraction: AtomicU64,
+    /// Number of requests that have been checked.
+    requests: AtomicU64,
+    samples: Mutex<Samples>,
+}
+
+#[derive(Debug, Default)]
+struct Samples {
+    /// Completion time and latency of recent requests.
+    samples: VecDeque<(Instant, Duration)>,
+    last_update: Option<Instant>,
+}
+
+impl LoadShedState {
+    /// Decide whether the next request should be rejected.
+    ///
+    /// Instead of rolling a die, the rejected requests are spread evenly: the
+    /// n-th request is rejected if `n * fraction` crosses an integer.
+    fn shed(&self) -> bool {
+        let fraction = f64::from_bits(self.fraction.load(Ordering::Relaxed));
+        if fraction <= 0.0 {
+            return false;
+        }
+        let n = self.requests.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * fraction).floor() > (n * fraction).floor()
+    }
+
+    fn record(&self, config: &LoadShedConfig, start: Instant, now: Instant) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.samples.len() >= MAX_SAMPLES {
+            samples.samples.pop_front();
+        }
+        samples
+            .samples
+            .push_back((now, now.saturating_duration_since(start)));
+
+        if samples
+            .last_update
+            .is_some_and(|last| now.saturating_duration_since(last) < UPDATE_INTERVAL)
+        {
+            return;
+        }
+        samples.last_update = Some(now);
+        while samples
+            .samples
+            .front()
+            .is_some_and(|(time, _)| now.saturating_duration_since(*time) > config.window)
+        {
+            samples.samples.pop_front();
+        }
+
+        let fraction = match p95(&samples.samples) {
+            Some(p95) if samples.samples.len() >= config.min_samples && p95 > config.target => {
+                (1.0 - config.target.as_secs_f64() / p95.as_secs_f64()).min(config.max_shed)
+            }
+            _ => 0.0,
+        };
+        self.fraction.store(fraction.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Return the 95th percentile of the latencies of the given samples.
+fn p95(samples: &VecDeque<(Instant, Duration)>) -> Option<Duration> {
+    let mut latencies = samples.iter().map(|(_, x)| *x).collect::<Vec<_>>();
+    if latencies.is_empty() {
+        return None;
+    }
+    let index = (latencies.len() * 95).div_ceil(100) - 1;
+    let (_, p95, _) = latencies.select_nth_unstable(index);
+    Some(*p95)
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::StatusCode, test::TestClient, EndpointExt, Route};
+
+    use super::*;
+
+    #[handler]
+    async fn slow() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        "slow"
+    }
+
+    #[handler]
+    fn fast() -> &'static str {
+        "fast"
+    }
+
+    #[test]
+    fn percentile() {
+        let now = Instant::now();
+        let samples = (1..=100)
+            .map(|x| (now, Duration::from_millis(x)))
+            .collect::<VecDeque<_>>();
+        assert_eq!(p95(&samples), Some(Duration::from_millis(95)));
+        assert_eq!(p95(&VecDeque::new()), None);
+    }
+
+    #[test]
+    fn spread() {
+        let state = LoadShedState::default();
+        state.fraction.store(0.25f64.to_bits(), Ordering::Relaxed);
+        let shed = (0..100).filter(|_| state.shed()).count();
+        assert_eq!(shed, 25);
+    }
+
+    #[tokio::test]
+    async fn load_shed() {
+        let mw = LoadShedMiddleware::new(Duration::from_millis(10))
+            .with_min_samples(3)
+            .with_max_shed(0.5)
+            .with_retry_after(Duration::from_secs(3));
+        let cli = TestClient::new(
+            Route::new()
+                .at("/slow", slow)
+                .at("/fast", fast)
+                .with(mw.clone()),
+        );
+
+        for _ in 0..3 {
+            cli.get("/slow").send().await.assert_status_is_ok();
+        }
+        tokio::time::sleep(UPDATE_INTERVAL).await;
+        cli.get("/slow").send().await.assert_status_is_ok();
+        assert_eq!(mw.shed_fraction(), 0.5);
+
+        let mut rejected = 0;
+        for _ in 0..4 {
+            let resp = cli.get("/fast").send().await;
+            if resp.0.status() == StatusCode::SERVICE_UNAVAILABLE {
+                resp.assert_header(RETRY_AFTER, "3");
+                resp.assert_text(r#"{"error":"service_unavailable"}"#).await;
+                rejected += 1;
+            }
+        }
+        assert_eq!(rejected, 2);
+    }
+
+    #[tokio::test]
+    async fn below_target() {
+        let mw = LoadShedMiddleware::new(Duration::from_secs(1)).with_min_samples(1);
+        let cli = TestClient::new(slow.with(mw.clone()));
+        for _ in 0..3 {
+            cli.get("/").send().await.assert_status_is_ok();
+        }
+        assert_eq!(mw.shed_fraction(), 0.0);
+    }
+}
@@ -0,0 +1,346 @@
+//! Contains an extractor for the preferred language of the client.
+//!
+//! The [`Locale`] extractor parses the `Accept-Language` header, ranks the
+//! requested languages by their quality values and picks the best match among
+//! the locales supported by the application (see [`SupportedLocales`]). A
+//! language range matches a supported locale if they are equal or if the
+//! locale is more specific than the range (e.g. `de` matches `de-DE`). If
+//! nothing matches, the range is truncated (e.g. `de-CH` becomes `de`) and
+//! matched again. If no requested language is supported, the first supported
+//! locale is used.
+//!
+//! The supported locales have to be attached to the endpoint using
+//! [`data`](poem::EndpointExt::data). Name the parameter `Accept-Language`
+//! (using `#[oai(name = "Accept-Language")]`) to document the header
+//! correctly.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::locale::{Locale, SupportedLocales};
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/hello", method = "get")]
+//!     async fn hello(
+//!         &self,
+//!         #[oai(name = "Accept-Language")] locale: Locale,
+//!     ) -> PlainText<&'static str> {
+//!         match locale.language() {
+//!             "de" => PlainText("Hallo Welt!"),
+//!             _ => PlainText("Hello World!"),
+//!         }
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .data(SupportedLocales::new(["en", "de-DE"]));
+//! ```
+
+use std::{fmt::Display, ops::Deref, str::FromStr, sync::Arc};
+
+use poem::{
+    http::{header::ACCEPT_LANGUAGE, StatusCode},
+    Request, RequestBody,
+};
+use poem_openapi::{
+    registry::{MetaParamIn, MetaSchema, MetaSchemaRef},
+    ApiExtractor, ApiExtractorType, ExtractParamOptions,
+};
+
+/// A validated and normalized language tag (e.g. `en`, `de-DE` or
+/// `zh-Hant-TW`).
+///
+/// The language subtag is converted to lowercase, region subtags to uppercase
+/// and script subtags to titlecase.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LanguageTag(String);
+
+impl LanguageTag {
+    /// Parse a language tag.
+    pub fn parse(tag: &str) -> Result<Self, String> {
+        let mut normalized = String::with_capacity(tag.len());
+        for (i, subtag) in tag.split('-').enumerate() {
+            let valid = match i {
+                0 => {
+                    (1..=8).contains(&subtag.len())
+                        && subtag.bytes().all(|c| c.is_ascii_alphabetic())
+                }
+                _ => {
+                    (1..=8).contains(&subtag.len())
+                        && subtag.bytes().all(|c| c.is_ascii_alphanumeric())
+                }
+            };
+            if !valid {
+                return Err(format!("invalid language tag {tag:?}"));
+            }
+            if i > 0 {
+                normalized.push('-');
+            }
+            match subtag.len() {
+                _ if i == 0 => normalized.push_str(&subtag.to_ascii_lowercase()),
+                2 => normalized.push_str(&subtag.to_ascii_uppercase()),
+                4 => {
+                    normalized.push_str(&subtag[..1].to_ascii_uppercase());
+                    normalized.push_str(&subtag[1..].to_ascii_lowercase());
+                }
+                _ => normalized.push_str(&subtag.to_ascii_lowercase()),
+            }
+        }
+        Ok(Self(normalized))
+    }
+
+    /// Return the language tag as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Return the primary language subtag (e.g. `de` for `de-DE`).
+    pub fn language(&self) -> &str {
+        self.0.split('-').next().unwrap_or_default()
+    }
+
+    /// Return `true` if this tag is equal to or more specific than `range`.
+    fn matches(&self, range: &str) -> bool {
+        self.0
+            .get(..range.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(range))
+            && matches!(self.0.as_bytes().get(range.len()), None | Some(b'-'))
+    }
+}
+
+impl FromStr for LanguageTag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Display for LanguageTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The locales supported by the application.
+#[derive(Debug, Clone)]
+pub struct SupportedLocales(Arc<[LanguageTag]>);
+
+impl SupportedLocales {
+    /// Create a new list of supported locales. The first locale is used if
+    /// the client does not request any supported locale.
+    ///
+    /// # Panics
+    /// Panics if the list is empty or contains invalid language tags.
+    pub fn new<I>(locales: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let locales = locales
+            .into_iter()
+            .map(|tag| LanguageTag::parse(tag.as_ref()).unwrap())
+            .collect::<Arc<[_]>>();
+        assert!(!locales.is_empty(), "no supported locales");
+        Self(locales)
+    }
+
+    /// Return the default locale.
+    pub fn default_locale(&self) -> &LanguageTag {
+        &self.0[0]
+    }
+
+    /// Return the supported locale that best matches the given value of an
+    /// `Accept-Language` header.
+    pub fn negotiate(&self, accept_language: Option<&str>) -> &LanguageTag {
+        accept_language
+            .into_iter()
+            .flat_map(parse_accept_language)
+            .find_map(|range| self.lookup(range))
+            .unwrap_or_else(|| self.default_locale())
+    }
+
+    fn lookup(&self, mut range: &str) -> Option<&LanguageTag> {
+        if range == "*" {
+            return Some(self.default_locale());
+        }
+        loop {
+            if let Some(tag) = self
+                .0
+                .iter()
+                .find(|tag| tag.as_str().eq_ignore_ascii_case(range))
+                .or_else(|| self.0.iter().find(|tag| tag.matches(range)))
+            {
+                return Some(tag);
+            }
+            range = &range[..range.rfind('-')?];
+        }
+    }
+}
+
+/// Parse the value of an `Accept-Language` header and return the language
+/// ranges ordered by their quality values. Ranges with a quality of `0` and
+/// invalid entries are ignored.
+fn parse_accept_language(value: &str) -> impl Iterator<Item = &str> {
+    let mut ranges = value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let range = parts.next().filter(|range| !range.is_empty())?;
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+            (quality > 0.0).then_some((range, quality))
+        })
+        .collect::<Vec<_>>();
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges.into_iter().map(|(range, _)| range)
+}
+
+/// Extractor for the locale that best matches the `Accept-Language` header
+/// of the request.
+///
+/// Requires [`SupportedLocales`] to be attached to the endpoint, otherwise
+/// the request fails with `500 Internal Server Error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale(pub LanguageTag);
+
+impl Locale {
+    /// Return the locale that best matches the `Accept-Language` header of a
+    /// request, if [`SupportedLocales`] are attached to the endpoint.
+    pub fn of(req: &Request) -> Option<Self> {
+        let supported = req.data::<SupportedLocales>()?;
+        let accept_language = req
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok());
+        Some(Self(supported.negotiate(accept_language).clone()))
+    }
+}
+
+impl Deref for Locale {
+    type Target = LanguageTag;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[poem::async_trait]
+impl<'a> ApiExtractor<'a> for Locale {
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::Parameter];
+
+    type ParamType = Self;
+    type ParamRawType = Self;
+
+    fn param_in() -> Option<MetaParamIn> {
+        Some(MetaParamIn::Header)
+    }
+
+    fn param_schema_ref() -> Option<MetaSchemaRef> {
+        Some(MetaSchemaRef::Inline(Box::new(MetaSchema {
+            description: Some(
+                "Comma separated list of preferred languages, each optionally followed by a \
+                 quality value.",
+            ),
+            example: Some("de-CH, de;q=0.9, en;q=0.8".into()),
+            ..MetaSchema::new("string")
+        })))
+    }
+
+    fn param_raw_type(&self) -> Option<&Self::ParamRawType> {
+        Some(self)
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        _body: &mut RequestBody,
+        _param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        Self::of(request).ok_or_else(|| {
+            poem::Error::from_string(
+                "supported locales have not been configured",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, EndpointExt};
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(LanguageTag::parse("en").unwrap().as_str(), "en");
+        assert_eq!(LanguageTag::parse("DE-de").unwrap().as_str(), "de-DE");
+        assert_eq!(
+            LanguageTag::parse("zh-hant-tw").unwrap().as_str(),
+            "zh-Hant-TW"
+        );
+        assert_eq!(LanguageTag::parse("de-DE").unwrap().language(), "de");
+        assert!(LanguageTag::parse("").is_err());
+        assert!(LanguageTag::parse("en-").is_err());
+        assert!(LanguageTag::parse("1a").is_err());
+        assert!(LanguageTag::parse("en_US").is_err());
+    }
+
+    #[test]
+    fn negotiate() {
+        let supported = SupportedLocales::new(["en", "de-DE", "fr"]);
+        let negotiate = |value| supported.negotiate(value).as_str();
+
+        assert_eq!(negotiate(None), "en");
+        assert_eq!(negotiate(Some("de")), "de-DE");
+        assert_eq!(negotiate(Some("de-CH, fr;q=0.5")), "de-DE");
+        assert_eq!(negotiate(Some("fr;q=0.5, de-de;q=0.9")), "de-DE");
+        assert_eq!(negotiate(Some("en-GB")), "en");
+        assert_eq!(negotiate(Some("es, *;q=0.1")), "en");
+        assert_eq!(negotiate(Some("de;q=0, fr")), "fr");
+        assert_eq!(negotiate(Some("es")), "en");
+        assert_eq!(negotiate(Some("garbage;q=x")), "en");
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test", method = "get")]
+        async fn test(&self, #[oai(name = "Accept-Language")] locale: Locale) -> PlainText<String> {
+            PlainText(locale.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn extractor() {
+        let cli = TestClient::new(
+            OpenApiService::new(Api, "test", "1").data(SupportedLocales::new(["en", "de"])),
+        );
+
+        let resp = cli
+            .get("/test")
+            .header(ACCEPT_LANGUAGE, "de-AT, en;q=0.5")
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text("de").await;
+
+        let resp = cli.get("/test").send().await;
+        resp.assert_text("en").await;
+
+        let resp = TestClient::new(OpenApiService::new(Api, "test", "1"))
+            .get("/test")
+            .send()
+            .await;
+        resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
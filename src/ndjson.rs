@@ -0,0 +1,154 @@
+//! Contains a response type that streams newline-delimited JSON.
+//!
+//! [`NdJsonStream<T>`] writes each item of a stream as a single line of JSON
+//! (`application/x-ndjson`), which is useful for large exports that should
+//! not be buffered in memory. Items are only pulled from the stream when the
+//! client is ready to receive more data, so slow clients do not cause
+//! unbounded buffering on the server. The item schema is included in the
+//! OpenAPI documentation.
+//!
+//! #### Example
+//! ```
+//! use futures_util::stream;
+//! use poem_ext::ndjson::NdJsonStream;
+//! use poem_openapi::{OpenApi, Object};
+//!
+//! #[derive(Debug, Object)]
+//! struct User {
+//!     id: u64,
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/users/export", method = "get")]
+//!     async fn export_users(&self) -> NdJsonStream<User> {
+//!         // e.g. stream the rows of a database query
+//!         NdJsonStream::new(stream::iter((0..1000).map(|id| User { id })))
+//!     }
+//! }
+//! ```
+
+use std::fmt::Debug;
+
+use bytes::Bytes;
+use futures_util::{stream::BoxStream, Stream, StreamExt};
+use poem::{Body, IntoResponse, Response};
+use poem_openapi::{
+    payload::Payload,
+    registry::{MetaResponse, MetaResponses, MetaSchema, MetaSchemaRef, Registry},
+    types::ToJSON,
+    ApiResponse, ResponseContent,
+};
+
+/// A response body that streams newline-delimited JSON items.
+pub struct NdJsonStream<T> {
+    stream: BoxStream<'static, T>,
+}
+
+impl<T> Debug for NdJsonStream<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NdJsonStream").finish_non_exhaustive()
+    }
+}
+
+impl<T> NdJsonStream<T> {
+    /// Create a new NdJsonStream that writes the items of the given stream.
+    pub fn new(stream: impl Stream<Item = T> + Send + 'static) -> Self {
+        Self {
+            stream: stream.boxed(),
+        }
+    }
+}
+
+impl<T: ToJSON> Payload for NdJsonStream<T> {
+    const CONTENT_TYPE: &'static str = "application/x-ndjson";
+
+    fn schema_ref() -> MetaSchemaRef {
+        MetaSchemaRef::Inline(Box::new(MetaSchema {
+            items: Some(Box::new(T::schema_ref())),
+            ..MetaSchema::new_with_format("array", "ndjson")
+        }))
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+}
+
+impl<T: ToJSON> ApiResponse for NdJsonStream<T> {
+    fn meta() -> MetaResponses {
+        MetaResponses {
+            responses: vec![MetaResponse {
+                description: "",
+                status: Some(200),
+                content: <Self as ResponseContent>::media_types(),
+                headers: vec![],
+            }],
+        }
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+}
+
+impl<T: ToJSON + 'static> IntoResponse for NdJsonStream<T> {
+    fn into_response(self) -> Response {
+        let body = self.stream.map(|item| {
+            let mut line = item.to_json_string();
+            line.push('\n');
+            Ok::<_, std::io::Error>(Bytes::from(line))
+        });
+        Response::builder()
+            .content_type(Self::CONTENT_TYPE)
+            .body(Body::from_bytes_stream(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+    use poem::test::TestClient;
+    use poem_openapi::{Object, OpenApi, OpenApiService};
+
+    use super::*;
+
+    #[derive(Debug, Object)]
+    struct Foo {
+        x: i32,
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/export", method = "get")]
+        async fn export(&self) -> NdJsonStream<Foo> {
+            NdJsonStream::new(stream::iter((1..=3).map(|x| Foo { x })))
+        }
+    }
+
+    #[tokio::test]
+    async fn ndjson() {
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+
+        let resp = cli.get("/export").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_content_type("application/x-ndjson");
+        resp.assert_text("{\"x\":1}\n{\"x\":2}\n{\"x\":3}\n").await;
+
+        let meta = NdJsonStream::<Foo>::meta();
+        let content = &meta.responses[0].content[0];
+        assert_eq!(content.content_type, "application/x-ndjson");
+        let MetaSchemaRef::Inline(schema) = &content.schema else {
+            panic!("expected inline schema");
+        };
+        assert_eq!(schema.ty, "array");
+        assert_eq!(
+            schema.items.as_deref(),
+            Some(&MetaSchemaRef::Reference("Foo".into()))
+        );
+    }
+}
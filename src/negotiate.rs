@@ -0,0 +1,625 @@
+//! Contains a payload type that supports multiple wire formats.
+//!
+//! [`Negotiated<T>`] can be used for request and response bodies. Request
+//! bodies are decoded according to their `Content-Type` header, and response
+//! bodies are encoded in the format that has been selected from the `Accept`
+//! header of the request (see [`Format`]). All supported media types are
+//! included in the OpenAPI documentation.
+//!
+//! JSON is always supported. MessagePack and CBOR are supported if the `msgpack`
+//! or `cbor` feature is enabled, respectively. Values are converted to and from
+//! the JSON representation of their type, so the same schema applies to all
+//! formats.
+//!
+//! Endpoints that only accept MessagePack request bodies can use the
+//! [`MsgPack<T>`] payload type (requires the `msgpack` feature) instead.
+//!
+//! #### Example
+//! ```
+//! use poem_ext::negotiate::{Format, Negotiated};
+//! use poem_openapi::{OpenApi, Object};
+//!
+//! #[derive(Debug, Object)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/users", method = "post")]
+//!     async fn create_user(&self, user: Negotiated<User>, format: Format) -> Negotiated<User> {
+//!         Negotiated(user.0, format)
+//!     }
+//! }
+//! ```
+
+use std::ops::{Deref, DerefMut};
+
+use poem::{
+    http::{
+        header::{ACCEPT, VARY},
+        HeaderValue, StatusCode,
+    },
+    FromRequest, IntoResponse, Request, RequestBody, Response,
+};
+use poem_openapi::{
+    error::{ContentTypeError, ParseRequestPayloadError},
+    registry::{MetaMediaType, MetaRequest, MetaResponse, MetaResponses, MetaSchemaRef, Registry},
+    types::{ParseFromJSON, ToJSON},
+    ApiExtractor, ApiExtractorType, ApiResponse, ExtractParamOptions, ResponseContent,
+};
+#[cfg(feature = "msgpack")]
+use poem_openapi::{
+    payload::{ParsePayload, Payload},
+    types::Type,
+};
+use serde_json::Value;
+
+/// A wire format supported by [`Negotiated<T>`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// JSON (`application/json`)
+    #[default]
+    Json,
+    /// MessagePack (`application/msgpack`)
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    /// CBOR (`application/cbor`)
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Format {
+    /// All enabled formats.
+    pub const ALL: &'static [Self] = &[
+        Self::Json,
+        #[cfg(feature = "msgpack")]
+        Self::MessagePack,
+        #[cfg(feature = "cbor")]
+        Self::Cbor,
+    ];
+
+    /// Return the content type of this format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json; charset=utf-8",
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => "application/msgpack",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "application/cbor",
+        }
+    }
+
+    /// Return the format of the given content type, if it is supported.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match essence.as_str() {
+            "application/json" => Some(Self::Json),
+            x if x.starts_with("application/") && x.ends_with("+json") => Some(Self::Json),
+            #[cfg(feature = "msgpack")]
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            #[cfg(feature = "cbor")]
+            "application/cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Select the preferred format from the value of an `Accept` header.
+    ///
+    /// Wildcards select JSON. If none of the accepted media types is
+    /// supported, JSON is used as well.
+    pub fn from_accept(accept: &str) -> Self {
+        accept
+            .split(',')
+            .filter_map(|media_type| {
+                let mut parts = media_type.split(';');
+                let essence = parts.next()?.trim();
+                let q = parts
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                let format = match essence {
+                    "*/*" | "application/*" => Self::Json,
+                    essence => Self::from_content_type(essence)?,
+                };
+                (q > 0.0).then_some((format, q))
+            })
+            .fold(None, |best: Option<(Self, f32)>, (format, q)| match best {
+                Some((_, best_q)) if best_q >= q => best,
+                _ => Some((format, q)),
+            })
+            .map(|(format, _)| format)
+            .unwrap_or_default()
+    }
+
+    fn media_types(schema: MetaSchemaRef) -> Vec<MetaMediaType> {
+        Self::ALL
+            .iter()
+            .map(|format| MetaMediaType {
+                content_type: format.content_type(),
+                schema: schema.clone(),
+            })
+            .collect()
+    }
+
+    fn decode(self, data: &[u8]) -> Result<Value, String> {
+        match self {
+            Self::Json if data.is_empty() => Ok(Value::Null),
+            Self::Json => serde_json::from_slice(data).map_err(|err| err.to_string()),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => rmp_serde::from_slice(data).map_err(|err| err.to_string()),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => ciborium::from_reader(data).map_err(|err| err.to_string()),
+        }
+    }
+
+    fn encode(self, value: &Value) -> Option<Vec<u8>> {
+        match self {
+            Self::Json => serde_json::to_vec(value).ok(),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => rmp_serde::to_vec(value).ok(),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(value, &mut buf).ok()?;
+                Some(buf)
+            }
+        }
+    }
+}
+
+/// Extract the preferred response format from the `Accept` header.
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for Format {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        Ok(req
+            .header(ACCEPT)
+            .map(Self::from_accept)
+            .unwrap_or_default())
+    }
+}
+
+/// A request or response body in one of the enabled [`Format`]s.
+///
+/// When used as a request body, the format is determined by the `Content-Type`
+/// header. Requests with an unsupported content type are rejected with
+/// `415 Unsupported Media Type`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated<T>(pub T, pub Format);
+
+impl<T> Negotiated<T> {
+    /// Create a new Negotiated payload that is encoded in the given format.
+    pub fn new(value: T, format: Format) -> Self {
+        Self(value, format)
+    }
+
+    /// Return the contained value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Negotiated<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Negotiated<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[poem::async_trait]
+impl<'a, T: ParseFromJSON> ApiExtractor<'a> for Negotiated<T> {
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::RequestObject];
+
+    type ParamType = ();
+    type ParamRawType = ();
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+
+    fn request_meta() -> Option<MetaRequest> {
+        Some(MetaRequest {
+            description: None,
+            content: Format::media_types(T::schema_ref()),
+            required: true,
+        })
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        body: &mut RequestBody,
+        _param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let content_type = request
+            .content_type()
+            .ok_or(ContentTypeError::ExpectContentType)?;
+        let format = Format::from_content_type(content_type).ok_or_else(|| {
+            ContentTypeError::NotSupported {
+                content_type: content_type.into(),
+            }
+        })?;
+        let data: Vec<u8> = FromRequest::from_request(request, body).await?;
+        let value = format
+            .decode(&data)
+            .map_err(|reason| ParseRequestPayloadError { reason })?;
+        let value = T::parse_from_json(Some(value)).map_err(|err| ParseRequestPayloadError {
+            reason: err.into_message(),
+        })?;
+        Ok(Self(value, format))
+    }
+}
+
+impl<T: ToJSON> ResponseContent for Negotiated<T> {
+    fn media_types() -> Vec<MetaMediaType> {
+        Format::media_types(T::schema_ref())
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+}
+
+impl<T: ToJSON> ApiResponse for Negotiated<T> {
+    fn meta() -> MetaResponses {
+        MetaResponses {
+            responses: vec![MetaResponse {
+                description: "",
+                status: Some(200),
+                content: <Self as ResponseContent>::media_types(),
+                headers: vec![],
+            }],
+        }
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+}
+
+impl<T: ToJSON> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        let Self(value, format) = self;
+        let Some(body) = value.to_json().and_then(|value| format.encode(&value)) else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+        let mut resp = Response::builder()
+            .content_type(format.content_type())
+            .body(body);
+        resp.headers_mut()
+            .insert(VARY, HeaderValue::from_static("accept"));
+        resp
+    }
+}
+
+/// A request body in the MessagePack format (`application/msgpack`).
+///
+/// The body is converted to the JSON representation of `T` and validated
+/// against its schema, just like [`Json<T>`](poem_openapi::payload::Json).
+/// Invalid bodies are rejected with `400 Bad Request` (which
+/// [`Response`](crate::responses::Response) turns into
+/// `422 Unprocessable Content`).
+///
+/// #### Example
+/// ```
+/// use poem_ext::{negotiate::MsgPack, responses::Response};
+/// use poem_openapi::{payload::Json, OpenApi, Object};
+///
+/// #[derive(Debug, Object)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/users", method = "post")]
+///     async fn create_user(&self, user: MsgPack<User>) -> Response<Json<User>> {
+///         Ok(Json(user.0).into())
+///     }
+/// }
+/// ```
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MsgPack<T>(pub T);
+
+#[cfg(feature = "msgpack")]
+impl<T> Deref for MsgPack<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<T> DerefMut for MsgPack<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<T: Type> Payload for MsgPack<T> {
+    const CONTENT_TYPE: &'static str = "application/msgpack";
+
+    fn check_content_type(content_type: &str) -> bool {
+        Format::from_content_type(content_type) == Some(Format::MessagePack)
+    }
+
+    fn schema_ref() -> MetaSchemaRef {
+        T::schema_ref()
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+}
+
+#[cfg(feature = "msgpack")]
+#[poem::async_trait]
+impl<T: ParseFromJSON> ParsePayload for MsgPack<T> {
+    const IS_REQUIRED: bool = true;
+
+    async fn from_request(request: &Request, body: &mut RequestBody) -> poem::Result<Self> {
+        let data: Vec<u8> = FromRequest::from_request(request, body).await?;
+        let value = Format::MessagePack
+            .decode(&data)
+            .map_err(|reason| ParseRequestPayloadError { reason })?;
+        let value = T::parse_from_json(Some(value)).map_err(|err| ParseRequestPayloadError {
+            reason: err.into_message(),
+        })?;
+        Ok(Self(value))
+    }
+}
+
+#[cfg(feature = "msgpack")]
+#[poem::async_trait]
+impl<'a, T: ParseFromJSON> ApiExtractor<'a> for MsgPack<T> {
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::RequestObject];
+
+    type ParamType = ();
+    type ParamRawType = ();
+
+    fn register(registry: &mut Registry) {
+        <Self as Payload>::register(registry);
+    }
+
+    fn request_meta() -> Option<MetaRequest> {
+        Some(MetaRequest {
+            description: None,
+            content: vec![MetaMediaType {
+                content_type: <Self as Payload>::CONTENT_TYPE,
+                schema: <Self as Payload>::schema_ref(),
+            }],
+            required: <Self as ParsePayload>::IS_REQUIRED,
+        })
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        body: &mut RequestBody,
+        _param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let content_type = request
+            .content_type()
+            .ok_or(ContentTypeError::ExpectContentType)?;
+        if !Self::check_content_type(content_type) {
+            return Err(ContentTypeError::NotSupported {
+                content_type: content_type.into(),
+            }
+            .into());
+        }
+        <Self as ParsePayload>::from_request(request, body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::test::TestClient;
+    #[cfg(feature = "msgpack")]
+    use poem_openapi::payload::PlainText;
+    use poem_openapi::{Object, OpenApi, OpenApiService};
+
+    use super::*;
+
+    #[derive(Debug, Object)]
+    struct Foo {
+        x: i32,
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/echo", method = "post")]
+        async fn echo(&self, data: Negotiated<Foo>, format: Format) -> Negotiated<Foo> {
+            Negotiated(data.0, format)
+        }
+    }
+
+    #[cfg(feature = "msgpack")]
+    struct MsgPackApi;
+
+    #[cfg(feature = "msgpack")]
+    #[OpenApi]
+    impl MsgPackApi {
+        #[oai(path = "/msgpack", method = "post")]
+        async fn msgpack(
+            &self,
+            data: MsgPack<Foo>,
+        ) -> crate::responses::Response<PlainText<String>> {
+            Ok(PlainText(data.x.to_string()).into())
+        }
+    }
+
+    #[test]
+    fn accept() {
+        assert_eq!(Format::from_accept("application/json"), Format::Json);
+        assert_eq!(Format::from_accept("*/*"), Format::Json);
+        assert_eq!(Format::from_accept("text/html"), Format::Json);
+        #[cfg(feature = "msgpack")]
+        assert_eq!(
+            Format::from_accept("application/json;q=0.5, application/msgpack"),
+            Format::MessagePack
+        );
+        #[cfg(feature = "cbor")]
+        assert_eq!(
+            Format::from_accept("application/cbor;q=0, application/json;q=0.1"),
+            Format::Json
+        );
+    }
+
+    #[tokio::test]
+    async fn json() {
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+
+        let resp = cli
+            .post("/echo")
+            .content_type("application/json")
+            .body(r#"{"x":42}"#)
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_content_type("application/json; charset=utf-8");
+        resp.assert_header(VARY, "accept");
+        resp.assert_text(r#"{"x":42}"#).await;
+
+        cli.post("/echo")
+            .content_type("text/plain")
+            .body("42")
+            .send()
+            .await
+            .assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[tokio::test]
+    async fn msgpack() {
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+        let body = rmp_serde::to_vec(&serde_json::json!({"x": 42})).unwrap();
+
+        let resp = cli
+            .post("/echo")
+            .content_type("application/msgpack")
+            .header(ACCEPT, "application/msgpack")
+            .body(body.clone())
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_content_type("application/msgpack");
+        resp.assert_bytes(body.clone()).await;
+
+        let resp = cli
+            .post("/echo")
+            .content_type("application/msgpack")
+            .body(body)
+            .send()
+            .await;
+        resp.assert_text(r#"{"x":42}"#).await;
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[tokio::test]
+    async fn msgpack_payload() {
+        let cli = TestClient::new(OpenApiService::new(MsgPackApi, "test", "1"));
+
+        let resp = cli
+            .post("/msgpack")
+            .content_type("application/msgpack")
+            .body(rmp_serde::to_vec(&serde_json::json!({"x": 42})).unwrap())
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text("42").await;
+
+        let resp = cli
+            .post("/msgpack")
+            .content_type("application/msgpack")
+            .body(rmp_serde::to_vec(&serde_json::json!({"x": "42"})).unwrap())
+            .send()
+            .await;
+        resp.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        let body = resp.json().await;
+        let body = body.value().object();
+        body.get("error").assert_string("unprocessable_content");
+        body.get("reason").string();
+
+        let resp = cli
+            .post("/msgpack")
+            .content_type("application/msgpack")
+            .body(vec![0xc1])
+            .send()
+            .await;
+        resp.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+        let resp = cli
+            .post("/msgpack")
+            .content_type("application/json")
+            .body(r#"{"x":42}"#)
+            .send()
+            .await;
+        resp.assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[cfg(feature = "cbor")]
+    #[tokio::test]
+    async fn cbor() {
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+        let mut body = Vec::new();
+        ciborium::into_writer(&serde_json::json!({"x": 42}), &mut body).unwrap();
+
+        let resp = cli
+            .post("/echo")
+            .content_type("application/cbor")
+            .header(ACCEPT, "application/cbor")
+            .body(body.clone())
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_content_type("application/cbor");
+        resp.assert_bytes(body.clone()).await;
+    }
+
+    #[test]
+    fn meta() {
+        let content_types = |media_types: Vec<MetaMediaType>| {
+            media_types
+                .into_iter()
+                .map(|media_type| media_type.content_type)
+                .collect::<Vec<_>>()
+        };
+        let expected = Format::ALL
+            .iter()
+            .map(|format| format.content_type())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            content_types(<Negotiated<Foo> as ResponseContent>::media_types()),
+            expected
+        );
+        assert_eq!(
+            content_types(
+                <Negotiated<Foo> as ApiExtractor>::request_meta()
+                    .unwrap()
+                    .content
+            ),
+            expected
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//! Contains a middleware for tracing requests with
+//! [OpenTelemetry](https://opentelemetry.io).
+//!
+//! The [`OpenTelemetryMiddleware`] extracts the trace context of incoming
+//! requests (e.g. the W3C `traceparent` header) using the global text map
+//! propagator, opens a server span for every request and injects the context
+//! of this span into the response headers. The span is named after the
+//! [operation id](poem_openapi::OperationId) of the endpoint, which is only
+//! available for operations that set it explicitly (e.g.
+//! `#[oai(path = "/users", method = "get", operation_id = "list_users")]`);
+//! other spans are named after the method and path of the request.
+//!
+//! The status code of the response is recorded as a span attribute. For error
+//! responses in the JSON format of this crate (e.g.
+//! `{"error": "user_not_found"}`), the error code is recorded in the
+//! `error.type` attribute and the error id of internal server errors in the
+//! `error.id` attribute. Server errors set the status of the span to
+//! [`Error`](opentelemetry::trace::Status::Error).
+//!
+//! #### Example
+//! ```
+//! use opentelemetry::global;
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::otel::OpenTelemetryMiddleware;
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get", operation_id = "test")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! // configure a tracer provider and the W3C trace context propagator here
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .with(OpenTelemetryMiddleware::new(global::tracer("my-service")));
+//! ```
+
+use std::{fmt::Debug, sync::Arc};
+
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector},
+    trace::{FutureExt, SpanKind, Status, TraceContextExt, Tracer},
+    KeyValue,
+};
+use poem::{
+    http::{HeaderMap, HeaderName, HeaderValue},
+    Endpoint, IntoResponse, Middleware, Request, Response,
+};
+use poem_openapi::OperationId;
+
+use crate::utils::read_error_body;
+
+/// A middleware for tracing requests with OpenTelemetry.
+pub struct OpenTelemetryMiddleware<T> {
+    tracer: Arc<T>,
+}
+
+impl<T> OpenTelemetryMiddleware<T> {
+    /// Create a new OpenTelemetryMiddleware that creates spans using the
+    /// given tracer.
+    pub fn new(tracer: T) -> Self {
+        Self {
+            tracer: Arc::new(tracer),
+        }
+    }
+}
+
+impl<T> Clone for OpenTelemetryMiddleware<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tracer: self.tracer.clone(),
+        }
+    }
+}
+
+impl<T> Debug for OpenTelemetryMiddleware<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenTelemetryMiddleware")
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, E> Middleware<E> for OpenTelemetryMiddleware<T>
+where
+    T: Tracer + Send + Sync,
+    T::Span: Send + Sync + 'static,
+    E: Endpoint,
+{
+    type Output = OpenTelemetryMwEndpoint<T, E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        OpenTelemetryMwEndpoint {
+            inner: ep,
+            tracer: self.tracer.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct OpenTelemetryMwEndpoint<T, E> {
+    inner: E,
+    tracer: Arc<T>,
+}
+
+impl<T, E: Debug> Debug for OpenTelemetryMwEndpoint<T, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenTelemetryMwEndpoint")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[poem::async_trait]
+impl<T, E> Endpoint for OpenTelemetryMwEndpoint<T, E>
+where
+    T: Tracer + Send + Sync,
+    T::Span: Send + Sync + 'static,
+    E: Endpoint,
+{
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let parent_cx = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(req.headers()))
+        });
+        let span = self
+            .tracer
+            .span_builder(format!("{} {}", req.method(), req.uri().path()))
+            .with_kind(SpanKind::Server)
+            .with_attributes(vec![
+                KeyValue::new("http.request.method", req.method().to_string()),
+                KeyValue::new("url.path", req.uri().path().to_owned()),
+            ])
+            .start_with_context(&*self.tracer, &parent_cx);
+        let cx = parent_cx.with_span(span);
+
+        let result = self
+            .inner
+            .call(req)
+            .with_context(cx.clone())
+            .await
+            .map(IntoResponse::into_response);
+
+        let span = cx.span();
+        let result = match result {
+            // errors returned by api responses (e.g. the error responses of this
+            // crate) are just wrappers of responses
+            Err(err) if err.is_from_response() => Ok(err.into_response()),
+            result => result,
+        };
+        let result = match result {
+            Ok(mut resp) => {
+                if let Some(operation_id) = resp.data::<OperationId>() {
+                    span.update_name(operation_id.0);
+                    span.set_attribute(KeyValue::new("openapi.operation_id", operation_id.0));
+                }
+                span.set_attribute(KeyValue::new(
+                    "http.response.status_code",
+                    i64::from(resp.status().as_u16()),
+                ));
+
+                let status = resp.status();
+                if status.is_client_error() || status.is_server_error() {
+                    let (code, error_id) = read_error_body(&mut resp).await;
+                    if let Some(code) = &code {
+                        span.set_attribute(KeyValue::new("error.type", code.clone()));
+                    }
+                    if let Some(error_id) = error_id {
+                        span.set_attribute(KeyValue::new("error.id", error_id));
+                    }
+                    if status.is_server_error() {
+                        span.set_status(Status::error(
+                            code.unwrap_or_else(|| status.as_u16().to_string()),
+                        ));
+                    }
+                }
+
+                global::get_text_map_propagator(|propagator| {
+                    propagator.inject_context(&cx, &mut HeaderInjector(resp.headers_mut()))
+                });
+                Ok(resp)
+            }
+            Err(err) => {
+                let status = err.status();
+                span.set_attribute(KeyValue::new(
+                    "http.response.status_code",
+                    i64::from(status.as_u16()),
+                ));
+                if status.is_server_error() {
+                    span.set_status(Status::error(err.to_string()));
+                }
+                Err(err)
+            }
+        };
+        span.end();
+        result
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (key.parse::<HeaderName>(), HeaderValue::from_str(&value)) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{SpanId, TraceId, TracerProvider as _};
+    use opentelemetry_sdk::{
+        propagation::TraceContextPropagator, testing::trace::InMemorySpanExporterBuilder,
+        trace::TracerProvider,
+    };
+    use poem::{http::StatusCode, test::TestClient, EndpointExt};
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+    use crate::responses::{internal_server_error, Response};
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/ok", method = "get", operation_id = "ok")]
+        async fn ok(&self) -> PlainText<&'static str> {
+            PlainText("ok")
+        }
+
+        #[oai(path = "/error", method = "get", operation_id = "error")]
+        async fn error(&self) -> Response<PlainText<&'static str>> {
+            Err(internal_server_error("failed"))
+        }
+    }
+
+    fn attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a opentelemetry::Value> {
+        attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| &kv.value)
+    }
+
+    #[tokio::test]
+    async fn spans() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let exporter = InMemorySpanExporterBuilder::new().build();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let cli = TestClient::new(
+            OpenApiService::new(Api, "test", "1")
+                .with(OpenTelemetryMiddleware::new(provider.tracer("test"))),
+        );
+
+        let resp = cli
+            .get("/ok")
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text("ok").await;
+        let resp = cli.get("/error").send().await;
+        resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+        let traceparent = resp.0.headers().get("traceparent").unwrap().to_owned();
+        let body = resp.json().await;
+        let error_id = body.value().object().get("error_id").string().to_owned();
+
+        provider.force_flush();
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans.len(), 2);
+
+        assert_eq!(spans[0].name, "ok");
+        assert_eq!(
+            spans[0].span_context.trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+        assert_eq!(
+            spans[0].parent_span_id,
+            SpanId::from_hex("00f067aa0ba902b7").unwrap()
+        );
+        assert_eq!(
+            attribute(&spans[0].attributes, "http.response.status_code"),
+            Some(&200.into())
+        );
+        assert_eq!(spans[0].status, Status::Unset);
+
+        assert_eq!(spans[1].name, "error");
+        assert!(traceparent
+            .to_str()
+            .unwrap()
+            .contains(&spans[1].span_context.span_id().to_string()));
+        assert_eq!(
+            attribute(&spans[1].attributes, "error.type"),
+            Some(&"internal_server_error".into())
+        );
+        assert_eq!(
+            attribute(&spans[1].attributes, "error.id"),
+            Some(&error_id.into())
+        );
+        assert_eq!(spans[1].status, Status::error("internal_server_error"));
+    }
+}
@@ -0,0 +1,493 @@
+//! Contains extractors and response objects for paginated endpoints.
+//!
+//! Both offset pagination ([`Pagination`] and [`Page`]) and cursor based
+//! pagination ([`CursorPagination`] and [`CursorPage`]) are supported.
+//!
+//! #### Example
+//! ```
+//! use poem_ext::{
+//!     pagination::{CursorPage, CursorPagination, Page, Pagination},
+//!     responses::Response,
+//! };
+//! use poem_openapi::{payload::Json, OpenApi};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     /// Return a list of numbers.
+//!     ///
+//!     /// At most 50 numbers are returned per request, 10 by default.
+//!     #[oai(path = "/numbers", method = "get")]
+//!     async fn numbers(&self, pagination: Pagination<10, 50>) -> Response<Json<Page<u64>>> {
+//!         let total = 1000;
+//!         let items = (pagination.offset..total)
+//!             .take(pagination.limit as _)
+//!             .collect();
+//!         Ok(Json(pagination.page(items, total)).into())
+//!     }
+//!
+//!     /// Return a list of numbers using keyset pagination.
+//!     #[oai(path = "/numbers_keyset", method = "get")]
+//!     async fn numbers_keyset(
+//!         &self,
+//!         pagination: CursorPagination<u64>,
+//!     ) -> Response<Json<CursorPage<u64>>> {
+//!         // fetch one more item than requested to find out whether there is a next page
+//!         let start = pagination.cursor.map_or(0, |last| last + 1);
+//!         let items = (start..1000).take(pagination.fetch_limit() as _).collect();
+//!         Ok(Json(pagination.page(items, |&item| item)).into())
+//!     }
+//! }
+//! ```
+//!
+//! Cursors are opaque to clients, but they are not encrypted. To prevent
+//! clients from crafting their own cursors, they can be signed by adding a
+//! [`CursorCodec`] with a secret key to the application data:
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::pagination::CursorCodec;
+//!
+//! # let api_service: Route = Route::new();
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .data(CursorCodec::with_hmac_key(b"secret"));
+//! ```
+
+use std::{fmt::Debug, sync::Arc};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use poem::{Request, RequestBody};
+use poem_openapi::{
+    __private::serde_json,
+    error::ParseParamError,
+    registry::{MetaParamIn, MetaSchema, MetaSchemaRef},
+    types::{ParseFromJSON, ToJSON, Type},
+    ApiExtractor, ApiExtractorType, ExtractParamOptions, Object,
+};
+use sha2::Sha256;
+
+/// Extractor for the `limit` and `offset` query parameters of paginated
+/// endpoints.
+///
+/// `DEFAULT_LIMIT` is used if the `limit` parameter is missing, larger values
+/// than `MAX_LIMIT` are rejected. Invalid values result in a `400 Bad Request`
+/// response (or a `422 Unprocessable Content` response if the endpoint uses
+/// the [`Response`](crate::responses::Response) type).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination<const DEFAULT_LIMIT: u64 = 20, const MAX_LIMIT: u64 = 100> {
+    /// The maximum number of items to return.
+    pub limit: u64,
+    /// The number of items to skip.
+    pub offset: u64,
+}
+
+impl<const DEFAULT_LIMIT: u64, const MAX_LIMIT: u64> Pagination<DEFAULT_LIMIT, MAX_LIMIT> {
+    /// Construct a [`Page`] from the items that have been selected using this
+    /// pagination and the total number of items.
+    pub fn page<T>(&self, items: Vec<T>, total: u64) -> Page<T>
+    where
+        T: Type + ParseFromJSON + ToJSON,
+    {
+        Page {
+            items,
+            total,
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+}
+
+#[poem::async_trait]
+impl<'a, const DEFAULT_LIMIT: u64, const MAX_LIMIT: u64> ApiExtractor<'a>
+    for Pagination<DEFAULT_LIMIT, MAX_LIMIT>
+{
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::Parameter];
+
+    type ParamType = Self;
+    type ParamRawType = Self;
+
+    fn param_in() -> Option<MetaParamIn> {
+        Some(MetaParamIn::Query)
+    }
+
+    fn param_schema_ref() -> Option<MetaSchemaRef> {
+        Some(MetaSchemaRef::Inline(Box::new(MetaSchema {
+            properties: vec![
+                (
+                    "limit",
+                    MetaSchemaRef::Inline(Box::new(MetaSchema {
+                        description: Some("The maximum number of items to return."),
+                        format: Some("uint64"),
+                        default: Some(DEFAULT_LIMIT.into()),
+                        minimum: Some(1.0),
+                        maximum: Some(MAX_LIMIT as f64),
+                        ..MetaSchema::new("integer")
+                    })),
+                ),
+                (
+                    "offset",
+                    MetaSchemaRef::Inline(Box::new(MetaSchema {
+                        description: Some("The number of items to skip."),
+                        format: Some("uint64"),
+                        default: Some(0.into()),
+                        minimum: Some(0.0),
+                        ..MetaSchema::new("integer")
+                    })),
+                ),
+            ],
+            ..MetaSchema::new("object")
+        })))
+    }
+
+    fn param_raw_type(&self) -> Option<&Self::ParamRawType> {
+        Some(self)
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        _body: &mut RequestBody,
+        _param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let mut pagination = Self {
+            limit: DEFAULT_LIMIT,
+            offset: 0,
+        };
+        for (key, value) in request.params::<Vec<(String, String)>>()? {
+            match key.as_str() {
+                "limit" => pagination.limit = parse_param("limit", &value, 1, MAX_LIMIT)?,
+                "offset" => pagination.offset = parse_param("offset", &value, 0, u64::MAX)?,
+                _ => {}
+            }
+        }
+        Ok(pagination)
+    }
+}
+
+fn parse_param(
+    name: &'static str,
+    value: &str,
+    min: u64,
+    max: u64,
+) -> Result<u64, ParseParamError> {
+    value
+        .parse()
+        .ok()
+        .filter(|value| (min..=max).contains(value))
+        .ok_or_else(|| ParseParamError {
+            name,
+            reason: format!("expected an integer between {min} and {max}"),
+        })
+}
+
+/// A page of items returned by a paginated endpoint.
+#[derive(Debug, Object)]
+pub struct Page<T: Type + ParseFromJSON + ToJSON> {
+    /// The items on this page.
+    pub items: Vec<T>,
+    /// The total number of items.
+    pub total: u64,
+    /// The maximum number of items on this page.
+    pub limit: u64,
+    /// The number of items that have been skipped.
+    pub offset: u64,
+}
+
+/// Encodes keys of type `K` into opaque cursors and decodes them again.
+///
+/// The key is serialized to JSON and encoded using URL safe base64. If an
+/// HMAC key is configured, the cursor is signed using HMAC-SHA256 and cursors
+/// with invalid signatures are rejected.
+///
+/// The [`CursorPagination`] extractor uses the codec from the application data
+/// or an unsigned codec if none has been added.
+#[derive(Clone, Default)]
+pub struct CursorCodec {
+    hmac_key: Option<Arc<[u8]>>,
+}
+
+impl Debug for CursorCodec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CursorCodec")
+            .field("signed", &self.hmac_key.is_some())
+            .finish()
+    }
+}
+
+impl CursorCodec {
+    /// Create a new CursorCodec that does not sign cursors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new CursorCodec that signs cursors using the given key.
+    pub fn with_hmac_key(key: impl AsRef<[u8]>) -> Self {
+        Self {
+            hmac_key: Some(key.as_ref().into()),
+        }
+    }
+
+    /// Encode a key into a cursor.
+    pub fn encode<K: ToJSON>(&self, key: &K) -> String {
+        let mut data = key.to_json_string().into_bytes();
+        if let Some(mac) = self.mac(&data) {
+            data.extend(mac.finalize().into_bytes());
+        }
+        URL_SAFE_NO_PAD.encode(data)
+    }
+
+    /// Decode a cursor into a key. Returns `None` if the cursor is invalid.
+    pub fn decode<K: ParseFromJSON>(&self, cursor: &str) -> Option<K> {
+        let mut data = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        if self.hmac_key.is_some() {
+            let tag = data.split_off(data.len().checked_sub(32)?);
+            self.mac(&data)?.verify_slice(&tag).ok()?;
+        }
+        K::parse_from_json(Some(serde_json::from_slice(&data).ok()?)).ok()
+    }
+
+    fn mac(&self, data: &[u8]) -> Option<Hmac<Sha256>> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.hmac_key.as_deref()?).ok()?;
+        mac.update(data);
+        Some(mac)
+    }
+}
+
+/// Extractor for the `cursor` and `limit` query parameters of endpoints that
+/// use cursor based (keyset) pagination.
+///
+/// The cursor is decoded into a key of type `K` (e.g. the id of the last item
+/// on the previous page) using the [`CursorCodec`] from the application data.
+/// `DEFAULT_LIMIT` and `MAX_LIMIT` behave as in [`Pagination`].
+#[derive(Debug, Clone)]
+pub struct CursorPagination<K, const DEFAULT_LIMIT: u64 = 20, const MAX_LIMIT: u64 = 100> {
+    /// The key decoded from the cursor or `None` to request the first page.
+    pub cursor: Option<K>,
+    /// The maximum number of items to return.
+    pub limit: u64,
+    codec: CursorCodec,
+}
+
+impl<K, const DEFAULT_LIMIT: u64, const MAX_LIMIT: u64>
+    CursorPagination<K, DEFAULT_LIMIT, MAX_LIMIT>
+{
+    /// Return the number of items that should be fetched, which is one more
+    /// than [`limit`](Self::limit) to determine whether there is a next page.
+    pub fn fetch_limit(&self) -> u64 {
+        self.limit + 1
+    }
+
+    /// Construct a [`CursorPage`] from items that have been fetched using
+    /// [`fetch_limit`](Self::fetch_limit). If there are more items than
+    /// requested, the surplus items are removed and the cursor for the next
+    /// page is derived from the last remaining item using the `key` function.
+    pub fn page<T>(&self, mut items: Vec<T>, key: impl FnOnce(&T) -> K) -> CursorPage<T>
+    where
+        T: Type + ParseFromJSON + ToJSON,
+        K: ToJSON,
+    {
+        let next_cursor = (items.len() as u64 > self.limit)
+            .then(|| {
+                items.truncate(self.limit as _);
+                items.last().map(|item| self.codec.encode(&key(item)))
+            })
+            .flatten();
+        CursorPage { items, next_cursor }
+    }
+}
+
+#[poem::async_trait]
+impl<'a, K, const DEFAULT_LIMIT: u64, const MAX_LIMIT: u64> ApiExtractor<'a>
+    for CursorPagination<K, DEFAULT_LIMIT, MAX_LIMIT>
+where
+    K: ParseFromJSON + Send,
+{
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::Parameter];
+
+    type ParamType = Self;
+    type ParamRawType = Self;
+
+    fn param_in() -> Option<MetaParamIn> {
+        Some(MetaParamIn::Query)
+    }
+
+    fn param_schema_ref() -> Option<MetaSchemaRef> {
+        Some(MetaSchemaRef::Inline(Box::new(MetaSchema {
+            properties: vec![
+                (
+                    "cursor",
+                    MetaSchemaRef::Inline(Box::new(MetaSchema {
+                        description: Some(
+                            "The cursor of the page to return, as returned in `next_cursor` of \
+                             the previous page. If omitted, the first page is returned.",
+                        ),
+                        ..MetaSchema::new("string")
+                    })),
+                ),
+                (
+                    "limit",
+                    MetaSchemaRef::Inline(Box::new(MetaSchema {
+                        description: Some("The maximum number of items to return."),
+                        format: Some("uint64"),
+                        default: Some(DEFAULT_LIMIT.into()),
+                        minimum: Some(1.0),
+                        maximum: Some(MAX_LIMIT as f64),
+                        ..MetaSchema::new("integer")
+                    })),
+                ),
+            ],
+            ..MetaSchema::new("object")
+        })))
+    }
+
+    fn param_raw_type(&self) -> Option<&Self::ParamRawType> {
+        Some(self)
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        _body: &mut RequestBody,
+        _param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let mut pagination = Self {
+            cursor: None,
+            limit: DEFAULT_LIMIT,
+            codec: request.data::<CursorCodec>().cloned().unwrap_or_default(),
+        };
+        for (key, value) in request.params::<Vec<(String, String)>>()? {
+            match key.as_str() {
+                "cursor" => {
+                    pagination.cursor =
+                        Some(
+                            pagination
+                                .codec
+                                .decode(&value)
+                                .ok_or_else(|| ParseParamError {
+                                    name: "cursor",
+                                    reason: "invalid cursor".into(),
+                                })?,
+                        )
+                }
+                "limit" => pagination.limit = parse_param("limit", &value, 1, MAX_LIMIT)?,
+                _ => {}
+            }
+        }
+        Ok(pagination)
+    }
+}
+
+/// A page of items returned by an endpoint that uses cursor based pagination.
+#[derive(Debug, Object)]
+pub struct CursorPage<T: Type + ParseFromJSON + ToJSON> {
+    /// The items on this page.
+    pub items: Vec<T>,
+    /// The cursor of the next page or `null` if this is the last page.
+    pub next_cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, Route};
+    use poem_openapi::{payload::Json, OpenApi, OpenApiService};
+
+    use super::*;
+    use crate::responses::Response;
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test", method = "get")]
+        async fn test(&self, pagination: Pagination<2, 5>) -> Response<Json<Page<u64>>> {
+            let items = (pagination.offset..10)
+                .take(pagination.limit as _)
+                .collect();
+            Ok(Json(pagination.page(items, 10)).into())
+        }
+
+        #[oai(path = "/cursor", method = "get")]
+        async fn cursor(
+            &self,
+            pagination: CursorPagination<u64, 2, 5>,
+        ) -> Response<Json<CursorPage<u64>>> {
+            let start = pagination.cursor.map_or(0, |last| last + 1);
+            let items = (start..5).take(pagination.fetch_limit() as _).collect();
+            Ok(Json(pagination.page(items, |&item| item)).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn pagination() {
+        let cli = TestClient::new(Route::new().nest("/", OpenApiService::new(Api, "test", "1")));
+
+        let resp = cli.get("/test").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text(r#"{"items":[0,1],"limit":2,"offset":0,"total":10}"#)
+            .await;
+
+        let resp = cli.get("/test?limit=3&offset=8").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text(r#"{"items":[8,9],"limit":3,"offset":8,"total":10}"#)
+            .await;
+
+        for query in ["limit=0", "limit=6", "limit=x", "offset=-1"] {
+            let resp = cli.get(format!("/test?{query}")).send().await;
+            resp.assert_status(poem::http::StatusCode::UNPROCESSABLE_ENTITY);
+        }
+    }
+
+    #[tokio::test]
+    async fn cursor_pagination() {
+        let cli = TestClient::new(Route::new().nest("/", OpenApiService::new(Api, "test", "1")));
+
+        let mut cursor = None::<String>;
+        let mut items = Vec::new();
+        loop {
+            let uri = match &cursor {
+                Some(cursor) => format!("/cursor?cursor={cursor}"),
+                None => "/cursor".into(),
+            };
+            let resp = cli.get(uri).send().await;
+            resp.assert_status_is_ok();
+            let page = resp.json().await;
+            let page = page.value().object();
+            items.extend(page.get("items").array().iter().map(|x| x.i64()));
+            match page.get("next_cursor").deserialize::<Option<String>>() {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(items, [0, 1, 2, 3, 4]);
+
+        let resp = cli.get("/cursor?cursor=foo").send().await;
+        resp.assert_status(poem::http::StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn cursor_codec() {
+        let unsigned = CursorCodec::new();
+        let signed = CursorCodec::with_hmac_key(b"secret");
+        let other = CursorCodec::with_hmac_key(b"other");
+
+        let cursor = unsigned.encode(&"test".to_owned());
+        assert_eq!(unsigned.decode::<String>(&cursor).unwrap(), "test");
+        assert!(signed.decode::<String>(&cursor).is_none());
+
+        let cursor = signed.encode(&42u64);
+        assert_eq!(signed.decode::<u64>(&cursor), Some(42));
+        assert!(other.decode::<u64>(&cursor).is_none());
+        assert!(unsigned.decode::<u64>(&cursor).is_none());
+    }
+
+    #[test]
+    fn spec() {
+        let spec: serde_json::Value =
+            serde_json::from_str(&OpenApiService::new(Api, "test", "1").spec()).unwrap();
+        let param = &spec["paths"]["/test"]["get"]["parameters"][0];
+        assert_eq!(param["in"], "query");
+        assert_eq!(param["explode"], true);
+        assert_eq!(param["schema"]["properties"]["limit"]["default"], 2);
+        assert_eq!(param["schema"]["properties"]["limit"]["maximum"], 5.0);
+    }
+}
@@ -1,9 +1,13 @@
 //! Contains a middlware that automatically responds with an internal server
 //! error whenever the current thread is panicking.
 //!
+//! [`PanicHandler::middleware`] returns a [`CatchPanic`] middleware, while
+//! [`PanicHandler::custom`] returns a [`PanicHandlerMiddleware`] that also
+//! supports panic hooks, custom responses and request context.
+//!
 //! #### Example
 //! ```
-//! use poem::{middleware::CatchPanic, EndpointExt, Route};
+//! use poem::{EndpointExt, Route};
 //! use poem_ext::panic_handler::PanicHandler;
 //! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
 //!
@@ -13,7 +17,7 @@
 //! impl Api {
 //!     #[oai(path = "/test", method = "get")]
 //!     async fn test(&self) -> PlainText<&'static str> {
-//!         // status = 500, content = {"error":"internal_server_error"}
+//!         // status = 500, content = {"error":"internal_server_error","error_id":"..."}
 //!         panic!("at the disco")
 //!     }
 //! }
@@ -24,9 +28,42 @@
 //!     .with(PanicHandler::middleware());
 //! ```
 
-use poem::middleware::CatchPanic;
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::RefCell,
+    fmt::Debug,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Once},
+};
+
+use futures_util::FutureExt;
+use poem::{
+    async_trait,
+    http::{Method, StatusCode, Uri},
+    middleware::CatchPanic,
+    Endpoint, IntoResponse, Middleware, Request, Response,
+};
+use poem_openapi::__private::serde_json;
+
+use crate::{
+    body_capture::CapturedBody,
+    error_sink::error_sink,
+    identity::Identity,
+    request_id::{RequestId, REQUEST_ID_HEADER},
+    responses::{make_internal_server_error, new_error_id, ErrorResponse},
+    utils::path_has_prefix,
+};
+
+/// A function that is called whenever a panic has been caught.
+pub type PanicHook = Arc<dyn Fn(&PanicInfo) + Send + Sync>;
 
-use crate::responses::{make_internal_server_error, ErrorResponse};
+/// A function that is called whenever an endpoint has responded with
+/// `500 Internal Server Error`.
+pub type ServerErrorHook = Arc<dyn Fn(&ServerErrorInfo) + Send + Sync>;
+
+/// A function that constructs the response for a caught panic.
+pub type PanicResponseFn = Arc<dyn Fn(&PanicInfo) -> Response + Send + Sync>;
 
 /// Custom panic handler.
 #[derive(Debug, Clone)]
@@ -37,12 +74,678 @@ impl PanicHandler {
     pub fn middleware() -> CatchPanic<Self> {
         CatchPanic::new().with_handler(Self)
     }
+
+    /// Creates a [`PanicHandlerMiddleware`], which additionally supports
+    /// panic hooks, custom responses and request context (e.g. the
+    /// [`RequestId`]) in the responses.
+    pub fn custom() -> PanicHandlerMiddleware {
+        PanicHandlerMiddleware::new()
+    }
 }
 
 impl poem::middleware::PanicHandler for PanicHandler {
     type Response = ErrorResponse;
 
-    fn get_response(&self, _err: Box<dyn std::any::Any + Send + 'static>) -> Self::Response {
-        make_internal_server_error()
+    fn get_response(&self, err: Box<dyn Any + Send + 'static>) -> Self::Response {
+        let error_id = new_error_id();
+        let error = format_args!(
+            "endpoint panicked: {}",
+            panic_message(&*err).unwrap_or("Box<dyn Any>")
+        );
+        error_sink().internal_error(&error_id, &error);
+        #[cfg(feature = "sentry")]
+        crate::sentry::capture_internal_error(&error_id, &error);
+        make_internal_server_error(error_id, None)
+    }
+}
+
+/// Information about a caught panic and the request that caused it.
+#[derive(Debug)]
+pub struct PanicInfo {
+    /// The payload the thread panicked with.
+    pub payload: Box<dyn Any + Send + 'static>,
+    /// The method of the request.
+    pub method: Method,
+    /// The uri of the request.
+    pub uri: Uri,
+    /// The id of the request (see [`RequestId`]).
+    pub request_id: Option<RequestId>,
+    /// The authenticated identity of the request (see [`Identity`]).
+    pub identity: Option<String>,
+    /// The randomly generated id of this error. This id is also included in
+    /// the default response body.
+    pub error_id: String,
+    /// The backtrace of the panic (only captured if enabled using
+    /// [`PanicHandlerMiddleware::with_backtraces`] and the `RUST_BACKTRACE` or
+    /// `RUST_LIB_BACKTRACE` environment variables).
+    pub backtrace: Option<Arc<Backtrace>>,
+    /// The redacted and truncated request body (only captured if the
+    /// [`RequestBodyCaptureMiddleware`](crate::body_capture::RequestBodyCaptureMiddleware)
+    /// is used).
+    pub request_body: Option<String>,
+}
+
+impl PanicInfo {
+    /// Return the panic message if the payload is a string.
+    pub fn message(&self) -> Option<&str> {
+        panic_message(&*self.payload)
+    }
+
+    /// Return the path of the request.
+    pub fn path(&self) -> &str {
+        self.uri.path()
+    }
+}
+
+/// Information about a request that has resulted in a
+/// `500 Internal Server Error` response.
+#[derive(Debug)]
+pub struct ServerErrorInfo {
+    /// The method of the request.
+    pub method: Method,
+    /// The uri of the request.
+    pub uri: Uri,
+    /// The id of the request (see [`RequestId`]).
+    pub request_id: Option<RequestId>,
+    /// The authenticated identity of the request (see [`Identity`]).
+    pub identity: Option<String>,
+    /// The error message, if the endpoint has returned an error instead of a
+    /// response.
+    pub error: Option<String>,
+    /// The redacted and truncated request body (only captured if the
+    /// [`RequestBodyCaptureMiddleware`](crate::body_capture::RequestBodyCaptureMiddleware)
+    /// is used).
+    pub request_body: Option<String>,
+}
+
+impl ServerErrorInfo {
+    /// Return the path of the request.
+    pub fn path(&self) -> &str {
+        self.uri.path()
+    }
+}
+
+/// A middleware that catches panics in endpoints and responds with an
+/// internal server error.
+///
+/// #### Example
+/// ```no_run
+/// use poem::{EndpointExt, Route};
+/// use poem_ext::panic_handler::PanicHandler;
+///
+/// # let api_service: poem_openapi::OpenApiService<(), ()> = todo!();
+/// let app = Route::new().nest("/", api_service).with(
+///     PanicHandler::custom().with_hook(|info| {
+///         // forward the panic to an alerting system
+///         eprintln!(
+///             "{} {} panicked: {:?} (request id: {:?})",
+///             info.method,
+///             info.path(),
+///             info.message(),
+///             info.request_id
+///         );
+///     }),
+/// );
+/// ```
+#[derive(Default, Clone)]
+pub struct PanicHandlerMiddleware {
+    hooks: Vec<PanicHook>,
+    error_hooks: Vec<ServerErrorHook>,
+    response_fn: Option<PanicResponseFn>,
+    route_response_fns: Vec<(String, PanicResponseFn)>,
+    backtraces: bool,
+}
+
+impl Debug for PanicHandlerMiddleware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PanicHandlerMiddleware")
+            .finish_non_exhaustive()
+    }
+}
+
+impl PanicHandlerMiddleware {
+    /// Create a new PanicHandlerMiddleware.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a function that is called with the panic payload and some
+    /// information about the request whenever a panic has been caught.
+    pub fn with_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&PanicInfo) + Send + Sync + 'static,
+    {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Register a function that is called with some information about the
+    /// request whenever an endpoint has responded with
+    /// `500 Internal Server Error` (e.g. because of a database error). Caught
+    /// panics only trigger the hooks registered via
+    /// [`with_hook`](Self::with_hook).
+    pub fn with_server_error_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ServerErrorInfo) + Send + Sync + 'static,
+    {
+        self.error_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Use a custom function to construct the response for a caught panic.
+    ///
+    /// By default the response has the status `500 Internal Server Error` and
+    /// contains `{"error":"internal_server_error","error_id":"..."}`.
+    ///
+    /// #### Example
+    /// ```no_run
+    /// use poem::{http::StatusCode, web::Json, EndpointExt, IntoResponse, Route};
+    /// use poem_ext::panic_handler::PanicHandler;
+    ///
+    /// # let api_service: poem_openapi::OpenApiService<(), ()> = todo!();
+    /// let app = Route::new().nest("/", api_service).with(
+    ///     PanicHandler::custom().with_response(|info| {
+    ///         Json(serde_json::json!({
+    ///             "error": "panic",
+    ///             "path": info.path(),
+    ///         }))
+    ///         .with_status(StatusCode::INTERNAL_SERVER_ERROR)
+    ///     }),
+    /// );
+    /// ```
+    pub fn with_response<F, R>(mut self, response_fn: F) -> Self
+    where
+        F: Fn(&PanicInfo) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.response_fn = Some(Arc::new(move |info| response_fn(info).into_response()));
+        self
+    }
+
+    /// Use a custom function to construct the response for panics caught in
+    /// requests whose path starts with the given prefix.
+    ///
+    /// If multiple prefixes match the path of a request, the longest one is
+    /// used. Requests that don't match any prefix use the response configured
+    /// via [`with_response`](Self::with_response).
+    pub fn with_response_for<F, R>(mut self, prefix: impl Into<String>, response_fn: F) -> Self
+    where
+        F: Fn(&PanicInfo) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route_response_fns.push((
+            prefix.into(),
+            Arc::new(move |info| response_fn(info).into_response()),
+        ));
+        self
+    }
+
+    /// Use one of the predefined [`PanicResponseFormat`]s to construct the
+    /// response for a caught panic.
+    pub fn with_format(self, format: PanicResponseFormat) -> Self {
+        self.with_response(move |info| format.response(info))
+    }
+
+    /// Use one of the predefined [`PanicResponseFormat`]s to construct the
+    /// response for panics caught in requests whose path starts with the given
+    /// prefix (see [`with_response_for`](Self::with_response_for)).
+    ///
+    /// #### Example
+    /// ```no_run
+    /// use poem::{EndpointExt, Route};
+    /// use poem_ext::panic_handler::{PanicHandler, PanicResponseFormat};
+    ///
+    /// # let api_service: poem_openapi::OpenApiService<(), ()> = todo!();
+    /// # let frontend: Route = todo!();
+    /// let app = Route::new()
+    ///     .nest("/api", api_service)
+    ///     .nest("/", frontend)
+    ///     .with(
+    ///         PanicHandler::custom()
+    ///             .with_format(PanicResponseFormat::Html)
+    ///             .with_format_for("/api", PanicResponseFormat::Json),
+    ///     );
+    /// ```
+    pub fn with_format_for(self, prefix: impl Into<String>, format: PanicResponseFormat) -> Self {
+        self.with_response_for(prefix, move |info| format.response(info))
+    }
+
+    /// Respond with an [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807)
+    /// `application/problem+json` document instead of the default response
+    /// body.
+    pub fn with_problem_json(self) -> Self {
+        self.with_format(PanicResponseFormat::ProblemJson)
+    }
+
+    /// Capture the backtraces of caught panics (see [`PanicInfo::backtrace`]).
+    ///
+    /// This installs a process-wide panic hook (see
+    /// [`std::panic::set_hook`]) that stores the backtrace of the current
+    /// panic and then calls the previously installed hook. Hooks that are
+    /// installed afterwards replace it unless they call it as well.
+    pub fn with_backtraces(self) -> Self {
+        Self {
+            backtraces: true,
+            ..self
+        }
+    }
+}
+
+/// Predefined response formats for caught panics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PanicResponseFormat {
+    /// `{"error":"internal_server_error","error_id":"..."}` (default)
+    Json,
+    /// An [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807)
+    /// `application/problem+json` document.
+    ProblemJson,
+    /// A `text/plain` response.
+    PlainText,
+    /// A minimal `text/html` error page.
+    Html,
+}
+
+impl PanicResponseFormat {
+    /// Construct the response for a caught panic.
+    pub fn response(&self, info: &PanicInfo) -> Response {
+        let builder = Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR);
+        match self {
+            Self::Json => make_internal_server_error(
+                info.error_id.clone(),
+                info.request_id.as_ref().map(ToString::to_string),
+            )
+            .into_response(),
+            Self::ProblemJson => builder.content_type("application/problem+json").body(
+                serde_json::json!({
+                    "type": "about:blank",
+                    "title": "Internal Server Error",
+                    "status": 500,
+                    "error_id": info.error_id,
+                    "request_id": info.request_id.as_deref(),
+                })
+                .to_string(),
+            ),
+            Self::PlainText => {
+                let mut body = format!("Internal Server Error\nError id: {}", info.error_id);
+                if let Some(request_id) = &info.request_id {
+                    body += &format!("\nRequest id: {request_id}");
+                }
+                builder.content_type("text/plain; charset=utf-8").body(body)
+            }
+            Self::Html => {
+                let mut body = format!(
+                    "<!DOCTYPE html><html><head><title>Internal Server Error</title></head><body>\
+                     <h1>Internal Server Error</h1><p>Error id: <code>{}</code></p>",
+                    info.error_id
+                );
+                if let Some(request_id) = &info.request_id {
+                    body += &format!(
+                        "<p>Request id: <code>{}</code></p>",
+                        escape_html(request_id)
+                    );
+                }
+                body += "</body></html>";
+                builder.content_type("text/html; charset=utf-8").body(body)
+            }
+        }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for PanicHandlerMiddleware {
+    type Output = PanicHandlerMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        if self.backtraces {
+            install_backtrace_hook();
+        }
+        PanicHandlerMwEndpoint {
+            inner: ep,
+            hooks: self.hooks.clone(),
+            error_hooks: self.error_hooks.clone(),
+            response_fn: self.response_fn.clone(),
+            route_response_fns: self.route_response_fns.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct PanicHandlerMwEndpoint<E> {
+    inner: E,
+    hooks: Vec<PanicHook>,
+    error_hooks: Vec<ServerErrorHook>,
+    response_fn: Option<PanicResponseFn>,
+    route_response_fns: Vec<(String, PanicResponseFn)>,
+}
+
+impl<E> PanicHandlerMwEndpoint<E> {
+    fn response_fn(&self, path: &str) -> Option<&PanicResponseFn> {
+        self.route_response_fns
+            .iter()
+            .filter(|(prefix, _)| path_has_prefix(path, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, response_fn)| response_fn)
+            .or(self.response_fn.as_ref())
+    }
+}
+
+impl<E: Debug> Debug for PanicHandlerMwEndpoint<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PanicHandlerMwEndpoint")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<E: Endpoint> Endpoint for PanicHandlerMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let method = req.method().clone();
+        let uri = req.uri().clone();
+        let request_id = RequestId::of(&req);
+        let identity = Identity::attach(&mut req);
+        let request_body = CapturedBody::attach(&mut req);
+
+        match AssertUnwindSafe(self.inner.call(req)).catch_unwind().await {
+            Ok(resp) => {
+                let resp = resp.map(IntoResponse::into_response);
+                if self.error_hooks.is_empty() {
+                    return resp;
+                }
+                let (status, error) = match &resp {
+                    Ok(resp) => (resp.status(), None),
+                    Err(err) => (err.status(), Some(err.to_string())),
+                };
+                if status == StatusCode::INTERNAL_SERVER_ERROR {
+                    let info = ServerErrorInfo {
+                        method,
+                        uri,
+                        request_id,
+                        identity: identity.value(),
+                        error,
+                        request_body: request_body.value(),
+                    };
+                    for hook in &self.error_hooks {
+                        hook(&info);
+                    }
+                }
+                resp
+            }
+            Err(payload) => {
+                let info = PanicInfo {
+                    payload,
+                    method,
+                    uri,
+                    request_id,
+                    identity: identity.value(),
+                    error_id: new_error_id(),
+                    backtrace: LAST_BACKTRACE.with(|bt| bt.borrow_mut().take()),
+                    request_body: request_body.value(),
+                };
+                error_sink().panic(&info);
+                #[cfg(feature = "sentry")]
+                crate::sentry::capture_panic(&info);
+                for hook in &self.hooks {
+                    hook(&info);
+                }
+                let mut resp = match self.response_fn(info.path()) {
+                    Some(response_fn) => response_fn(&info),
+                    None => PanicResponseFormat::Json.response(&info),
+                };
+                if let Some(request_id) = info.request_id.as_deref().and_then(|id| id.parse().ok())
+                {
+                    resp.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+                }
+                Ok(resp)
+            }
+        }
+    }
+}
+
+/// Return the panic message if the payload is a string.
+fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    payload
+        .downcast_ref::<&'static str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+}
+
+fn escape_html(s: &str) -> String {
+    s.chars()
+        .fold(String::with_capacity(s.len()), |mut out, c| {
+            match c {
+                '&' => out.push_str("&amp;"),
+                '<' => out.push_str("&lt;"),
+                '>' => out.push_str("&gt;"),
+                '"' => out.push_str("&quot;"),
+                '\'' => out.push_str("&#39;"),
+                c => out.push(c),
+            }
+            out
+        })
+}
+
+thread_local! {
+    static LAST_BACKTRACE: RefCell<Option<Arc<Backtrace>>> = const { RefCell::new(None) };
+}
+
+/// Extend the panic hook to store the backtrace of the current panic, so it
+/// can be retrieved after the panic has been caught. The previous hook is
+/// still called afterwards. This works because
+/// [`catch_unwind`](FutureExt::catch_unwind) catches the panic on the same
+/// thread it has occurred on.
+fn install_backtrace_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let prev = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let backtrace = Backtrace::capture();
+            LAST_BACKTRACE.with(|bt| {
+                *bt.borrow_mut() = (backtrace.status()
+                    == std::backtrace::BacktraceStatus::Captured)
+                    .then(|| Arc::new(backtrace));
+            });
+            prev(info);
+        }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use poem::{handler, test::TestClient, EndpointExt};
+
+    use super::*;
+    use crate::request_id::RequestIdMiddleware;
+
+    #[handler]
+    fn panicking() -> &'static str {
+        panic!("at the disco")
+    }
+
+    #[tokio::test]
+    async fn test_hook() {
+        let caught = Arc::new(Mutex::new(Vec::new()));
+        let app = panicking
+            .with(PanicHandler::custom().with_hook({
+                let caught = Arc::clone(&caught);
+                move |info| {
+                    caught.lock().unwrap().push((
+                        info.method.clone(),
+                        info.path().to_owned(),
+                        info.request_id.as_ref().map(ToString::to_string),
+                        info.message().map(ToOwned::to_owned),
+                        info.error_id.clone(),
+                    ))
+                }
+            }))
+            .with(RequestIdMiddleware::new().trust_header(true));
+
+        let resp = TestClient::new(app)
+            .post("/foo")
+            .header(REQUEST_ID_HEADER, "abc")
+            .send()
+            .await;
+        resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+        resp.assert_header(REQUEST_ID_HEADER, "abc");
+        let body = resp.json().await;
+        let body = body.value().object();
+        body.get("error").assert_string("internal_server_error");
+        body.get("request_id").assert_string("abc");
+        let error_id = body.get("error_id").string().to_owned();
+
+        assert_eq!(
+            *caught.lock().unwrap(),
+            [(
+                Method::POST,
+                "/foo".to_owned(),
+                Some("abc".to_owned()),
+                Some("at the disco".to_owned()),
+                error_id,
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_response() {
+        let app = panicking.with(PanicHandler::custom().with_response(|info| {
+            format!("{} failed", info.path()).with_status(StatusCode::BAD_GATEWAY)
+        }));
+        let resp = TestClient::new(app).get("/foo").send().await;
+        resp.assert_status(StatusCode::BAD_GATEWAY);
+        resp.assert_text("/foo failed").await;
+    }
+
+    #[tokio::test]
+    async fn test_problem_json() {
+        let app = panicking.with(PanicHandler::custom().with_problem_json());
+        let resp = TestClient::new(app).get("/").send().await;
+        resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+        resp.assert_content_type("application/problem+json");
+        let body = resp.json().await;
+        let body = body.value().object();
+        body.get("type").assert_string("about:blank");
+        body.get("title").assert_string("Internal Server Error");
+        body.get("status").assert_i64(500);
+        assert!(!body.get("error_id").string().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_identity() {
+        #[handler]
+        fn authenticated(req: &Request) {
+            Identity::set(req, "user42");
+            panic!()
+        }
+
+        let identity = Arc::new(Mutex::new(None));
+        let app = authenticated.with(PanicHandler::custom().with_hook({
+            let identity = Arc::clone(&identity);
+            move |info| *identity.lock().unwrap() = info.identity.clone()
+        }));
+        TestClient::new(app).get("/").send().await;
+        assert_eq!(identity.lock().unwrap().as_deref(), Some("user42"));
+    }
+
+    #[tokio::test]
+    async fn test_route_formats() {
+        let app = panicking.with(
+            PanicHandler::custom()
+                .with_format(PanicResponseFormat::Html)
+                .with_format_for("/api", PanicResponseFormat::Json)
+                .with_format_for("/api/text/", PanicResponseFormat::PlainText),
+        );
+        let cli = TestClient::new(app);
+
+        let check = |path, content_type| {
+            let cli = &cli;
+            async move {
+                let resp = cli.get(path).send().await;
+                resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+                resp.assert_content_type(content_type);
+            }
+        };
+        check("/", "text/html; charset=utf-8").await;
+        check("/apiary", "text/html; charset=utf-8").await;
+        check("/api", "application/json; charset=utf-8").await;
+        check("/api/foo", "application/json; charset=utf-8").await;
+        check("/api/text", "text/plain; charset=utf-8").await;
+        check("/api/text/foo", "text/plain; charset=utf-8").await;
+    }
+
+    #[tokio::test]
+    async fn test_request_id_middleware() {
+        let app = panicking
+            .with(PanicHandler::custom())
+            .with(RequestIdMiddleware::new());
+        let resp = TestClient::new(app)
+            .get("/")
+            .header(REQUEST_ID_HEADER, "abc")
+            .send()
+            .await;
+        let request_id = resp
+            .0
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        assert_ne!(request_id, "abc");
+        resp.json()
+            .await
+            .value()
+            .object()
+            .get("request_id")
+            .assert_string(&request_id);
+    }
+
+    #[tokio::test]
+    async fn test_request_body() {
+        use crate::body_capture::RequestBodyCaptureMiddleware;
+
+        #[handler]
+        fn failing(body: String) -> StatusCode {
+            match body.as_str() {
+                r#"{"panic":true}"# => panic!(),
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        }
+
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let app = failing.with(RequestBodyCaptureMiddleware::new(1024)).with(
+            PanicHandler::custom()
+                .with_hook({
+                    let bodies = Arc::clone(&bodies);
+                    move |info| bodies.lock().unwrap().push(info.request_body.clone())
+                })
+                .with_server_error_hook({
+                    let bodies = Arc::clone(&bodies);
+                    move |info| {
+                        assert_eq!(info.error, None);
+                        bodies.lock().unwrap().push(info.request_body.clone())
+                    }
+                }),
+        );
+        let cli = TestClient::new(app);
+        for body in [r#"{"panic":true}"#, r#"{"panic":false}"#] {
+            cli.post("/")
+                .content_type("application/json")
+                .body(body)
+                .send()
+                .await
+                .assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+
+        assert_eq!(
+            *bodies.lock().unwrap(),
+            [
+                Some(r#"{"panic":true}"#.to_owned()),
+                Some(r#"{"panic":false}"#.to_owned())
+            ]
+        );
     }
 }
@@ -0,0 +1,435 @@
+//! Contains a middleware that enforces daily or monthly usage quotas per api
+//! key.
+//!
+//! Unlike the [`RateLimitMiddleware`](crate::rate_limit::RateLimitMiddleware),
+//! which smooths out bursts of requests, quotas limit the total number of
+//! requests a client can perform in a calendar period (a day or a month in
+//! UTC), e.g. to enforce the limits of a pricing plan. The counters are kept
+//! in a [`QuotaStore`] (e.g. the in-memory [`MemoryQuotaStore`] or a Redis or
+//! database based implementation shared by multiple instances).
+//!
+//! Every response of a quota-managed endpoint contains the following headers:
+//!
+//! - `X-RateLimit-Limit`: the number of requests allowed per period
+//! - `X-RateLimit-Remaining`: the number of requests left in the current
+//!   period
+//! - `X-RateLimit-Reset`: the unix timestamp at which the current period ends
+//!
+//! Requests that exceed the quota are rejected with a [`TooManyRequests`]
+//! response and a `Retry-After` header. Use [`QuotaLimited`] to add this
+//! response to the documentation of the affected endpoints.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     quota::{MemoryQuotaStore, QuotaLimited, QuotaMiddleware, QuotaPeriod},
+//!     responses::Response,
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> Response<PlainText<&'static str>, QuotaLimited> {
+//!         Ok(PlainText("Hello World!").into())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     // allow 1000 requests per day and api key
+//!     QuotaMiddleware::per_header(MemoryQuotaStore::new(), QuotaPeriod::Daily, 1000, "x-api-key"),
+//! );
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    sync::{Arc, Mutex},
+};
+
+use poem::{
+    http::{header::RETRY_AFTER, HeaderMap},
+    Endpoint, IntoResponse, Middleware, Request, Response,
+};
+use poem_openapi::{
+    registry::{MetaHeader, MetaResponse, Registry},
+    types::Type,
+    ApiResponse,
+};
+
+use crate::{
+    responses::{MetaResponsesExt, TooManyRequests},
+    utils::unix_now,
+};
+
+/// The name of the header that contains the number of requests allowed per
+/// period.
+pub const LIMIT_HEADER: &str = "x-ratelimit-limit";
+
+/// The name of the header that contains the number of requests left in the
+/// current period.
+pub const REMAINING_HEADER: &str = "x-ratelimit-remaining";
+
+/// The name of the header that contains the unix timestamp at which the
+/// current period ends.
+pub const RESET_HEADER: &str = "x-ratelimit-reset";
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Marker type that adds the [`TooManyRequests`] response (including the
+/// `X-RateLimit-*` headers) to the documentation of endpoints that return a
+/// [`Response<T, QuotaLimited>`](crate::responses::Response).
+#[derive(Debug)]
+pub struct QuotaLimited;
+
+impl MetaResponsesExt for QuotaLimited {
+    type Iter = Vec<MetaResponse>;
+
+    fn responses() -> Self::Iter {
+        let header = |name: &str, description: &str| MetaHeader {
+            name: name.into(),
+            description: Some(description.into()),
+            required: true,
+            deprecated: false,
+            schema: u64::schema_ref(),
+        };
+        let mut responses = TooManyRequests::raw::Response::meta().responses;
+        for response in &mut responses {
+            response.headers.extend([
+                header(
+                    "X-RateLimit-Limit",
+                    "The number of requests allowed per period.",
+                ),
+                header(
+                    "X-RateLimit-Remaining",
+                    "The number of requests left in the current period.",
+                ),
+                header(
+                    "X-RateLimit-Reset",
+                    "The unix timestamp at which the current period ends.",
+                ),
+                header(
+                    "Retry-After",
+                    "The number of seconds until the current period ends.",
+                ),
+            ]);
+        }
+        responses
+    }
+
+    fn register(registry: &mut Registry) {
+        TooManyRequests::raw::Response::register(registry);
+    }
+}
+
+/// The period after which the quota of a client is reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuotaPeriod {
+    /// The quota is reset at midnight (UTC).
+    Daily,
+    /// The quota is reset on the first day of each month (UTC).
+    Monthly,
+}
+
+impl QuotaPeriod {
+    /// Return the unix timestamps of the start and the end of the period that
+    /// contains the given unix timestamp.
+    pub fn window(&self, now: u64) -> (u64, u64) {
+        let day = now / SECONDS_PER_DAY;
+        match self {
+            Self::Daily => (day * SECONDS_PER_DAY, (day + 1) * SECONDS_PER_DAY),
+            Self::Monthly => {
+                let (year, month) = year_month_from_days(day);
+                let (next_year, next_month) = match month {
+                    12 => (year + 1, 1),
+                    _ => (year, month + 1),
+                };
+                (
+                    days_from_year_month(year, month) * SECONDS_PER_DAY,
+                    days_from_year_month(next_year, next_month) * SECONDS_PER_DAY,
+                )
+            }
+        }
+    }
+}
+
+impl Display for QuotaPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Daily => "daily",
+            Self::Monthly => "monthly",
+        })
+    }
+}
+
+/// Return the year and month of a day (number of days since the unix epoch).
+fn year_month_from_days(days: u64) -> (u64, u64) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month)
+}
+
+/// Return the number of days between the unix epoch and the first day of a
+/// month.
+fn days_from_year_month(year: u64, month: u64) -> u64 {
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// A storage backend for quota counters (e.g. [`MemoryQuotaStore`] or a Redis
+/// based implementation).
+#[poem::async_trait]
+pub trait QuotaStore: Send + Sync + 'static {
+    /// Increment the counter with the given key and return its new value.
+    ///
+    /// The counter is no longer needed after the unix timestamp `expires_at`.
+    /// Implementations must perform the increment atomically.
+    async fn increment(&self, key: &str, expires_at: u64) -> u64;
+}
+
+/// An in-memory [`QuotaStore`].
+#[derive(Debug, Default)]
+pub struct MemoryQuotaStore {
+    inner: Mutex<MemoryQuotaStoreInner>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryQuotaStoreInner {
+    counters: HashMap<String, (u64, u64)>,
+    last_prune: u64,
+}
+
+impl MemoryQuotaStore {
+    /// Create a new empty MemoryQuotaStore.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[poem::async_trait]
+impl QuotaStore for MemoryQuotaStore {
+    async fn increment(&self, key: &str, expires_at: u64) -> u64 {
+        let now = unix_now();
+        let mut inner = self.inner.lock().unwrap();
+
+        // remove expired counters at most once per minute
+        if now.saturating_sub(inner.last_prune) >= 60 {
+            inner.last_prune = now;
+            inner
+                .counters
+                .retain(|_, (_, expires_at)| *expires_at > now);
+        }
+
+        let (count, _) = inner.counters.entry(key.into()).or_insert((0, expires_at));
+        *count += 1;
+        *count
+    }
+}
+
+/// A function that returns the api key by which quotas are tracked.
+///
+/// Requests for which this function returns `None` are not limited.
+pub type QuotaKeyFn = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// A middleware that enforces usage quotas per api key.
+///
+/// Clones of this middleware share their store, so a single instance can be
+/// used to apply a common quota to multiple endpoints.
+#[derive(Clone)]
+pub struct QuotaMiddleware {
+    store: Arc<dyn QuotaStore>,
+    period: QuotaPeriod,
+    limit: u64,
+    key_fn: QuotaKeyFn,
+}
+
+impl Debug for QuotaMiddleware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuotaMiddleware")
+            .field("period", &self.period)
+            .field("limit", &self.limit)
+            .finish_non_exhaustive()
+    }
+}
+
+impl QuotaMiddleware {
+    /// Create a new QuotaMiddleware that allows `limit` requests per `period`
+    /// for each key returned by `key_fn`.
+    ///
+    /// Requests for which `key_fn` returns `None` are not limited.
+    pub fn new<F>(store: impl QuotaStore, period: QuotaPeriod, limit: u64, key_fn: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            store: Arc::new(store),
+            period,
+            limit,
+            key_fn: Arc::new(key_fn),
+        }
+    }
+
+    /// Create a new QuotaMiddleware that allows `limit` requests per `period`
+    /// for each value of the given header (e.g. `X-Api-Key`).
+    pub fn per_header(
+        store: impl QuotaStore,
+        period: QuotaPeriod,
+        limit: u64,
+        header: impl Into<String>,
+    ) -> Self {
+        let header = header.into();
+        Self::new(store, period, limit, move |req| {
+            req.header(&header).map(ToOwned::to_owned)
+        })
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for QuotaMiddleware {
+    type Output = QuotaMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        QuotaMwEndpoint {
+            inner: ep,
+            config: self.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct QuotaMwEndpoint<E> {
+    inner: E,
+    config: QuotaMiddleware,
+}
+
+impl<E: Debug> Debug for QuotaMwEndpoint<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuotaMwEndpoint")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for QuotaMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let Some(key) = (self.config.key_fn)(&req) else {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        };
+
+        let now = unix_now();
+        let (start, reset) = self.config.period.window(now);
+        let count = self
+            .config
+            .store
+            .increment(&format!("{key}:{}:{start}", self.config.period), reset)
+            .await;
+        let limit = self.config.limit;
+
+        if count > limit {
+            let mut resp = TooManyRequests::raw::too_many_requests().into_response();
+            resp.headers_mut()
+                .insert(RETRY_AFTER, reset.saturating_sub(now).max(1).into());
+            set_headers(resp.headers_mut(), limit, 0, reset);
+            return Ok(resp);
+        }
+
+        let mut resp = match self.inner.call(req).await {
+            Ok(resp) => resp.into_response(),
+            // errors returned by api responses (e.g. the error responses of this
+            // crate) are just wrappers of responses
+            Err(err) if err.is_from_response() => err.into_response(),
+            Err(err) => return Err(err),
+        };
+        set_headers(resp.headers_mut(), limit, limit - count, reset);
+        Ok(resp)
+    }
+}
+
+fn set_headers(headers: &mut HeaderMap, limit: u64, remaining: u64, reset: u64) {
+    headers.insert(LIMIT_HEADER, limit.into());
+    headers.insert(REMAINING_HEADER, remaining.into());
+    headers.insert(RESET_HEADER, reset.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::StatusCode, test::TestClient, EndpointExt};
+
+    use super::*;
+
+    #[handler]
+    fn index() -> &'static str {
+        "ok"
+    }
+
+    #[test]
+    fn window() {
+        // 2024-02-29T13:00:00Z
+        let now = 1709211600;
+        assert_eq!(QuotaPeriod::Daily.window(now), (1709164800, 1709251200));
+        assert_eq!(QuotaPeriod::Monthly.window(now), (1706745600, 1709251200));
+        // 2023-12-31T23:59:59Z
+        assert_eq!(
+            QuotaPeriod::Monthly.window(1704067199),
+            (1701388800, 1704067200)
+        );
+        assert_eq!(QuotaPeriod::Monthly.window(0), (0, 31 * SECONDS_PER_DAY));
+    }
+
+    #[tokio::test]
+    async fn quota() {
+        let cli = TestClient::new(index.with(QuotaMiddleware::per_header(
+            MemoryQuotaStore::new(),
+            QuotaPeriod::Daily,
+            2,
+            "x-api-key",
+        )));
+        let (_, reset) = QuotaPeriod::Daily.window(unix_now());
+
+        for remaining in ["1", "0"] {
+            let resp = cli.get("/").header("x-api-key", "foo").send().await;
+            resp.assert_status_is_ok();
+            resp.assert_header(LIMIT_HEADER, "2");
+            resp.assert_header(REMAINING_HEADER, remaining);
+            resp.assert_header(RESET_HEADER, reset.to_string());
+        }
+
+        let resp = cli.get("/").header("x-api-key", "foo").send().await;
+        resp.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        resp.assert_header(REMAINING_HEADER, "0");
+        resp.assert_header_exist(RETRY_AFTER);
+        resp.assert_text(r#"{"error":"too_many_requests"}"#).await;
+
+        // other keys have their own quota
+        let resp = cli.get("/").header("x-api-key", "bar").send().await;
+        resp.assert_header(REMAINING_HEADER, "1");
+
+        // requests without a key are not limited
+        let resp = cli.get("/").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_header_is_not_exist(LIMIT_HEADER);
+
+        let responses = QuotaLimited::responses();
+        assert_eq!(responses[0].status, Some(429));
+        assert_eq!(responses[0].headers[0].name, "X-RateLimit-Limit");
+    }
+}
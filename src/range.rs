@@ -0,0 +1,346 @@
+//! Contains a response type for downloads that supports range requests.
+//!
+//! [`RangeResponse`] answers requests with a valid `Range` header (e.g.
+//! `bytes=0-1023`) with `206 Partial Content` and the requested part of the
+//! body, and requests for ranges that are not satisfiable with
+//! `416 Range Not Satisfiable` (see [`RangeNotSatisfiable`]). Requests without
+//! or with an unsupported `Range` header (e.g. multiple ranges) receive the
+//! whole body.
+//!
+//! #### Example
+//! ```
+//! use poem_ext::range::RangeResponse;
+//! use poem_openapi::{param::Header, OpenApi};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/download", method = "get")]
+//!     async fn download(
+//!         &self,
+//!         #[oai(name = "Range")] range: Header<Option<String>>,
+//!     ) -> RangeResponse {
+//!         let data = b"Hello World!".to_vec();
+//!         RangeResponse::from_bytes(data, range.as_deref()).with_content_type("text/plain")
+//!     }
+//! }
+//! ```
+
+use std::io::SeekFrom;
+
+use poem::{
+    http::{
+        header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE},
+        HeaderValue, StatusCode,
+    },
+    Body, IntoResponse, Response,
+};
+use poem_openapi::{
+    payload::{Binary, Payload},
+    registry::{MetaHeader, MetaMediaType, MetaResponse, MetaResponses, Registry},
+    types::Type,
+    ApiResponse,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+use crate::responses::RangeNotSatisfiable;
+
+/// A response that contains the whole body or the part of it that has been
+/// requested using the `Range` header.
+#[derive(Debug)]
+pub struct RangeResponse {
+    kind: RangeResponseKind,
+    len: u64,
+    content_type: Option<String>,
+}
+
+#[derive(Debug)]
+enum RangeResponseKind {
+    Full(Body),
+    Partial { start: u64, end: u64, body: Body },
+    NotSatisfiable,
+}
+
+impl RangeResponse {
+    /// Create a new RangeResponse for the given data and the value of the
+    /// `Range` header of the request.
+    pub fn from_bytes(data: impl Into<Vec<u8>>, range: Option<&str>) -> Self {
+        let data = data.into();
+        let len = data.len() as u64;
+        let kind = match range.and_then(|range| parse_range(range, len)) {
+            None => RangeResponseKind::Full(data.into()),
+            Some(Ok((start, end))) => RangeResponseKind::Partial {
+                start,
+                end,
+                body: data[start as usize..=end as usize].to_vec().into(),
+            },
+            Some(Err(())) => RangeResponseKind::NotSatisfiable,
+        };
+        Self {
+            kind,
+            len,
+            content_type: None,
+        }
+    }
+
+    /// Create a new RangeResponse that reads the requested part of a seekable
+    /// body (e.g. a file) with the given length.
+    pub async fn from_reader<R>(
+        mut reader: R,
+        len: u64,
+        range: Option<&str>,
+    ) -> std::io::Result<Self>
+    where
+        R: AsyncRead + AsyncSeek + Send + Unpin + 'static,
+    {
+        let kind = match range.and_then(|range| parse_range(range, len)) {
+            None => RangeResponseKind::Full(Body::from_async_read(reader.take(len))),
+            Some(Ok((start, end))) => {
+                reader.seek(SeekFrom::Start(start)).await?;
+                RangeResponseKind::Partial {
+                    start,
+                    end,
+                    body: Body::from_async_read(reader.take(end - start + 1)),
+                }
+            }
+            Some(Err(())) => RangeResponseKind::NotSatisfiable,
+        };
+        Ok(Self {
+            kind,
+            len,
+            content_type: None,
+        })
+    }
+
+    /// Set the content type of the body (default:
+    /// `application/octet-stream`).
+    pub fn with_content_type(self, content_type: impl Into<String>) -> Self {
+        Self {
+            content_type: Some(content_type.into()),
+            ..self
+        }
+    }
+
+    /// Return the status code of the response.
+    pub fn status(&self) -> StatusCode {
+        match self.kind {
+            RangeResponseKind::Full(_) => StatusCode::OK,
+            RangeResponseKind::Partial { .. } => StatusCode::PARTIAL_CONTENT,
+            RangeResponseKind::NotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+        }
+    }
+}
+
+/// Parse the value of a `Range` header for a body with the given length.
+///
+/// Returns `None` if the header should be ignored, `Some(Ok((start, end)))`
+/// with the inclusive bounds of the requested range, or `Some(Err(()))` if the
+/// range is not satisfiable.
+fn parse_range(range: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let range = range.trim().strip_prefix("bytes=")?;
+    if range.contains(',') {
+        return None;
+    }
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    Some(match (start, end) {
+        ("", suffix) => {
+            let suffix = suffix.parse::<u64>().ok()?;
+            if suffix == 0 || len == 0 {
+                Err(())
+            } else {
+                Ok((len.saturating_sub(suffix), len - 1))
+            }
+        }
+        (start, end) => {
+            let start = start.parse::<u64>().ok()?;
+            let end = match end {
+                "" => u64::MAX,
+                end => end.parse::<u64>().ok().filter(|&end| end >= start)?,
+            };
+            if start >= len {
+                Err(())
+            } else {
+                Ok((start, end.min(len - 1)))
+            }
+        }
+    })
+}
+
+fn header(name: &str, description: &str) -> MetaHeader {
+    MetaHeader {
+        name: name.into(),
+        description: Some(description.into()),
+        required: true,
+        deprecated: false,
+        schema: String::schema_ref(),
+    }
+}
+
+impl ApiResponse for RangeResponse {
+    fn meta() -> MetaResponses {
+        let content = || {
+            vec![MetaMediaType {
+                content_type: Binary::<Vec<u8>>::CONTENT_TYPE,
+                schema: Binary::<Vec<u8>>::schema_ref(),
+            }]
+        };
+        let accept_ranges = || header("Accept-Ranges", "The supported range unit (`bytes`).");
+        let content_range = || {
+            header(
+                "Content-Range",
+                "The range of the body that is contained in the response.",
+            )
+        };
+        let mut responses = vec![
+            MetaResponse {
+                description: "OK",
+                status: Some(200),
+                content: content(),
+                headers: vec![accept_ranges()],
+            },
+            MetaResponse {
+                description: "Partial Content",
+                status: Some(206),
+                content: content(),
+                headers: vec![accept_ranges(), content_range()],
+            },
+        ];
+        for mut response in RangeNotSatisfiable::raw::Response::meta().responses {
+            response.headers.push(content_range());
+            responses.push(response);
+        }
+        MetaResponses { responses }
+    }
+
+    fn register(registry: &mut Registry) {
+        RangeNotSatisfiable::raw::Response::register(registry);
+    }
+}
+
+impl IntoResponse for RangeResponse {
+    fn into_response(self) -> Response {
+        let content_type = self
+            .content_type
+            .as_deref()
+            .unwrap_or(Binary::<Vec<u8>>::CONTENT_TYPE);
+        let (mut resp, length) = match self.kind {
+            RangeResponseKind::Full(body) => (
+                Response::builder().content_type(content_type).body(body),
+                Some(self.len),
+            ),
+            RangeResponseKind::Partial { start, end, body } => {
+                let mut resp = Response::builder()
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .content_type(content_type)
+                    .body(body);
+                if let Ok(value) = format!("bytes {start}-{end}/{}", self.len).parse() {
+                    resp.headers_mut().insert(CONTENT_RANGE, value);
+                }
+                (resp, Some(end - start + 1))
+            }
+            RangeResponseKind::NotSatisfiable => {
+                let mut resp = RangeNotSatisfiable::raw::range_not_satisfiable().into_response();
+                if let Ok(value) = format!("bytes */{}", self.len).parse() {
+                    resp.headers_mut().insert(CONTENT_RANGE, value);
+                }
+                (resp, None)
+            }
+        };
+        let headers = resp.headers_mut();
+        headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        if let Some(length) = length {
+            headers.insert(CONTENT_LENGTH, length.into());
+        }
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::test::TestClient;
+    use poem_openapi::{param::Header, OpenApi, OpenApiService};
+
+    use super::*;
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/bytes", method = "get")]
+        async fn bytes(
+            &self,
+            #[oai(name = "Range")] range: Header<Option<String>>,
+        ) -> RangeResponse {
+            RangeResponse::from_bytes(b"0123456789".as_slice(), range.as_deref())
+        }
+
+        #[oai(path = "/reader", method = "get")]
+        async fn reader(
+            &self,
+            #[oai(name = "Range")] range: Header<Option<String>>,
+        ) -> poem::Result<RangeResponse> {
+            let reader = std::io::Cursor::new(b"0123456789".to_vec());
+            Ok(RangeResponse::from_reader(reader, 10, range.as_deref())
+                .await
+                .map_err(poem::error::InternalServerError)?
+                .with_content_type("text/plain"))
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_range("bytes=0-3", 10), Some(Ok((0, 3))));
+        assert_eq!(parse_range("bytes=2-", 10), Some(Ok((2, 9))));
+        assert_eq!(parse_range("bytes=5-100", 10), Some(Ok((5, 9))));
+        assert_eq!(parse_range("bytes=-3", 10), Some(Ok((7, 9))));
+        assert_eq!(parse_range("bytes=-30", 10), Some(Ok((0, 9))));
+        assert_eq!(parse_range("bytes=10-", 10), Some(Err(())));
+        assert_eq!(parse_range("bytes=-0", 10), Some(Err(())));
+        assert_eq!(parse_range("bytes=0-", 0), Some(Err(())));
+        assert_eq!(parse_range("bytes=3-2", 10), None);
+        assert_eq!(parse_range("bytes=0-1,3-4", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+        assert_eq!(parse_range("bytes=x-1", 10), None);
+    }
+
+    #[tokio::test]
+    async fn range_response() {
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+        for path in ["/bytes", "/reader"] {
+            let resp = cli.get(path).send().await;
+            resp.assert_status_is_ok();
+            resp.assert_header(ACCEPT_RANGES, "bytes");
+            resp.assert_header(CONTENT_LENGTH, "10");
+            resp.assert_text("0123456789").await;
+
+            let resp = cli.get(path).header("Range", "bytes=2-4").send().await;
+            resp.assert_status(StatusCode::PARTIAL_CONTENT);
+            resp.assert_header(CONTENT_RANGE, "bytes 2-4/10");
+            resp.assert_header(CONTENT_LENGTH, "3");
+            resp.assert_text("234").await;
+
+            let resp = cli.get(path).header("Range", "bytes=-2").send().await;
+            resp.assert_status(StatusCode::PARTIAL_CONTENT);
+            resp.assert_header(CONTENT_RANGE, "bytes 8-9/10");
+            resp.assert_text("89").await;
+
+            let resp = cli.get(path).header("Range", "bytes=20-").send().await;
+            resp.assert_status(StatusCode::RANGE_NOT_SATISFIABLE);
+            resp.assert_header(CONTENT_RANGE, "bytes */10");
+            resp.assert_text(r#"{"error":"range_not_satisfiable"}"#)
+                .await;
+        }
+    }
+
+    #[test]
+    fn meta() {
+        let statuses = RangeResponse::meta()
+            .responses
+            .iter()
+            .map(|response| response.status)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, [Some(200), Some(206), Some(416)]);
+    }
+}
@@ -0,0 +1,324 @@
+//! Contains a middleware that limits the number of requests per client.
+//!
+//! The limiter uses a token bucket per client key: every key can perform up
+//! to `burst` requests at once, after which requests are only allowed at the
+//! configured rate. Requests that exceed the limit are rejected with a
+//! [`TooManyRequests`] response and a `Retry-After` header.
+//!
+//! #### Example
+//! ```
+//! use std::time::Duration;
+//!
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     add_response_schemas,
+//!     rate_limit::RateLimitMiddleware,
+//!     responses::{Response, TooManyRequests},
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! /// Marker type used to document the 429 response.
+//! struct RateLimited;
+//! add_response_schemas!(RateLimited, TooManyRequests::raw::Response);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> Response<PlainText<&'static str>, RateLimited> {
+//!         Ok(PlainText("Hello World!").into())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     // allow 10 requests per second and ip address
+//!     RateLimitMiddleware::per_ip(10, Duration::from_secs(1)),
+//! );
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use poem::{http::header::RETRY_AFTER, Endpoint, IntoResponse, Middleware, Request, Response};
+
+use crate::{client_ip::ClientIp, responses::TooManyRequests};
+
+/// A function that returns the key by which requests are rate limited.
+///
+/// Requests for which this function returns `None` are not rate limited.
+pub type RateLimitKeyFn = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// A middleware that limits the number of requests per client.
+///
+/// Clones of this middleware share their state, so a single instance can be
+/// used to apply a common limit to multiple endpoints.
+#[derive(Clone)]
+pub struct RateLimitMiddleware {
+    key_fn: RateLimitKeyFn,
+    state: Arc<RateLimitState>,
+}
+
+impl Debug for RateLimitMiddleware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimitMiddleware")
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateLimitMiddleware {
+    /// Create a new RateLimitMiddleware that allows `requests` requests per
+    /// `period` for each key returned by `key_fn`.
+    ///
+    /// This can be used to rate limit authenticated users by e.g. returning
+    /// the subject of the access token. Requests for which `key_fn` returns
+    /// `None` are not rate limited.
+    pub fn new<F>(requests: u32, period: Duration, key_fn: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        assert!(requests > 0, "requests must be greater than zero");
+        assert!(!period.is_zero(), "period must be greater than zero");
+        Self {
+            key_fn: Arc::new(key_fn),
+            state: Arc::new(RateLimitState::new(
+                requests as f64,
+                requests as f64 / period.as_secs_f64(),
+                period,
+            )),
+        }
+    }
+
+    /// Create a new RateLimitMiddleware that allows `requests` requests per
+    /// `period` for each client ip address (see [`ClientIp`]).
+    pub fn per_ip(requests: u32, period: Duration) -> Self {
+        Self::new(requests, period, |req| {
+            ClientIp::of(req).map(|ip| ip.to_string())
+        })
+    }
+
+    /// Create a new RateLimitMiddleware that allows `requests` requests per
+    /// `period` for each value of the given header (e.g. an api key).
+    pub fn per_header(requests: u32, period: Duration, header: impl Into<String>) -> Self {
+        let header = header.into();
+        Self::new(requests, period, move |req| {
+            req.header(&header).map(ToOwned::to_owned)
+        })
+    }
+
+    /// Allow up to `burst` requests at once instead of the number of requests
+    /// per period that has been passed to the constructor.
+    pub fn with_burst(self, burst: u32) -> Self {
+        assert!(burst > 0, "burst must be greater than zero");
+        Self {
+            key_fn: self.key_fn,
+            state: Arc::new(RateLimitState::new(
+                burst as f64,
+                self.state.rate,
+                self.state.period,
+            )),
+        }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RateLimitMiddleware {
+    type Output = RateLimitMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RateLimitMwEndpoint {
+            inner: ep,
+            key_fn: self.key_fn.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct RateLimitMwEndpoint<E> {
+    inner: E,
+    key_fn: RateLimitKeyFn,
+    state: Arc<RateLimitState>,
+}
+
+impl<E: Debug> Debug for RateLimitMwEndpoint<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimitMwEndpoint")
+            .field("inner", &self.inner)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for RateLimitMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        if let Some(key) = (self.key_fn)(&req) {
+            if let Err(retry_after) = self.state.acquire(key, Instant::now()) {
+                let mut resp = TooManyRequests::raw::too_many_requests().into_response();
+                resp.headers_mut()
+                    .insert(RETRY_AFTER, retry_after.as_secs().max(1).into());
+                return Ok(resp);
+            }
+        }
+
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+#[derive(Debug)]
+struct RateLimitState {
+    /// Maximum number of tokens in a bucket.
+    burst: f64,
+    /// Number of tokens that are added to a bucket per second.
+    rate: f64,
+    period: Duration,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    last_prune: Instant,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimitState {
+    fn new(burst: f64, rate: f64, period: Duration) -> Self {
+        Self {
+            burst,
+            rate,
+            period,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take a token from the bucket of the given key or return the time after
+    /// which the next token will be available.
+    fn acquire(&self, key: String, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        self.prune(&mut buckets, now);
+
+        let bucket = buckets.buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.tokens(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                ((1.0 - bucket.tokens) / self.rate).ceil(),
+            ))
+        }
+    }
+
+    fn tokens(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated);
+        (bucket.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst)
+    }
+
+    /// Remove full buckets, as they are equivalent to missing ones. This is
+    /// done at most once per period to keep the cost per request low.
+    fn prune(&self, buckets: &mut Buckets, now: Instant) {
+        if now.saturating_duration_since(buckets.last_prune) < self.period {
+            return;
+        }
+        buckets.last_prune = now;
+        buckets
+            .buckets
+            .retain(|_, bucket| self.tokens(bucket, now) < self.burst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, test::TestClient, EndpointExt};
+
+    use super::*;
+
+    #[handler]
+    fn index() -> &'static str {
+        "ok"
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        let cli = TestClient::new(index.with(RateLimitMiddleware::per_header(
+            2,
+            Duration::from_secs(60),
+            "x-api-key",
+        )));
+
+        for _ in 0..2 {
+            let resp = cli.get("/").header("x-api-key", "foo").send().await;
+            resp.assert_status_is_ok();
+        }
+
+        let resp = cli.get("/").header("x-api-key", "foo").send().await;
+        resp.assert_status(poem::http::StatusCode::TOO_MANY_REQUESTS);
+        resp.assert_header(RETRY_AFTER, "30");
+        resp.assert_text(r#"{"error":"too_many_requests"}"#).await;
+
+        // other keys have their own limit
+        let resp = cli.get("/").header("x-api-key", "bar").send().await;
+        resp.assert_status_is_ok();
+
+        // requests without a key are not rate limited
+        for _ in 0..3 {
+            let resp = cli.get("/").send().await;
+            resp.assert_status_is_ok();
+        }
+    }
+
+    #[test]
+    fn refill() {
+        let state = RateLimitMiddleware::new(2, Duration::from_secs(2), |_| None).state;
+        let start = Instant::now();
+        assert!(state.acquire("foo".into(), start).is_ok());
+        assert!(state.acquire("foo".into(), start).is_ok());
+        assert_eq!(
+            state.acquire("foo".into(), start),
+            Err(Duration::from_secs(1))
+        );
+        assert!(state
+            .acquire("foo".into(), start + Duration::from_secs(1))
+            .is_ok());
+        assert!(state
+            .acquire("foo".into(), start + Duration::from_secs(1))
+            .is_err());
+
+        // full buckets are removed after one period
+        state
+            .acquire("bar".into(), start + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(
+            state
+                .buckets
+                .lock()
+                .unwrap()
+                .buckets
+                .keys()
+                .collect::<Vec<_>>(),
+            ["bar"]
+        );
+    }
+}
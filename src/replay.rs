@@ -0,0 +1,310 @@
+//! Contains a middleware that protects against replayed requests.
+//!
+//! Clients have to send a unique nonce of at most 128 characters (e.g. a random
+//! uuid) in the `X-Nonce` header and the current unix timestamp in the
+//! `X-Timestamp` header with every request that uses an unsafe method (i.e.
+//! `POST`, `PUT`, `PATCH`, `DELETE`, ...). Requests whose timestamp is outside
+//! of the configured window are rejected with a `401 Unauthorized` response,
+//! and requests that reuse a nonce within the window are rejected with a
+//! `409 Conflict` response (see [`ReplayRejected`]).
+//!
+//! The timestamp limits the time for which used nonces have to be remembered.
+//! Nonces are stored in a [`NonceStore`], which should be shared between all
+//! instances of the application (e.g. a Redis based implementation) if it is
+//! scaled horizontally.
+//!
+//! #### Example
+//! ```
+//! use std::time::Duration;
+//!
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     replay::{MemoryNonceStore, ReplayProtected, ReplayProtectionMiddleware},
+//!     responses::Response,
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/transfers", method = "post")]
+//!     async fn transfer(&self) -> Response<PlainText<&'static str>, ReplayProtected> {
+//!         Ok(PlainText("ok").into())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     // accept requests that have been sent at most five minutes ago
+//!     ReplayProtectionMiddleware::new(MemoryNonceStore::new(), Duration::from_secs(300)),
+//! );
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response};
+
+use crate::{add_response_schemas, responses::ReplayRejected};
+
+/// Default name of the header that contains the nonce of a request.
+pub const NONCE_HEADER: &str = "x-nonce";
+
+/// Default name of the header that contains the unix timestamp of a request.
+pub const TIMESTAMP_HEADER: &str = "x-timestamp";
+
+/// Marker type that adds the [`ReplayRejected`] responses to the
+/// documentation of endpoints that return a
+/// [`Response<T, ReplayProtected>`](crate::responses::Response).
+#[derive(Debug)]
+pub struct ReplayProtected;
+add_response_schemas!(ReplayProtected, ReplayRejected::raw::Response);
+
+/// A storage backend for used nonces (e.g. [`MemoryNonceStore`] or a Redis
+/// based implementation).
+#[poem::async_trait]
+pub trait NonceStore: Send + Sync + 'static {
+    /// Remember a nonce for the given duration.
+    ///
+    /// Returns `false` if the nonce is already known. Implementations must
+    /// perform the check and the insertion atomically.
+    async fn insert(&self, nonce: &str, ttl: Duration) -> bool;
+}
+
+/// An in-memory [`NonceStore`].
+#[derive(Debug, Default)]
+pub struct MemoryNonceStore {
+    inner: Mutex<MemoryNonceStoreInner>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryNonceStoreInner {
+    nonces: HashMap<String, Instant>,
+    last_prune: Option<Instant>,
+}
+
+impl MemoryNonceStore {
+    /// Create a new empty MemoryNonceStore.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[poem::async_trait]
+impl NonceStore for MemoryNonceStore {
+    async fn insert(&self, nonce: &str, ttl: Duration) -> bool {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+
+        // remove expired nonces at most once per second
+        if inner.last_prune.map_or(true, |last| {
+            now.saturating_duration_since(last) >= Duration::from_secs(1)
+        }) {
+            inner.last_prune = Some(now);
+            inner.nonces.retain(|_, expires_at| *expires_at > now);
+        }
+
+        if inner
+            .nonces
+            .get(nonce)
+            .is_some_and(|expires_at| *expires_at > now)
+        {
+            return false;
+        }
+        inner.nonces.insert(nonce.into(), now + ttl);
+        true
+    }
+}
+
+/// A middleware that rejects requests with a missing, expired or reused nonce.
+///
+/// Clones of this middleware share their store.
+#[derive(Clone)]
+pub struct ReplayProtectionMiddleware {
+    store: Arc<dyn NonceStore>,
+    window: Duration,
+    nonce_header: String,
+    timestamp_header: String,
+}
+
+impl std::fmt::Debug for ReplayProtectionMiddleware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplayProtectionMiddleware")
+            .field("window", &self.window)
+            .field("nonce_header", &self.nonce_header)
+            .field("timestamp_header", &self.timestamp_header)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ReplayProtectionMiddleware {
+    /// Create a new ReplayProtectionMiddleware that accepts requests whose
+    /// timestamp differs from the current time by at most `window`.
+    pub fn new(store: impl NonceStore, window: Duration) -> Self {
+        Self {
+            store: Arc::new(store),
+            window,
+            nonce_header: NONCE_HEADER.into(),
+            timestamp_header: TIMESTAMP_HEADER.into(),
+        }
+    }
+
+    /// Set the name of the header that contains the nonce (default:
+    /// [`NONCE_HEADER`]).
+    pub fn with_nonce_header(self, name: impl Into<String>) -> Self {
+        Self {
+            nonce_header: name.into(),
+            ..self
+        }
+    }
+
+    /// Set the name of the header that contains the unix timestamp (default:
+    /// [`TIMESTAMP_HEADER`]).
+    pub fn with_timestamp_header(self, name: impl Into<String>) -> Self {
+        Self {
+            timestamp_header: name.into(),
+            ..self
+        }
+    }
+
+    async fn check(&self, req: &Request, now: u64) -> Result<(), ReplayRejected::raw::Response> {
+        let nonce = req
+            .header(&self.nonce_header)
+            .map(str::trim)
+            .filter(|nonce| !nonce.is_empty() && nonce.len() <= 128);
+        let timestamp = req
+            .header(&self.timestamp_header)
+            .and_then(|x| x.trim().parse::<u64>().ok());
+        let (Some(nonce), Some(timestamp)) = (nonce, timestamp) else {
+            return Err(ReplayRejected::raw::invalid_nonce());
+        };
+        if now.abs_diff(timestamp) > self.window.as_secs() {
+            return Err(ReplayRejected::raw::invalid_nonce());
+        }
+
+        // a request can be accepted until `window` after its timestamp, which
+        // itself may be up to `window` in the future
+        if !self.store.insert(nonce, self.window * 2).await {
+            return Err(ReplayRejected::raw::nonce_reused());
+        }
+        Ok(())
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for ReplayProtectionMiddleware {
+    type Output = ReplayProtectionMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ReplayProtectionMwEndpoint {
+            inner: ep,
+            config: self.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ReplayProtectionMwEndpoint<E> {
+    inner: E,
+    config: ReplayProtectionMiddleware,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for ReplayProtectionMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        if !req.method().is_safe() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            if let Err(resp) = self.config.check(&req, now).await {
+                return Ok(resp.into_response());
+            }
+        }
+
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::StatusCode, test::TestClient, EndpointExt};
+
+    use super::*;
+
+    #[handler]
+    fn index() -> &'static str {
+        "ok"
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[tokio::test]
+    async fn replay_protection() {
+        let cli = TestClient::new(index.with(ReplayProtectionMiddleware::new(
+            MemoryNonceStore::new(),
+            Duration::from_secs(60),
+        )));
+
+        // safe requests are not checked
+        cli.get("/").send().await.assert_status_is_ok();
+
+        let resp = cli.post("/").send().await;
+        resp.assert_status(StatusCode::UNAUTHORIZED);
+        resp.assert_text(r#"{"error":"invalid_nonce"}"#).await;
+
+        let resp = cli
+            .post("/")
+            .header(NONCE_HEADER, "foo")
+            .header(TIMESTAMP_HEADER, now() - 120)
+            .send()
+            .await;
+        resp.assert_status(StatusCode::UNAUTHORIZED);
+
+        let resp = cli
+            .post("/")
+            .header(NONCE_HEADER, "foo")
+            .header(TIMESTAMP_HEADER, now())
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text("ok").await;
+
+        let resp = cli
+            .post("/")
+            .header(NONCE_HEADER, "foo")
+            .header(TIMESTAMP_HEADER, now())
+            .send()
+            .await;
+        resp.assert_status(StatusCode::CONFLICT);
+        resp.assert_text(r#"{"error":"nonce_reused"}"#).await;
+
+        cli.post("/")
+            .header(NONCE_HEADER, "bar")
+            .header(TIMESTAMP_HEADER, now())
+            .send()
+            .await
+            .assert_status_is_ok();
+    }
+
+    #[tokio::test]
+    async fn memory_store() {
+        let store = MemoryNonceStore::new();
+        assert!(store.insert("foo", Duration::from_millis(50)).await);
+        assert!(!store.insert("foo", Duration::from_millis(50)).await);
+        assert!(store.insert("bar", Duration::from_millis(50)).await);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(store.insert("foo", Duration::from_millis(50)).await);
+    }
+}
@@ -0,0 +1,317 @@
+//! Contains a middleware that emits an event for every completed request.
+//!
+//! The [`RequestEventMiddleware`] creates a [`RequestEvent`] after the
+//! endpoint has returned, which contains the operation id of the endpoint
+//! (set via `#[oai(operation_id = "...")]`), the response status, the
+//! latency, the [`RequestContext`] (request id, client ip, identity, tenant
+//! and locale) and the error code of error responses in the JSON format of
+//! this crate (e.g. `{"error": "user_not_found"}`). The event is passed to one
+//! or more [`RequestEventSink`]s, which can e.g. forward it to audit,
+//! analytics or billing consumers.
+//!
+//! Sinks are called synchronously before the response is sent to the client,
+//! so they should not block. Consumers that have to do asynchronous work
+//! should subscribe using a channel: [`RequestEventSink`] is implemented for
+//! the senders of tokio's [`mpsc`] and [`broadcast`] channels. Events are
+//! dropped (and a warning is logged) if a bounded channel is full.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::request_events::{RequestEvent, RequestEventMiddleware};
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//! use tokio::sync::mpsc;
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get", operation_id = "test")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! let (tx, mut rx) = mpsc::channel::<RequestEvent>(1024);
+//! // tokio::spawn(async move {
+//! //     while let Some(event) = rx.recv().await {
+//! //         // record the event for billing
+//! //     }
+//! // });
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     RequestEventMiddleware::new(tx).with_sink(|event: RequestEvent| {
+//!         // update analytics counters
+//!     }),
+//! );
+//! ```
+
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use poem::{
+    http::{Method, StatusCode},
+    Endpoint, IntoResponse, Middleware, Request, Response,
+};
+use poem_openapi::OperationId;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{context::RequestContext, identity::Identity, utils::read_error_body};
+
+/// An event that is emitted for every completed request.
+#[derive(Debug, Clone)]
+pub struct RequestEvent {
+    /// The time at which the request has been received.
+    pub timestamp: SystemTime,
+    /// The method of the request.
+    pub method: Method,
+    /// The path of the request.
+    pub path: String,
+    /// The operation id of the endpoint.
+    pub operation: Option<&'static str>,
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The time it took the endpoint to handle the request.
+    pub latency: Duration,
+    /// Cross-cutting information about the request (see [`RequestContext`]).
+    pub context: RequestContext,
+    /// The error code of an error response (e.g. `user_not_found`).
+    pub error: Option<String>,
+    /// The id of an internal server error (see
+    /// [`internal_server_error`](crate::responses::internal_server_error)).
+    pub error_id: Option<String>,
+}
+
+impl RequestEvent {
+    /// Return `true` if the request has been successful (i.e. the response
+    /// status is not a client or server error).
+    pub fn is_success(&self) -> bool {
+        !self.status.is_client_error() && !self.status.is_server_error()
+    }
+}
+
+/// A consumer of request events.
+///
+/// This trait is implemented for functions that take a [`RequestEvent`] and
+/// for the senders of tokio's [`mpsc`] and [`broadcast`] channels.
+pub trait RequestEventSink: Send + Sync + 'static {
+    /// Consume a request event. This method should not block.
+    fn emit(&self, event: RequestEvent);
+}
+
+impl<F> RequestEventSink for F
+where
+    F: Fn(RequestEvent) + Send + Sync + 'static,
+{
+    fn emit(&self, event: RequestEvent) {
+        self(event)
+    }
+}
+
+impl RequestEventSink for mpsc::Sender<RequestEvent> {
+    fn emit(&self, event: RequestEvent) {
+        if let Err(mpsc::error::TrySendError::Full(_)) = self.try_send(event) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("request event channel is full, dropping event");
+        }
+    }
+}
+
+impl RequestEventSink for mpsc::UnboundedSender<RequestEvent> {
+    fn emit(&self, event: RequestEvent) {
+        let _ = self.send(event);
+    }
+}
+
+impl RequestEventSink for broadcast::Sender<RequestEvent> {
+    fn emit(&self, event: RequestEvent) {
+        // sending only fails if there are no subscribers
+        let _ = self.send(event);
+    }
+}
+
+/// A middleware that emits a [`RequestEvent`] for every completed request.
+#[derive(Clone)]
+pub struct RequestEventMiddleware {
+    sinks: Vec<Arc<dyn RequestEventSink>>,
+}
+
+impl Debug for RequestEventMiddleware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestEventMiddleware")
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
+}
+
+impl RequestEventMiddleware {
+    /// Create a new RequestEventMiddleware that passes request events to the
+    /// given sink.
+    pub fn new(sink: impl RequestEventSink) -> Self {
+        Self {
+            sinks: vec![Arc::new(sink)],
+        }
+    }
+
+    /// Also pass request events to the given sink.
+    pub fn with_sink(mut self, sink: impl RequestEventSink) -> Self {
+        self.sinks.push(Arc::new(sink));
+        self
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RequestEventMiddleware {
+    type Output = RequestEventMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RequestEventMwEndpoint {
+            inner: ep,
+            sinks: self.sinks.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct RequestEventMwEndpoint<E> {
+    inner: E,
+    sinks: Vec<Arc<dyn RequestEventSink>>,
+}
+
+impl<E: Debug> Debug for RequestEventMwEndpoint<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestEventMwEndpoint")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E> RequestEventMwEndpoint<E> {
+    fn emit(&self, event: RequestEvent) {
+        if let Some((last, sinks)) = self.sinks.split_last() {
+            for sink in sinks {
+                sink.emit(event.clone());
+            }
+            last.emit(event);
+        }
+    }
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for RequestEventMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let timestamp = SystemTime::now();
+        let method = req.method().clone();
+        let path = req.uri().path().to_owned();
+        let identity = Identity::attach(&mut req);
+        let mut context = RequestContext::of(&req);
+
+        let start = Instant::now();
+        let mut result = match self.inner.call(req).await {
+            Ok(resp) => Ok(resp.into_response()),
+            // errors returned by api responses (e.g. the error responses of this
+            // crate) are just wrappers of responses
+            Err(err) if err.is_from_response() => Ok(err.into_response()),
+            Err(err) => Err(err),
+        };
+        let latency = start.elapsed();
+        context.identity = identity.value();
+
+        let (status, operation) = match &result {
+            Ok(resp) => (resp.status(), resp.data::<OperationId>().map(|op| op.0)),
+            Err(err) => (err.status(), err.data::<OperationId>().map(|op| op.0)),
+        };
+        let (error, error_id) = match result {
+            Ok(ref mut resp) if status.is_client_error() || status.is_server_error() => {
+                read_error_body(resp).await
+            }
+            _ => (None, None),
+        };
+
+        self.emit(RequestEvent {
+            timestamp,
+            method,
+            path,
+            operation,
+            status,
+            latency,
+            context,
+            error,
+            error_id,
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, EndpointExt};
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+    use crate::responses::{internal_server_error, Response};
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/ok", method = "get", operation_id = "ok")]
+        async fn ok(&self, req: &Request) -> PlainText<&'static str> {
+            Identity::set(req, "user");
+            PlainText("ok")
+        }
+
+        #[oai(path = "/error", method = "get", operation_id = "error")]
+        async fn error(&self) -> Response<PlainText<&'static str>> {
+            Err(internal_server_error("failed"))
+        }
+    }
+
+    #[tokio::test]
+    async fn events() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let (btx, mut brx) = broadcast::channel(16);
+        let cli = TestClient::new(
+            OpenApiService::new(Api, "test", "1")
+                .with(RequestEventMiddleware::new(tx).with_sink(btx)),
+        );
+
+        let resp = cli.get("/ok").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("ok").await;
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.method, Method::GET);
+        assert_eq!(event.path, "/ok");
+        assert_eq!(event.operation, Some("ok"));
+        assert_eq!(event.status, StatusCode::OK);
+        assert_eq!(event.context.identity.as_deref(), Some("user"));
+        assert_eq!(event.error, None);
+        assert!(event.is_success());
+        assert_eq!(brx.recv().await.unwrap().path, "/ok");
+
+        let resp = cli.get("/error").send().await;
+        resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = resp.json().await;
+        let error_id = body.value().object().get("error_id").string().to_owned();
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.operation, Some("error"));
+        assert_eq!(event.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(event.context.identity, None);
+        assert_eq!(event.error.as_deref(), Some("internal_server_error"));
+        assert_eq!(event.error_id, Some(error_id));
+        assert!(!event.is_success());
+
+        cli.get("/missing")
+            .send()
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.operation, None);
+        assert_eq!(event.status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,190 @@
+//! Contains a middleware that assigns an id to each incoming request.
+//!
+//! The id is randomly generated for each request. If the middleware is
+//! configured to trust the `X-Request-Id` request header (e.g. because it is
+//! set by a reverse proxy), the id is taken from this header instead, as long
+//! as it is a [valid request id](is_valid_request_id). It is made available
+//! to endpoints and other middlewares via the [`RequestId`] request extension
+//! and echoed in the `X-Request-Id` response header.
+//!
+//! #### Example
+//! ```
+//! use poem::{web::Data, EndpointExt, Route};
+//! use poem_ext::{
+//!     panic_handler::PanicHandler,
+//!     request_id::{RequestId, RequestIdMiddleware},
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self, request_id: Data<&RequestId>) -> PlainText<String> {
+//!         PlainText(request_id.to_string())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     // the request id middleware has to be added after the panic handler, so
+//!     // the panic handler can include the request id in its responses
+//!     .with(PanicHandler::custom())
+//!     .with(RequestIdMiddleware::new());
+//! ```
+
+use std::{fmt::Display, ops::Deref};
+
+use poem::{async_trait, Endpoint, IntoResponse, Middleware, Request, Response};
+use uuid::Uuid;
+
+/// Name of the header that contains the request id.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Maximum length of a request id that is taken from the
+/// [`REQUEST_ID_HEADER`].
+pub const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Return `true` if the given request id is not empty, not longer than
+/// [`MAX_REQUEST_ID_LENGTH`] and only contains ASCII letters, digits and the
+/// characters `-`, `_`, `.` and `:`.
+pub fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'_' | b'.' | b':'))
+}
+
+/// The id of a request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Return the id of a request that has been assigned by the
+    /// [`RequestIdMiddleware`].
+    ///
+    /// Returns `None` if no [`RequestIdMiddleware`] is present. The
+    /// [`REQUEST_ID_HEADER`] of the request is never used directly, as it is
+    /// controlled by the client.
+    pub fn of(req: &Request) -> Option<Self> {
+        req.extensions().get::<Self>().cloned()
+    }
+}
+
+impl Deref for RequestId {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A middleware that assigns an id to each incoming request.
+#[derive(Debug, Clone, Default)]
+pub struct RequestIdMiddleware {
+    trust_header: bool,
+}
+
+impl RequestIdMiddleware {
+    /// Create a new RequestIdMiddleware.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Control whether the request id should be taken from the
+    /// [`REQUEST_ID_HEADER`] of incoming requests (default: `false`). Ids
+    /// that are not [valid](is_valid_request_id) are replaced with a new id.
+    /// This should only be enabled if the header is set by a trusted reverse
+    /// proxy, as the id is included in logs and error responses.
+    pub fn trust_header(self, trust_header: bool) -> Self {
+        Self { trust_header }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RequestIdMiddleware {
+    type Output = RequestIdMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RequestIdMwEndpoint {
+            inner: ep,
+            trust_header: self.trust_header,
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct RequestIdMwEndpoint<E> {
+    inner: E,
+    trust_header: bool,
+}
+
+#[async_trait]
+impl<E: Endpoint> Endpoint for RequestIdMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let request_id = self
+            .trust_header
+            .then(|| req.header(REQUEST_ID_HEADER))
+            .flatten()
+            .filter(|id| is_valid_request_id(id))
+            .map_or_else(|| Uuid::new_v4().to_string(), ToOwned::to_owned);
+        req.extensions_mut().insert(RequestId(request_id.clone()));
+
+        let mut resp = match self.inner.call(req).await {
+            Ok(resp) => resp.into_response(),
+            Err(err) => err.into_response(),
+        };
+        if let Ok(value) = request_id.parse() {
+            resp.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, test::TestClient, EndpointExt};
+
+    use super::*;
+
+    #[handler]
+    fn index(req: &Request) -> String {
+        RequestId::of(req).unwrap().0
+    }
+
+    #[tokio::test]
+    async fn request_id() {
+        let check = |trust_header, id: &'static str| async move {
+            let cli =
+                TestClient::new(index.with(RequestIdMiddleware::new().trust_header(trust_header)));
+            let resp = cli.get("/").header(REQUEST_ID_HEADER, id).send().await;
+            resp.assert_status_is_ok();
+            let header = resp.0.headers()[REQUEST_ID_HEADER]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            assert_eq!(resp.0.into_body().into_string().await.unwrap(), header);
+            header
+        };
+
+        assert_ne!(check(false, "abc").await, "abc");
+        assert_eq!(check(true, "abc").await, "abc");
+        assert_eq!(check(true, "proxy-1:42.a_b").await, "proxy-1:42.a_b");
+        assert_ne!(check(true, "").await, "");
+        assert_ne!(check(true, "<script>").await, "<script>");
+        let long = "a".repeat(MAX_REQUEST_ID_LENGTH + 1);
+        let long: &'static str = Box::leak(long.into_boxed_str());
+        assert_ne!(check(true, long).await, long);
+    }
+}
@@ -0,0 +1,322 @@
+use poem::http::StatusCode;
+use serde_json::{Map, Value};
+
+/// Name of the extension that lists the error codes of a response in the
+/// OpenAPI document.
+pub const ERROR_CODE_EXTENSION: &str = "x-error-code";
+
+/// Trait for enums of error codes. Use the [`error_code!`](crate::error_code!)
+/// macro to implement it.
+pub trait ErrorCodeEnum: Copy + Send + Sync + 'static {
+    /// All error codes.
+    const ALL: &'static [Self];
+
+    /// Return the identifier of this error code, which is used as the `error`
+    /// field in response bodies (e.g. `user_not_found`).
+    fn code(self) -> &'static str;
+
+    /// Return the status code of the response.
+    fn status(self) -> StatusCode;
+
+    /// Return the description of this error code.
+    fn description(self) -> &'static str;
+}
+
+/// Add the `x-error-code` extension to all responses of an OpenAPI document
+/// in JSON format whose body contains one of the error codes of `E`.
+///
+/// The extension contains the list of error codes that may be returned with
+/// the response.
+pub fn add_error_code_extensions<E: ErrorCodeEnum>(spec: &str) -> String {
+    let Ok(mut spec) = serde_json::from_str::<Value>(spec) else {
+        return spec.into();
+    };
+    let schemas = spec
+        .pointer("/components/schemas")
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+    if let Some(paths) = spec.get_mut("paths").and_then(Value::as_object_mut) {
+        for response in paths
+            .values_mut()
+            .filter_map(Value::as_object_mut)
+            .flat_map(|path| path.values_mut())
+            .filter_map(|operation| operation.get_mut("responses")?.as_object_mut())
+            .flat_map(|responses| responses.values_mut())
+            .filter_map(Value::as_object_mut)
+        {
+            let mut codes = Vec::new();
+            if let Some(content) = response.get("content") {
+                collect_error_codes::<E>(content, &schemas, &mut codes);
+            }
+            if !codes.is_empty() {
+                response.insert(ERROR_CODE_EXTENSION.into(), codes.into());
+            }
+        }
+    }
+    spec.to_string()
+}
+
+/// Collect the error codes of `E` that are used as the `error` property of
+/// any schema in `value` (following references).
+fn collect_error_codes<E: ErrorCodeEnum>(
+    value: &Value,
+    schemas: &Map<String, Value>,
+    codes: &mut Vec<&'static str>,
+) {
+    match value {
+        Value::Object(object) => {
+            if let Some(schema) = object
+                .get("$ref")
+                .and_then(Value::as_str)
+                .and_then(|x| x.strip_prefix("#/components/schemas/"))
+                .and_then(|name| schemas.get(name))
+            {
+                collect_error_codes::<E>(schema, schemas, codes);
+            }
+            if let Some(Value::Array(items)) = object
+                .get("properties")
+                .and_then(|properties| properties.pointer("/error/enum"))
+            {
+                for code in E::ALL.iter().map(|e| e.code()) {
+                    if items.iter().any(|x| x == code) && !codes.contains(&code) {
+                        codes.push(code);
+                    }
+                }
+            }
+            for (key, value) in object {
+                if key != "properties" {
+                    collect_error_codes::<E>(value, schemas, codes);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_error_codes::<E>(item, schemas, codes);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Define an enum of error codes with status codes and descriptions that
+/// serves as the single source of truth for all error identifiers.
+///
+/// The macro generates:
+/// 1. An enum with the given name and variants that implements
+///    [`ErrorCodeEnum`](crate::responses::ErrorCodeEnum). The identifier of
+///    each error code is the snake_case version of the variant's name.
+/// 2. A response type with the name of the enum followed by `Response` (see
+///    [`response!`](crate::response!)), which contains one variant for each
+///    error code with the body `{"error": "<code>"}`.
+/// 3. Conversions from the enum into the response type and into
+///    [`poem::Error`].
+///
+/// Use [`add_error_code_extensions`](crate::responses::add_error_code_extensions)
+/// to mark the responses in the OpenAPI document with the error codes they
+/// may contain.
+///
+/// #### Example
+/// ```
+/// use poem::http::StatusCode;
+/// use poem_ext::{
+///     error_code,
+///     responses::{add_error_code_extensions, ErrorCodeEnum},
+/// };
+/// use poem_openapi::{param::Path, payload::PlainText, OpenApi, OpenApiService};
+///
+/// error_code!(pub ErrorCode = {
+///     /// The user does not exist.
+///     UserNotFound(404),
+///     /// The user has been banned.
+///     UserBanned(403),
+/// });
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/users/:id", method = "get")]
+///     async fn get_user(
+///         &self,
+///         id: Path<u64>,
+///     ) -> Result<PlainText<String>, ErrorCodeResponse::raw::Response> {
+///         match id.0 {
+///             0 => Err(ErrorCode::UserNotFound.into()),
+///             1 => Err(ErrorCode::UserBanned.into()),
+///             _ => Ok(PlainText(format!("user {}", id.0))),
+///         }
+///     }
+/// }
+///
+/// assert_eq!(ErrorCode::UserNotFound.code(), "user_not_found");
+/// assert_eq!(ErrorCode::UserNotFound.status(), StatusCode::NOT_FOUND);
+/// assert_eq!(ErrorCode::UserNotFound.description(), "The user does not exist.");
+///
+/// let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+/// let spec = add_error_code_extensions::<ErrorCode>(&api_service.spec());
+/// ```
+#[macro_export]
+macro_rules! error_code {
+    ($(#[$attr:meta])* $vis:vis $name:ident = {
+        $(
+            $(#[doc = $doc:literal])*
+            $var:ident($status:literal),
+        )*
+    }) => {
+        $crate::responses::macros::paste! {
+            $(#[$attr])*
+            #[derive(::std::fmt::Debug, ::std::clone::Clone, ::std::marker::Copy, ::std::cmp::PartialEq, ::std::cmp::Eq, ::std::hash::Hash)]
+            $vis enum $name {
+                $(
+                    $(#[doc = $doc])*
+                    $var,
+                )*
+            }
+
+            impl $crate::responses::ErrorCodeEnum for $name {
+                const ALL: &'static [Self] = &[$(Self::$var),*];
+
+                fn code(self) -> &'static str {
+                    match self {
+                        $(Self::$var => ::std::stringify!([< $var:snake >]),)*
+                    }
+                }
+
+                fn status(self) -> ::poem_openapi::__private::poem::http::StatusCode {
+                    match self {
+                        $(Self::$var => ::poem_openapi::__private::poem::http::StatusCode::from_u16($status).unwrap(),)*
+                    }
+                }
+
+                fn description(self) -> &'static str {
+                    match self {
+                        $(Self::$var => ::std::concat!($($doc, "\n"),*).trim(),)*
+                    }
+                }
+            }
+
+            impl ::std::fmt::Display for $name {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    f.write_str($crate::responses::ErrorCodeEnum::code(*self))
+                }
+            }
+
+            $crate::response!(
+                #[doc = ::std::concat!("Responses for the error codes of [`", ::std::stringify!($name), "`].")]
+                $vis [< $name Response >] = {
+                    $(
+                        $(#[doc = $doc])*
+                        $var($status, error),
+                    )*
+                }
+            );
+
+            impl ::std::convert::From<$name> for [< $name Response >]::raw::Response {
+                fn from(code: $name) -> Self {
+                    match code {
+                        $($name::$var => [< $name Response >]::raw::[< $var:snake >](),)*
+                    }
+                }
+            }
+
+            impl ::std::convert::From<$name> for ::poem_openapi::__private::poem::Error {
+                fn from(code: $name) -> Self {
+                    [< $name Response >]::raw::Response::from(code).into()
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, IntoResponse};
+    use poem_openapi::{param::Path, payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+    use crate::responses::Response;
+
+    error_code!(TestError = {
+        /// Foo not found
+        FooNotFound(404),
+        /// Bar not found
+        BarNotFound(404),
+        /// The bar is
+        /// closed.
+        BarClosed(409),
+    });
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test/:x", method = "get")]
+        async fn test(
+            &self,
+            x: Path<u8>,
+        ) -> Result<PlainText<&'static str>, TestErrorResponse::raw::Response> {
+            match x.0 {
+                0 => Err(TestError::FooNotFound.into()),
+                _ => Ok(PlainText("ok")),
+            }
+        }
+
+        #[oai(path = "/other", method = "get")]
+        async fn other(&self) -> Response<PlainText<&'static str>> {
+            Ok(PlainText("ok").into())
+        }
+    }
+
+    #[test]
+    fn error_code() {
+        assert_eq!(
+            TestError::ALL,
+            [
+                TestError::FooNotFound,
+                TestError::BarNotFound,
+                TestError::BarClosed
+            ]
+        );
+        assert_eq!(TestError::BarClosed.code(), "bar_closed");
+        assert_eq!(TestError::BarClosed.to_string(), "bar_closed");
+        assert_eq!(TestError::BarClosed.status(), StatusCode::CONFLICT);
+        assert_eq!(TestError::BarClosed.description(), "The bar is\n closed.");
+    }
+
+    #[tokio::test]
+    async fn response() {
+        let resp = TestErrorResponse::raw::Response::from(TestError::BarClosed).into_response();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            resp.into_body().into_string().await.unwrap(),
+            r#"{"error":"bar_closed"}"#
+        );
+
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+        let resp = cli.get("/test/0").send().await;
+        resp.assert_status(StatusCode::NOT_FOUND);
+        resp.assert_text(r#"{"error":"foo_not_found"}"#).await;
+    }
+
+    #[test]
+    fn spec() {
+        let spec =
+            add_error_code_extensions::<TestError>(&OpenApiService::new(Api, "test", "1").spec());
+        let spec: Value = serde_json::from_str(&spec).unwrap();
+
+        let responses = &spec["paths"]["/test/{x}"]["get"]["responses"];
+        assert_eq!(
+            responses["404"][ERROR_CODE_EXTENSION],
+            serde_json::json!(["foo_not_found", "bar_not_found"])
+        );
+        assert_eq!(
+            responses["409"][ERROR_CODE_EXTENSION],
+            serde_json::json!(["bar_closed"])
+        );
+        assert!(responses["200"].get(ERROR_CODE_EXTENSION).is_none());
+        assert!(spec["paths"]["/other"]["get"]["responses"]["422"]
+            .get(ERROR_CODE_EXTENSION)
+            .is_none());
+    }
+}
@@ -1,6 +1,11 @@
 #[doc(hidden)]
 pub use paste::paste;
 
+#[doc(hidden)]
+pub use super::merge_schemas::merge_meta_responses;
+use poem::{http::StatusCode, Response};
+use poem_openapi::registry::{MetaMediaType, MetaResponse, MetaResponses, Registry};
+
 /// Construct an [`ApiResponse`](derive@poem_openapi::ApiResponse) enum with
 /// some helper functions to easily create both success and error responses.
 ///
@@ -96,6 +101,93 @@ pub use paste::paste;
 ///    snake_case version of the variant's name. If the variant contains data or
 ///    error details (like `Ok` and `Conflict` in this example), this function
 ///    accepts exactly one parameter with the specified type.
+/// 3. For each variant a type alias for the type of its response body in the
+///    `raw` module (e.g. `Test::raw::ConflictBody`). All body types implement
+///    both [`ToJSON`](poem_openapi::types::ToJSON) and
+///    [`ParseFromJSON`](poem_openapi::types::ParseFromJSON) (the `error` field
+///    only accepts the exact error code), so they can be reused to parse the
+///    responses in typed API clients:
+///
+/// ```
+/// # use poem_ext::response;
+/// # use poem_openapi::{types::{ParseFromJSON, ToJSON}, Object};
+/// # #[derive(Debug, Object)]
+/// # pub struct ConflictDetails {
+/// #     test: bool,
+/// # }
+/// # response!(Test = {
+/// #     Conflict(409, error) => ConflictDetails,
+/// # });
+/// # fn main() {
+/// let body = Test::raw::ConflictBody::parse_from_json_string(
+///     r#"{"error": "conflict", "details": {"test": true}}"#,
+/// )
+/// .unwrap();
+/// assert!(body.details.test);
+/// assert_eq!(body.to_json_string(), r#"{"details":{"test":true},"error":"conflict"}"#);
+///
+/// assert!(Test::raw::ConflictBody::parse_from_json_string(
+///     r#"{"error": "teapot", "details": {"test": true}}"#,
+/// )
+/// .is_err());
+/// # }
+/// ```
+///
+/// Attributes (e.g. doc comments) in front of the name are applied to the
+/// generated module, except for `#[derive(...)]` attributes: The listed traits
+/// (e.g. `Clone` and `PartialEq`) are derived for the response enum and all
+/// body types instead, so responses can be compared in unit tests or reused.
+/// This requires all data and details types as well as the included responses
+/// to implement these traits:
+///
+/// ```
+/// # use poem_ext::response;
+/// # use poem_openapi::Object;
+/// #[derive(Debug, Clone, PartialEq, Object)]
+/// pub struct ConflictDetails {
+///     test: bool,
+/// }
+///
+/// response!(
+///     /// Response of the update endpoint
+///     #[derive(Clone, PartialEq)]
+///     Test = {
+///         Ok(200),
+///         Conflict(409, error) => ConflictDetails,
+///     }
+/// );
+///
+/// # fn main() {
+/// let resp = Test::raw::conflict(ConflictDetails { test: true });
+/// assert_eq!(resp.clone(), resp);
+/// assert_ne!(resp, Test::raw::ok());
+/// # }
+/// ```
+///
+/// If the status of a variant depends on the request (e.g. `206 Partial
+/// Content` for truncated results), the alternative statuses can be declared
+/// using an `#[alt_status(...)]` attribute after the doc comments. They are
+/// documented in the OpenAPI spec with the same description and body, and an
+/// additional `*_with_status` function is generated that accepts the status as
+/// its last parameter. If the status has not been declared for the variant,
+/// the response keeps the status of the variant (and an error is logged if the
+/// `tracing` feature is enabled), so the response always matches the spec:
+///
+/// ```
+/// # use poem_ext::response;
+/// use poem::http::StatusCode;
+///
+/// response!(Items = {
+///     /// The requested items
+///     #[alt_status(206)]
+///     Ok(200) => Vec<i32>,
+/// });
+///
+/// # fn main() {
+/// let resp: Items::Response = Items::ok_with_status(vec![1, 2, 3], StatusCode::PARTIAL_CONTENT);
+/// assert_eq!(resp.unwrap().status(), Some(StatusCode::PARTIAL_CONTENT));
+/// # }
+/// ```
 ///
 /// The signature of the generated module for this example would look roughly
 /// like this:
@@ -124,14 +216,29 @@ pub use paste::paste;
 ///         fn conflict(teapot: ConflictDetails) -> Response;
 ///         fn teapot() -> Response;
 /// #       }
+///
+/// #       pub struct Empty;
+/// #       pub struct ConflictError { error: &'static str, details: ConflictDetails }
+/// #       pub struct TeapotError { error: &'static str }
+///         type OkBody = Data;
+///         type CreatedBody = Empty; // `{}`
+///         type ConflictBody = ConflictError; // `{"error": "conflict", "details": ...}`
+///         type TeapotBody = TeapotError; // `{"error": "teapot"}`
 ///     }
 /// }
 /// ```
 #[macro_export]
 macro_rules! response {
-    ($vis:vis $name:ident = {
+    (@attrs [$($attr:tt)*] [$($derive:tt)*] #[derive($($d:path),* $(,)?)] $($rest:tt)*) => {
+        $crate::response!(@attrs [$($attr)*] [$($derive)* $($d,)*] $($rest)*);
+    };
+    (@attrs [$($attr:tt)*] [$($derive:tt)*] #[$a:meta] $($rest:tt)*) => {
+        $crate::response!(@attrs [$($attr)* #[$a]] [$($derive)*] $($rest)*);
+    };
+    (@attrs [$(#[$attr:meta])*] $derive:tt $vis:vis $name:ident = {
         $(
             $(#[doc = $doc:literal])*
+            $(#[alt_status($($alt:expr),+ $(,)?)])?
             $var:ident($status:expr $(,$error:ident)?) $(=> $data:ty)?,
         )*
         $(
@@ -139,7 +246,8 @@ macro_rules! response {
         )*
     }) => {
         $crate::responses::macros::paste! {
-            #[allow(dead_code, unused, non_snake_case, non_camel_case_types, clippy::enum_variant_names)]
+            $(#[$attr])*
+            #[allow(dead_code, unused, missing_docs, non_snake_case, non_camel_case_types, clippy::enum_variant_names)]
             $vis mod $name {
                 use super::*;
 
@@ -147,29 +255,52 @@ macro_rules! response {
                     use super::*;
 
                     $(
-                        $crate::__response__response_type!($name, $var, $($error)?, $($data)?);
+                        $crate::__response__response_type!($name, $var, $derive, $($error)?, $($data)?);
                     )*
 
-                    #[derive(::std::fmt::Debug)]
-                    pub enum $name {
-                        $(
-                            $(#[doc = $doc])*
-                            $var(::poem_openapi::payload::Json<[< __ $name __ $var >]>),
-                        )*
-                        $(
-                            [< __Include__ $($include)__+ >]($($include)::+),
-                        )*
+                    $crate::__response__derive! {
+                        $derive
+                        pub enum $name {
+                            $(
+                                $(#[doc = $doc])*
+                                $var(::poem_openapi::payload::Json<[< __ $name __ $var >]>),
+                            )*
+                            $(
+                                [< __Include__ $($include)__+ >]($($include)::+),
+                            )*
+                        }
                     }
 
+                    const __TABLE: $crate::responses::macros::ResponseTable = $crate::responses::macros::ResponseTable {
+                        variants: &[
+                            $(
+                                $crate::responses::macros::ResponseVariant {
+                                    status: $status,
+                                    alt_statuses: &[$($($alt),+)?],
+                                    description: ::std::concat!($($doc, "\n"),*),
+                                    media_types: <::poem_openapi::payload::Json<[< __ $name __ $var >]> as ::poem_openapi::ResponseContent>::media_types,
+                                    register: <::poem_openapi::payload::Json<[< __ $name __ $var >]> as ::poem_openapi::ResponseContent>::register,
+                                },
+                            )*
+                        ],
+                        includes: &[
+                            $(
+                                $crate::responses::macros::IncludedResponse {
+                                    meta: <$($include)::+ as ::poem_openapi::ApiResponse>::meta,
+                                    register: <$($include)::+ as ::poem_openapi::ApiResponse>::register,
+                                },
+                            )*
+                        ],
+                    };
+
                     impl ::poem_openapi::__private::poem::IntoResponse for $name {
                         fn into_response(self) -> ::poem_openapi::__private::poem::Response {
                             match self {
                                 $(
-                                    Self::$var(media) => {
-                                        let mut resp = ::poem_openapi::__private::poem::IntoResponse::into_response(media);
-                                        resp.set_status(poem_openapi::__private::poem::http::StatusCode::from_u16($status).unwrap());
-                                        resp
-                                    }
+                                    Self::$var(media) => $crate::responses::macros::with_status(
+                                        ::poem_openapi::__private::poem::IntoResponse::into_response(media),
+                                        $status,
+                                    ),
                                 )*
                                 $(
                                     Self::[< __Include__ $($include)__+ >](inner) => ::poem_openapi::__private::poem::IntoResponse::into_response(inner),
@@ -178,55 +309,43 @@ macro_rules! response {
                         }
                     }
 
-                    impl ::poem_openapi::ApiResponse for $name {
-                        const BAD_REQUEST_HANDLER: bool = false;
-                        fn meta() -> ::poem_openapi::registry::MetaResponses {
-                            ::poem_openapi::registry::MetaResponses {
-                                responses: vec![
-                                    $(
-                                        ::poem_openapi::registry::MetaResponse {
-                                            description: ::std::concat!($($doc, "\n"),*),
-                                            status: ::std::option::Option::Some($status),
-                                            content: <::poem_openapi::payload::Json<[< __ $name __ $var >]> as ::poem_openapi::ResponseContent>::media_types(),
-                                            headers: vec![],
-                                        },
-                                    )*
-                                ]
-                                .into_iter()
+                    impl $crate::responses::ResponseStatus for $name {
+                        fn status(&self) -> ::std::option::Option<::poem_openapi::__private::poem::http::StatusCode> {
+                            match self {
                                 $(
-                                    .chain(<$($include)::+ as ::poem_openapi::ApiResponse>::meta().responses)
+                                    Self::$var(_) => ::poem_openapi::__private::poem::http::StatusCode::from_u16($status).ok(),
+                                )*
+                                $(
+                                    Self::[< __Include__ $($include)__+ >](_) => ::std::option::Option::None,
                                 )*
-                                .collect()
                             }
                         }
+                    }
+
+                    impl ::poem_openapi::ApiResponse for $name {
+                        const BAD_REQUEST_HANDLER: bool = false;
+                        fn meta() -> ::poem_openapi::registry::MetaResponses {
+                            __TABLE.meta()
+                        }
                         fn register(registry: &mut ::poem_openapi::registry::Registry) {
-                            $(
-                                <::poem_openapi::payload::Json<[< __ $name __ $var >]> as ::poem_openapi::ResponseContent>::register(registry);
-                            )*
-                            $(
-                                <$($include)::+ as ::poem_openapi::ApiResponse>::register(registry);
-                            )*
+                            __TABLE.register(registry)
                         }
                     }
 
                     impl ::std::convert::From<$name> for ::poem_openapi::__private::poem::Error {
                         fn from(resp: $name) -> ::poem_openapi::__private::poem::Error {
-                            use ::poem_openapi::__private::poem::IntoResponse;
-                            let error_msg: ::std::option::Option<&str> = match resp {
+                            let error_msg: &str = match resp {
                                 $(
-                                    $name::$var(_) => ::std::option::Option::Some(::std::concat!($($doc, "\n"),*)),
+                                    $name::$var(_) => ::std::concat!($($doc, "\n"),*),
                                 )*
                                 $(
                                     $name::[< __Include__ $($include)__+ >](inner) => return ::poem_openapi::__private::poem::Error::from(inner),
                                 )*
                             };
-                            let mut err = ::poem_openapi::__private::poem::Error::from_response(
-                                resp.into_response(),
-                            );
-                            if let ::std::option::Option::Some(error_msg) = error_msg {
-                                err.set_error_message(error_msg);
-                            }
-                            err
+                            $crate::responses::macros::into_error(
+                                ::poem_openapi::__private::poem::IntoResponse::into_response(resp),
+                                error_msg,
+                            )
                         }
                     }
 
@@ -249,6 +368,7 @@ macro_rules! response {
 
                     pub type Response = super::__inner::$name;
                     $(
+                        pub type [< $var Body >] = super::__inner::[< __ $name __ $var >];
                         $crate::__response__raw_fn!($name, $var, $($error)?, $($data)?);
                     )*
                 }
@@ -257,29 +377,42 @@ macro_rules! response {
 
                 $(
                     $crate::__response__fn!($name, $var, $($error)?, $($data)?);
+                    $crate::__response__with_status_fn!($var, [$($($alt),+)?], $status, $($error)?, $($data)?);
                 )*
             }
         }
     };
+    ($($input:tt)*) => {
+        $crate::response!(@attrs [] [] $($input)*);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __response__derive {
+    ([$($derive:path,)*] $($item:tt)*) => {
+        #[derive(::std::fmt::Debug $(, $derive)*)]
+        $($item)*
+    };
 }
 
 #[doc(hidden)]
 #[macro_export]
 macro_rules! __response__response_type {
-    ($name:ident, $var:ident, , ) => {
+    ($name:ident, $var:ident, [$($derive:path,)*], , ) => {
         $crate::responses::macros::paste! {
             pub type [< __ $name __ $var >] = $crate::responses::macros::Empty;
         }
     };
-    ($name:ident, $var:ident, , $data:ty) => {
+    ($name:ident, $var:ident, [$($derive:path,)*], , $data:ty) => {
         $crate::responses::macros::paste! {
             pub type [< __ $name __ $var >] = $data;
         }
     };
-    ($name:ident, $var:ident, error,) => {
+    ($name:ident, $var:ident, [$($derive:path,)*], error,) => {
         $crate::responses::macros::paste! {
             $crate::static_string!(pub [< __ $name __ $var __Error >], ::std::stringify!([< $var:snake >]));
-            #[derive(::std::fmt::Debug, ::std::default::Default, ::poem_openapi::Object)]
+            #[derive(::std::fmt::Debug, ::std::default::Default, ::poem_openapi::Object $(, $derive)*)]
             pub struct [< __ $name __ $var >] {
                 pub error: [< __ $name __ $var __Error >],
             }
@@ -290,10 +423,10 @@ macro_rules! __response__response_type {
             }
         }
     };
-    ($name:ident, $var:ident, error, $details:ty) => {
+    ($name:ident, $var:ident, [$($derive:path,)*], error, $details:ty) => {
         $crate::responses::macros::paste! {
             $crate::static_string!(pub [< __ $name __ $var __Error >], ::std::stringify!([< $var:snake >]));
-            #[derive(::std::fmt::Debug, ::poem_openapi::Object)]
+            #[derive(::std::fmt::Debug, ::poem_openapi::Object $(, $derive)*)]
             pub struct [< __ $name __ $var >] {
                 pub error: [< __ $name __ $var __Error >],
                 pub details: $details,
@@ -377,5 +510,228 @@ macro_rules! __response__fn {
 }
 
 #[doc(hidden)]
-#[derive(Debug, poem_openapi::Object)]
+#[macro_export]
+macro_rules! __response__with_status_fn {
+    ($var:ident, [], $($rest:tt)*) => {};
+    ($var:ident, [$($alt:expr),+], $status:expr, ,) => {
+        $crate::responses::macros::paste! {
+            pub fn [< $var:snake _with_status >]<A>(status: ::poem_openapi::__private::poem::http::StatusCode) -> Response<A> {
+                $crate::responses::macros::with_alt_status(self::[< $var:snake >](), status, &[$status, $($alt),+])
+            }
+        }
+    };
+    ($var:ident, [$($alt:expr),+], $status:expr, , $data:ty) => {
+        $crate::responses::macros::paste! {
+            pub fn [< $var:snake _with_status >]<A>(data: $data, status: ::poem_openapi::__private::poem::http::StatusCode) -> Response<A> {
+                $crate::responses::macros::with_alt_status(self::[< $var:snake >](data), status, &[$status, $($alt),+])
+            }
+        }
+    };
+    ($var:ident, [$($alt:expr),+], $status:expr, error,) => {
+        $crate::responses::macros::paste! {
+            pub fn [< $var:snake _with_status >]<A>(status: ::poem_openapi::__private::poem::http::StatusCode) -> Response<A> {
+                $crate::responses::macros::with_alt_status(self::[< $var:snake >](), status, &[$status, $($alt),+])
+            }
+        }
+    };
+    ($var:ident, [$($alt:expr),+], $status:expr, error, $details:ty) => {
+        $crate::responses::macros::paste! {
+            pub fn [< $var:snake _with_status >]<A>(details: $details, status: ::poem_openapi::__private::poem::http::StatusCode) -> Response<A> {
+                $crate::responses::macros::with_alt_status(self::[< $var:snake >](details), status, &[$status, $($alt),+])
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, poem_openapi::Object)]
 pub struct Empty;
+
+// The following types and functions contain the parts of the `ApiResponse`,
+// `IntoResponse` and `From<_> for poem::Error` implementations generated by
+// `response!` that do not depend on the concrete payload types, so they are
+// only compiled once instead of once per response module.
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ResponseVariant {
+    pub status: u16,
+    pub alt_statuses: &'static [u16],
+    pub description: &'static str,
+    pub media_types: fn() -> Vec<MetaMediaType>,
+    pub register: fn(&mut Registry),
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct IncludedResponse {
+    pub meta: fn() -> MetaResponses,
+    pub register: fn(&mut Registry),
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ResponseTable {
+    pub variants: &'static [ResponseVariant],
+    pub includes: &'static [IncludedResponse],
+}
+
+impl ResponseTable {
+    pub fn meta(&self) -> MetaResponses {
+        let variants = self.variants.iter().flat_map(|variant| {
+            std::iter::once(variant.status)
+                .chain(variant.alt_statuses.iter().copied())
+                .map(|status| MetaResponse {
+                    description: variant.description,
+                    status: Some(status),
+                    content: (variant.media_types)(),
+                    headers: vec![],
+                })
+        });
+        let includes = self
+            .includes
+            .iter()
+            .flat_map(|include| (include.meta)().responses);
+        MetaResponses {
+            responses: merge_meta_responses(variants.chain(includes)),
+        }
+    }
+
+    pub fn register(&self, registry: &mut Registry) {
+        for variant in self.variants {
+            (variant.register)(registry);
+        }
+        for include in self.includes {
+            (include.register)(registry);
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn with_status(mut resp: Response, status: u16) -> Response {
+    resp.set_status(StatusCode::from_u16(status).unwrap());
+    resp
+}
+
+#[doc(hidden)]
+pub fn with_alt_status<T, A>(
+    resp: super::Response<T, A>,
+    status: StatusCode,
+    statuses: &[u16],
+) -> super::Response<T, A> {
+    if !statuses.contains(&status.as_u16()) {
+        #[cfg(feature = "tracing")]
+        tracing::error!(
+            %status,
+            declared = ?statuses,
+            "status has not been declared for this response, using the default status"
+        );
+        return resp;
+    }
+    resp.map(|resp| resp.with_status(status))
+}
+
+#[doc(hidden)]
+pub fn into_error(resp: Response, error_msg: &str) -> poem::Error {
+    let mut err = poem::Error::from_response(resp);
+    err.set_error_message(error_msg);
+    err
+}
+
+#[cfg(test)]
+mod tests {
+    use poem_openapi::{ApiResponse, Object, OpenApi, OpenApiService};
+    use serde_json::{json, Value};
+
+    #[derive(Debug, Object)]
+    pub struct Data {
+        foo: i32,
+    }
+
+    #[derive(Debug, Object)]
+    pub struct ConflictDetails {
+        test: bool,
+    }
+
+    #[derive(Debug, ApiResponse)]
+    pub enum OtherResponse {
+        /// Payment required
+        #[oai(status = 402)]
+        PaymentRequired,
+    }
+
+    response!(Included = {
+        /// Included conflict
+        Conflict(409, error),
+        /// Forbidden
+        Forbidden(403, error),
+    });
+
+    response!(Test = {
+        /// Data found
+        Ok(200) => Data,
+        /// Data has been created
+        Created(201),
+        /// Data conflicts with stuff
+        Conflict(409, error) => ConflictDetails,
+        ..OtherResponse,
+        ..Included::raw::Response,
+    });
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test", method = "get")]
+        async fn test(&self) -> Test::Response {
+            Test::created()
+        }
+    }
+
+    #[test]
+    fn spec_with_includes() {
+        let spec = OpenApiService::new(Api, "test", "1").spec();
+        let spec = serde_json::from_str::<Value>(&spec).unwrap();
+        let json = |schema: &str| {
+            json!({"application/json; charset=utf-8": {
+                "schema": {"$ref": format!("#/components/schemas/{schema}")}
+            }})
+        };
+
+        assert_eq!(
+            spec["paths"]["/test"]["get"]["responses"],
+            json!({
+                "200": {"content": json("Data"), "description": " Data found\n"},
+                "201": {"content": json("Empty"), "description": " Data has been created\n"},
+                "402": {"description": "Payment required"},
+                "403": {"content": json("__Included__Forbidden"), "description": " Forbidden\n"},
+                "409": {
+                    "content": {"application/json; charset=utf-8": {"schema": {"oneOf": [
+                        {"$ref": "#/components/schemas/__Test__Conflict"},
+                        {"$ref": "#/components/schemas/__Included__Conflict"},
+                    ]}}},
+                    "description": "There are multiple possible responses with this status code:\n-  Data conflicts with stuff\n\n-  Included conflict\n",
+                },
+                "422": {"content": json("BadRequestError"), "description": "Unprocessable Content"},
+                "500": {"content": json("InternalServerError"), "description": "Internal Server Error"},
+            })
+        );
+        assert_eq!(
+            spec["components"]["schemas"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            [
+                "BadRequestError",
+                "ConflictDetails",
+                "Data",
+                "Empty",
+                "InternalServerError",
+                "__Included__Conflict",
+                "__Included__Forbidden",
+                "__Test__Conflict",
+            ]
+        );
+    }
+}
@@ -24,7 +24,8 @@ pub(super) fn merge_meta_media_types(
         .collect()
 }
 
-pub(super) fn merge_meta_responses(
+/// Merge responses with the same status code into a single response.
+pub fn merge_meta_responses(
     responses: impl IntoIterator<Item = MetaResponse>,
 ) -> Vec<MetaResponse> {
     responses
@@ -5,17 +5,19 @@
 
 use std::marker::PhantomData;
 
-use poem::IntoResponse;
+use poem::{http::StatusCode, IntoResponse};
 use poem_openapi::{
     payload::Json,
     registry::{MetaResponse, MetaResponses, Registry},
     ApiResponse, Object,
 };
-use tracing::error;
+use uuid::Uuid;
 
+pub use self::error_code::{add_error_code_extensions, ErrorCodeEnum, ERROR_CODE_EXTENSION};
 use self::merge_schemas::merge_meta_responses;
-use crate::static_string;
+use crate::{response, static_string};
 
+mod error_code;
 #[doc(hidden)]
 pub mod macros;
 mod merge_schemas;
@@ -66,27 +68,181 @@ mod merge_schemas;
 /// ```
 pub type Response<T, A = ()> = Result<InnerResponse<T, A>, ErrorResponse>;
 
-#[doc(hidden)]
+/// The successful part of a [`Response`], which contains either the response
+/// of the endpoint or the error of a request that could not be parsed.
+///
+/// #### Example
+/// ```
+/// use poem::http::StatusCode;
+/// use poem_ext::{response, responses::Response};
+///
+/// response!(Test = {
+///     Ok(200) => i32,
+///     NotFound(404, error),
+/// });
+///
+/// # fn main() {
+/// let resp: Test::Response = Test::ok(42);
+/// let resp = resp.unwrap();
+/// assert!(!resp.is_bad_request());
+/// assert_eq!(resp.status(), Some(StatusCode::OK));
+///
+/// let resp = resp.map(|resp| match resp {
+///     Test::raw::Response::Ok(_) => Test::raw::not_found(),
+///     resp => resp,
+/// });
+/// assert_eq!(resp.status(), Some(StatusCode::NOT_FOUND));
+/// # }
+/// ```
 #[derive(Debug)]
 pub struct InnerResponse<T, A>(InnerResponseData<T, A>);
 
 #[derive(Debug)]
 enum InnerResponseData<T, A> {
-    Ok { value: T, _auth: PhantomData<A> },
-    BadRequest { error: poem::Error },
+    Ok {
+        value: T,
+        status: Option<StatusCode>,
+        _auth: PhantomData<A>,
+    },
+    BadRequest {
+        error: poem::Error,
+    },
 }
 
 impl<T, A> From<T> for InnerResponse<T, A> {
     fn from(value: T) -> Self {
         Self(InnerResponseData::Ok {
             value,
+            status: None,
             _auth: PhantomData,
         })
     }
 }
 
+impl<T, A> InnerResponse<T, A> {
+    /// Return `true` if the request could not be parsed, i.e. this response
+    /// does not contain a response of the endpoint.
+    pub fn is_bad_request(&self) -> bool {
+        matches!(self.0, InnerResponseData::BadRequest { .. })
+    }
+
+    /// Return the error of the request that could not be parsed.
+    pub fn bad_request_error(&self) -> Option<&poem::Error> {
+        match &self.0 {
+            InnerResponseData::Ok { .. } => None,
+            InnerResponseData::BadRequest { error } => Some(error),
+        }
+    }
+
+    /// Return a reference to the response of the endpoint.
+    pub fn value(&self) -> Option<&T> {
+        match &self.0 {
+            InnerResponseData::Ok { value, .. } => Some(value),
+            InnerResponseData::BadRequest { .. } => None,
+        }
+    }
+
+    /// Return the response of the endpoint.
+    pub fn into_value(self) -> Option<T> {
+        match self.0 {
+            InnerResponseData::Ok { value, .. } => Some(value),
+            InnerResponseData::BadRequest { .. } => None,
+        }
+    }
+
+    /// Transform the response of the endpoint using the given function.
+    ///
+    /// A status that has been set using the `*_with_status` constructors of a
+    /// [`response!`](crate::response!) is kept.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> InnerResponse<U, A> {
+        InnerResponse(match self.0 {
+            InnerResponseData::Ok {
+                value,
+                status,
+                _auth,
+            } => InnerResponseData::Ok {
+                value: f(value),
+                status,
+                _auth,
+            },
+            InnerResponseData::BadRequest { error } => InnerResponseData::BadRequest { error },
+        })
+    }
+
+    /// Call the given function with a reference to the response of the
+    /// endpoint.
+    pub fn inspect(self, f: impl FnOnce(&T)) -> Self {
+        if let InnerResponseData::Ok { value, .. } = &self.0 {
+            f(value);
+        }
+        self
+    }
+
+    /// Return the status code of this response, if it can be determined
+    /// without converting it into a [`poem::Response`] (see
+    /// [`ResponseStatus`]).
+    pub fn status(&self) -> Option<StatusCode>
+    where
+        T: ResponseStatus,
+    {
+        match &self.0 {
+            InnerResponseData::Ok {
+                status: Some(status),
+                ..
+            } => Some(*status),
+            InnerResponseData::Ok { value, .. } => value.status(),
+            InnerResponseData::BadRequest { error } if error.status() == 400 => {
+                Some(StatusCode::UNPROCESSABLE_ENTITY)
+            }
+            InnerResponseData::BadRequest { error } => Some(error.status()),
+        }
+    }
+
+    /// Override the status code of the response of the endpoint.
+    pub(crate) fn with_status(mut self, new_status: StatusCode) -> Self {
+        if let InnerResponseData::Ok { status, .. } = &mut self.0 {
+            *status = Some(new_status);
+        }
+        self
+    }
+}
+
+/// Trait for responses whose status code is known before they are converted
+/// into a [`poem::Response`].
+///
+/// This trait is implemented for the responses generated by
+/// [`response!`](crate::response!) and for the payload types of poem-openapi,
+/// which always respond with `200 OK`.
+pub trait ResponseStatus {
+    /// Return the status code of this response, or `None` if it is only known
+    /// after the conversion (e.g. for responses that have been included in a
+    /// [`response!`](crate::response!) using `..Other`).
+    fn status(&self) -> Option<StatusCode>;
+}
+
+macro_rules! impl_response_status_ok {
+    ($($ty:ident),*) => {
+        $(
+            impl<T> ResponseStatus for poem_openapi::payload::$ty<T> {
+                fn status(&self) -> Option<StatusCode> {
+                    Some(StatusCode::OK)
+                }
+            }
+        )*
+    };
+}
+
+impl_response_status_ok!(Json, PlainText, Html, Binary);
+
 /// Construct an internal server error response and log the error.
 ///
+/// A random error id is generated for every internal server error. It is
+/// included both in the log event and in the response body, so errors reported
+/// by clients can be correlated with the logs. The error is reported to the
+/// global [`ErrorSink`](crate::error_sink::ErrorSink), which logs it using
+/// `tracing` by default. If the `sentry` feature is
+/// enabled, the error is also reported to Sentry (see [`crate::sentry`]).
+///
 /// #### Example
 /// ```
 /// use poem_ext::{response, responses::{internal_server_error, Response}};
@@ -113,8 +269,11 @@ pub fn internal_server_error<E>(error: E) -> ErrorResponse
 where
     E: std::fmt::Display,
 {
-    error!("{error}");
-    make_internal_server_error()
+    let error_id = new_error_id();
+    crate::error_sink::error_sink().internal_error(&error_id, &error);
+    #[cfg(feature = "sentry")]
+    crate::sentry::capture_internal_error(&error_id, &error);
+    make_internal_server_error(error_id, None)
 }
 
 impl<T: std::fmt::Display> From<T> for ErrorResponse {
@@ -123,9 +282,18 @@ impl<T: std::fmt::Display> From<T> for ErrorResponse {
     }
 }
 
-pub(crate) fn make_internal_server_error() -> ErrorResponse {
+pub(crate) fn new_error_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+pub(crate) fn make_internal_server_error(
+    error_id: String,
+    request_id: Option<String>,
+) -> ErrorResponse {
     ErrorResponse::InternalServerError(Json(InternalServerError {
         error: InternalServerErrorText,
+        error_id,
+        request_id,
     }))
 }
 
@@ -143,6 +311,11 @@ pub struct BadRequestError {
 #[derive(Debug, Object)]
 pub struct InternalServerError {
     error: InternalServerErrorText,
+    /// Id that can be used to find this error in the logs.
+    error_id: String,
+    /// Id of the request that caused this error.
+    #[oai(skip_serializing_if_is_none)]
+    request_id: Option<String>,
 }
 
 #[doc(hidden)]
@@ -156,6 +329,241 @@ pub enum ErrorResponse {
     InternalServerError(Json<InternalServerError>),
 }
 
+response!(
+    /// Response that is returned if an endpoint did not complete within its
+    /// deadline (see [`TimeoutMiddleware`](crate::timeout::TimeoutMiddleware)
+    /// or [`shield_with_timeout`](crate::shield_mw::shield_with_timeout)).
+    ///
+    /// Use [`add_response_schemas!`](crate::add_response_schemas!) with
+    /// `GatewayTimeout::raw::Response` to add this response to the
+    /// documentation of the affected endpoints.
+    pub GatewayTimeout = {
+        /// Gateway Timeout
+        GatewayTimeout(504, error),
+    }
+);
+
+response!(
+    /// Responses that are returned if a precondition of a conditional request
+    /// is not met (see [`ETag::check_if_match`](crate::etag::ETag::check_if_match)).
+    ///
+    /// Use `Precondition::raw::Response` as the error type of the affected
+    /// endpoints (e.g. `Result<Test::raw::Response, Precondition::raw::Response>`)
+    /// to add these responses to their documentation.
+    pub Precondition = {
+        /// Precondition Failed
+        PreconditionFailed(412, error),
+        /// Precondition Required
+        PreconditionRequired(428, error),
+    }
+);
+
+response!(
+    /// Response that is returned if a request body exceeds the configured
+    /// maximum size (see [`BodyLimitMiddleware`](crate::body_limit::BodyLimitMiddleware)).
+    ///
+    /// Use [`BodyLimited`](crate::body_limit::BodyLimited) to add this response
+    /// to the documentation of the affected endpoints.
+    pub PayloadTooLarge = {
+        /// Payload Too Large
+        PayloadTooLarge(413, error) => crate::body_limit::BodyLimitDetails,
+    }
+);
+
+response!(
+    /// Responses that are returned if a JSON request body exceeds one of the
+    /// configured limits (see
+    /// [`JsonLimitsMiddleware`](crate::json_limits::JsonLimitsMiddleware)).
+    ///
+    /// Use [`add_response_schemas!`](crate::add_response_schemas!) with
+    /// `JsonLimitExceeded::raw::Response` to add these responses to the
+    /// documentation of the affected endpoints.
+    pub JsonLimitExceeded = {
+        /// Payload Too Large
+        PayloadTooLarge(413, error) => crate::json_limits::JsonLimitDetails,
+        /// Unprocessable Content
+        JsonLimitExceeded(422, error) => crate::json_limits::JsonLimitDetails,
+    }
+);
+
+response!(
+    /// Response that is returned if the query string of a request contains
+    /// unknown or duplicated parameters (see
+    /// [`StrictQuery`](crate::strict_query::StrictQuery)).
+    ///
+    /// Use [`add_response_schemas!`](crate::add_response_schemas!) with
+    /// `InvalidQueryParameters::raw::Response` to add this response to the
+    /// documentation of the affected endpoints.
+    pub InvalidQueryParameters = {
+        /// Unprocessable Content
+        InvalidQueryParameters(422, error) => crate::strict_query::InvalidQueryDetails,
+    }
+);
+
+response!(
+    /// Response that is returned if a request body violates the validation
+    /// rules of its type (see
+    /// [`ValidatedJson`](crate::validation::ValidatedJson)).
+    ///
+    /// Use [`add_response_schemas!`](crate::add_response_schemas!) with
+    /// `ValidationFailed::raw::Response` to add this response to the
+    /// documentation of the affected endpoints.
+    pub ValidationFailed = {
+        /// Unprocessable Content
+        ValidationFailed(422, error) => crate::validation::ValidationErrors,
+    }
+);
+
+response!(
+    /// Response that is returned if a client exceeded its rate limit (see
+    /// [`RateLimitMiddleware`](crate::rate_limit::RateLimitMiddleware)).
+    ///
+    /// Use [`add_response_schemas!`](crate::add_response_schemas!) with
+    /// `TooManyRequests::raw::Response` to add this response to the
+    /// documentation of the affected endpoints.
+    pub TooManyRequests = {
+        /// Too Many Requests
+        TooManyRequests(429, error),
+    }
+);
+
+response!(
+    /// Response that is returned if the server is overloaded (see
+    /// [`ConcurrencyLimitMiddleware`](crate::concurrency_limit::ConcurrencyLimitMiddleware)).
+    ///
+    /// Use [`add_response_schemas!`](crate::add_response_schemas!) with
+    /// `ServiceUnavailable::raw::Response` to add this response to the
+    /// documentation of the affected endpoints.
+    pub ServiceUnavailable = {
+        /// Service Unavailable
+        ServiceUnavailable(503, error),
+    }
+);
+
+response!(
+    /// Responses that are returned if a request is rejected by the
+    /// [`ReplayProtectionMiddleware`](crate::replay::ReplayProtectionMiddleware).
+    ///
+    /// Use [`ReplayProtected`](crate::replay::ReplayProtected) to add these
+    /// responses to the documentation of the affected endpoints.
+    pub ReplayRejected = {
+        /// The nonce or the timestamp of the request is missing or invalid.
+        InvalidNonce(401, error),
+        /// The nonce of the request has already been used.
+        NonceReused(409, error),
+    }
+);
+
+response!(
+    /// Response that is returned if the requested range of a download is not
+    /// satisfiable. It is already included in the documentation of
+    /// [`RangeResponse`](crate::range::RangeResponse).
+    pub RangeNotSatisfiable = {
+        /// Range Not Satisfiable
+        RangeNotSatisfiable(416, error),
+    }
+);
+
+response!(
+    /// Response that is returned if a request with an unsafe method does not
+    /// contain a valid CSRF token (see
+    /// [`CsrfMiddleware`](crate::csrf::CsrfMiddleware)).
+    ///
+    /// Use [`add_response_schemas!`](crate::add_response_schemas!) with
+    /// `InvalidCsrfToken::raw::Response` to add this response to the
+    /// documentation of the affected endpoints.
+    pub InvalidCsrfToken = {
+        /// Forbidden
+        InvalidCsrfToken(403, error),
+    }
+);
+
+response!(
+    /// Responses that are returned if a feature flag is disabled (see
+    /// [`FeatureGate`](crate::feature_flag::FeatureGate)).
+    ///
+    /// Use [`add_response_schemas!`](crate::add_response_schemas!) with
+    /// `FeatureDisabled::raw::Response` to add these responses to the
+    /// documentation of the affected endpoints.
+    pub FeatureDisabled = {
+        /// Not Found
+        NotFound(404, error),
+        /// Forbidden
+        Forbidden(403, error),
+    }
+);
+
+response!(
+    /// Responses that are returned if an uploaded file is rejected (see
+    /// [`UploadPolicy`](crate::upload::UploadPolicy)).
+    ///
+    /// Use `UploadRejected::raw::Response` as the error type of the affected
+    /// endpoints (e.g. `Result<Test::raw::Response, UploadRejected::raw::Response>`)
+    /// to add these responses to their documentation.
+    pub UploadRejected = {
+        /// Payload Too Large
+        FileTooLarge(413, error) => crate::upload::FileTooLargeDetails,
+        /// Unsupported Media Type
+        UnsupportedFileType(415, error) => crate::upload::UnsupportedFileTypeDetails,
+        /// Unprocessable Content
+        EmptyFile(422, error),
+    }
+);
+
+response!(
+    /// Responses that are returned if the signature of an incoming webhook
+    /// cannot be verified (see
+    /// [`SignedWebhook`](crate::webhook_signing::SignedWebhook)).
+    ///
+    /// Use `InvalidWebhookSignature::raw::Response` as the error type of the
+    /// affected endpoints (e.g.
+    /// `Result<Json<T>, InvalidWebhookSignature::raw::Response>`) to add these
+    /// responses to their documentation. Note that [`Response`] turns all
+    /// `400 Bad Request` errors into `422 Unprocessable Content` responses.
+    pub InvalidWebhookSignature = {
+        /// Bad Request
+        MalformedSignature(400, error),
+        /// Unauthorized
+        InvalidSignature(401, error),
+    }
+);
+
+#[cfg(feature = "sea-orm")]
+response!(
+    /// Response that is returned if a request targets a resource that has
+    /// been soft-deleted (see [`soft_delete`](crate::soft_delete)).
+    ///
+    /// Use `Gone::raw::Response` as the error type of the affected endpoints
+    /// (e.g. `Result<Test::raw::Response, Gone::raw::Response>`) to add this
+    /// response to their documentation.
+    pub Gone = {
+        /// Gone
+        Gone(410, error),
+    }
+);
+
+response!(
+    /// Response that is returned if a client requested an api version that
+    /// does not exist (see [`ApiVersions`](crate::versioning::ApiVersions)).
+    pub UnsupportedApiVersion = {
+        /// Not Acceptable
+        NotAcceptable(406, error),
+    }
+);
+
+response!(
+    /// Response that is returned if an authenticated caller does not have the
+    /// role that is required by an endpoint (see
+    /// [`Require`](crate::roles::Require)).
+    ///
+    /// It is added automatically to the documentation of endpoints that return
+    /// a [`Response<T, Require<A, R>>`](Response).
+    pub MissingRole = {
+        /// Forbidden
+        MissingRole(403, error),
+    }
+);
+
 impl<T, A> ApiResponse for InnerResponse<T, A>
 where
     T: ApiResponse,
@@ -187,7 +595,12 @@ where
 {
     fn into_response(self) -> poem::Response {
         match self.0 {
-            InnerResponseData::Ok { value, _auth } => value.into_response(),
+            InnerResponseData::Ok {
+                value,
+                status: Some(status),
+                ..
+            } => value.with_status(status).into_response(),
+            InnerResponseData::Ok { value, .. } => value.into_response(),
             InnerResponseData::BadRequest { error } => {
                 if error.status() == 400 {
                     ErrorResponse::UnprocessableContent(Json(BadRequestError {
@@ -251,11 +664,34 @@ pub trait MetaResponsesExt {
 ///
 /// Endpoints that return a [`Response<T, Auth>`] will now additionally list all
 /// `AuthError` and `OtherError` variants in their OpenAPI documentation.
+///
+/// Generic types (e.g. reusable guard wrappers) are supported by prefixing the
+/// type with `impl<...>` and optionally appending a `where` clause:
+/// ```
+/// use std::marker::PhantomData;
+///
+/// use poem_ext::add_response_schemas;
+/// use poem_openapi::ApiResponse;
+///
+/// #[derive(ApiResponse)]
+/// enum TenantError {
+///     /// Tenant not found
+///     #[oai(status = 404)]
+///     NotFound,
+/// }
+///
+/// trait Scope {}
+///
+/// struct Tenant<A>(PhantomData<A>);
+/// struct Scoped<A, S>(PhantomData<(A, S)>);
+///
+/// add_response_schemas!(impl<A> Tenant<A>, TenantError);
+/// add_response_schemas!(impl<A, S> Scoped<A, S>, TenantError where S: Scope);
+/// ```
 #[macro_export]
 macro_rules! add_response_schemas {
-    ($type:ty) => {$crate::add_response_schemas!($type,);};
-    ($type:ty, $($responses:ty),*) => {
-        impl $crate::responses::MetaResponsesExt for $type {
+    (impl<$($generic:ident),* $(,)?> $type:ty $(, $responses:ty)* $(where $($bounds:tt)+)?) => {
+        impl<$($generic),*> $crate::responses::MetaResponsesExt for $type $(where $($bounds)+)? {
             type Iter = ::std::vec::Vec<::poem_openapi::registry::MetaResponse>;
             fn responses() -> Self::Iter {
                 ::std::iter::empty()
@@ -270,6 +706,8 @@ macro_rules! add_response_schemas {
             }
         }
     };
+    ($type:ty) => {$crate::add_response_schemas!(impl<> $type);};
+    ($type:ty, $($responses:ty),*) => {$crate::add_response_schemas!(impl<> $type $(, $responses)*);};
 }
 
 // Implement `MetaResponsesExt` on unit, so we can use it as a default for the
@@ -306,6 +744,134 @@ mod tests {
         assert!(responses.next().is_none());
     }
 
+    #[test]
+    fn test_body_round_trip() {
+        use poem_openapi::types::{ParseFromJSON, ToJSON};
+
+        response!(Test = {
+            Created(201),
+            Teapot(418, error),
+        });
+
+        fn round_trip<T: ParseFromJSON + ToJSON + std::fmt::Debug>(json: &str) {
+            assert_eq!(
+                T::parse_from_json_string(json).unwrap().to_json_string(),
+                json
+            );
+        }
+        round_trip::<Test::raw::CreatedBody>("{}");
+        round_trip::<Test::raw::TeapotBody>(r#"{"error":"teapot"}"#);
+        round_trip::<TooManyRequests::raw::TooManyRequestsBody>(r#"{"error":"too_many_requests"}"#);
+        assert!(Test::raw::TeapotBody::parse_from_json_string(r#"{"error":"conflict"}"#).is_err());
+        assert!(Test::raw::TeapotBody::parse_from_json_string("{}").is_err());
+    }
+
+    #[test]
+    fn test_alt_status() {
+        response!(Test = {
+            /// Items
+            #[alt_status(206)]
+            Ok(200) => Vec<i32>,
+            /// Gone
+            #[alt_status(404, 451)]
+            Gone(410, error),
+        });
+
+        let statuses = Test::raw::Response::meta()
+            .responses
+            .iter()
+            .map(|resp| (resp.status.unwrap(), resp.description.trim()))
+            .sorted()
+            .collect_vec();
+        assert_eq!(
+            statuses,
+            [
+                (200, "Items"),
+                (206, "Items"),
+                (404, "Gone"),
+                (410, "Gone"),
+                (451, "Gone"),
+            ]
+        );
+
+        let resp = Test::ok_with_status::<()>(vec![1], StatusCode::PARTIAL_CONTENT).unwrap();
+        assert_eq!(resp.status(), Some(StatusCode::PARTIAL_CONTENT));
+        let resp = resp.into_response();
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+
+        let resp = Test::ok_with_status::<()>(vec![1], StatusCode::OK).unwrap();
+        assert_eq!(resp.status(), Some(StatusCode::OK));
+
+        let resp = Test::gone_with_status::<()>(StatusCode::NOT_FOUND).unwrap();
+        assert_eq!(resp.status(), Some(StatusCode::NOT_FOUND));
+        let resp = resp.map(|_| Test::raw::ok(vec![]));
+        assert_eq!(resp.status(), Some(StatusCode::NOT_FOUND));
+
+        let resp = Test::ok_with_status::<()>(vec![1], StatusCode::PARTIAL_CONTENT)
+            .unwrap()
+            .map(|resp| resp);
+        assert_eq!(resp.status(), Some(StatusCode::PARTIAL_CONTENT));
+        assert_eq!(resp.into_response().status(), StatusCode::PARTIAL_CONTENT);
+    }
+
+    #[test]
+    fn test_alt_status_undeclared() {
+        response!(Test = {
+            #[alt_status(206)]
+            Ok(200),
+        });
+
+        let resp = Test::ok_with_status::<()>(StatusCode::NOT_FOUND).unwrap();
+        assert_eq!(resp.status(), Some(StatusCode::OK));
+        assert_eq!(resp.into_response().status(), StatusCode::OK);
+    }
+
+    #[derive(Debug, Clone, PartialEq, Object)]
+    pub struct Data {
+        pub x: i32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ApiResponse)]
+    pub enum Other {
+        /// Gone
+        #[oai(status = 410)]
+        Gone,
+    }
+
+    #[test]
+    fn test_derive() {
+        response!(
+            /// Test response
+            #[derive(Clone, PartialEq)]
+            Test = {
+                Ok(200) => Data,
+                Created(201),
+                Conflict(409, error) => Data,
+                Teapot(418, error),
+                ..Other,
+            }
+        );
+
+        let ok = Test::raw::ok(Data { x: 1 });
+        assert_eq!(ok.clone(), ok);
+        assert_ne!(ok, Test::raw::ok(Data { x: 2 }));
+        assert_ne!(ok, Test::raw::created());
+        assert_eq!(Test::raw::created(), Test::raw::created());
+        assert_eq!(
+            Test::raw::conflict(Data { x: 1 }),
+            Test::raw::conflict(Data { x: 1 })
+        );
+        assert_eq!(Test::raw::teapot(), Test::raw::teapot().clone());
+        assert_eq!(
+            Test::raw::Response::from(Other::Gone),
+            Test::raw::Response::from(Other::Gone)
+        );
+        assert_eq!(
+            Test::raw::ConflictBody::new(Data { x: 3 }).clone(),
+            Test::raw::ConflictBody::new(Data { x: 3 })
+        );
+    }
+
     struct Auth;
 
     #[allow(dead_code)]
@@ -334,4 +900,44 @@ mod tests {
     }
 
     add_response_schemas!(Auth, AuthError);
+
+    #[test]
+    fn test_inner_response() {
+        use poem_openapi::payload::PlainText;
+
+        let resp = InnerResponse::<_, ()>::from(PlainText("foo"));
+        let mut seen = None;
+        let resp = resp.inspect(|value| seen = Some(value.0));
+        assert_eq!(seen, Some("foo"));
+        assert_eq!(resp.status(), Some(StatusCode::OK));
+        assert!(resp.bad_request_error().is_none());
+        let resp = resp.map(|value| PlainText(value.0.len()));
+        assert_eq!(resp.value().map(|value| value.0), Some(3));
+
+        let resp = InnerResponse::<PlainText<&str>, ()>::from_parse_request_error(
+            poem::Error::from_string("invalid", StatusCode::BAD_REQUEST),
+        );
+        assert!(resp.is_bad_request());
+        assert_eq!(resp.status(), Some(StatusCode::UNPROCESSABLE_ENTITY));
+        let resp = resp
+            .inspect(|_| unreachable!())
+            .map(|_| -> PlainText<()> { unreachable!() });
+        assert_eq!(resp.bad_request_error().unwrap().to_string(), "invalid");
+        assert!(resp.into_value().is_none());
+    }
+
+    #[test]
+    fn test_generic_response_schemas() {
+        struct Wrapper<A, B>(PhantomData<(A, B)>);
+        add_response_schemas!(impl<A, B> Wrapper<A, B>, EndpointResponse, AuthError where A: Send);
+
+        let statuses = Wrapper::<(), ()>::responses()
+            .into_iter()
+            .map(|resp| resp.status)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            [Some(200), Some(404), Some(401), Some(403), Some(404)]
+        );
+    }
 }
@@ -0,0 +1,239 @@
+//! Contains an extractor for endpoints that require a specific role or
+//! permission.
+//!
+//! [`Require<A, R>`] wraps an authorization dependency `A` defined using the
+//! [`custom_auth!`](crate::custom_auth) macro and rejects callers for which the
+//! [`Role`] implementation of the marker type `R` returns `false` with a
+//! [`MissingRole`] response, so endpoints don't have to check the role of the
+//! authenticated user themselves. Endpoints that return a
+//! [`Response<T, Require<A, R>>`](crate::responses::Response) document this
+//! response in addition to the responses of `A`.
+//!
+//! #### Example
+//! ```
+//! use poem::Request;
+//! use poem_ext::{
+//!     add_response_schemas, custom_auth, response,
+//!     responses::Response,
+//!     roles::{Require, Role},
+//! };
+//! use poem_openapi::{auth::Bearer, payload::PlainText, OpenApi};
+//!
+//! struct User {
+//!     name: String,
+//!     is_admin: bool,
+//! }
+//!
+//! struct UserAuth(User);
+//!
+//! response!(AuthResult = {
+//!     /// The user is unauthenticated.
+//!     Unauthorized(401, error),
+//! });
+//!
+//! async fn user_auth_check(
+//!     _req: &Request,
+//!     token: Option<Bearer>,
+//! ) -> Result<User, AuthResult::raw::Response> {
+//!     match token {
+//!         Some(Bearer { token }) if token == "admin_token" => Ok(User {
+//!             name: "admin".into(),
+//!             is_admin: true,
+//!         }),
+//!         _ => Err(AuthResult::raw::unauthorized()),
+//!     }
+//! }
+//!
+//! custom_auth!(UserAuth, user_auth_check);
+//! add_response_schemas!(UserAuth, AuthResult::raw::Response);
+//!
+//! struct Admin;
+//!
+//! impl Role<UserAuth> for Admin {
+//!     fn check(auth: &UserAuth) -> bool {
+//!         auth.0.is_admin
+//!     }
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/admin", method = "get")]
+//!     async fn admin(
+//!         &self,
+//!         auth: Require<UserAuth, Admin>,
+//!     ) -> Response<PlainText<String>, Require<UserAuth, Admin>> {
+//!         Ok(PlainText(auth.0 .0.name).into())
+//!     }
+//! }
+//! ```
+
+use std::{fmt::Debug, marker::PhantomData, ops::Deref};
+
+use poem::{Request, RequestBody};
+use poem_openapi::{
+    registry::{MetaResponse, Registry},
+    ApiExtractor, ApiExtractorType, ApiResponse, ExtractParamOptions,
+};
+
+use crate::responses::{MetaResponsesExt, MissingRole};
+
+/// A role or permission that can be required using [`Require`].
+pub trait Role<A> {
+    /// Return `true` if the authenticated caller has this role.
+    fn check(auth: &A) -> bool;
+}
+
+/// Extractor that requires the role `R` for the authorization dependency
+/// `A`.
+pub struct Require<A, R>(pub A, PhantomData<fn() -> R>);
+
+impl<A: Debug, R> Debug for Require<A, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Require").field(&self.0).finish()
+    }
+}
+
+impl<A, R> Require<A, R> {
+    /// Return the wrapped authorization dependency.
+    pub fn into_inner(self) -> A {
+        self.0
+    }
+}
+
+impl<A, R> Deref for Require<A, R> {
+    type Target = A;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[poem::async_trait]
+impl<'a, A, R> ApiExtractor<'a> for Require<A, R>
+where
+    A: ApiExtractor<'a>,
+    R: Role<A>,
+{
+    const TYPES: &'static [ApiExtractorType] = A::TYPES;
+
+    type ParamType = A::ParamType;
+    type ParamRawType = A::ParamRawType;
+
+    fn register(registry: &mut Registry) {
+        A::register(registry);
+    }
+
+    fn security_schemes() -> Vec<&'static str> {
+        A::security_schemes()
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        body: &mut RequestBody,
+        param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let auth = A::from_request(request, body, param_opts).await?;
+        if !R::check(&auth) {
+            return Err(MissingRole::raw::missing_role().into());
+        }
+        Ok(Self(auth, PhantomData))
+    }
+}
+
+impl<A: MetaResponsesExt, R> MetaResponsesExt for Require<A, R> {
+    type Iter = Vec<MetaResponse>;
+
+    fn responses() -> Self::Iter {
+        A::responses()
+            .into_iter()
+            .chain(MissingRole::raw::Response::meta().responses)
+            .collect()
+    }
+
+    fn register(registry: &mut Registry) {
+        A::register(registry);
+        MissingRole::raw::Response::register(registry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{http::StatusCode, test::TestClient};
+    use poem_openapi::{auth::Bearer, payload::PlainText, OpenApi, OpenApiService};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{add_response_schemas, custom_auth, response, responses::Response};
+
+    #[derive(Debug)]
+    struct UserAuth(&'static str);
+
+    response!(AuthResult = {
+        Unauthorized(401, error),
+    });
+
+    async fn user_auth_check(
+        _req: &Request,
+        token: Option<Bearer>,
+    ) -> Result<&'static str, AuthResult::raw::Response> {
+        match token.as_ref().map(|token| token.token.as_str()) {
+            Some("user") => Ok("user"),
+            Some("admin") => Ok("admin"),
+            _ => Err(AuthResult::raw::unauthorized()),
+        }
+    }
+
+    custom_auth!(UserAuth, user_auth_check);
+    add_response_schemas!(UserAuth, AuthResult::raw::Response);
+
+    struct Admin;
+
+    impl Role<UserAuth> for Admin {
+        fn check(auth: &UserAuth) -> bool {
+            auth.0 == "admin"
+        }
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/admin", method = "get")]
+        async fn admin(
+            &self,
+            auth: Require<UserAuth, Admin>,
+        ) -> Response<PlainText<&'static str>, Require<UserAuth, Admin>> {
+            Ok(PlainText(auth.0 .0).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn require() {
+        let service = OpenApiService::new(Api, "test", "1");
+        let spec = serde_json::from_str::<Value>(&service.spec()).unwrap();
+        let cli = TestClient::new(service);
+
+        let check = |token: &'static str| {
+            cli.get("/admin")
+                .header("Authorization", format!("Bearer {token}"))
+                .send()
+        };
+        let resp = check("admin").await;
+        resp.assert_status_is_ok();
+        resp.assert_text("admin").await;
+        let resp = check("user").await;
+        resp.assert_status(StatusCode::FORBIDDEN);
+        resp.assert_text(r#"{"error":"missing_role"}"#).await;
+        check("guest").await.assert_status(StatusCode::UNAUTHORIZED);
+
+        let operation = &spec["paths"]["/admin"]["get"];
+        assert_eq!(operation["security"], serde_json::json!([{"UserAuth": []}]));
+        let responses = operation["responses"].as_object().unwrap();
+        assert_eq!(
+            responses.keys().collect::<Vec<_>>(),
+            ["200", "401", "403", "422", "500"]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! Contains an extractor for endpoints that require specific OAuth2 scopes.
+//!
+//! [`Scoped<A, S>`] wraps an authorization dependency `A` defined using the
+//! [`custom_auth!`](crate::custom_auth) macro and declares that the endpoint
+//! requires the scopes of the marker type `S` (see [`Scopes`]). The checker of
+//! `A` can access these scopes using the [`RequiredScopes`] extractor (see
+//! `extract(...)` in [`custom_auth!`](crate::custom_auth)) and reject tokens
+//! that have not been granted all of them.
+//!
+//! poem-openapi only documents scopes that are passed using the
+//! `#[oai(scope = "...")]` attribute, so `Scoped<A, S>` is documented with a
+//! copy of the security scheme of `A` that is named after the scopes (e.g.
+//! `UserAuth.users_write`). The [`scoped_security`] hook of a
+//! [`SpecProcessor`](crate::spec::SpecProcessor) replaces these copies with
+//! regular security requirements of `A` that list the scopes.
+//!
+//! #### Example
+//! ```
+//! use poem::Request;
+//! use poem_ext::{
+//!     custom_auth, response,
+//!     scopes::{scoped_security, RequiredScopes, Scoped, Scopes},
+//!     spec::SpecProcessor,
+//! };
+//! use poem_openapi::{auth::Bearer, payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct UserAuth(String);
+//!
+//! response!(AuthResult = {
+//!     /// The user is unauthenticated.
+//!     Unauthorized(401, error),
+//!     /// The token has not been granted the required scopes.
+//!     Forbidden(403, error),
+//! });
+//!
+//! async fn user_auth_check(
+//!     _req: &Request,
+//!     token: Option<Bearer>,
+//!     required: RequiredScopes,
+//! ) -> Result<String, AuthResult::raw::Response> {
+//!     let (user, granted) = match token {
+//!         Some(Bearer { token }) if token == "secret_token" => ("alice", ["users:read"]),
+//!         _ => return Err(AuthResult::raw::unauthorized()),
+//!     };
+//!     if !required.iter().all(|scope| granted.contains(scope)) {
+//!         return Err(AuthResult::raw::forbidden());
+//!     }
+//!     Ok(user.into())
+//! }
+//!
+//! custom_auth!(UserAuth, user_auth_check, extract(RequiredScopes));
+//!
+//! struct UsersWrite;
+//!
+//! impl Scopes for UsersWrite {
+//!     const SCOPES: &'static [&'static str] = &["users:write"];
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/users", method = "post")]
+//!     async fn create_user(&self, auth: Scoped<UserAuth, UsersWrite>) -> PlainText<String> {
+//!         PlainText(auth.into_inner().0)
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let spec = SpecProcessor::new()
+//!     .with_hook(scoped_security)
+//!     .endpoint(&api_service);
+//! ```
+
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    ops::Deref,
+    sync::{Mutex, PoisonError},
+};
+
+use poem::{FromRequest, Request, RequestBody};
+use poem_openapi::{registry::Registry, ApiExtractor, ApiExtractorType, ExtractParamOptions};
+use serde_json::Value;
+
+tokio::task_local! {
+    static REQUIRED_SCOPES: &'static [&'static str];
+}
+
+/// The copies of security schemes that have been created for [`Scoped`]
+/// dependencies (name of the copy, name of the scheme and scopes).
+static SCOPED_SCHEMES: Mutex<Vec<(&'static str, &'static str, &'static [&'static str])>> =
+    Mutex::new(Vec::new());
+
+/// A set of OAuth2 scopes that is required by an endpoint (see [`Scoped`]).
+pub trait Scopes {
+    /// The names of the required scopes.
+    const SCOPES: &'static [&'static str];
+}
+
+/// Extractor that requires the scopes of `S` for the authorization
+/// dependency `A`.
+pub struct Scoped<A, S>(pub A, PhantomData<fn() -> S>);
+
+impl<A: Debug, S> Debug for Scoped<A, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Scoped").field(&self.0).finish()
+    }
+}
+
+impl<A, S> Scoped<A, S> {
+    /// Return the wrapped authorization dependency.
+    pub fn into_inner(self) -> A {
+        self.0
+    }
+}
+
+impl<A, S> Deref for Scoped<A, S> {
+    type Target = A;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[poem::async_trait]
+impl<'a, A, S> ApiExtractor<'a> for Scoped<A, S>
+where
+    A: ApiExtractor<'a>,
+    S: Scopes,
+{
+    const TYPES: &'static [ApiExtractorType] = A::TYPES;
+
+    type ParamType = A::ParamType;
+    type ParamRawType = A::ParamRawType;
+
+    fn register(registry: &mut Registry) {
+        A::register(registry);
+        let mut schemes = Registry::new();
+        A::register(&mut schemes);
+        for name in A::security_schemes() {
+            if let Some(scheme) = schemes.security_schemes.remove(name) {
+                registry.create_security_scheme(scoped_scheme_name(name, S::SCOPES), scheme);
+            }
+        }
+    }
+
+    fn security_schemes() -> Vec<&'static str> {
+        A::security_schemes()
+            .into_iter()
+            .map(|name| scoped_scheme_name(name, S::SCOPES))
+            .collect()
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        body: &mut RequestBody,
+        param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        REQUIRED_SCOPES
+            .scope(S::SCOPES, A::from_request(request, body, param_opts))
+            .await
+            .map(|auth| Self(auth, PhantomData))
+    }
+}
+
+/// Extractor for the scopes that are required by the endpoint.
+///
+/// This is meant to be used by checkers of authorization dependencies (see
+/// `extract(...)` in [`custom_auth!`](crate::custom_auth)) and is empty unless
+/// the dependency is wrapped in [`Scoped`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequiredScopes(pub &'static [&'static str]);
+
+impl Deref for RequiredScopes {
+    type Target = [&'static str];
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for RequiredScopes {
+    async fn from_request(_req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        Ok(Self(
+            REQUIRED_SCOPES.try_with(|&scopes| scopes).unwrap_or(&[]),
+        ))
+    }
+}
+
+/// Replace the copies of security schemes that document [`Scoped`]
+/// dependencies with security requirements that list the required scopes.
+///
+/// This function can be used as a hook of a
+/// [`SpecProcessor`](crate::spec::SpecProcessor).
+pub fn scoped_security(spec: &mut Value) {
+    let scoped = SCOPED_SCHEMES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let lookup = |name: &str| {
+        scoped
+            .iter()
+            .find(|&&(scoped_name, _, _)| scoped_name == name)
+            .map(|&(_, scheme, scopes)| (scheme, scopes))
+    };
+
+    if let Some(paths) = spec.get_mut("paths").and_then(Value::as_object_mut) {
+        for requirement in paths
+            .values_mut()
+            .filter_map(Value::as_object_mut)
+            .flat_map(|path| path.values_mut())
+            .filter_map(|operation| operation.get_mut("security")?.as_array_mut())
+            .flatten()
+            .filter_map(Value::as_object_mut)
+        {
+            *requirement = std::mem::take(requirement)
+                .into_iter()
+                .map(|(name, value)| match lookup(&name) {
+                    Some((scheme, scopes)) => (scheme.into(), scopes.iter().copied().collect()),
+                    None => (name, value),
+                })
+                .collect();
+        }
+    }
+
+    if let Some(schemes) = spec
+        .pointer_mut("/components/securitySchemes")
+        .and_then(Value::as_object_mut)
+    {
+        schemes.retain(|name, _| lookup(name).is_none());
+    }
+}
+
+/// Return the name of the copy of the given security scheme that documents
+/// the given scopes.
+fn scoped_scheme_name(scheme: &'static str, scopes: &'static [&'static str]) -> &'static str {
+    let mut scoped = SCOPED_SCHEMES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(&(name, _, _)) = scoped
+        .iter()
+        .find(|&&(_, s, sc)| s == scheme && sc == scopes)
+    {
+        return name;
+    }
+    let name = std::iter::once(scheme)
+        .chain(scopes.iter().copied())
+        .map(|part| {
+            part.chars()
+                .map(|c| match c {
+                    'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                    _ => '_',
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(".");
+    let name = &*Box::leak(name.into_boxed_str());
+    scoped.push((name, scheme, scopes));
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use poem_openapi::{auth::Bearer, payload::PlainText, OpenApi, OpenApiService};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{custom_auth, response};
+
+    #[derive(Debug)]
+    struct UserAuth(&'static [&'static str]);
+
+    response!(AuthResult = {
+        Unauthorized(401, error),
+        Forbidden(403, error),
+    });
+
+    async fn user_auth_check(
+        _req: &Request,
+        token: Option<Bearer>,
+        required: RequiredScopes,
+    ) -> Result<&'static [&'static str], AuthResult::raw::Response> {
+        let granted: &[&str] = match token.as_ref().map(|token| token.token.as_str()) {
+            Some("reader") => &["users:read"],
+            Some("admin") => &["users:read", "users:write"],
+            _ => return Err(AuthResult::raw::unauthorized()),
+        };
+        if !required.iter().all(|scope| granted.contains(scope)) {
+            return Err(AuthResult::raw::forbidden());
+        }
+        Ok(required.0)
+    }
+
+    custom_auth!(UserAuth, user_auth_check, extract(RequiredScopes));
+
+    struct UsersWrite;
+
+    impl Scopes for UsersWrite {
+        const SCOPES: &'static [&'static str] = &["users:write"];
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/read", method = "get")]
+        async fn read(&self, auth: UserAuth) -> PlainText<String> {
+            PlainText(auth.0.join(","))
+        }
+
+        #[oai(path = "/write", method = "post")]
+        async fn write(&self, auth: Scoped<UserAuth, UsersWrite>) -> PlainText<String> {
+            PlainText(auth.0 .0.join(","))
+        }
+    }
+
+    #[tokio::test]
+    async fn scoped() {
+        let service = OpenApiService::new(Api, "test", "1");
+        let mut spec = serde_json::from_str::<Value>(&service.spec()).unwrap();
+        let cli = poem::test::TestClient::new(service);
+
+        let check = |method: &'static str, path: &'static str, token: &'static str| {
+            let req = match method {
+                "get" => cli.get(path),
+                _ => cli.post(path),
+            };
+            req.header("Authorization", format!("Bearer {token}"))
+                .send()
+        };
+        let resp = check("get", "/read", "reader").await;
+        resp.assert_status_is_ok();
+        resp.assert_text("").await;
+        check("post", "/write", "reader")
+            .await
+            .assert_status(poem::http::StatusCode::FORBIDDEN);
+        let resp = check("post", "/write", "admin").await;
+        resp.assert_status_is_ok();
+        resp.assert_text("users:write").await;
+
+        assert_eq!(
+            spec["paths"]["/write"]["post"]["security"],
+            json!([{"UserAuth.users_write": []}])
+        );
+        assert!(spec["components"]["securitySchemes"]["UserAuth.users_write"].is_object());
+
+        scoped_security(&mut spec);
+        assert_eq!(
+            spec["paths"]["/write"]["post"]["security"],
+            json!([{"UserAuth": ["users:write"]}])
+        );
+        assert_eq!(
+            spec["paths"]["/read"]["get"]["security"],
+            json!([{"UserAuth": []}])
+        );
+        assert_eq!(
+            spec["components"]["securitySchemes"],
+            json!({"UserAuth": {"type": "http", "scheme": "bearer"}})
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Contains a middleware for reporting errors to [Sentry](https://sentry.io).
+//!
+//! When the `sentry` feature is enabled, every internal server error created
+//! by [`internal_server_error`](crate::responses::internal_server_error)
+//! (including the failure paths of the
+//! [`DbTransactionMiddleware`](crate::db::DbTransactionMiddleware)) and every
+//! panic caught by the
+//! [`PanicHandlerMiddleware`](crate::panic_handler::PanicHandlerMiddleware) is
+//! reported to the active Sentry hub. The events contain the error message and
+//! the error id that is also included in the response body, so the events can
+//! be found using the error id reported by a client. Nothing is reported if no
+//! Sentry client has been initialized.
+//!
+//! The [`SentryMiddleware`] creates a separate hub for every request and adds
+//! the method, url and [request id](crate::request_id) of the request as well
+//! as the [authenticated identity](crate::identity::Identity) to all events
+//! reported while handling it. It should be the outermost of these
+//! middlewares, so panics caught by the panic handler are reported with the
+//! request metadata as well.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{panic_handler::PanicHandler, sentry::SentryMiddleware};
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! // initialize the sentry client here
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .with(PanicHandler::custom())
+//!     .with(SentryMiddleware::new());
+//! ```
+
+use std::{fmt::Display, sync::Arc};
+
+use poem::{http::header::HOST, Endpoint, IntoResponse, Middleware, Request, Response};
+use sentry_core::{
+    protocol::{Event, Level, Request as SentryRequest, User},
+    Hub, SentryFutureExt,
+};
+
+use crate::{identity::Identity, panic_handler::PanicInfo, request_id::RequestId};
+
+/// A middleware that adds request metadata to the Sentry events reported while
+/// handling a request.
+#[derive(Debug, Clone, Default)]
+pub struct SentryMiddleware;
+
+impl SentryMiddleware {
+    /// Create a new SentryMiddleware.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for SentryMiddleware {
+    type Output = SentryMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        SentryMwEndpoint { inner: ep }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct SentryMwEndpoint<E> {
+    inner: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for SentryMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let request = request_info(&req);
+        let request_id = RequestId::of(&req);
+        let identity = Identity::attach(&mut req);
+
+        let hub = Arc::new(Hub::new_from_top(Hub::current()));
+        hub.configure_scope(|scope| {
+            scope.set_transaction(Some(&format!("{} {}", req.method(), req.uri().path())));
+            if let Some(request_id) = &request_id {
+                scope.set_tag("request_id", request_id);
+            }
+            scope.add_event_processor(move |mut event| {
+                if event.request.is_none() {
+                    event.request = Some(request.clone());
+                }
+                if event.user.is_none() {
+                    event.user = identity.value().map(user);
+                }
+                Some(event)
+            });
+        });
+
+        self.inner
+            .call(req)
+            .bind_hub(hub)
+            .await
+            .map(IntoResponse::into_response)
+    }
+}
+
+/// Return the request metadata that is attached to events. Headers, cookies
+/// and the body are not included, as they may contain credentials.
+fn request_info(req: &Request) -> SentryRequest {
+    let uri = req.uri();
+    let host = uri
+        .authority()
+        .map(|x| x.as_str())
+        .or_else(|| req.header(HOST))
+        .unwrap_or("localhost");
+    let scheme = uri.scheme_str().unwrap_or(req.scheme().as_str());
+    SentryRequest {
+        url: format!("{scheme}://{host}{}", uri.path()).parse().ok(),
+        method: Some(req.method().to_string()),
+        query_string: uri.query().map(ToOwned::to_owned),
+        ..Default::default()
+    }
+}
+
+fn user(id: String) -> User {
+    User {
+        id: Some(id),
+        ..Default::default()
+    }
+}
+
+/// Report an internal server error to the active Sentry hub.
+pub(crate) fn capture_internal_error(error_id: &str, error: &dyn Display) {
+    let mut event = Event {
+        level: Level::Error,
+        message: Some(error.to_string()),
+        ..Default::default()
+    };
+    event.tags.insert("error_id".into(), error_id.into());
+    sentry_core::capture_event(event);
+}
+
+/// Report a caught panic to the active Sentry hub.
+pub(crate) fn capture_panic(info: &PanicInfo) {
+    let mut event = Event {
+        level: Level::Fatal,
+        message: Some(info.message().unwrap_or("Box<dyn Any>").into()),
+        transaction: Some(format!("{} {}", info.method, info.path())),
+        user: info.identity.clone().map(user),
+        ..Default::default()
+    };
+    event.tags.insert("error_id".into(), info.error_id.clone());
+    if let Some(request_id) = &info.request_id {
+        event
+            .tags
+            .insert("request_id".into(), request_id.to_string());
+    }
+    if let Some(backtrace) = &info.backtrace {
+        event
+            .extra
+            .insert("backtrace".into(), backtrace.to_string().into());
+    }
+    if let Some(body) = &info.request_body {
+        event
+            .extra
+            .insert("request_body".into(), body.clone().into());
+    }
+    sentry_core::capture_event(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::StatusCode, test::TestClient, EndpointExt};
+    use sentry_core::test::with_captured_events;
+
+    use super::*;
+    use crate::{panic_handler::PanicHandler, responses::internal_server_error};
+
+    #[handler]
+    fn failing(req: &Request) -> StatusCode {
+        Identity::set(req, "user");
+        let _ = internal_server_error("db connection lost");
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    #[handler]
+    fn panicking() {
+        panic!("at the disco")
+    }
+
+    fn run(f: impl std::future::Future<Output = ()>) -> Vec<Event<'static>> {
+        with_captured_events(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(f)
+        })
+    }
+
+    #[test]
+    fn internal_error() {
+        let events = run(async {
+            let cli = TestClient::new(failing.with(SentryMiddleware::new()));
+            cli.get("/test")
+                .query("x", &1)
+                .send()
+                .await
+                .assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+        });
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.level, Level::Error);
+        assert_eq!(event.message.as_deref(), Some("db connection lost"));
+        assert!(event.tags.contains_key("error_id"));
+        assert_eq!(event.user.as_ref().unwrap().id.as_deref(), Some("user"));
+        let request = event.request.as_ref().unwrap();
+        assert_eq!(request.method.as_deref(), Some("GET"));
+        assert_eq!(request.query_string.as_deref(), Some("x=1"));
+        assert_eq!(request.url.as_ref().unwrap().path(), "/test");
+    }
+
+    #[test]
+    fn panic() {
+        let mut error_id = String::new();
+        let events = run(async {
+            let cli = TestClient::new(
+                panicking
+                    .with(PanicHandler::custom())
+                    .with(SentryMiddleware::new()),
+            );
+            let resp = cli.get("/").send().await;
+            resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+            let body = resp.json().await;
+            error_id = body.value().object().get("error_id").string().to_owned();
+        });
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].level, Level::Fatal);
+        assert_eq!(events[0].message.as_deref(), Some("at the disco"));
+        assert_eq!(events[0].tags.get("error_id"), Some(&error_id));
+        assert!(events[0].request.is_some());
+    }
+}
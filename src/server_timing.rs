@@ -0,0 +1,351 @@
+//! Contains a middleware that reports the processing time of requests in
+//! response headers.
+//!
+//! The [`ServerTimingMiddleware`] measures the time it takes the endpoint to
+//! handle a request and adds it to the response in the `X-Response-Time`
+//! header (e.g. `12.345ms`) and the
+//! [`Server-Timing`](https://www.w3.org/TR/server-timing/) header (e.g.
+//! `total;dur=12.345`), which is displayed by the developer tools of most
+//! browsers.
+//!
+//! Optionally, the `Server-Timing` header also contains sub-timings that have
+//! been recorded using [`ServerTiming::record`] and the durations of the
+//! transaction of the
+//! [`DbTransactionMiddleware`](crate::db::DbTransactionMiddleware) (if it is
+//! used inside of this middleware). As these timings may reveal details about
+//! the implementation, they are not included by default.
+//!
+//! Use [`ServerTimingMiddleware::apply`] (e.g. in a hook of a
+//! [`SpecProcessor`](crate::spec::SpecProcessor)) to document the headers on
+//! all operations.
+//!
+//! #### Example
+//! ```
+//! use std::time::Instant;
+//!
+//! use poem::{EndpointExt, Request, Route};
+//! use poem_ext::{
+//!     server_timing::{ServerTiming, ServerTimingMiddleware},
+//!     spec::SpecProcessor,
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self, req: &Request) -> PlainText<&'static str> {
+//!         let start = Instant::now();
+//!         // query an external service
+//!         ServerTiming::record(req, "upstream", start.elapsed());
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! let timing = ServerTimingMiddleware::new().with_sub_timings();
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let spec = SpecProcessor::new()
+//!     .with_hook({
+//!         let timing = timing.clone();
+//!         move |spec| timing.apply(spec)
+//!     })
+//!     .endpoint(&api_service);
+//! let app = Route::new()
+//!     .at("/openapi.json", spec)
+//!     .nest("/", api_service)
+//!     .with(timing);
+//! ```
+
+use std::{
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use poem::{http::HeaderValue, Endpoint, IntoResponse, Middleware, Request, Response};
+use serde_json::Value;
+
+use crate::spec::add_response_header;
+
+/// The name of the header that contains the total processing time.
+pub const RESPONSE_TIME_HEADER: &str = "x-response-time";
+
+/// The name of the header that contains the processing time and sub-timings
+/// in the [Server Timing](https://www.w3.org/TR/server-timing/) format.
+pub const SERVER_TIMING_HEADER: &str = "server-timing";
+
+/// Shared slot for the sub-timings of a request.
+#[derive(Debug, Clone, Default)]
+pub struct ServerTiming(Arc<Mutex<Vec<(String, Duration)>>>);
+
+impl ServerTiming {
+    /// Record a sub-timing of a request (e.g. the duration of a call to an
+    /// external service). Characters that are not allowed in the name of a
+    /// metric are replaced with `_`.
+    ///
+    /// This is a no-op if no [`ServerTimingMiddleware`] with enabled
+    /// sub-timings is present.
+    pub fn record(req: &Request, name: &str, duration: Duration) {
+        if let Some(slot) = req.extensions().get::<Self>() {
+            slot.0.lock().unwrap().push((sanitize(name), duration));
+        }
+    }
+
+    /// Attach a [`ServerTiming`] to a request (if not already present) and
+    /// return a handle to it that can be used to read the sub-timings after
+    /// the request has been passed to the endpoint.
+    pub fn attach(req: &mut Request) -> Self {
+        if let Some(slot) = req.extensions().get::<Self>() {
+            return slot.clone();
+        }
+        let slot = Self::default();
+        req.extensions_mut().insert(slot.clone());
+        slot
+    }
+
+    /// Return the recorded sub-timings.
+    pub fn value(&self) -> Vec<(String, Duration)> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Replace all characters that are not allowed in a token.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c,
+            '!' | '#' | '$' | '%' | '&' | '\'' | '*' | '+' | '-' | '.' | '^' | '_' | '`' | '|'
+            | '~' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// A middleware that reports the processing time of requests in response
+/// headers.
+#[derive(Debug, Clone)]
+pub struct ServerTimingMiddleware {
+    response_time: bool,
+    server_timing: bool,
+    sub_timings: bool,
+}
+
+impl Default for ServerTimingMiddleware {
+    fn default() -> Self {
+        Self {
+            response_time: true,
+            server_timing: true,
+            sub_timings: false,
+        }
+    }
+}
+
+impl ServerTimingMiddleware {
+    /// Create a new ServerTimingMiddleware that adds the `X-Response-Time`
+    /// and `Server-Timing` headers without any sub-timings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Include the sub-timings recorded using [`ServerTiming::record`] and the
+    /// durations of the db transaction in the `Server-Timing` header.
+    pub fn with_sub_timings(self) -> Self {
+        Self {
+            sub_timings: true,
+            ..self
+        }
+    }
+
+    /// Do not add the `X-Response-Time` header.
+    pub fn without_response_time(self) -> Self {
+        Self {
+            response_time: false,
+            ..self
+        }
+    }
+
+    /// Do not add the `Server-Timing` header.
+    pub fn without_server_timing(self) -> Self {
+        Self {
+            server_timing: false,
+            ..self
+        }
+    }
+
+    /// Document the headers added by this middleware on all responses of all
+    /// operations in an OpenAPI document.
+    pub fn apply(&self, spec: &mut Value) {
+        if self.response_time {
+            add_response_header(
+                spec,
+                "X-Response-Time",
+                "The time it took the server to process the request (e.g. `12.345ms`).",
+            );
+        }
+        if self.server_timing {
+            add_response_header(
+                spec,
+                "Server-Timing",
+                "The time it took the server to process the request in the Server Timing format \
+                 (e.g. `total;dur=12.345`).",
+            );
+        }
+    }
+
+    fn server_timing(
+        &self,
+        resp: &Response,
+        total: Duration,
+        sub_timings: Vec<(String, Duration)>,
+    ) -> String {
+        let mut value = format!("total;dur={:.3}", millis(total));
+        if self.sub_timings {
+            for (name, duration) in transaction_timings(resp).into_iter().chain(sub_timings) {
+                let _ = write!(value, ", {name};dur={:.3}", millis(duration));
+            }
+        }
+        value
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Return the sub-timings of the db transaction of a request (see
+/// [`TransactionStats`](crate::db::TransactionStats)).
+#[cfg(feature = "sea-orm")]
+fn transaction_timings(resp: &Response) -> Vec<(String, Duration)> {
+    let Some(txn) = resp.data::<crate::db::TransactionStats>() else {
+        return Vec::new();
+    };
+    let finish = if txn.committed {
+        "db-commit"
+    } else {
+        "db-rollback"
+    };
+    vec![
+        ("db".into(), txn.duration),
+        (finish.into(), txn.finish_duration),
+    ]
+}
+
+#[cfg(not(feature = "sea-orm"))]
+fn transaction_timings(_resp: &Response) -> Vec<(String, Duration)> {
+    Vec::new()
+}
+
+impl<E: Endpoint> Middleware<E> for ServerTimingMiddleware {
+    type Output = ServerTimingMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ServerTimingMwEndpoint {
+            inner: ep,
+            config: self.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ServerTimingMwEndpoint<E> {
+    inner: E,
+    config: ServerTimingMiddleware,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for ServerTimingMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let timing = (self.config.server_timing && self.config.sub_timings)
+            .then(|| ServerTiming::attach(&mut req));
+
+        let start = Instant::now();
+        let mut resp = self.inner.call(req).await?.into_response();
+        let total = start.elapsed();
+
+        if self.config.response_time {
+            if let Ok(value) = HeaderValue::from_str(&format!("{:.3}ms", millis(total))) {
+                resp.headers_mut().insert(RESPONSE_TIME_HEADER, value);
+            }
+        }
+        if self.config.server_timing {
+            let sub_timings = timing.map(|timing| timing.value()).unwrap_or_default();
+            let value = self.config.server_timing(&resp, total, sub_timings);
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                resp.headers_mut().append(SERVER_TIMING_HEADER, value);
+            }
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, test::TestClient, EndpointExt};
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+
+    #[handler]
+    fn timed(req: &Request) -> &'static str {
+        ServerTiming::record(req, "cache hit", Duration::from_millis(2));
+        "ok"
+    }
+
+    fn header<'a>(resp: &'a poem::test::TestResponse, name: &str) -> &'a str {
+        resp.0.headers().get(name).unwrap().to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn headers() {
+        let resp = TestClient::new(timed.with(ServerTimingMiddleware::new()))
+            .get("/")
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        assert!(header(&resp, RESPONSE_TIME_HEADER).ends_with("ms"));
+        let server_timing = header(&resp, SERVER_TIMING_HEADER);
+        assert!(server_timing.starts_with("total;dur="));
+        assert!(!server_timing.contains("cache"));
+    }
+
+    #[tokio::test]
+    async fn sub_timings() {
+        let resp = TestClient::new(
+            timed.with(
+                ServerTimingMiddleware::new()
+                    .with_sub_timings()
+                    .without_response_time(),
+            ),
+        )
+        .get("/")
+        .send()
+        .await;
+        assert!(resp.0.headers().get(RESPONSE_TIME_HEADER).is_none());
+        assert!(header(&resp, SERVER_TIMING_HEADER).ends_with(", cache_hit;dur=2.000"));
+    }
+
+    #[test]
+    fn spec() {
+        struct Api;
+
+        #[OpenApi]
+        impl Api {
+            #[oai(path = "/test", method = "get")]
+            async fn test(&self) -> PlainText<&'static str> {
+                PlainText("test")
+            }
+        }
+
+        let mut spec = serde_json::from_str(&OpenApiService::new(Api, "test", "1").spec()).unwrap();
+        ServerTimingMiddleware::new()
+            .without_server_timing()
+            .apply(&mut spec);
+        let headers = &spec["paths"]["/test"]["get"]["responses"]["200"]["headers"];
+        assert!(headers.get("X-Response-Time").is_some());
+        assert!(headers.get("Server-Timing").is_none());
+    }
+}
@@ -1,11 +1,48 @@
 //! Contains a middleware that prevents endpoint handlers from being canceled if
 //! the connection is closed.
+//!
+//! #### Metrics
+//! If the `metrics` feature is enabled, the following metrics are recorded
+//! using the [`metrics`](https://docs.rs/metrics) crate for shielded handlers
+//! that completed after the client had already disconnected:
+//! - [`ORPHANED_COMPLETIONS_METRIC`] (counter): number of such handlers
+//! - [`ORPHANED_DURATION_METRIC`] (histogram): total run time of such handlers
+//!   in seconds
 
-use std::sync::Arc;
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    time::Duration,
+};
 
-use poem::{Endpoint, Middleware};
+use poem::{http::Method, Endpoint, IntoResponse, Middleware, Request, Response};
+use tokio::sync::Notify;
 use tokio_shield::Shield;
 
+use crate::{add_response_schemas, responses::GatewayTimeout, utils::path_has_prefix};
+
+/// Name of the counter that counts shielded handlers that completed after the
+/// client had disconnected.
+#[cfg(feature = "metrics")]
+pub const ORPHANED_COMPLETIONS_METRIC: &str = "poem_ext_shield_orphaned_completions_total";
+
+/// Name of the histogram that records the run time of shielded handlers that
+/// completed after the client had disconnected.
+#[cfg(feature = "metrics")]
+pub const ORPHANED_DURATION_METRIC: &str = "poem_ext_shield_orphaned_duration_seconds";
+
+/// Marker type that adds the [`GatewayTimeout`] response to the documentation
+/// of endpoints that return a
+/// [`Response<T, ShieldTimeout>`](crate::responses::Response) and are shielded
+/// with a timeout.
+#[derive(Debug)]
+pub struct ShieldTimeout;
+add_response_schemas!(ShieldTimeout, GatewayTimeout::raw::Response);
+
 /// Prevent endpoint handlers from being canceled.
 ///
 /// #### Example
@@ -27,7 +64,53 @@ use tokio_shield::Shield;
 /// }
 /// ````
 pub fn shield<E: Endpoint + 'static>(ep: E) -> ShieldEndpoint<E> {
-    ShieldEndpoint(Arc::new(ep))
+    ShieldEndpoint(Arc::new(ShieldState {
+        inner: ep,
+        timeout: None,
+        tracker: ShieldTracker::global().clone(),
+        filter: None,
+    }))
+}
+
+/// Prevent endpoint handlers from being canceled, but abort them if they don't
+/// complete within the given `timeout`.
+///
+/// If the deadline is exceeded, a [`GatewayTimeout`] response is returned,
+/// which can be documented using [`ShieldTimeout`].
+///
+/// #### Example
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use poem::Endpoint;
+/// use poem_ext::{
+///     responses::Response,
+///     shield_mw::{shield_with_timeout, ShieldTimeout},
+/// };
+/// use poem_openapi::{payload::PlainText, OpenApi};
+///
+/// fn shield_5s<E: Endpoint + 'static>(ep: E) -> impl Endpoint {
+///     shield_with_timeout(ep, Duration::from_secs(5))
+/// }
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/test", method = "post", transform = "shield_5s")]
+///     async fn test(&self) -> Response<PlainText<&'static str>, ShieldTimeout> {
+///         tokio::time::sleep(Duration::from_secs(2)).await;
+///         Ok(PlainText("done").into())
+///     }
+/// }
+/// ```
+pub fn shield_with_timeout<E: Endpoint + 'static>(ep: E, timeout: Duration) -> ShieldEndpoint<E> {
+    ShieldEndpoint(Arc::new(ShieldState {
+        inner: ep,
+        timeout: Some(timeout),
+        tracker: ShieldTracker::global().clone(),
+        filter: None,
+    }))
 }
 
 /// Prevent endpoint handlers from being canceled.
@@ -54,27 +137,529 @@ pub fn shield<E: Endpoint + 'static>(ep: E) -> ShieldEndpoint<E> {
 /// let api_service = OpenApiService::new(Api, "Test", "0.1.0");
 /// let app = Route::new().nest("/", api_service).with(ShieldMiddleware);
 /// ```
+///
+/// By default all requests are shielded. Use [`custom`](Self::custom) to
+/// configure a timeout, a [`ShieldTracker`] or a filter (e.g.
+/// [`with_methods`](CustomShieldMiddleware::with_methods) or
+/// [`with_path_prefix`](CustomShieldMiddleware::with_path_prefix)) to shield
+/// only some of the requests:
+/// ```
+/// use poem::{http::Method, EndpointExt, Route};
+/// use poem_ext::shield_mw::ShieldMiddleware;
+///
+/// # let api_service: Route = Route::new();
+/// let app = Route::new().nest("/", api_service).with(
+///     // only shield mutating requests to /payments and /orders
+///     ShieldMiddleware::custom()
+///         .with_methods([Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+///         .with_path_prefix("/payments")
+///         .with_path_prefix("/orders"),
+/// );
+/// ```
 #[derive(Debug, Clone)]
 pub struct ShieldMiddleware;
 
+impl ShieldMiddleware {
+    /// Create a [`CustomShieldMiddleware`] that can be configured.
+    pub fn custom() -> CustomShieldMiddleware {
+        CustomShieldMiddleware::default()
+    }
+
+    /// Create a [`CustomShieldMiddleware`] that aborts endpoint handlers that
+    /// don't complete within the given `timeout` with a [`GatewayTimeout`]
+    /// response, which can be documented using [`ShieldTimeout`] (see
+    /// [`shield_with_timeout`]).
+    pub fn with_timeout(timeout: Duration) -> CustomShieldMiddleware {
+        Self::custom().with_timeout(timeout)
+    }
+}
+
 impl<E: Endpoint + 'static> Middleware<E> for ShieldMiddleware {
     type Output = ShieldEndpoint<E>;
 
     fn transform(&self, ep: E) -> Self::Output {
-        shield(ep)
+        CustomShieldMiddleware::default().transform(ep)
+    }
+}
+
+/// A [`ShieldMiddleware`] with a timeout, a custom [`ShieldTracker`] or a
+/// filter (see [`ShieldMiddleware::custom`]).
+#[derive(Debug, Clone, Default)]
+pub struct CustomShieldMiddleware {
+    timeout: Option<Duration>,
+    tracker: Option<ShieldTracker>,
+    filter: ShieldFilter,
+}
+
+/// A function that decides whether a request should be shielded.
+pub type ShieldFilterFn = Arc<dyn Fn(&Request) -> bool + Send + Sync>;
+
+#[derive(Clone, Default)]
+struct ShieldFilter {
+    methods: Option<Vec<Method>>,
+    path_prefixes: Vec<String>,
+    filter_fn: Option<ShieldFilterFn>,
+}
+
+impl Debug for ShieldFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShieldFilter")
+            .field("methods", &self.methods)
+            .field("path_prefixes", &self.path_prefixes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ShieldFilter {
+    fn matches(&self, req: &Request) -> bool {
+        self.methods
+            .as_ref()
+            .map_or(true, |methods| methods.contains(req.method()))
+            && (self.path_prefixes.is_empty()
+                || self
+                    .path_prefixes
+                    .iter()
+                    .any(|prefix| path_has_prefix(req.uri().path(), prefix)))
+            && self
+                .filter_fn
+                .as_ref()
+                .map_or(true, |filter_fn| filter_fn(req))
+    }
+}
+
+impl CustomShieldMiddleware {
+    /// Abort endpoint handlers that don't complete within the given `timeout`
+    /// with a [`GatewayTimeout`] response, which can be documented using
+    /// [`ShieldTimeout`] (see [`shield_with_timeout`]).
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Register shielded handlers with the given [`ShieldTracker`] instead of
+    /// the [global](ShieldTracker::global) one.
+    pub fn with_tracker(self, tracker: ShieldTracker) -> Self {
+        Self {
+            tracker: Some(tracker),
+            ..self
+        }
+    }
+
+    /// Only shield requests with one of the given methods.
+    pub fn with_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.filter
+            .methods
+            .get_or_insert_with(Vec::new)
+            .extend(methods);
+        self
+    }
+
+    /// Only shield requests with methods that are not
+    /// [safe](https://developer.mozilla.org/en-US/docs/Glossary/Safe/HTTP)
+    /// (i.e. `POST`, `PUT`, `PATCH` and `DELETE`).
+    pub fn only_unsafe_methods(self) -> Self {
+        self.with_methods([Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+    }
+
+    /// Only shield requests whose path starts with one of the prefixes added
+    /// using this method.
+    pub fn with_path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.filter.path_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Only shield requests for which the given function returns `true`.
+    pub fn with_filter<F>(mut self, filter_fn: F) -> Self
+    where
+        F: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        self.filter.filter_fn = Some(Arc::new(filter_fn));
+        self
+    }
+}
+
+impl<E: Endpoint + 'static> Middleware<E> for CustomShieldMiddleware {
+    type Output = ShieldEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ShieldEndpoint(Arc::new(ShieldState {
+            inner: ep,
+            timeout: self.timeout,
+            tracker: self
+                .tracker
+                .clone()
+                .unwrap_or_else(|| ShieldTracker::global().clone()),
+            filter: Some(self.filter.clone()),
+        }))
     }
 }
 
 #[doc(hidden)]
 #[derive(Debug)]
-pub struct ShieldEndpoint<E>(Arc<E>);
+pub struct ShieldEndpoint<E>(Arc<ShieldState<E>>);
+
+/// Everything a shielded handler needs is kept behind a single [`Arc`], which
+/// is cloned into the spawned task (it has to be `'static`). The same
+/// reference is used to keep track of the handler in the [`ShieldTracker`].
+#[derive(Debug)]
+struct ShieldState<E> {
+    inner: E,
+    timeout: Option<Duration>,
+    tracker: ShieldTracker,
+    filter: Option<ShieldFilter>,
+}
 
 #[poem::async_trait]
 impl<E: Endpoint + 'static> Endpoint for ShieldEndpoint<E> {
-    type Output = E::Output;
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let disconnected = Disconnected::default();
+        req.extensions_mut().insert(disconnected.clone());
+        if !self
+            .0
+            .filter
+            .as_ref()
+            .map_or(true, |filter| filter.matches(&req))
+        {
+            return self
+                .0
+                .inner
+                .call(req)
+                .await
+                .map(IntoResponse::into_response);
+        }
+
+        let state = ShieldGuard::enter(&self.0);
+        #[cfg(feature = "metrics")]
+        let task_disconnected = disconnected.clone();
+        let disconnect_guard = DisconnectGuard(Some(disconnected));
+        let result = async move {
+            #[cfg(feature = "metrics")]
+            let start = std::time::Instant::now();
+            let result = match state.timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, state.inner.call(req)).await {
+                    Ok(resp) => resp.map(IntoResponse::into_response),
+                    Err(_) => Ok(GatewayTimeout::raw::gateway_timeout().into_response()),
+                },
+                None => state.inner.call(req).await.map(IntoResponse::into_response),
+            };
+            #[cfg(feature = "metrics")]
+            if task_disconnected.is_disconnected() {
+                metrics::counter!(ORPHANED_COMPLETIONS_METRIC).increment(1);
+                metrics::histogram!(ORPHANED_DURATION_METRIC).record(start.elapsed());
+            }
+            drop(state);
+            result
+        }
+        .shield()
+        .await;
+        disconnect_guard.defuse();
+        result
+    }
+}
+
+/// Registers a running shielded handler with its [`ShieldTracker`] for as long
+/// as it is alive.
+struct ShieldGuard<E>(Arc<ShieldState<E>>);
+
+impl<E> ShieldGuard<E> {
+    fn enter(state: &Arc<ShieldState<E>>) -> Self {
+        state.tracker.0.in_flight.fetch_add(1, Ordering::SeqCst);
+        Self(Arc::clone(state))
+    }
+}
+
+impl<E> Deref for ShieldGuard<E> {
+    type Target = ShieldState<E>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<E> Drop for ShieldGuard<E> {
+    fn drop(&mut self) {
+        self.tracker.leave();
+    }
+}
+
+/// Notifies a shielded endpoint handler that the client has disconnected.
+///
+/// The [`ShieldMiddleware`] and [`shield`] functions add this type to the
+/// extensions of every request, so shielded handlers can skip work that is
+/// only needed to construct the response once the client has gone away. This
+/// includes requests that are not shielded because of a filter (see
+/// [`ShieldMiddleware::custom`]), but their handlers are canceled when the
+/// client disconnects, so they never observe a disconnect.
+///
+/// #### Example
+/// ```no_run
+/// use poem::web::Data;
+/// use poem_ext::shield_mw::{shield, Disconnected};
+/// use poem_openapi::{payload::PlainText, OpenApi};
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/test", method = "post", transform = "shield")]
+///     async fn test(&self, disconnected: Data<&Disconnected>) -> PlainText<String> {
+///         // perform the critical write
+///         # async fn write() {}
+///         write().await;
+///         if disconnected.is_disconnected() {
+///             // nobody is going to read the response anyway
+///             return PlainText(String::new());
+///         }
+///         # fn build_large_response() -> String { todo!() }
+///         PlainText(build_large_response())
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Disconnected(Arc<DisconnectedInner>);
+
+#[derive(Debug, Default)]
+struct DisconnectedInner {
+    flag: AtomicBool,
+    notify: Notify,
+}
+
+impl Disconnected {
+    /// Return whether the client has disconnected.
+    pub fn is_disconnected(&self) -> bool {
+        self.0.flag.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the client has disconnected.
+    pub async fn wait(&self) {
+        loop {
+            let notified = self.0.notify.notified();
+            if self.is_disconnected() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    fn set(&self) {
+        self.0.flag.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+}
+
+/// Marks the request as disconnected if dropped before the shielded handler
+/// has completed.
+struct DisconnectGuard(Option<Disconnected>);
+
+impl DisconnectGuard {
+    fn defuse(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        if let Some(disconnected) = self.0.take() {
+            disconnected.set();
+        }
+    }
+}
+
+/// Keeps track of shielded endpoint handlers that are currently running.
+///
+/// Shielded handlers still get killed if the process exits. To prevent this,
+/// wait until all of them have completed before shutting down the server
+/// ([`Lifecycle`](crate::lifecycle::Lifecycle) does this automatically).
+///
+/// #### Example
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use poem::{listener::TcpListener, EndpointExt, Route, Server};
+/// use poem_ext::shield_mw::{ShieldMiddleware, ShieldTracker};
+///
+/// # async fn run(api_service: poem_openapi::OpenApiService<(), ()>) -> std::io::Result<()> {
+/// # let shutdown_signal = async {};
+/// let app = Route::new()
+///     .nest("/", api_service)
+///     .with(ShieldMiddleware);
+/// Server::new(TcpListener::bind("127.0.0.1:3000"))
+///     .run_with_graceful_shutdown(
+///         app,
+///         shutdown_signal,
+///         Some(Duration::from_secs(5)),
+///     )
+///     .await?;
+/// // wait until all shielded handlers have completed
+/// ShieldTracker::global().wait_idle().await;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ShieldTracker(Arc<ShieldTrackerInner>);
+
+#[derive(Debug, Default)]
+struct ShieldTrackerInner {
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+impl ShieldTracker {
+    /// Create a new ShieldTracker.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the tracker that is used by default for all shielded handlers.
+    pub fn global() -> &'static Self {
+        static GLOBAL: OnceLock<ShieldTracker> = OnceLock::new();
+        GLOBAL.get_or_init(Self::new)
+    }
+
+    /// Return the number of shielded handlers that are currently running.
+    pub fn in_flight(&self) -> usize {
+        self.0.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Wait until no shielded handler is running anymore.
+    pub async fn wait_idle(&self) {
+        loop {
+            let idle = self.0.idle.notified();
+            if self.in_flight() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    fn leave(&self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::StatusCode, test::TestClient, web::Data, EndpointExt};
+    use poem_openapi::ApiResponse;
+
+    use super::*;
+
+    #[handler]
+    async fn slow() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        "done"
+    }
+
+    #[tokio::test]
+    async fn test_completes_before_deadline() {
+        let app = slow.with(ShieldMiddleware::with_timeout(Duration::from_secs(5)));
+        let resp = TestClient::new(app).get("/").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("done").await;
+    }
+
+    #[tokio::test]
+    async fn test_deadline_exceeded() {
+        let app = shield_with_timeout(slow, Duration::from_millis(10));
+        let resp = TestClient::new(app).get("/").send().await;
+        resp.assert_status(StatusCode::GATEWAY_TIMEOUT);
+        resp.assert_text(r#"{"error":"gateway_timeout"}"#).await;
+    }
+
+    #[test]
+    fn test_timeout_schema() {
+        let statuses = crate::responses::Response::<(), ShieldTimeout>::meta()
+            .responses
+            .into_iter()
+            .map(|resp| resp.status)
+            .collect::<Vec<_>>();
+        assert!(statuses.contains(&Some(504)));
+    }
+
+    #[tokio::test]
+    async fn test_wait_idle() {
+        let tracker = ShieldTracker::new();
+        let app = slow.with(ShieldMiddleware::custom().with_tracker(tracker.clone()));
+        tracker.wait_idle().await;
+
+        let request = tokio::spawn(async move { TestClient::new(app).get("/").send().await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(tracker.in_flight(), 1);
+
+        // cancel the request, the shielded handler should still run to completion
+        request.abort();
+        tracker.wait_idle().await;
+        assert_eq!(tracker.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_disconnected() {
+        #[handler]
+        async fn handler(disconnected: Data<&Disconnected>) -> &'static str {
+            let was_connected = !disconnected.is_disconnected();
+            disconnected.wait().await;
+            assert!(was_connected);
+            "done"
+        }
+
+        let tracker = ShieldTracker::new();
+        let app = handler.with(ShieldMiddleware::custom().with_tracker(tracker.clone()));
+        let request = tokio::spawn(async move { TestClient::new(app).get("/").send().await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(tracker.in_flight(), 1);
+
+        request.abort();
+        tokio::time::timeout(Duration::from_secs(1), tracker.wait_idle())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_filter() {
+        let tracker = ShieldTracker::new();
+        let app = Arc::new(
+            slow.with(
+                ShieldMiddleware::custom()
+                    .with_tracker(tracker.clone())
+                    .only_unsafe_methods()
+                    .with_path_prefix("/payments"),
+            ),
+        );
+
+        for (method, path, shielded) in [
+            (Method::GET, "/payments", false),
+            (Method::POST, "/payments/42", true),
+            (Method::POST, "/payment", false),
+            (Method::DELETE, "/orders", false),
+        ] {
+            let app = Arc::clone(&app);
+            let request =
+                tokio::spawn(
+                    async move { TestClient::new(app).request(method, path).send().await },
+                );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(tracker.in_flight(), shielded as usize, "{path}");
+            request.abort();
+            tracker.wait_idle().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disconnected_unshielded() {
+        #[handler]
+        fn handler(disconnected: Data<&Disconnected>) -> String {
+            disconnected.is_disconnected().to_string()
+        }
 
-    async fn call(&self, req: poem::Request) -> poem::Result<Self::Output> {
-        let ep = Arc::clone(&self.0);
-        async move { ep.call(req).await }.shield().await
+        let app = handler.with(ShieldMiddleware::custom().only_unsafe_methods());
+        let resp = TestClient::new(app).get("/").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("false").await;
     }
 }
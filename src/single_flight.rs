@@ -0,0 +1,229 @@
+//! Contains a middleware that coalesces concurrent identical `GET` requests.
+//!
+//! When many clients request the same expensive resource at the same time
+//! (e.g. right after a cached response has expired), every request would hit
+//! the database. The [`SingleFlightMiddleware`] only passes the first request
+//! to the endpoint and lets all identical requests that arrive while it is
+//! running wait for its response, which is then returned to all of them.
+//!
+//! Requests are identical if they have the same path, the same query
+//! parameters (in any order) and the same values of the configured
+//! [vary headers](SingleFlightMiddleware::with_vary). Headers that affect the
+//! response (e.g. `Authorization` or `Accept-Language`) must be added as vary
+//! headers, otherwise clients may receive responses meant for other clients.
+//!
+//! Responses with a `Set-Cookie` header are never shared. If the first request
+//! fails with an error or is cancelled, the waiting requests are passed to the
+//! endpoint themselves. This middleware should only be used for idempotent
+//! endpoints and should be added after middlewares that add per-request
+//! headers (e.g. [`RequestIdMiddleware`](crate::request_id::RequestIdMiddleware)),
+//! so these are not shared as well.
+//!
+//! #### Example
+//! ```
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::single_flight::SingleFlightMiddleware;
+//! use poem_openapi::{param::Path, payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/reports/:id", method = "get")]
+//!     async fn get_report(&self, id: Path<u64>) -> PlainText<String> {
+//!         // run an expensive query
+//!         PlainText(format!("report {}", id.0))
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .with(SingleFlightMiddleware::new().with_vary(poem::http::header::AUTHORIZATION));
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use itertools::Itertools;
+use poem::{
+    http::{header::SET_COOKIE, HeaderName, Method},
+    Body, Endpoint, IntoResponse, Middleware, Request, Response,
+};
+use tokio::sync::watch;
+
+use crate::cache::{CacheKey, CachedResponse};
+
+type Flights = Arc<Mutex<HashMap<CacheKey, watch::Receiver<Option<CachedResponse>>>>>;
+
+/// A middleware that coalesces concurrent identical `GET` requests.
+#[derive(Debug, Clone, Default)]
+pub struct SingleFlightMiddleware {
+    vary: Vec<HeaderName>,
+}
+
+impl SingleFlightMiddleware {
+    /// Create a new SingleFlightMiddleware.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only coalesce requests with the same value of the given request header
+    /// (e.g. `Authorization` or `Accept-Language`).
+    pub fn with_vary(mut self, header: HeaderName) -> Self {
+        self.vary.push(header);
+        self
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for SingleFlightMiddleware {
+    type Output = SingleFlightMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        SingleFlightMwEndpoint {
+            inner: ep,
+            vary: self.vary.clone(),
+            flights: Default::default(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct SingleFlightMwEndpoint<E> {
+    inner: E,
+    vary: Vec<HeaderName>,
+    flights: Flights,
+}
+
+/// Removes a flight when the request that runs it completes or is cancelled.
+struct FlightGuard<'a> {
+    flights: &'a Flights,
+    key: CacheKey,
+}
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        self.flights.lock().unwrap().remove(&self.key);
+    }
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for SingleFlightMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        if req.method() != Method::GET {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        }
+
+        let mut key = CacheKey::from_request(&req, &self.vary);
+        key.query = key.query.split('&').sorted_unstable().join("&");
+        let flight = {
+            let mut flights = self.flights.lock().unwrap();
+            match flights.get(&key) {
+                Some(rx) => Err(rx.clone()),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    flights.insert(key.clone(), rx);
+                    Ok(tx)
+                }
+            }
+        };
+
+        let tx = match flight {
+            Ok(tx) => tx,
+            Err(mut rx) => {
+                if rx.changed().await.is_ok() {
+                    if let Some(resp) = rx.borrow().clone() {
+                        return Ok(resp.into_response());
+                    }
+                }
+                // the first request has failed or its response cannot be shared
+                return self.inner.call(req).await.map(IntoResponse::into_response);
+            }
+        };
+
+        let _guard = FlightGuard {
+            flights: &self.flights,
+            key,
+        };
+        let resp = self.inner.call(req).await?.into_response();
+        if resp.headers().contains_key(SET_COOKIE) {
+            return Ok(resp);
+        }
+        let (parts, body) = resp.into_parts();
+        let body = body.into_vec().await?;
+        let _ = tx.send(Some(CachedResponse {
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        }));
+        Ok(Response::from_parts(parts, Body::from_vec(body)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use futures_util::future::join_all;
+    use poem::{handler, http::header::AUTHORIZATION, test::TestClient, web::Data, EndpointExt};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    #[handler]
+    async fn counter(counter: Data<&Arc<Counter>>) -> String {
+        let n = counter.0 .0.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        n.to_string()
+    }
+
+    #[tokio::test]
+    async fn single_flight() {
+        let count = Arc::new(Counter::default());
+        let cli = TestClient::new(
+            counter
+                .with(SingleFlightMiddleware::new().with_vary(AUTHORIZATION))
+                .data(count.clone()),
+        );
+        let cli = &cli;
+
+        let bodies = join_all((0..5).map(|i| async move {
+            let req = if i % 2 == 0 {
+                cli.get("/").query("a", &1).query("b", &2)
+            } else {
+                cli.get("/").query("b", &2).query("a", &1)
+            };
+            let resp = req.send().await;
+            resp.assert_status_is_ok();
+            resp.0.into_body().into_string().await.unwrap()
+        }))
+        .await;
+        assert_eq!(bodies, ["0"; 5]);
+        assert_eq!(count.0.load(Ordering::Relaxed), 1);
+
+        // different keys and sequential requests are not coalesced
+        let (a, b) = tokio::join!(
+            cli.get("/").header(AUTHORIZATION, "a").send(),
+            cli.get("/").header(AUTHORIZATION, "b").send(),
+        );
+        a.assert_status_is_ok();
+        b.assert_status_is_ok();
+        cli.get("/")
+            .query("a", &1)
+            .send()
+            .await
+            .assert_text("3")
+            .await;
+        cli.post("/").send().await.assert_text("4").await;
+    }
+}
@@ -0,0 +1,207 @@
+//! Contains a middleware that logs requests which take longer than a
+//! threshold.
+//!
+//! For every slow request a warning with the method, path, status, duration,
+//! [operation id](poem_openapi::OperationId),
+//! [authenticated identity](crate::identity::Identity) and
+//! [request id](crate::request_id) is emitted using the
+//! [`tracing`](https://docs.rs/tracing) crate (target `poem_ext::slow_log`).
+//! If the [`DbTransactionMiddleware`](crate::db::DbTransactionMiddleware) is
+//! used inside of this middleware, the duration of the transaction and whether
+//! it has been committed are included as well.
+//!
+//! The operation id is only available for operations that set it explicitly
+//! (e.g. `#[oai(path = "/users", method = "get", operation_id = "list_users")]`).
+//!
+//! #### Example
+//! ```
+//! use std::time::Duration;
+//!
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::slow_log::SlowRequestLogMiddleware;
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     // log requests that take longer than 500ms, but allow 5s for exports
+//!     SlowRequestLogMiddleware::new(Duration::from_millis(500))
+//!         .with_path_threshold("/exports", Duration::from_secs(5)),
+//! );
+//! ```
+
+use std::time::{Duration, Instant};
+
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response};
+use poem_openapi::OperationId;
+
+use crate::{identity::Identity, request_id::RequestId, utils::path_has_prefix};
+
+/// A middleware that logs requests which take longer than a threshold.
+#[derive(Debug, Clone)]
+pub struct SlowRequestLogMiddleware {
+    threshold: Option<Duration>,
+    path_thresholds: Vec<(String, Option<Duration>)>,
+}
+
+impl SlowRequestLogMiddleware {
+    /// Create a new SlowRequestLogMiddleware that logs requests which take
+    /// longer than `threshold`.
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold: Some(threshold),
+            path_thresholds: Vec::new(),
+        }
+    }
+
+    /// Use a different threshold for requests whose path starts with the
+    /// given prefix.
+    ///
+    /// If multiple prefixes match a request, the longest one is used.
+    pub fn with_path_threshold(mut self, prefix: impl Into<String>, threshold: Duration) -> Self {
+        self.path_thresholds.push((prefix.into(), Some(threshold)));
+        self
+    }
+
+    /// Never log requests whose path starts with the given prefix (e.g. long
+    /// polling or streaming endpoints).
+    pub fn without_logging_for(mut self, prefix: impl Into<String>) -> Self {
+        self.path_thresholds.push((prefix.into(), None));
+        self
+    }
+
+    fn threshold_for(&self, path: &str) -> Option<Duration> {
+        self.path_thresholds
+            .iter()
+            .filter(|(prefix, _)| path_has_prefix(path, prefix))
+            .max_by_key(|(prefix, _)| prefix.trim_end_matches('/').len())
+            .map_or(self.threshold, |(_, threshold)| *threshold)
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for SlowRequestLogMiddleware {
+    type Output = SlowRequestLogMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        SlowRequestLogMwEndpoint {
+            inner: ep,
+            config: self.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct SlowRequestLogMwEndpoint<E> {
+    inner: E,
+    config: SlowRequestLogMiddleware,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for SlowRequestLogMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> poem::Result<Self::Output> {
+        let Some(threshold) = self.config.threshold_for(req.uri().path()) else {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        };
+
+        let method = req.method().clone();
+        let path = req.uri().path().to_owned();
+        let request_id = RequestId::of(&req);
+        let identity = Identity::attach(&mut req);
+
+        let start = Instant::now();
+        let result = self.inner.call(req).await.map(IntoResponse::into_response);
+        let duration = start.elapsed();
+        if duration <= threshold {
+            return result;
+        }
+
+        let (status, operation_id) = match &result {
+            Ok(resp) => (resp.status(), resp.data::<OperationId>().map(|x| x.0)),
+            Err(err) => (err.status(), None),
+        };
+        let txn = result.as_ref().ok().and_then(transaction_stats);
+
+        tracing::warn!(
+            target: "poem_ext::slow_log",
+            %method,
+            path,
+            status = status.as_u16(),
+            duration_ms = duration.as_secs_f64() * 1000.0,
+            threshold_ms = threshold.as_secs_f64() * 1000.0,
+            operation_id,
+            identity = identity.value(),
+            request_id = request_id.as_deref(),
+            db_txn_ms = txn.map(|(duration, _, _)| duration.as_secs_f64() * 1000.0),
+            db_finish_ms = txn.map(|(_, finish, _)| finish.as_secs_f64() * 1000.0),
+            db_committed = txn.map(|(_, _, committed)| committed),
+            "slow request",
+        );
+
+        result
+    }
+}
+
+/// Return the total duration, the commit or rollback duration and the outcome
+/// of the db transaction of a request (see
+/// [`TransactionStats`](crate::db::TransactionStats)).
+#[cfg(feature = "sea-orm")]
+fn transaction_stats(resp: &Response) -> Option<(Duration, Duration, bool)> {
+    resp.data::<crate::db::TransactionStats>()
+        .map(|txn| (txn.duration, txn.finish_duration, txn.committed))
+}
+
+#[cfg(not(feature = "sea-orm"))]
+fn transaction_stats(_resp: &Response) -> Option<(Duration, Duration, bool)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, test::TestClient, EndpointExt};
+
+    use super::*;
+
+    #[handler]
+    async fn slow(req: &Request) -> String {
+        Identity::set(req, "user");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        Identity::get(req).unwrap_or_default()
+    }
+
+    #[test]
+    fn threshold() {
+        let mw = SlowRequestLogMiddleware::new(Duration::from_secs(1))
+            .with_path_threshold("/exports", Duration::from_secs(5))
+            .with_path_threshold("/exports/large", Duration::from_secs(30))
+            .without_logging_for("/events");
+
+        assert_eq!(mw.threshold_for("/"), Some(Duration::from_secs(1)));
+        assert_eq!(mw.threshold_for("/exports/1"), Some(Duration::from_secs(5)));
+        assert_eq!(
+            mw.threshold_for("/exports/large/1"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(mw.threshold_for("/events"), None);
+    }
+
+    #[tokio::test]
+    async fn slow_request() {
+        let cli =
+            TestClient::new(slow.with(SlowRequestLogMiddleware::new(Duration::from_millis(1))));
+        let resp = cli.get("/").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("user").await;
+    }
+}
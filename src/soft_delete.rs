@@ -0,0 +1,181 @@
+//! Contains helpers for entities that are soft-deleted by setting a
+//! `deleted_at` column instead of removing their rows.
+//!
+//! Implement [`SoftDelete`] for an entity to get queries that ignore
+//! soft-deleted rows ([`SoftDelete::find_active`]) and to soft-delete rows
+//! using the transaction managed by the
+//! [`DbTransactionMiddleware`](crate::db::DbTransactionMiddleware)
+//! ([`SoftDelete::soft_delete`]). Use the [`Gone`](crate::responses::Gone)
+//! response for requests that target soft-deleted resources.
+//!
+//! #### Example
+//! ```no_run
+//! use poem::web::Data;
+//! use poem_ext::{db::DbTxn, response, responses::Gone, soft_delete::SoftDelete};
+//! use poem_openapi::{param::Path, OpenApi};
+//!
+//! # mod user {
+//! #     use sea_orm::entity::prelude::*;
+//! #     #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+//! #     #[sea_orm(table_name = "user")]
+//! #     pub struct Model {
+//! #         #[sea_orm(primary_key)]
+//! #         pub id: i32,
+//! #         pub name: String,
+//! #         pub deleted_at: Option<String>,
+//! #     }
+//! #     #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+//! #     pub enum Relation {}
+//! #     impl ActiveModelBehavior for ActiveModel {}
+//! # }
+//! impl SoftDelete for user::Entity {
+//!     fn deleted_at_column() -> user::Column {
+//!         user::Column::DeletedAt
+//!     }
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/users/:id", method = "get")]
+//!     async fn get_user(
+//!         &self,
+//!         id: Path<i32>,
+//!     ) -> Result<GetUser::raw::Response, Gone::raw::Response> {
+//!         // load the user including soft-deleted rows
+//!         let user: Option<user::Model> = todo!();
+//!         match user {
+//!             Some(user) if user.deleted_at.is_some() => Err(Gone::raw::gone()),
+//!             Some(user) => Ok(GetUser::raw::ok(user.name)),
+//!             None => Ok(GetUser::raw::not_found()),
+//!         }
+//!     }
+//!
+//!     #[oai(path = "/users/:id", method = "delete")]
+//!     async fn delete_user(&self, id: Path<i32>, txn: Data<&DbTxn>) -> DeleteUser::Response {
+//!         match user::Entity::soft_delete(&***txn, id.0).await? {
+//!             true => DeleteUser::ok(),
+//!             false => DeleteUser::not_found(),
+//!         }
+//!     }
+//! }
+//!
+//! response!(GetUser = {
+//!     Ok(200) => String,
+//!     NotFound(404, error),
+//! });
+//!
+//! response!(DeleteUser = {
+//!     Ok(200),
+//!     NotFound(404, error),
+//! });
+//! ```
+
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, Condition, ConnectionTrait, DbErr, EntityTrait, Iterable,
+    PrimaryKeyToColumn, PrimaryKeyTrait, QueryFilter, Select,
+};
+
+/// Trait for entities that support soft-deletion.
+#[poem::async_trait]
+pub trait SoftDelete: EntityTrait {
+    /// Return the nullable timestamp column that is set when a row is
+    /// soft-deleted.
+    fn deleted_at_column() -> Self::Column;
+
+    /// Select all rows that have not been soft-deleted.
+    fn find_active() -> Select<Self> {
+        Self::find().filter(Self::deleted_at_column().is_null())
+    }
+
+    /// Select the row with the given primary key if it has not been
+    /// soft-deleted.
+    fn find_active_by_id<T>(id: T) -> Select<Self>
+    where
+        T: Into<<Self::PrimaryKey as PrimaryKeyTrait>::ValueType>,
+    {
+        Self::find_active().filter(primary_key_condition::<Self>(id.into()))
+    }
+
+    /// Soft-delete the row with the given primary key by setting its
+    /// `deleted_at` column to the current timestamp.
+    ///
+    /// Pass the [`DbTxn`](crate::db::DbTxn) of the current request as `db` to
+    /// perform the update in the managed transaction. Returns `false` if the
+    /// row does not exist or has already been soft-deleted.
+    async fn soft_delete<C, T>(db: &C, id: T) -> Result<bool, DbErr>
+    where
+        C: ConnectionTrait,
+        T: Into<<Self::PrimaryKey as PrimaryKeyTrait>::ValueType> + Send,
+    {
+        let result = Self::update_many()
+            .col_expr(Self::deleted_at_column(), Expr::current_timestamp().into())
+            .filter(primary_key_condition::<Self>(id.into()))
+            .filter(Self::deleted_at_column().is_null())
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+}
+
+/// Build a condition that matches the row with the given primary key.
+fn primary_key_condition<E: EntityTrait>(
+    id: <E::PrimaryKey as PrimaryKeyTrait>::ValueType,
+) -> Condition {
+    let values = sea_orm::sea_query::IntoValueTuple::into_value_tuple(id);
+    let mut keys = E::PrimaryKey::iter();
+    let mut condition = Condition::all();
+    for value in values {
+        let key = keys.next().expect("primary key arity mismatch");
+        condition = condition.add(key.into_column().eq(value));
+    }
+    assert!(keys.next().is_none(), "primary key arity mismatch");
+    condition
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DbBackend, QueryTrait};
+
+    use super::*;
+
+    mod user {
+        use sea_orm::entity::prelude::*;
+
+        #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+        #[sea_orm(table_name = "user")]
+        pub struct Model {
+            #[sea_orm(primary_key)]
+            pub id: i32,
+            pub deleted_at: Option<String>,
+        }
+
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+        pub enum Relation {}
+
+        impl ActiveModelBehavior for ActiveModel {}
+
+        impl super::SoftDelete for Entity {
+            fn deleted_at_column() -> Self::Column {
+                Column::DeletedAt
+            }
+        }
+    }
+
+    #[test]
+    fn find_active() {
+        assert_eq!(
+            user::Entity::find_active()
+                .build(DbBackend::Postgres)
+                .to_string(),
+            r#"SELECT "user"."id", "user"."deleted_at" FROM "user" WHERE "user"."deleted_at" IS NULL"#
+        );
+        assert_eq!(
+            user::Entity::find_active_by_id(42)
+                .build(DbBackend::Postgres)
+                .to_string(),
+            r#"SELECT "user"."id", "user"."deleted_at" FROM "user" WHERE "user"."deleted_at" IS NULL AND "user"."id" = 42"#
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Contains an extractor for sorting query parameters.
+//!
+//! #### Example
+//! ```
+//! use poem_ext::{responses::Response, sort::Sort, static_enum};
+//! use poem_openapi::{payload::Json, OpenApi};
+//!
+//! static_enum!(UserField {
+//!     Name = "name",
+//!     CreatedAt = "created_at",
+//! });
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     /// Return a list of users.
+//!     ///
+//!     /// Use e.g. `?sort=name:asc,created_at:desc` to sort the users.
+//!     #[oai(path = "/users", method = "get")]
+//!     async fn list_users(&self, sort: Sort<UserField>) -> Response<Json<Vec<String>>> {
+//!         for (field, order) in &sort.0 {
+//!             // ...
+//!         }
+//!         todo!()
+//!     }
+//! }
+//! ```
+
+use poem::{Request, RequestBody};
+use poem_openapi::{
+    error::ParseParamError,
+    registry::{MetaParamIn, MetaSchema, MetaSchemaRef, Registry},
+    types::{ParseFromParameter, Type},
+    ApiExtractor, ApiExtractorType, ExtractParamOptions,
+};
+
+/// The direction in which to sort a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SortOrder {
+    /// Ascending order.
+    Asc,
+    /// Descending order.
+    Desc,
+}
+
+/// Extractor for a query parameter that contains a comma separated list of
+/// fields to sort by, each optionally followed by `:asc` (default) or
+/// `:desc`.
+///
+/// The allowed fields are defined by the type `F`, which is usually an enum
+/// created using e.g. the [`static_enum!`](crate::static_enum!) macro or the
+/// [`Enum`](derive@poem_openapi::Enum) derive macro. Unknown or duplicate
+/// fields result in a `400 Bad Request` response (or a
+/// `422 Unprocessable Content` response if the endpoint uses the
+/// [`Response`](crate::responses::Response) type). If the parameter is
+/// missing, the list is empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sort<F>(pub Vec<(F, SortOrder)>);
+
+impl<F> Default for Sort<F> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<F> Sort<F> {
+    /// Return `true` if no fields have been specified.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Add `ORDER BY` clauses for all fields to a sea-orm query.
+    ///
+    /// The `column` function maps each field to the column (or any other
+    /// expression) to sort by.
+    ///
+    /// #### Example
+    /// ```no_run
+    /// # use poem_ext::{sort::Sort, static_enum};
+    /// # use sea_orm::{entity::prelude::*, EntityTrait};
+    /// # mod user {
+    /// #     use sea_orm::entity::prelude::*;
+    /// #     #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    /// #     #[sea_orm(table_name = "user")]
+    /// #     pub struct Model {
+    /// #         #[sea_orm(primary_key)]
+    /// #         pub id: i32,
+    /// #         pub name: String,
+    /// #     }
+    /// #     #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    /// #     pub enum Relation {}
+    /// #     impl ActiveModelBehavior for ActiveModel {}
+    /// # }
+    /// static_enum!(UserField {
+    ///     Id = "id",
+    ///     Name = "name",
+    /// });
+    ///
+    /// # let sort: Sort<UserField> = todo!();
+    /// let query = sort.order_by(user::Entity::find(), |field| match field {
+    ///     UserField::Id => user::Column::Id,
+    ///     UserField::Name => user::Column::Name,
+    /// });
+    /// ```
+    #[cfg(feature = "sea-orm")]
+    pub fn order_by<Q, C>(&self, mut query: Q, column: impl Fn(&F) -> C) -> Q
+    where
+        Q: sea_orm::QueryOrder,
+        C: sea_orm::IntoSimpleExpr,
+    {
+        for (field, order) in &self.0 {
+            let order = match order {
+                SortOrder::Asc => sea_orm::Order::Asc,
+                SortOrder::Desc => sea_orm::Order::Desc,
+            };
+            query = query.order_by(column(field), order);
+        }
+        query
+    }
+}
+
+impl<F> Sort<F>
+where
+    F: ParseFromParameter + PartialEq,
+{
+    fn parse(value: &str) -> Result<Self, String> {
+        let mut fields = Vec::new();
+        for item in value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !str::is_empty(item))
+        {
+            let (field, order) = match item.rsplit_once(':') {
+                Some((field, "asc")) => (field, SortOrder::Asc),
+                Some((field, "desc")) => (field, SortOrder::Desc),
+                Some((_, order)) => {
+                    return Err(format!(
+                        "invalid sort order {order:?}, expected \"asc\" or \"desc\""
+                    ))
+                }
+                None => (item, SortOrder::Asc),
+            };
+            let parsed = F::parse_from_parameter(field)
+                .map_err(|err| format!("invalid sort field {field:?}: {}", err.into_message()))?;
+            if fields.iter().any(|(f, _)| *f == parsed) {
+                return Err(format!("duplicate sort field {field:?}"));
+            }
+            fields.push((parsed, order));
+        }
+        Ok(Self(fields))
+    }
+}
+
+#[poem::async_trait]
+impl<'a, F> ApiExtractor<'a> for Sort<F>
+where
+    F: Type + ParseFromParameter + PartialEq,
+{
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::Parameter];
+
+    type ParamType = Self;
+    type ParamRawType = Self;
+
+    fn register(registry: &mut Registry) {
+        F::register(registry);
+    }
+
+    fn param_in() -> Option<MetaParamIn> {
+        Some(MetaParamIn::Query)
+    }
+
+    fn param_schema_ref() -> Option<MetaSchemaRef> {
+        Some(MetaSchemaRef::Inline(Box::new(MetaSchema {
+            description: Some(
+                "Comma separated list of fields to sort by, each optionally followed by `:asc` \
+                 (default) or `:desc`.",
+            ),
+            example: Some("field1:asc,field2:desc".into()),
+            ..MetaSchema::new("string")
+        })))
+    }
+
+    fn param_raw_type(&self) -> Option<&Self::ParamRawType> {
+        Some(self)
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        _body: &mut RequestBody,
+        param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let value = request
+            .params::<Vec<(String, String)>>()?
+            .into_iter()
+            .find(|(key, _)| key == param_opts.name)
+            .map(|(_, value)| value);
+        match value {
+            Some(value) => Self::parse(&value).map_err(|reason| {
+                ParseParamError {
+                    name: param_opts.name,
+                    reason,
+                }
+                .into()
+            }),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, Route};
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+    use crate::{responses::Response, static_enum};
+
+    static_enum!(Field {
+        Name = "name",
+        CreatedAt = "created_at",
+    });
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Sort::<Field>::parse("name:asc, created_at:desc").unwrap().0,
+            [
+                (Field::Name, SortOrder::Asc),
+                (Field::CreatedAt, SortOrder::Desc)
+            ]
+        );
+        assert_eq!(
+            Sort::<Field>::parse("created_at").unwrap().0,
+            [(Field::CreatedAt, SortOrder::Asc)]
+        );
+        assert!(Sort::<Field>::parse("").unwrap().is_empty());
+        assert!(Sort::<Field>::parse("foo").is_err());
+        assert!(Sort::<Field>::parse("name:up").is_err());
+        assert!(Sort::<Field>::parse("name,name:desc").is_err());
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test", method = "get")]
+        async fn test(&self, sort: Sort<Field>) -> Response<PlainText<String>> {
+            Ok(PlainText(format!("{:?}", sort.0)).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn extractor() {
+        let cli = TestClient::new(Route::new().nest("/", OpenApiService::new(Api, "test", "1")));
+
+        let resp = cli.get("/test?sort=created_at:desc").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("[(CreatedAt, Desc)]").await;
+
+        let resp = cli.get("/test").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("[]").await;
+
+        let resp = cli.get("/test?sort=foo").send().await;
+        resp.assert_status(poem::http::StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
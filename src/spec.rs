@@ -0,0 +1,457 @@
+//! Contains a builder for post-processing the OpenAPI document of a service.
+//!
+//! [`SpecProcessor`] applies a list of hooks to the final OpenAPI document in
+//! JSON format before it is served, e.g. to add `servers`, global security
+//! requirements or vendor extensions, or to strip internal endpoints. Custom
+//! hooks can modify the document in arbitrary ways.
+//!
+//! #### Example
+//! ```
+//! use poem::Route;
+//! use poem_ext::spec::SpecProcessor;
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//! use serde_json::json;
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//!
+//!     #[oai(path = "/internal/metrics", method = "get")]
+//!     async fn metrics(&self) -> PlainText<&'static str> {
+//!         PlainText("")
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let spec = SpecProcessor::new()
+//!     .with_server("https://api.example.com", Some("Production"))
+//!     .with_extension("x-logo", json!({"url": "https://example.com/logo.png"}))
+//!     .without_paths("/internal")
+//!     .with_hook(|spec| spec["info"]["description"] = "My API".into())
+//!     .endpoint(&api_service);
+//! let app = Route::new()
+//!     .at("/openapi.json", spec)
+//!     .nest("/", api_service);
+//! ```
+//!
+//! The processed document can also be written to a file (e.g. in a test or a
+//! small binary), so CI and client generators can consume it without starting
+//! the server. YAML output requires the `yaml` feature.
+//!
+//! ```no_run
+//! # use poem_ext::spec::{SpecFormat, SpecProcessor};
+//! # use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//! # struct Api;
+//! # #[OpenApi]
+//! # impl Api {
+//! #     #[oai(path = "/test", method = "get")]
+//! #     async fn test(&self) -> PlainText<&'static str> {
+//! #         PlainText("Hello World!")
+//! #     }
+//! # }
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! SpecProcessor::new()
+//!     .with_server("https://api.example.com", None)
+//!     .write_to_file(&api_service, "openapi.json", SpecFormat::PrettyJson)
+//!     .unwrap();
+//! ```
+
+use std::{fmt::Debug, io, path::Path, sync::Arc};
+
+use poem::{endpoint::make_sync, Endpoint, Response};
+use poem_openapi::{OpenApi, OpenApiService, Webhook};
+use serde_json::{Map, Value};
+
+use crate::utils::path_has_prefix;
+
+/// The format of an exported OpenAPI document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SpecFormat {
+    /// Compact JSON
+    #[default]
+    Json,
+    /// Indented JSON
+    PrettyJson,
+    /// YAML
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl SpecFormat {
+    /// Return the format that matches the extension of the given path
+    /// (`.json`, `.yaml` or `.yml`), if it is supported.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "json" => Some(Self::PrettyJson),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    fn render(self, spec: &Value) -> String {
+        match self {
+            Self::Json => spec.to_string(),
+            Self::PrettyJson => serde_json::to_string_pretty(spec).unwrap_or_default(),
+            #[cfg(feature = "yaml")]
+            Self::Yaml => serde_yaml::to_string(spec).unwrap_or_default(),
+        }
+    }
+}
+
+/// A function that modifies an OpenAPI document.
+pub type SpecHook = Arc<dyn Fn(&mut Value) + Send + Sync>;
+
+/// A builder for post-processing the OpenAPI document of a service.
+///
+/// The hooks are applied in the order in which they have been added.
+#[derive(Clone, Default)]
+pub struct SpecProcessor {
+    hooks: Vec<SpecHook>,
+}
+
+impl Debug for SpecProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpecProcessor")
+            .field("hooks", &self.hooks.len())
+            .finish()
+    }
+}
+
+impl SpecProcessor {
+    /// Create a new SpecProcessor without any hooks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a hook that modifies the OpenAPI document.
+    pub fn with_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&mut Value) + Send + Sync + 'static,
+    {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Add a server to the `servers` list of the OpenAPI document.
+    pub fn with_server(self, url: impl Into<String>, description: Option<&str>) -> Self {
+        let mut server = Map::new();
+        server.insert("url".into(), url.into().into());
+        if let Some(description) = description {
+            server.insert("description".into(), description.into());
+        }
+        self.with_hook(move |spec| {
+            if let Some(servers) = object_entry(spec, "servers", Value::Array(Vec::new()))
+                .and_then(Value::as_array_mut)
+            {
+                servers.push(server.clone().into());
+            }
+        })
+    }
+
+    /// Add a global security requirement for the security scheme with the
+    /// given name and scopes, which applies to all operations that do not
+    /// specify their own requirements.
+    pub fn with_security(self, scheme: impl Into<String>, scopes: &[&str]) -> Self {
+        let mut requirement = Map::new();
+        requirement.insert(scheme.into(), scopes.iter().copied().collect());
+        self.with_hook(move |spec| {
+            if let Some(security) = object_entry(spec, "security", Value::Array(Vec::new()))
+                .and_then(Value::as_array_mut)
+            {
+                security.push(requirement.clone().into());
+            }
+        })
+    }
+
+    /// Set a vendor extension (e.g. `x-logo`) at the top level of the OpenAPI
+    /// document.
+    pub fn with_extension(self, name: impl Into<String>, value: Value) -> Self {
+        let name = name.into();
+        self.with_hook(move |spec| {
+            if let Some(spec) = spec.as_object_mut() {
+                spec.insert(name.clone(), value.clone());
+            }
+        })
+    }
+
+    /// Document a response header (e.g. one that is added by a middleware) on
+    /// all responses of all operations.
+    pub fn with_response_header(
+        self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        let name = name.into();
+        let description = description.into();
+        self.with_hook(move |spec| add_response_header(spec, &name, &description))
+    }
+
+    /// Remove all paths that are equal to the given prefix or start with it
+    /// followed by a `/` (e.g. internal endpoints).
+    ///
+    /// Schemas that are only referenced by the removed operations are kept.
+    pub fn without_paths(self, prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        self.with_hook(move |spec| {
+            if let Some(paths) = spec.get_mut("paths").and_then(Value::as_object_mut) {
+                paths.retain(|path, _| !path_has_prefix(path, &prefix));
+            }
+        })
+    }
+
+    /// Remove all operations that have the given tag.
+    pub fn without_tag(self, tag: impl Into<String>) -> Self {
+        let tag = tag.into();
+        self.with_hook(move |spec| {
+            let Some(paths) = spec.get_mut("paths").and_then(Value::as_object_mut) else {
+                return;
+            };
+            for path in paths.values_mut().filter_map(Value::as_object_mut) {
+                path.retain(|_, operation| {
+                    !operation
+                        .get("tags")
+                        .and_then(Value::as_array)
+                        .is_some_and(|tags| tags.iter().any(|x| x.as_str() == Some(&tag)))
+                });
+            }
+            paths.retain(|_, path| path.as_object().map_or(true, |path| !path.is_empty()));
+        })
+    }
+
+    /// Apply all hooks to an OpenAPI document.
+    pub fn apply(&self, spec: &mut Value) {
+        for hook in &self.hooks {
+            hook(spec);
+        }
+    }
+
+    /// Apply all hooks to an OpenAPI document in JSON format.
+    ///
+    /// The document is returned unchanged if it cannot be parsed.
+    pub fn process(&self, spec: &str) -> String {
+        let Ok(mut spec) = serde_json::from_str::<Value>(spec) else {
+            return spec.into();
+        };
+        self.apply(&mut spec);
+        spec.to_string()
+    }
+
+    /// Return the processed OpenAPI document of the given service in the given
+    /// format.
+    pub fn render<T, W>(&self, service: &OpenApiService<T, W>, format: SpecFormat) -> String
+    where
+        T: OpenApi,
+        W: Webhook,
+    {
+        let mut spec = serde_json::from_str(&service.spec()).unwrap_or_default();
+        self.apply(&mut spec);
+        format.render(&spec)
+    }
+
+    /// Write the processed OpenAPI document of the given service to a file in
+    /// the given format.
+    ///
+    /// Missing parent directories are created.
+    pub fn write_to_file<T, W>(
+        &self,
+        service: &OpenApiService<T, W>,
+        path: impl AsRef<Path>,
+        format: SpecFormat,
+    ) -> io::Result<()>
+    where
+        T: OpenApi,
+        W: Webhook,
+    {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.render(service, format))
+    }
+
+    /// Create an endpoint that serves the processed OpenAPI document of the
+    /// given service in JSON format.
+    ///
+    /// The document is generated once when this method is called.
+    pub fn endpoint<T, W>(&self, service: &OpenApiService<T, W>) -> impl Endpoint<Output = Response>
+    where
+        T: OpenApi,
+        W: Webhook,
+    {
+        let spec = self.process(&service.spec());
+        make_sync(move |_| {
+            Response::builder()
+                .content_type("application/json; charset=utf-8")
+                .body(spec.clone())
+        })
+    }
+}
+
+/// Add a string header with the given name and description to all responses
+/// of all operations in an OpenAPI document.
+pub(crate) fn add_response_header(spec: &mut Value, name: &str, description: &str) {
+    let Some(paths) = spec.get_mut("paths").and_then(Value::as_object_mut) else {
+        return;
+    };
+    let header = serde_json::json!({
+        "description": description,
+        "schema": {"type": "string"},
+    });
+    for response in paths
+        .values_mut()
+        .filter_map(Value::as_object_mut)
+        .flat_map(|path| path.values_mut())
+        .filter_map(|operation| operation.get_mut("responses")?.as_object_mut())
+        .flat_map(|responses| responses.values_mut())
+    {
+        if let Some(headers) = object_entry(response, "headers", Value::Object(Map::new()))
+            .and_then(Value::as_object_mut)
+        {
+            headers.insert(name.into(), header.clone());
+        }
+    }
+}
+
+/// Return the entry with the given key of a JSON object, inserting `default`
+/// if it does not exist yet.
+fn object_entry<'a>(value: &'a mut Value, key: &str, default: Value) -> Option<&'a mut Value> {
+    Some(value.as_object_mut()?.entry(key).or_insert(default))
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::test::TestClient;
+    use poem_openapi::{payload::PlainText, Tags};
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Tags)]
+    enum Tag {
+        Internal,
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/test", method = "get")]
+        async fn test(&self) -> PlainText<&'static str> {
+            PlainText("test")
+        }
+
+        #[oai(path = "/internal", method = "get")]
+        async fn internal(&self) -> PlainText<&'static str> {
+            PlainText("internal")
+        }
+
+        #[oai(path = "/tagged", method = "get", tag = "Tag::Internal")]
+        async fn tagged(&self) -> PlainText<&'static str> {
+            PlainText("tagged")
+        }
+
+        #[oai(path = "/tagged", method = "post")]
+        async fn tagged_post(&self) -> PlainText<&'static str> {
+            PlainText("tagged")
+        }
+
+        #[oai(path = "/tagged/only", method = "get", tag = "Tag::Internal")]
+        async fn tagged_only(&self) -> PlainText<&'static str> {
+            PlainText("tagged")
+        }
+    }
+
+    fn processor() -> SpecProcessor {
+        SpecProcessor::new()
+            .with_server("https://example.com", Some("Production"))
+            .with_server("http://localhost", None)
+            .with_security("token", &[])
+            .with_extension("x-foo", json!({"bar": 42}))
+            .with_response_header("X-Foo", "Foo")
+            .without_paths("/internal")
+            .without_tag("Internal")
+            .with_hook(|spec| spec["info"]["title"] = "Processed".into())
+    }
+
+    #[test]
+    fn process() {
+        let spec = processor().process(&OpenApiService::new(Api, "test", "1").spec());
+        let spec: Value = serde_json::from_str(&spec).unwrap();
+
+        assert_eq!(
+            spec["servers"],
+            json!([
+                {"url": "https://example.com", "description": "Production"},
+                {"url": "http://localhost"},
+            ])
+        );
+        assert_eq!(spec["security"], json!([{"token": []}]));
+        assert_eq!(spec["x-foo"], json!({"bar": 42}));
+        assert_eq!(spec["info"]["title"], "Processed");
+        assert_eq!(
+            spec["paths"]["/test"]["get"]["responses"]["200"]["headers"]["X-Foo"],
+            json!({"description": "Foo", "schema": {"type": "string"}})
+        );
+
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/test"));
+        assert!(!paths.contains_key("/internal"));
+        assert!(!paths.contains_key("/tagged/only"));
+        assert!(paths["/tagged"].get("get").is_none());
+        assert!(paths["/tagged"].get("post").is_some());
+    }
+
+    #[test]
+    fn write_to_file() {
+        let service = OpenApiService::new(Api, "test", "1");
+        let dir = std::env::temp_dir().join(format!("poem-ext-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("spec").join("openapi.json");
+
+        assert_eq!(SpecFormat::from_path(&path), Some(SpecFormat::PrettyJson));
+        assert_eq!(SpecFormat::from_path("openapi.txt"), None);
+        processor()
+            .write_to_file(&service, &path, SpecFormat::PrettyJson)
+            .unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains('\n'));
+        let spec: Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(spec["info"]["title"], "Processed");
+        assert_eq!(
+            processor().render(&service, SpecFormat::Json),
+            spec.to_string()
+        );
+
+        #[cfg(feature = "yaml")]
+        {
+            let path = dir.join("openapi.yaml");
+            let format = SpecFormat::from_path(&path).unwrap();
+            processor().write_to_file(&service, &path, format).unwrap();
+            let content = std::fs::read_to_string(&path).unwrap();
+            let yaml: Value = serde_yaml::from_str(&content).unwrap();
+            assert_eq!(yaml, spec);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn endpoint() {
+        let cli = TestClient::new(processor().endpoint(&OpenApiService::new(Api, "test", "1")));
+        let resp = cli.get("/").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_content_type("application/json; charset=utf-8");
+        let spec = resp.json().await;
+        spec.value()
+            .object()
+            .get("info")
+            .object()
+            .get("title")
+            .assert_string("Processed");
+    }
+}
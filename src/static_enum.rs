@@ -0,0 +1,256 @@
+/// Construct an OpenApi enum type whose variants evaluate to static strings
+/// that are set at compile time.
+///
+/// In contrast to the [`Enum`](derive@poem_openapi::Enum) derive macro, the
+/// string values of the variants are specified explicitly. If the `serde`
+/// feature is enabled, [`serde::Serialize`] and [`serde::Deserialize`] are
+/// implemented as well.
+///
+/// #### Example
+/// ```
+/// use poem_ext::static_enum;
+/// use poem_openapi::{
+///     types::{ParseFromJSON, ToJSON},
+///     Object,
+/// };
+///
+/// static_enum!(pub Status {
+///     /// The account is active.
+///     Active = "active",
+///     /// The account has been deactivated.
+///     Inactive = "inactive",
+///     Banned = "banned_permanently",
+/// });
+///
+/// #[derive(Debug, Object)]
+/// struct Account {
+///     status: Status,
+/// }
+///
+/// let account = Account {
+///     status: Status::Banned,
+/// };
+/// assert_eq!(account.to_json_string(), r#"{"status":"banned_permanently"}"#);
+///
+/// let account = Account::parse_from_json_string(r#"{"status":"active"}"#).unwrap();
+/// assert_eq!(account.status, Status::Active);
+/// assert_eq!(account.status.as_str(), "active");
+/// assert!(Account::parse_from_json_string(r#"{"status":"Active"}"#).is_err());
+/// ```
+#[macro_export]
+macro_rules! static_enum {
+    ($(#[$attr:meta])* $vis:vis $name:ident {
+        $(
+            $(#[doc = $doc:literal])*
+            $var:ident = $str:expr
+        ),* $(,)?
+    }) => {
+        $(#[$attr])*
+        #[derive(
+            ::std::fmt::Debug,
+            ::std::clone::Clone,
+            ::std::marker::Copy,
+            ::std::cmp::PartialEq,
+            ::std::cmp::Eq,
+            ::std::hash::Hash,
+        )]
+        $vis enum $name {
+            $(
+                $(#[doc = $doc])*
+                $var,
+            )*
+        }
+
+        impl $name {
+            /// All variants of this enum.
+            #[allow(dead_code)]
+            pub const VARIANTS: &'static [Self] = &[$(Self::$var),*];
+
+            /// Return the string value of this variant.
+            #[allow(dead_code)]
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(Self::$var => $str,)*
+                }
+            }
+
+            /// Return the variant with the given string value.
+            #[allow(dead_code)]
+            pub fn from_str_value(value: &str) -> ::std::option::Option<Self> {
+                $(
+                    if value == $str {
+                        return ::std::option::Option::Some(Self::$var);
+                    }
+                )*
+                ::std::option::Option::None
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl ::poem_openapi::types::Type for $name {
+            const IS_REQUIRED: bool = true;
+
+            type RawValueType = Self;
+
+            type RawElementValueType = Self;
+
+            fn name() -> ::std::borrow::Cow<'static, str> {
+                ::std::stringify!($name).into()
+            }
+
+            fn schema_ref() -> ::poem_openapi::registry::MetaSchemaRef {
+                ::poem_openapi::registry::MetaSchemaRef::Reference(
+                    <Self as ::poem_openapi::types::Type>::name().into_owned(),
+                )
+            }
+
+            fn register(registry: &mut ::poem_openapi::registry::Registry) {
+                registry.create_schema::<Self, _>(
+                    <Self as ::poem_openapi::types::Type>::name().into_owned(),
+                    |_| ::poem_openapi::registry::MetaSchema {
+                        ty: "string",
+                        enum_items: ::std::vec![
+                            $(::poem_openapi::__private::serde_json::Value::String(
+                                ::std::string::ToString::to_string($str),
+                            ),)*
+                        ],
+                        ..::poem_openapi::registry::MetaSchema::ANY
+                    },
+                );
+            }
+
+            fn as_raw_value(&self) -> ::std::option::Option<&Self::RawValueType> {
+                ::std::option::Option::Some(self)
+            }
+
+            fn raw_element_iter<'a>(
+                &'a self,
+            ) -> ::std::boxed::Box<dyn ::std::iter::Iterator<Item = &'a Self::RawElementValueType> + 'a> {
+                ::std::boxed::Box::new(self.as_raw_value().into_iter())
+            }
+        }
+
+        impl ::poem_openapi::types::ParseFromJSON for $name {
+            fn parse_from_json(
+                value: ::std::option::Option<::poem_openapi::__private::serde_json::Value>,
+            ) -> ::poem_openapi::types::ParseResult<Self> {
+                match value {
+                    ::std::option::Option::Some(::poem_openapi::__private::serde_json::Value::String(value)) => {
+                        <Self as ::poem_openapi::types::ParseFromParameter>::parse_from_parameter(&value)
+                    }
+                    value => ::std::result::Result::Err(::poem_openapi::types::ParseError::expected_type(
+                        value.unwrap_or_default(),
+                    )),
+                }
+            }
+        }
+
+        impl ::poem_openapi::types::ParseFromParameter for $name {
+            fn parse_from_parameter(value: &str) -> ::poem_openapi::types::ParseResult<Self> {
+                Self::from_str_value(value).ok_or_else(|| {
+                    ::poem_openapi::types::ParseError::custom(::std::format!(
+                        "expected one of {:?}",
+                        [$($str),*]
+                    ))
+                })
+            }
+        }
+
+        impl ::poem_openapi::types::ToJSON for $name {
+            fn to_json(&self) -> ::std::option::Option<::poem_openapi::__private::serde_json::Value> {
+                ::std::option::Option::Some(::poem_openapi::__private::serde_json::Value::String(
+                    self.as_str().into(),
+                ))
+            }
+        }
+
+        $crate::__static_enum_serde!($name);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "serde")]
+macro_rules! __static_enum_serde {
+    ($name:ident) => {
+        impl $crate::__private::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: $crate::__private::serde::Serializer,
+            {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> $crate::__private::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: $crate::__private::serde::Deserializer<'de>,
+            {
+                struct Visitor;
+
+                impl<'de> $crate::__private::serde::de::Visitor<'de> for Visitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                        ::std::write!(
+                            f,
+                            "one of {:?}",
+                            <$name>::VARIANTS
+                                .iter()
+                                .map(<$name>::as_str)
+                                .collect::<::std::vec::Vec<_>>()
+                        )
+                    }
+
+                    fn visit_str<E>(self, value: &str) -> ::std::result::Result<Self::Value, E>
+                    where
+                        E: $crate::__private::serde::de::Error,
+                    {
+                        <$name>::from_str_value(value).ok_or_else(|| {
+                            E::invalid_value(
+                                $crate::__private::serde::de::Unexpected::Str(value),
+                                &self,
+                            )
+                        })
+                    }
+                }
+
+                deserializer.deserialize_str(Visitor)
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "serde"))]
+macro_rules! __static_enum_serde {
+    ($name:ident) => {};
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    static_enum!(Status {
+        Active = "active",
+        Inactive = "inactive",
+    });
+
+    #[test]
+    fn serde() {
+        assert_eq!(
+            serde_json::to_string(&Status::Inactive).unwrap(),
+            r#""inactive""#
+        );
+        assert_eq!(
+            serde_json::from_str::<Status>(r#""active""#).unwrap(),
+            Status::Active
+        );
+        assert!(serde_json::from_str::<Status>(r#""Active""#).is_err());
+    }
+}
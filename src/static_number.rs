@@ -0,0 +1,138 @@
+/// Construct an OpenApi type that always evaluates to a static integer that is
+/// set at compile time.
+///
+/// #### Example
+/// ```
+/// use poem_ext::static_number;
+/// use poem_openapi::{
+///     types::{ParseFromJSON, ToJSON},
+///     Object,
+/// };
+///
+/// static_number!(pub ApiVersion, 2);
+///
+/// #[derive(Debug, Object)]
+/// struct VersionInfo {
+///     version: ApiVersion,
+///     name: String,
+/// }
+///
+/// let response = VersionInfo {
+///     version: Default::default(),
+///     name: "test".into(),
+/// };
+/// assert_eq!(response.to_json_string(), r#"{"name":"test","version":2}"#);
+///
+/// // parsing only accepts the exact number
+/// assert!(VersionInfo::parse_from_json_string(r#"{"version":2,"name":"test"}"#).is_ok());
+/// assert!(VersionInfo::parse_from_json_string(r#"{"version":1,"name":"test"}"#).is_err());
+/// ```
+#[macro_export]
+macro_rules! static_number {
+    ($vis:vis $name:ident, $value:expr) => {
+        #[derive(::std::fmt::Debug, ::std::clone::Clone, ::std::marker::Copy, ::std::cmp::PartialEq, ::std::cmp::Eq)]
+        $vis struct $name;
+
+        impl $name {
+            /// The value of this type.
+            #[allow(dead_code)]
+            pub const VALUE: i64 = $value;
+        }
+
+        impl ::std::default::Default for $name {
+            fn default() -> Self {
+                Self
+            }
+        }
+
+        impl ::poem_openapi::types::Type for $name {
+            const IS_REQUIRED: bool = true;
+
+            type RawValueType = i64;
+
+            type RawElementValueType = i64;
+
+            fn name() -> ::std::borrow::Cow<'static, str> {
+                ::std::stringify!($name).into()
+            }
+
+            fn schema_ref() -> ::poem_openapi::registry::MetaSchemaRef {
+                ::poem_openapi::registry::MetaSchemaRef::Inline(Box::new(
+                    ::poem_openapi::registry::MetaSchema {
+                        ty: "integer",
+                        format: ::std::option::Option::Some("int64"),
+                        read_only: true,
+                        default: ::std::option::Option::Some(Self::VALUE.into()),
+                        enum_items: ::std::vec![Self::VALUE.into()],
+                        ..::poem_openapi::registry::MetaSchema::ANY
+                    },
+                ))
+            }
+
+            fn as_raw_value(&self) -> ::std::option::Option<&Self::RawValueType> {
+                ::std::option::Option::Some(&Self::VALUE)
+            }
+
+            fn raw_element_iter<'a>(
+                &'a self,
+            ) -> ::std::boxed::Box<dyn ::std::iter::Iterator<Item = &'a Self::RawElementValueType> + 'a> {
+                ::std::boxed::Box::new(self.as_raw_value().into_iter())
+            }
+        }
+
+        impl ::poem_openapi::types::ParseFromJSON for $name {
+            fn parse_from_json(
+                value: ::std::option::Option<::poem_openapi::__private::serde_json::Value>,
+            ) -> ::poem_openapi::types::ParseResult<Self> {
+                match value {
+                    ::std::option::Option::Some(::poem_openapi::__private::serde_json::Value::Number(value))
+                        if value.as_i64() == ::std::option::Option::Some(Self::VALUE) =>
+                    {
+                        ::std::result::Result::Ok(Self)
+                    }
+                    _ => ::std::result::Result::Err(::poem_openapi::types::ParseError::custom(
+                        ::std::format!("expected {}", Self::VALUE),
+                    )),
+                }
+            }
+        }
+
+        impl ::poem_openapi::types::ToJSON for $name {
+            fn to_json(&self) -> ::std::option::Option<poem_openapi::__private::serde_json::Value> {
+                ::std::option::Option::Some(Self::VALUE.into())
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use poem_openapi::{
+        registry::MetaSchemaRef,
+        types::{ParseFromJSON, ToJSON, Type},
+    };
+    use serde_json::json;
+
+    static_number!(Version, 2);
+
+    #[test]
+    fn json() {
+        assert_eq!(Version.to_json(), Some(json!(2)));
+        assert!(Version::parse_from_json(Some(json!(2))).is_ok());
+        assert!(Version::parse_from_json(Some(json!(1))).is_err());
+        assert!(Version::parse_from_json(Some(json!(2.5))).is_err());
+        assert!(Version::parse_from_json(Some(json!("2"))).is_err());
+        assert!(Version::parse_from_json(None).is_err());
+    }
+
+    #[test]
+    fn schema() {
+        let MetaSchemaRef::Inline(schema) = Version::schema_ref() else {
+            panic!("expected inline schema");
+        };
+        assert_eq!(schema.ty, "integer");
+        assert_eq!(schema.format, Some("int64"));
+        assert_eq!(schema.enum_items, [json!(2)]);
+        assert_eq!(schema.default, Some(json!(2)));
+    }
+}
@@ -1,12 +1,36 @@
 /// Construct an OpenApi type that always evaluates to a static string that is
 /// set at compile time.
 ///
+/// The generated schema is an enum with the static string as its only value,
+/// so client generators can produce a literal type. An optional description
+/// can be added using `static_string!(Name, "value", description = "...")`.
+/// Instead of a literal, the value can also be any constant expression of type
+/// `&'static str`, e.g. a path to a `const` that is shared with other parts of
+/// the application. It is available as the associated constant `VALUE`.
+///
+/// The generated type implements [`Clone`], [`Copy`], [`PartialEq`], [`Eq`]
+/// and [`Hash`]. If the `serde` feature is enabled, [`serde::Serialize`] and
+/// [`serde::Deserialize`] are implemented as well.
+///
 /// #### Example
 /// ```
 /// use poem_ext::static_string;
-/// use poem_openapi::{types::ToJSON, Object};
+/// use poem_openapi::{
+///     registry::MetaSchemaRef,
+///     types::{ParseFromJSON, ToJSON, Type},
+///     Object,
+/// };
+///
+/// mod error_codes {
+///     pub const CONFLICT: &str = "conflict";
+/// }
 ///
 /// static_string!(NotFoundError, "not found");
+/// static_string!(
+///     pub ConflictError,
+///     error_codes::CONFLICT,
+///     description = "The resource already exists."
+/// );
 ///
 /// #[derive(Debug, Object)]
 /// struct NotFoundDetails {
@@ -22,13 +46,45 @@
 ///     response.to_json_string(),
 ///     r#"{"error":"not found","foobar":42}"#
 /// );
+///
+/// // parsing only accepts the exact string
+/// assert!(NotFoundDetails::parse_from_json_string(r#"{"error":"not found","foobar":42}"#).is_ok());
+/// assert!(NotFoundDetails::parse_from_json_string(r#"{"error":"conflict","foobar":42}"#).is_err());
+///
+/// let MetaSchemaRef::Inline(schema) = ConflictError::schema_ref() else {
+///     unreachable!()
+/// };
+/// assert_eq!(schema.description, Some("The resource already exists."));
+/// assert_eq!(schema.enum_items, ["conflict"]);
+/// assert_eq!(ConflictError::VALUE, "conflict");
 /// ```
 #[macro_export]
 macro_rules! static_string {
-    ($vis:vis $name:ident, $str:expr) => {
-        #[derive(::std::fmt::Debug)]
+    ($vis:vis $name:ident, $str:expr $(, description = $description:expr)? $(,)?) => {
+        #[derive(
+            ::std::fmt::Debug,
+            ::std::clone::Clone,
+            ::std::marker::Copy,
+            ::std::cmp::PartialEq,
+            ::std::cmp::Eq,
+            ::std::hash::Hash,
+        )]
         $vis struct $name;
 
+        $crate::__static_string_impls!($name, $str $(, description = $description)?);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __static_string_impls {
+    ($name:ident, $str:expr $(, description = $description:expr)?) => {
+        impl $name {
+            /// The value of this type.
+            #[allow(dead_code)]
+            pub const VALUE: &'static str = $str;
+        }
+
         impl ::std::default::Default for $name {
             fn default() -> Self {
                 Self
@@ -50,15 +106,22 @@ macro_rules! static_string {
                 ::poem_openapi::registry::MetaSchemaRef::Inline(Box::new(
                     ::poem_openapi::registry::MetaSchema {
                         ty: "string",
+                        description: {
+                            let description: ::std::option::Option<&'static str> = ::std::option::Option::None;
+                            $(let description = ::std::option::Option::Some($description);)?
+                            description
+                        },
                         read_only: true,
-                        default: ::std::option::Option::Some($str.into()),
+                        default: ::std::option::Option::Some(Self::VALUE.into()),
+                        example: ::std::option::Option::Some(Self::VALUE.into()),
+                        enum_items: ::std::vec![Self::VALUE.into()],
                         ..::poem_openapi::registry::MetaSchema::ANY
                     },
                 ))
             }
 
             fn as_raw_value(&self) -> ::std::option::Option<&Self::RawValueType> {
-                ::std::option::Option::Some(&$str)
+                ::std::option::Option::Some(&Self::VALUE)
             }
 
             fn raw_element_iter<'a>(
@@ -70,18 +133,107 @@ macro_rules! static_string {
 
         impl ::poem_openapi::types::ParseFromJSON for $name {
             fn parse_from_json(
-                _value: ::std::option::Option<::poem_openapi::__private::serde_json::Value>,
+                value: ::std::option::Option<::poem_openapi::__private::serde_json::Value>,
             ) -> ::poem_openapi::types::ParseResult<Self> {
-                ::std::panic!("Cannot parse static string")
+                match value {
+                    ::std::option::Option::Some(::poem_openapi::__private::serde_json::Value::String(value))
+                        if value == Self::VALUE =>
+                    {
+                        ::std::result::Result::Ok(Self)
+                    }
+                    _ => ::std::result::Result::Err(::poem_openapi::types::ParseError::custom(
+                        ::std::format!("expected {:?}", Self::VALUE),
+                    )),
+                }
             }
         }
 
         impl ::poem_openapi::types::ToJSON for $name {
             fn to_json(&self) -> ::std::option::Option<poem_openapi::__private::serde_json::Value> {
                 ::std::option::Option::Some(::poem_openapi::__private::serde_json::Value::String(
-                    $str.into(),
+                    Self::VALUE.into(),
                 ))
             }
         }
+
+        $crate::__static_string_serde!($name);
     };
 }
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "serde")]
+macro_rules! __static_string_serde {
+    ($name:ident) => {
+        impl $crate::__private::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: $crate::__private::serde::Serializer,
+            {
+                serializer.serialize_str(<$name>::VALUE)
+            }
+        }
+
+        impl<'de> $crate::__private::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: $crate::__private::serde::Deserializer<'de>,
+            {
+                struct Visitor;
+
+                impl<'de> $crate::__private::serde::de::Visitor<'de> for Visitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                        ::std::write!(f, "{:?}", <$name>::VALUE)
+                    }
+
+                    fn visit_str<E>(self, value: &str) -> ::std::result::Result<Self::Value, E>
+                    where
+                        E: $crate::__private::serde::de::Error,
+                    {
+                        if value == <$name>::VALUE {
+                            ::std::result::Result::Ok($name)
+                        } else {
+                            ::std::result::Result::Err(E::invalid_value(
+                                $crate::__private::serde::de::Unexpected::Str(value),
+                                &self,
+                            ))
+                        }
+                    }
+                }
+
+                deserializer.deserialize_str(Visitor)
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "serde"))]
+macro_rules! __static_string_serde {
+    ($name:ident) => {};
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    static_string!(NotFound, "not_found");
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Error {
+        error: NotFound,
+    }
+
+    #[test]
+    fn serde() {
+        assert_eq!(
+            serde_json::to_string(&Error { error: NotFound }).unwrap(),
+            r#"{"error":"not_found"}"#
+        );
+        assert!(serde_json::from_str::<Error>(r#"{"error":"not_found"}"#).is_ok());
+        assert!(serde_json::from_str::<Error>(r#"{"error":"conflict"}"#).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! Contains an extractor that deserializes query parameters into a struct and
+//! rejects unknown or duplicated parameters.
+//!
+//! Query parameters that are not used by an endpoint are usually ignored, so
+//! typos like `?limt=10` go unnoticed by clients. The [`StrictQuery`]
+//! extractor deserializes all query parameters into an [`Object`] and responds
+//! with `422 Unprocessable Content` (see [`InvalidQueryParameters`]) if the
+//! query string contains parameters that are not fields of the object or
+//! parameters that occur more than once (except for fields with an array
+//! type). The response lists the offending parameters:
+//!
+//! ```json
+//! {"error": "invalid_query_parameters", "details": {"unknown": ["limt"], "duplicated": []}}
+//! ```
+//!
+//! The fields of the object are documented as query parameters. Values are
+//! converted according to the schema of the respective field, invalid values
+//! result in a `400 Bad Request` response (or a `422 Unprocessable Content`
+//! response if the endpoint uses the [`Response`](crate::responses::Response)
+//! type).
+//!
+//! #### Example
+//! ```
+//! use poem_ext::{
+//!     add_response_schemas,
+//!     responses::{InvalidQueryParameters, Response},
+//!     strict_query::StrictQuery,
+//! };
+//! use poem_openapi::{payload::Json, Object, OpenApi};
+//!
+//! #[derive(Debug, Object)]
+//! struct Search {
+//!     /// The search term.
+//!     q: String,
+//!     /// The maximum number of results.
+//!     limit: Option<u64>,
+//!     /// Only return results with these tags.
+//!     #[oai(default)]
+//!     tag: Vec<String>,
+//! }
+//!
+//! /// Marker type used to document the 422 response.
+//! struct Strict;
+//! add_response_schemas!(Strict, InvalidQueryParameters::raw::Response);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/search", method = "get")]
+//!     async fn search(&self, query: StrictQuery<Search>) -> Response<Json<Vec<String>>, Strict> {
+//!         Ok(Json(query.tag.clone()).into())
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    ops::{Deref, DerefMut},
+};
+
+use poem::{Request, RequestBody};
+use poem_openapi::{
+    error::ParseParamError,
+    registry::{MetaParamIn, MetaSchema, MetaSchemaRef, Registry},
+    types::{ParseFromJSON, Type},
+    ApiExtractor, ApiExtractorType, ExtractParamOptions, Object,
+};
+use serde_json::Value;
+
+use crate::responses::InvalidQueryParameters;
+
+/// Extractor that deserializes the query parameters of a request into `T` and
+/// rejects unknown or duplicated parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrictQuery<T>(pub T);
+
+impl<T> Deref for StrictQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for StrictQuery<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Details of an [`InvalidQueryParameters`] response.
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct InvalidQueryDetails {
+    /// The parameters that are not supported by the endpoint.
+    pub unknown: Vec<String>,
+    /// The parameters that may occur only once, but have been specified
+    /// multiple times.
+    pub duplicated: Vec<String>,
+}
+
+#[poem::async_trait]
+impl<'a, T: Type + ParseFromJSON> ApiExtractor<'a> for StrictQuery<T> {
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::Parameter];
+
+    type ParamType = T;
+    type ParamRawType = T::RawValueType;
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+
+    fn param_in() -> Option<MetaParamIn> {
+        Some(MetaParamIn::Query)
+    }
+
+    fn param_schema_ref() -> Option<MetaSchemaRef> {
+        Some(T::schema_ref())
+    }
+
+    fn param_raw_type(&self) -> Option<&Self::ParamRawType> {
+        self.0.as_raw_value()
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        _body: &mut RequestBody,
+        param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let mut registry = Registry::new();
+        T::register(&mut registry);
+        let schema_ref = T::schema_ref();
+        let fields = resolve(&registry, &schema_ref)
+            .map(|schema| schema.properties.as_slice())
+            .unwrap_or_default();
+
+        let mut values = BTreeMap::<String, Vec<String>>::new();
+        for (key, value) in request.params::<Vec<(String, String)>>()? {
+            values.entry(key).or_default().push(value);
+        }
+
+        let mut details = InvalidQueryDetails {
+            unknown: Vec::new(),
+            duplicated: Vec::new(),
+        };
+        let mut object = serde_json::Map::new();
+        for (key, values) in values {
+            let Some(schema) = fields
+                .iter()
+                .find(|(name, _)| *name == key)
+                .and_then(|(_, schema)| resolve(&registry, schema))
+            else {
+                details.unknown.push(key);
+                continue;
+            };
+            let value = if schema.ty == "array" {
+                let items = schema.items.as_deref().and_then(|x| resolve(&registry, x));
+                Value::Array(values.iter().map(|x| convert(items, x)).collect())
+            } else if let [value] = values.as_slice() {
+                convert(Some(schema), value)
+            } else {
+                details.duplicated.push(key);
+                continue;
+            };
+            object.insert(key, value);
+        }
+        if !details.unknown.is_empty() || !details.duplicated.is_empty() {
+            return Err(InvalidQueryParameters::raw::invalid_query_parameters(details).into());
+        }
+
+        T::parse_from_json(Some(Value::Object(object)))
+            .map(Self)
+            .map_err(|err| {
+                ParseParamError {
+                    name: param_opts.name,
+                    reason: err.into_message(),
+                }
+                .into()
+            })
+    }
+}
+
+/// Look up the schema a reference points to.
+fn resolve<'a>(registry: &'a Registry, schema: &'a MetaSchemaRef) -> Option<&'a MetaSchema> {
+    let schema = match schema {
+        MetaSchemaRef::Inline(schema) => schema,
+        MetaSchemaRef::Reference(name) => registry.schemas.get(name)?,
+    };
+    match schema.all_of.first() {
+        Some(inner) if schema.all_of.len() == 1 => resolve(registry, inner),
+        _ => Some(schema),
+    }
+}
+
+/// Convert the value of a query parameter to the JSON type of its schema.
+/// Values that cannot be converted are passed through as strings, so they are
+/// rejected when `T` is deserialized.
+fn convert(schema: Option<&MetaSchema>, value: &str) -> Value {
+    let converted = match schema.map(|schema| schema.ty) {
+        Some("integer" | "number") => serde_json::from_str(value).ok().map(Value::Number),
+        Some("boolean") => value.parse().ok().map(Value::Bool),
+        _ => None,
+    };
+    converted.unwrap_or_else(|| Value::String(value.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{http::StatusCode, test::TestClient};
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+
+    use super::*;
+    use crate::responses::Response;
+
+    #[derive(Debug, Object)]
+    struct Search {
+        q: String,
+        limit: Option<u64>,
+        #[oai(default)]
+        exact: bool,
+        #[oai(default)]
+        tag: Vec<String>,
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/search", method = "get")]
+        async fn search(&self, query: StrictQuery<Search>) -> Response<PlainText<String>> {
+            Ok(PlainText(format!(
+                "{} {:?} {} {}",
+                query.q,
+                query.limit,
+                query.exact,
+                query.tag.join(",")
+            ))
+            .into())
+        }
+    }
+
+    #[tokio::test]
+    async fn strict_query() {
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+
+        let resp = cli
+            .get("/search")
+            .query("q", &"foo")
+            .query("limit", &10)
+            .query("exact", &true)
+            .query("tag", &"a")
+            .query("tag", &"b")
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text("foo Some(10) true a,b").await;
+
+        let resp = cli.get("/search").query("q", &"foo").send().await;
+        resp.assert_text("foo None false ").await;
+
+        let resp = cli
+            .get("/search")
+            .query("q", &"foo")
+            .query("q", &"bar")
+            .query("limt", &10)
+            .send()
+            .await;
+        resp.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        resp.assert_text(
+            r#"{"details":{"duplicated":["q"],"unknown":["limt"]},"error":"invalid_query_parameters"}"#,
+        )
+        .await;
+
+        let resp = cli
+            .get("/search")
+            .query("q", &"foo")
+            .query("limit", &"ten")
+            .send()
+            .await;
+        resp.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        let body = resp.json().await;
+        body.value()
+            .object()
+            .get("error")
+            .assert_string("unprocessable_content");
+    }
+
+    #[test]
+    fn spec() {
+        let spec =
+            serde_json::from_str::<Value>(&OpenApiService::new(Api, "test", "1").spec()).unwrap();
+        let param = &spec["paths"]["/search"]["get"]["parameters"][0];
+        assert_eq!(param["in"], "query");
+        assert_eq!(param["schema"]["$ref"], "#/components/schemas/Search");
+    }
+}
@@ -0,0 +1,263 @@
+/// Construct an OpenApi union type whose variants are distinguished by a
+/// static string tag field.
+///
+/// The generated schema is a `oneOf` with an explicit discriminator mapping,
+/// so client generators can produce proper polymorphic types. In contrast to
+/// the [`Union`](derive@poem_openapi::Union) derive macro, the tag values can
+/// be arbitrary `&'static str` expressions (e.g. constants shared with other
+/// parts of the application) and the tag property of each variant is
+/// documented as an enum with exactly one value.
+///
+/// #### Example
+/// ```
+/// use poem_ext::tagged_union;
+/// use poem_openapi::{
+///     types::{ParseFromJSON, ToJSON},
+///     Object,
+/// };
+///
+/// mod error_codes {
+///     pub const NOT_FOUND: &str = "not_found";
+/// }
+///
+/// #[derive(Debug, Object)]
+/// struct NotFoundDetails {
+///     resource: String,
+/// }
+///
+/// #[derive(Debug, Object)]
+/// struct ConflictDetails {
+///     field: String,
+/// }
+///
+/// tagged_union!(pub ApiError, tag = "error" {
+///     NotFound(NotFoundDetails) = error_codes::NOT_FOUND,
+///     Conflict(ConflictDetails) = "conflict",
+/// });
+///
+/// let error = ApiError::NotFound(NotFoundDetails {
+///     resource: "user".into(),
+/// });
+/// assert_eq!(
+///     error.to_json_string(),
+///     r#"{"error":"not_found","resource":"user"}"#
+/// );
+///
+/// let error = ApiError::parse_from_json_string(r#"{"error":"conflict","field":"name"}"#).unwrap();
+/// assert!(matches!(error, ApiError::Conflict(ConflictDetails { field }) if field == "name"));
+/// assert!(ApiError::parse_from_json_string(r#"{"error":"unknown","field":"name"}"#).is_err());
+/// ```
+#[macro_export]
+macro_rules! tagged_union {
+    ($(#[$attr:meta])* $vis:vis $name:ident, tag = $tag:literal {
+        $(
+            $(#[doc = $doc:literal])*
+            $var:ident($ty:ty) = $value:expr
+        ),* $(,)?
+    }) => {
+        $(#[$attr])*
+        #[derive(::std::fmt::Debug)]
+        $vis enum $name {
+            $(
+                $(#[doc = $doc])*
+                $var($ty),
+            )*
+        }
+
+        impl $name {
+            /// Name of the tag field.
+            #[allow(dead_code)]
+            pub const TAG: &'static str = $tag;
+
+            /// Return the tag value of this variant.
+            #[allow(dead_code)]
+            pub fn tag(&self) -> &'static str {
+                match self {
+                    $(Self::$var(_) => $value,)*
+                }
+            }
+        }
+
+        impl ::poem_openapi::types::Type for $name {
+            const IS_REQUIRED: bool = true;
+
+            type RawValueType = Self;
+
+            type RawElementValueType = Self;
+
+            fn name() -> ::std::borrow::Cow<'static, str> {
+                ::std::stringify!($name).into()
+            }
+
+            fn schema_ref() -> ::poem_openapi::registry::MetaSchemaRef {
+                ::poem_openapi::registry::MetaSchemaRef::Reference(
+                    <Self as ::poem_openapi::types::Type>::name().into_owned(),
+                )
+            }
+
+            fn register(registry: &mut ::poem_openapi::registry::Registry) {
+                registry.create_schema::<Self, _>(
+                    <Self as ::poem_openapi::types::Type>::name().into_owned(),
+                    |registry| {
+                        let mut one_of = ::std::vec::Vec::new();
+                        let mut mapping = ::std::vec::Vec::new();
+                        $(
+                            <$ty as ::poem_openapi::types::Type>::register(registry);
+                            let schema_name = ::std::format!(
+                                "{}_{}",
+                                <Self as ::poem_openapi::types::Type>::name(),
+                                <$ty as ::poem_openapi::types::Type>::name(),
+                            );
+                            registry.schemas.insert(
+                                schema_name.clone(),
+                                ::poem_openapi::registry::MetaSchema {
+                                    all_of: ::std::vec![
+                                        ::poem_openapi::registry::MetaSchemaRef::Inline(::std::boxed::Box::new(
+                                            ::poem_openapi::registry::MetaSchema {
+                                                required: ::std::vec![Self::TAG],
+                                                properties: ::std::vec![(
+                                                    Self::TAG,
+                                                    ::poem_openapi::registry::MetaSchemaRef::Inline(::std::boxed::Box::new(
+                                                        ::poem_openapi::registry::MetaSchema {
+                                                            example: ::std::option::Option::Some($value.into()),
+                                                            enum_items: ::std::vec![$value.into()],
+                                                            ..::poem_openapi::registry::MetaSchema::new("string")
+                                                        },
+                                                    )),
+                                                )],
+                                                ..::poem_openapi::registry::MetaSchema::new("object")
+                                            },
+                                        )),
+                                        <$ty as ::poem_openapi::types::Type>::schema_ref(),
+                                    ],
+                                    ..::poem_openapi::registry::MetaSchema::ANY
+                                },
+                            );
+                            one_of.push(::poem_openapi::registry::MetaSchemaRef::Reference(schema_name.clone()));
+                            mapping.push((
+                                ::std::string::ToString::to_string($value),
+                                ::std::format!("#/components/schemas/{schema_name}"),
+                            ));
+                        )*
+                        ::poem_openapi::registry::MetaSchema {
+                            ty: "object",
+                            one_of,
+                            discriminator: ::std::option::Option::Some(
+                                ::poem_openapi::registry::MetaDiscriminatorObject {
+                                    property_name: Self::TAG,
+                                    mapping,
+                                },
+                            ),
+                            ..::poem_openapi::registry::MetaSchema::ANY
+                        }
+                    },
+                );
+            }
+
+            fn as_raw_value(&self) -> ::std::option::Option<&Self::RawValueType> {
+                ::std::option::Option::Some(self)
+            }
+
+            fn raw_element_iter<'a>(
+                &'a self,
+            ) -> ::std::boxed::Box<dyn ::std::iter::Iterator<Item = &'a Self::RawElementValueType> + 'a> {
+                ::std::boxed::Box::new(self.as_raw_value().into_iter())
+            }
+        }
+
+        impl ::poem_openapi::types::ParseFromJSON for $name {
+            fn parse_from_json(
+                value: ::std::option::Option<::poem_openapi::__private::serde_json::Value>,
+            ) -> ::poem_openapi::types::ParseResult<Self> {
+                let value = value.unwrap_or_default();
+                let tag = value
+                    .as_object()
+                    .and_then(|obj| obj.get(Self::TAG))
+                    .and_then(|tag| tag.as_str());
+                $(
+                    if tag == ::std::option::Option::Some($value) {
+                        return <$ty as ::poem_openapi::types::ParseFromJSON>::parse_from_json(
+                            ::std::option::Option::Some(value),
+                        )
+                        .map(Self::$var)
+                        .map_err(::poem_openapi::types::ParseError::propagate);
+                    }
+                )*
+                ::std::result::Result::Err(::poem_openapi::types::ParseError::custom(::std::format!(
+                    "expected {:?} to be one of {:?}",
+                    Self::TAG,
+                    [$($value),*]
+                )))
+            }
+        }
+
+        impl ::poem_openapi::types::ToJSON for $name {
+            fn to_json(&self) -> ::std::option::Option<::poem_openapi::__private::serde_json::Value> {
+                let mut value = match self {
+                    $(Self::$var(obj) => <$ty as ::poem_openapi::types::ToJSON>::to_json(obj),)*
+                };
+                if let ::std::option::Option::Some(obj) = value.as_mut().and_then(|value| value.as_object_mut()) {
+                    obj.insert(Self::TAG.into(), self.tag().into());
+                }
+                value
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use poem_openapi::{registry::Registry, types::Type, Object};
+
+    #[derive(Debug, Object)]
+    struct Circle {
+        radius: f64,
+    }
+
+    #[derive(Debug, Object)]
+    struct Square {
+        side: f64,
+    }
+
+    const CIRCLE: &str = "circle";
+
+    tagged_union!(Shape, tag = "kind" {
+        Circle(Circle) = CIRCLE,
+        Square(Square) = "square",
+    });
+
+    #[test]
+    fn schema() {
+        let mut registry = Registry::new();
+        Shape::register(&mut registry);
+
+        let schema = &registry.schemas["Shape"];
+        assert_eq!(schema.one_of.len(), 2);
+        let discriminator = schema.discriminator.as_ref().unwrap();
+        assert_eq!(discriminator.property_name, "kind");
+        assert_eq!(
+            discriminator.mapping,
+            [
+                (
+                    "circle".to_owned(),
+                    "#/components/schemas/Shape_Circle".to_owned()
+                ),
+                (
+                    "square".to_owned(),
+                    "#/components/schemas/Shape_Square".to_owned()
+                ),
+            ]
+        );
+
+        let variant = &registry.schemas["Shape_Square"];
+        let poem_openapi::registry::MetaSchemaRef::Inline(tag) = &variant.all_of[0] else {
+            unreachable!()
+        };
+        assert_eq!(tag.required, ["kind"]);
+        let poem_openapi::registry::MetaSchemaRef::Inline(kind) = &tag.properties[0].1 else {
+            unreachable!()
+        };
+        assert_eq!(kind.enum_items, ["square"]);
+        assert!(registry.schemas.contains_key("Square"));
+    }
+}
@@ -0,0 +1,819 @@
+//! Contains utilities for snapshot and contract tests of OpenAPI documents.
+//!
+//! [`assert_spec_snapshot`] compares an OpenAPI document with a baseline that
+//! is committed to the repository and fails with a summary of the added,
+//! removed and changed operations and schemas if they differ. Missing
+//! snapshots are created automatically, existing snapshots are updated if the
+//! [`UPDATE_SNAPSHOTS_ENV`] environment variable is set.
+//!
+//! [`SpecLint`] checks that all operations document the status codes that the
+//! middlewares of the application may produce.
+//!
+//! [`TestClientExt`], [`TestRequestBuilderExt`] and [`TestResponseExt`] add
+//! helpers for authenticated requests and for checking the JSON error
+//! responses of this crate to poem's [`TestClient`].
+//!
+//! #### Example
+//! ```no_run
+//! use poem::{test::TestClient, Route};
+//! use poem_ext::test::{assert_served_spec_snapshot, assert_spec_snapshot};
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> PlainText<&'static str> {
+//!         PlainText("Hello World!")
+//!     }
+//! }
+//!
+//! # async {
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! assert_spec_snapshot(&api_service.spec(), "tests/openapi.json");
+//!
+//! // or compare the document that is served by the application
+//! let spec = api_service.spec_endpoint();
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .at("/openapi.json", spec);
+//! let cli = TestClient::new(app);
+//! assert_served_spec_snapshot(&cli, "/openapi.json", "tests/openapi.json").await;
+//! # };
+//! ```
+
+use std::{collections::BTreeSet, fmt::Display, path::Path, sync::Arc};
+
+use poem::{
+    http::header::AUTHORIZATION,
+    test::{TestClient, TestRequestBuilder, TestResponse},
+    Endpoint,
+};
+use serde_json::{Map, Value};
+
+/// Name of the environment variable that causes [`assert_spec_snapshot`] to
+/// overwrite existing snapshots instead of comparing them.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "UPDATE_SPEC_SNAPSHOTS";
+
+/// The differences between two OpenAPI documents.
+///
+/// Operations are identified by their method and path (e.g. `GET /users`),
+/// schemas by their name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpecDiff {
+    /// Operations that only exist in the new document.
+    pub added_operations: Vec<String>,
+    /// Operations that only exist in the old document.
+    pub removed_operations: Vec<String>,
+    /// Operations that exist in both documents but are not equal.
+    pub changed_operations: Vec<String>,
+    /// Schemas that only exist in the new document.
+    pub added_schemas: Vec<String>,
+    /// Schemas that only exist in the old document.
+    pub removed_schemas: Vec<String>,
+    /// Schemas that exist in both documents but are not equal.
+    pub changed_schemas: Vec<String>,
+    /// Other parts of the documents that are not equal (e.g. `info` or
+    /// `components.securitySchemes`).
+    pub changed_other: Vec<String>,
+}
+
+impl SpecDiff {
+    /// Compare two OpenAPI documents.
+    pub fn new(old: &Value, new: &Value) -> Self {
+        let mut diff = Self::default();
+
+        diff_maps(
+            &operations(old),
+            &operations(new),
+            &mut diff.added_operations,
+            &mut diff.removed_operations,
+            &mut diff.changed_operations,
+        );
+        diff_maps(
+            &schemas(old),
+            &schemas(new),
+            &mut diff.added_schemas,
+            &mut diff.removed_schemas,
+            &mut diff.changed_schemas,
+        );
+
+        diff.changed_other = changed_keys(old, new, &["paths", "components"], "");
+        diff.changed_other.extend(changed_keys(
+            old.get("components").unwrap_or(&Value::Null),
+            new.get("components").unwrap_or(&Value::Null),
+            &["schemas"],
+            "components.",
+        ));
+
+        diff
+    }
+
+    /// Parse and compare two OpenAPI documents in JSON format.
+    pub fn parse(old: &str, new: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self::new(
+            &serde_json::from_str(old)?,
+            &serde_json::from_str(new)?,
+        ))
+    }
+
+    /// Return whether the documents are equal.
+    pub fn is_empty(&self) -> bool {
+        self.added_operations.is_empty()
+            && self.removed_operations.is_empty()
+            && self.changed_operations.is_empty()
+            && self.added_schemas.is_empty()
+            && self.removed_schemas.is_empty()
+            && self.changed_schemas.is_empty()
+            && self.changed_other.is_empty()
+    }
+}
+
+impl Display for SpecDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (prefix, kind, items) in [
+            ('+', "operation", &self.added_operations),
+            ('-', "operation", &self.removed_operations),
+            ('~', "operation", &self.changed_operations),
+            ('+', "schema", &self.added_schemas),
+            ('-', "schema", &self.removed_schemas),
+            ('~', "schema", &self.changed_schemas),
+            ('~', "other", &self.changed_other),
+        ] {
+            for item in items {
+                writeln!(f, "{prefix} {kind} {item}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Assert that an OpenAPI document in JSON format matches the snapshot at
+/// `path`.
+///
+/// If the snapshot does not exist yet or the [`UPDATE_SNAPSHOTS_ENV`]
+/// environment variable is set, the snapshot is (over)written instead.
+///
+/// # Panics
+/// Panics if the document does not match the snapshot or if the snapshot
+/// cannot be read or written.
+#[track_caller]
+pub fn assert_spec_snapshot(spec: &str, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    let spec: Value = serde_json::from_str(spec).expect("invalid OpenAPI document");
+
+    if !path.exists() || std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some() {
+        let mut content = serde_json::to_string_pretty(&spec).unwrap();
+        content.push('\n');
+        if let Err(err) = std::fs::write(path, content) {
+            panic!("failed to write snapshot `{}`: {err}", path.display());
+        }
+        return;
+    }
+
+    let baseline = match std::fs::read_to_string(path) {
+        Ok(baseline) => baseline,
+        Err(err) => panic!("failed to read snapshot `{}`: {err}", path.display()),
+    };
+    let baseline: Value = serde_json::from_str(&baseline).expect("invalid snapshot");
+    if baseline != spec {
+        panic!(
+            "OpenAPI document does not match snapshot `{}`:\n{}\nSet {UPDATE_SNAPSHOTS_ENV}=1 to update the snapshot.",
+            path.display(),
+            SpecDiff::new(&baseline, &spec)
+        );
+    }
+}
+
+/// Fetch the OpenAPI document that is served at `path`.
+///
+/// # Panics
+/// Panics if the response is not successful.
+pub async fn fetch_spec<E: Endpoint>(cli: &TestClient<E>, path: &str) -> String {
+    let resp = cli.get(path).send().await;
+    resp.assert_status_is_ok();
+    resp.0.into_body().into_string().await.unwrap()
+}
+
+/// Assert that the OpenAPI document that is served at `path` matches the
+/// snapshot at `snapshot` (see [`assert_spec_snapshot`]).
+pub async fn assert_served_spec_snapshot<E: Endpoint>(
+    cli: &TestClient<E>,
+    path: &str,
+    snapshot: impl AsRef<Path>,
+) {
+    assert_spec_snapshot(&fetch_spec(cli, path).await, snapshot);
+}
+
+/// Extension trait for [`TestClient`].
+pub trait TestClientExt {
+    /// Send the given bearer token in the `Authorization` header of all
+    /// requests.
+    fn bearer(self, token: impl Display) -> Self;
+}
+
+impl<E: Endpoint> TestClientExt for TestClient<E> {
+    fn bearer(self, token: impl Display) -> Self {
+        self.default_header(AUTHORIZATION, format!("Bearer {token}"))
+    }
+}
+
+/// Extension trait for [`TestRequestBuilder`].
+pub trait TestRequestBuilderExt {
+    /// Send the given bearer token in the `Authorization` header.
+    fn bearer(self, token: impl Display) -> Self;
+}
+
+impl<E: Endpoint> TestRequestBuilderExt for TestRequestBuilder<'_, E> {
+    fn bearer(self, token: impl Display) -> Self {
+        self.header(AUTHORIZATION, format!("Bearer {token}"))
+    }
+}
+
+/// Extension trait for [`TestResponse`] that understands the JSON error
+/// responses of this crate (e.g. `{"error": "conflict"}`).
+///
+/// #### Example
+/// ```
+/// use poem::{http::StatusCode, test::TestClient};
+/// use poem_ext::{
+///     response,
+///     test::{TestRequestBuilderExt, TestResponseExt},
+/// };
+/// use poem_openapi::{auth::Bearer, param::Query, OpenApi, OpenApiService, SecurityScheme};
+///
+/// #[derive(SecurityScheme)]
+/// #[oai(ty = "bearer")]
+/// struct Auth(Bearer);
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/users", method = "post")]
+///     async fn create_user(&self, _auth: Auth, email: Query<String>) -> CreateUser::Response {
+///         match email.0.as_str() {
+///             "taken@example.com" => CreateUser::conflict(),
+///             _ => CreateUser::ok(),
+///         }
+///     }
+/// }
+///
+/// response!(CreateUser = {
+///     Ok(201),
+///     Conflict(409, error),
+/// });
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let cli = TestClient::new(OpenApiService::new(Api, "Test", "0.1.0"));
+///
+/// cli.post("/users")
+///     .bearer("secret")
+///     .query("email", &"taken@example.com")
+///     .send()
+///     .await
+///     .assert_error("conflict")
+///     .await;
+///
+/// cli.post("/users")
+///     .bearer("secret")
+///     .query("email", &"new@example.com")
+///     .send()
+///     .await
+///     .assert_status(StatusCode::CREATED);
+///
+/// cli.post("/users")
+///     .bearer("secret")
+///     .send()
+///     .await
+///     .assert_unprocessable_field("email")
+///     .await;
+/// # });
+/// ```
+#[poem::async_trait]
+pub trait TestResponseExt {
+    /// Assert that the response is an error response with the given error
+    /// code (the `error` field of the body) and return the body.
+    async fn assert_error(self, error: &str) -> Value;
+
+    /// Assert that the response is a `422 Unprocessable Content` response
+    /// that refers to the given field or parameter (either in its `reason` or
+    /// in its `details`) and return the body.
+    async fn assert_unprocessable_field(self, field: &str) -> Value;
+}
+
+#[poem::async_trait]
+impl TestResponseExt for TestResponse {
+    async fn assert_error(self, error: &str) -> Value {
+        let status = self.0.status();
+        let body = self.0.into_body().into_string().await.unwrap();
+        let value = serde_json::from_str::<Value>(&body).unwrap_or_default();
+        let actual = value.get("error").and_then(Value::as_str);
+        if !(status.is_client_error() || status.is_server_error()) || actual != Some(error) {
+            panic!("expected error `{error}`, got {status}: {body}");
+        }
+        value
+    }
+
+    async fn assert_unprocessable_field(self, field: &str) -> Value {
+        let status = self.0.status();
+        let body = self.0.into_body().into_string().await.unwrap();
+        let value = serde_json::from_str::<Value>(&body).unwrap_or_default();
+        if status.as_u16() != 422 || !mentions_field(&value, field) {
+            panic!("expected 422 response for field `{field}`, got {status}: {body}");
+        }
+        value
+    }
+}
+
+/// Return whether the `reason` or `details` of an error response refer to the
+/// given field.
+fn mentions_field(value: &Value, field: &str) -> bool {
+    fn contains(value: &Value, field: &str) -> bool {
+        match value {
+            Value::String(x) => x == field || x.contains(&format!("`{field}`")),
+            Value::Array(x) => x.iter().any(|x| contains(x, field)),
+            Value::Object(x) => x.iter().any(|(k, v)| k == field || contains(v, field)),
+            _ => false,
+        }
+    }
+    ["reason", "details"]
+        .iter()
+        .filter_map(|key| value.get(key))
+        .any(|x| contains(x, field))
+}
+
+/// An operation of an OpenAPI document that is checked by a [`SpecLint`].
+#[derive(Debug, Clone, Copy)]
+pub struct LintOperation<'a> {
+    /// The uppercase http method of the operation.
+    pub method: &'a str,
+    /// The path of the operation.
+    pub path: &'a str,
+    /// The operation object.
+    pub operation: &'a Value,
+}
+
+impl LintOperation<'_> {
+    /// Return whether the operation requires authentication.
+    pub fn is_secured(&self) -> bool {
+        self.operation
+            .get("security")
+            .and_then(Value::as_array)
+            .is_some_and(|security| !security.is_empty())
+    }
+
+    /// Return whether the operation uses an unsafe method (i.e. neither
+    /// `GET`, `HEAD`, `OPTIONS` nor `TRACE`).
+    pub fn is_unsafe(&self) -> bool {
+        !matches!(self.method, "GET" | "HEAD" | "OPTIONS" | "TRACE")
+    }
+
+    /// Return whether the given status code is documented for this operation
+    /// (either explicitly, by a range like `4XX` or by a `default` response).
+    pub fn documents(&self, status: u16) -> bool {
+        let Some(responses) = self.operation.get("responses").and_then(Value::as_object) else {
+            return false;
+        };
+        [
+            status.to_string(),
+            format!("{}XX", status / 100),
+            "default".into(),
+        ]
+        .iter()
+        .any(|key| responses.contains_key(key))
+    }
+}
+
+/// A status code that may be returned by an operation but is not documented.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndocumentedStatus {
+    /// The operation (e.g. `GET /users`).
+    pub operation: String,
+    /// The undocumented status code.
+    pub status: u16,
+    /// The component that may produce the status code (e.g. `rate limit`).
+    pub source: String,
+}
+
+impl Display for UndocumentedStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} ({})", self.operation, self.status, self.source)
+    }
+}
+
+type LintFilter = Arc<dyn Fn(&LintOperation<'_>) -> bool + Send + Sync>;
+
+struct LintRule {
+    status: u16,
+    source: String,
+    filter: LintFilter,
+}
+
+/// Linter that checks that the operations of an OpenAPI document document the
+/// status codes that may be produced by the middlewares of the application.
+///
+/// #### Example
+/// ```
+/// use poem_ext::{
+///     add_response_schemas,
+///     responses::{Response, TooManyRequests},
+///     test::SpecLint,
+/// };
+/// use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+///
+/// struct RateLimited;
+/// add_response_schemas!(RateLimited, TooManyRequests::raw::Response);
+///
+/// struct Api;
+///
+/// #[OpenApi]
+/// impl Api {
+///     #[oai(path = "/test", method = "get")]
+///     async fn test(&self) -> Response<PlainText<&'static str>, RateLimited> {
+///         Ok(PlainText("Hello World!").into())
+///     }
+/// }
+///
+/// let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+/// SpecLint::new()
+///     .rate_limit()
+///     .require(500, "internal server error")
+///     .assert(&api_service.spec());
+/// ```
+#[derive(Default)]
+pub struct SpecLint {
+    rules: Vec<LintRule>,
+}
+
+impl std::fmt::Debug for SpecLint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpecLint").finish_non_exhaustive()
+    }
+}
+
+impl SpecLint {
+    /// Create a new linter without any rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require all operations to document the given status code, which may be
+    /// produced by `source`.
+    pub fn require(self, status: u16, source: impl Into<String>) -> Self {
+        self.require_if(status, source, |_| true)
+    }
+
+    /// Require all operations that match `filter` to document the given
+    /// status code, which may be produced by `source`.
+    pub fn require_if(
+        mut self,
+        status: u16,
+        source: impl Into<String>,
+        filter: impl Fn(&LintOperation<'_>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.rules.push(LintRule {
+            status,
+            source: source.into(),
+            filter: Arc::new(filter),
+        });
+        self
+    }
+
+    /// Require all operations to document the `429 Too Many Requests`
+    /// response of the [`RateLimitMiddleware`](crate::rate_limit::RateLimitMiddleware).
+    pub fn rate_limit(self) -> Self {
+        self.require(429, "rate limit")
+    }
+
+    /// Require all operations that require authentication to document the
+    /// `401 Unauthorized` and `403 Forbidden` responses of the authentication
+    /// checker (see [`custom_auth!`](crate::custom_auth!)).
+    pub fn auth(self) -> Self {
+        self.require_if(401, "auth", |op| op.is_secured())
+            .require_if(403, "auth", |op| op.is_secured())
+    }
+
+    /// Require all operations with unsafe methods to document the
+    /// `403 Forbidden` response of the [`CsrfMiddleware`](crate::csrf::CsrfMiddleware).
+    pub fn csrf(self) -> Self {
+        self.require_if(403, "csrf", |op| op.is_unsafe())
+    }
+
+    /// Require all operations to document the `503 Service Unavailable`
+    /// response of the [`ConcurrencyLimitMiddleware`](crate::concurrency_limit::ConcurrencyLimitMiddleware).
+    pub fn concurrency_limit(self) -> Self {
+        self.require(503, "concurrency limit")
+    }
+
+    /// Require all operations to document the `504 Gateway Timeout` response
+    /// of the [`TimeoutMiddleware`](crate::timeout::TimeoutMiddleware).
+    pub fn timeout(self) -> Self {
+        self.require(504, "timeout")
+    }
+
+    /// Check the OpenAPI document in JSON format and return all undocumented
+    /// status codes.
+    pub fn check(&self, spec: &str) -> Result<Vec<UndocumentedStatus>, serde_json::Error> {
+        let spec: Value = serde_json::from_str(spec)?;
+        let mut result = Vec::new();
+        for (path, item) in spec
+            .get("paths")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .filter_map(|(path, item)| Some((path, item.as_object()?)))
+        {
+            for (method, operation) in item {
+                let method = method.to_uppercase();
+                let op = LintOperation {
+                    method: &method,
+                    path,
+                    operation,
+                };
+                for rule in &self.rules {
+                    if (rule.filter)(&op) && !op.documents(rule.status) {
+                        result.push(UndocumentedStatus {
+                            operation: format!("{method} {path}"),
+                            status: rule.status,
+                            source: rule.source.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Assert that the OpenAPI document in JSON format does not contain any
+    /// undocumented status codes.
+    ///
+    /// # Panics
+    /// Panics if the document is invalid or contains undocumented status
+    /// codes.
+    #[track_caller]
+    pub fn assert(&self, spec: &str) {
+        let undocumented = self.check(spec).expect("invalid OpenAPI document");
+        if !undocumented.is_empty() {
+            panic!(
+                "OpenAPI document contains undocumented status codes:\n{}",
+                undocumented
+                    .iter()
+                    .map(|x| format!("  {x}\n"))
+                    .collect::<String>()
+            );
+        }
+    }
+}
+
+fn operations(spec: &Value) -> Map<String, Value> {
+    spec.get("paths")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .filter_map(|(path, item)| Some((path, item.as_object()?)))
+        .flat_map(|(path, item)| {
+            item.iter().map(move |(method, operation)| {
+                (
+                    format!("{} {path}", method.to_uppercase()),
+                    operation.clone(),
+                )
+            })
+        })
+        .collect()
+}
+
+fn schemas(spec: &Value) -> Map<String, Value> {
+    spec.pointer("/components/schemas")
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn diff_maps(
+    old: &Map<String, Value>,
+    new: &Map<String, Value>,
+    added: &mut Vec<String>,
+    removed: &mut Vec<String>,
+    changed: &mut Vec<String>,
+) {
+    for (key, value) in new {
+        match old.get(key) {
+            None => added.push(key.clone()),
+            Some(old) if old != value => changed.push(key.clone()),
+            Some(_) => {}
+        }
+    }
+    removed.extend(old.keys().filter(|key| !new.contains_key(*key)).cloned());
+}
+
+fn changed_keys(old: &Value, new: &Value, ignore: &[&str], prefix: &str) -> Vec<String> {
+    let empty = Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+    old.keys()
+        .chain(new.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|key| !ignore.contains(&key.as_str()) && old.get(*key) != new.get(*key))
+        .map(|key| format!("{prefix}{key}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::Route;
+    use poem_openapi::{
+        payload::{Json, PlainText},
+        Object, OpenApi, OpenApiService,
+    };
+
+    use super::*;
+
+    #[derive(Object)]
+    struct Foo {
+        foo: i32,
+    }
+
+    #[derive(Object)]
+    #[oai(rename = "Foo")]
+    struct Foo2 {
+        foo: String,
+    }
+
+    #[derive(Object)]
+    struct Bar {
+        bar: i32,
+    }
+
+    struct Old;
+
+    #[OpenApi]
+    impl Old {
+        #[oai(path = "/foo", method = "get")]
+        async fn get_foo(&self) -> Json<Foo> {
+            unimplemented!()
+        }
+
+        #[oai(path = "/foo", method = "delete")]
+        async fn delete_foo(&self) -> PlainText<&'static str> {
+            unimplemented!()
+        }
+    }
+
+    struct New;
+
+    #[OpenApi]
+    impl New {
+        #[oai(path = "/foo", method = "get")]
+        async fn get_foo(&self) -> Json<Foo2> {
+            unimplemented!()
+        }
+
+        #[oai(path = "/bar", method = "post")]
+        async fn create_bar(&self) -> Json<Bar> {
+            unimplemented!()
+        }
+    }
+
+    fn snapshot_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("poem-ext-{}.json", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn diff() {
+        let old = OpenApiService::new(Old, "test", "1").spec();
+        let new = OpenApiService::new(New, "test", "2").spec();
+
+        let diff = SpecDiff::parse(&old, &old).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "");
+
+        let diff = SpecDiff::parse(&old, &new).unwrap();
+        assert!(!diff.is_empty());
+        assert_eq!(diff.added_operations, ["POST /bar"]);
+        assert_eq!(diff.removed_operations, ["DELETE /foo"]);
+        assert_eq!(diff.changed_operations, Vec::<String>::new());
+        assert_eq!(diff.added_schemas, ["Bar"]);
+        assert_eq!(diff.removed_schemas, Vec::<String>::new());
+        assert_eq!(diff.changed_schemas, ["Foo"]);
+        assert_eq!(diff.changed_other, ["info"]);
+        assert_eq!(
+            diff.to_string(),
+            "+ operation POST /bar\n- operation DELETE /foo\n+ schema Bar\n~ schema Foo\n~ other info\n"
+        );
+    }
+
+    #[test]
+    fn snapshot() {
+        let path = snapshot_path();
+        let spec = OpenApiService::new(Old, "test", "1").spec();
+        assert_spec_snapshot(&spec, &path);
+        assert!(path.exists());
+        assert_spec_snapshot(&spec, &path);
+
+        let new = OpenApiService::new(New, "test", "1").spec();
+        let err = std::panic::catch_unwind(|| assert_spec_snapshot(&new, &path)).unwrap_err();
+        let err = err.downcast_ref::<String>().unwrap();
+        assert!(err.contains("+ operation POST /bar\n- operation DELETE /foo\n"));
+        assert!(err.contains(UPDATE_SNAPSHOTS_ENV));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn served_snapshot() {
+        let path = snapshot_path();
+        let api_service = OpenApiService::new(Old, "test", "1");
+        std::fs::write(&path, api_service.spec()).unwrap();
+
+        let spec = api_service.spec_endpoint();
+        let cli = TestClient::new(
+            Route::new()
+                .nest("/", api_service)
+                .at("/openapi.json", spec),
+        );
+        assert_served_spec_snapshot(&cli, "/openapi.json", &path).await;
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_ext() {
+        #[poem::handler]
+        fn echo(req: &poem::Request) -> poem::Response {
+            let auth = req.header(AUTHORIZATION).unwrap_or_default().to_owned();
+            poem::Response::builder()
+                .status(poem::http::StatusCode::CONFLICT)
+                .body(
+                    serde_json::json!({"error": "conflict", "details": {"auth": auth}}).to_string(),
+                )
+        }
+
+        let cli = TestClient::new(echo).bearer("foo");
+        let body = cli.get("/").send().await.assert_error("conflict").await;
+        assert_eq!(body["details"]["auth"], "Bearer foo");
+        let body = cli
+            .get("/")
+            .bearer("bar")
+            .send()
+            .await
+            .assert_error("conflict")
+            .await;
+        assert_eq!(body["details"]["auth"], "Bearer bar");
+
+        let err = tokio::spawn(async move {
+            cli.get("/").send().await.assert_error("not_found").await;
+        })
+        .await
+        .unwrap_err();
+        assert!(err.is_panic());
+
+        let field = |value| mentions_field(&value, "email");
+        assert!(field(
+            serde_json::json!({"reason": "failed to parse parameter `email`"})
+        ));
+        assert!(field(
+            serde_json::json!({"details": {"unknown": ["email"]}})
+        ));
+        assert!(field(serde_json::json!({"details": {"email": "invalid"}})));
+        assert!(!field(serde_json::json!({"reason": "invalid emails"})));
+        assert!(!field(serde_json::json!({"error": "email"})));
+    }
+
+    #[test]
+    fn lint() {
+        let spec = serde_json::json!({
+            "paths": {
+                "/public": {
+                    "get": {"responses": {"200": {}, "429": {}}},
+                    "post": {"responses": {"200": {}, "4XX": {}}},
+                },
+                "/secret": {
+                    "get": {"security": [{"Auth": []}], "responses": {"200": {}, "401": {}}},
+                    "delete": {"security": [{"Auth": []}], "responses": {"default": {}}},
+                },
+            }
+        })
+        .to_string();
+
+        let lint = SpecLint::new().rate_limit().auth().csrf();
+        assert_eq!(
+            lint.check(&spec)
+                .unwrap()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["GET /secret: 429 (rate limit)", "GET /secret: 403 (auth)",]
+        );
+        assert_eq!(SpecLint::new().timeout().check(&spec).unwrap().len(), 3);
+
+        let err = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| lint.assert(&spec)))
+            .unwrap_err();
+        assert!(err
+            .downcast_ref::<String>()
+            .unwrap()
+            .ends_with("  GET /secret: 429 (rate limit)\n  GET /secret: 403 (auth)\n"));
+    }
+}
@@ -0,0 +1,175 @@
+//! Contains a middleware that aborts requests which take too long.
+//!
+//! If a handler does not complete within its deadline, it is canceled and a
+//! [`GatewayTimeout`] response is returned. Handlers that are protected by the
+//! [`ShieldMiddleware`](crate::shield_mw::ShieldMiddleware) (i.e. the
+//! `TimeoutMiddleware` is applied after the `ShieldMiddleware`) are not
+//! canceled but detached instead: the client receives the `504` response while
+//! the handler keeps running in the background.
+//!
+//! #### Example
+//! ```
+//! use std::time::Duration;
+//!
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     add_response_schemas,
+//!     responses::{GatewayTimeout, Response},
+//!     timeout::TimeoutMiddleware,
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! /// Marker type used to document the 504 response.
+//! struct Timeout;
+//! add_response_schemas!(Timeout, GatewayTimeout::raw::Response);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/test", method = "get")]
+//!     async fn test(&self) -> Response<PlainText<&'static str>, Timeout> {
+//!         Ok(PlainText("Hello World!").into())
+//!     }
+//!
+//!     #[oai(path = "/reports", method = "post")]
+//!     async fn create_report(&self) -> Response<PlainText<&'static str>, Timeout> {
+//!         Ok(PlainText("Hello World!").into())
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new().nest("/", api_service).with(
+//!     TimeoutMiddleware::new(Duration::from_secs(10))
+//!         // reports may take longer
+//!         .with_path_timeout("/reports", Duration::from_secs(60)),
+//! );
+//! ```
+
+use std::time::Duration;
+
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response};
+
+use crate::{responses::GatewayTimeout, utils::path_has_prefix};
+
+/// A middleware that aborts requests which don't complete within a
+/// configurable duration.
+#[derive(Debug, Clone)]
+pub struct TimeoutMiddleware {
+    timeout: Option<Duration>,
+    path_timeouts: Vec<(String, Option<Duration>)>,
+}
+
+impl TimeoutMiddleware {
+    /// Create a new TimeoutMiddleware that aborts requests after the given
+    /// `timeout`.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            path_timeouts: Vec::new(),
+        }
+    }
+
+    /// Use a different timeout for requests whose path starts with the given
+    /// prefix.
+    ///
+    /// If multiple prefixes match a request, the longest one is used.
+    pub fn with_path_timeout(mut self, prefix: impl Into<String>, timeout: Duration) -> Self {
+        self.path_timeouts.push((prefix.into(), Some(timeout)));
+        self
+    }
+
+    /// Disable the timeout for requests whose path starts with the given
+    /// prefix (e.g. for streaming endpoints).
+    pub fn without_timeout_for(mut self, prefix: impl Into<String>) -> Self {
+        self.path_timeouts.push((prefix.into(), None));
+        self
+    }
+
+    fn timeout_for(&self, req: &Request) -> Option<Duration> {
+        self.path_timeouts
+            .iter()
+            .filter(|(prefix, _)| path_has_prefix(req.uri().path(), prefix))
+            .max_by_key(|(prefix, _)| prefix.trim_end_matches('/').len())
+            .map_or(self.timeout, |(_, timeout)| *timeout)
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for TimeoutMiddleware {
+    type Output = TimeoutMwEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        TimeoutMwEndpoint {
+            inner: ep,
+            config: self.clone(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct TimeoutMwEndpoint<E> {
+    inner: E,
+    config: TimeoutMiddleware,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for TimeoutMwEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let Some(timeout) = self.config.timeout_for(&req) else {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        };
+        match tokio::time::timeout(timeout, self.inner.call(req)).await {
+            Ok(resp) => resp.map(IntoResponse::into_response),
+            Err(_) => Ok(GatewayTimeout::raw::gateway_timeout().into_response()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, http::StatusCode, test::TestClient, EndpointExt, Route};
+
+    use super::*;
+
+    #[handler]
+    async fn slow() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        "done"
+    }
+
+    fn app() -> impl Endpoint {
+        Route::new()
+            .at("/fast", slow)
+            .at("/slow", slow)
+            .at("/slow/none", slow)
+            .with(
+                TimeoutMiddleware::new(Duration::from_secs(5))
+                    .with_path_timeout("/slow", Duration::from_millis(10))
+                    .without_timeout_for("/slow/none"),
+            )
+    }
+
+    #[tokio::test]
+    async fn completes_before_deadline() {
+        let resp = TestClient::new(app()).get("/fast").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("done").await;
+    }
+
+    #[tokio::test]
+    async fn deadline_exceeded() {
+        let resp = TestClient::new(app()).get("/slow").send().await;
+        resp.assert_status(StatusCode::GATEWAY_TIMEOUT);
+        resp.assert_text(r#"{"error":"gateway_timeout"}"#).await;
+    }
+
+    #[tokio::test]
+    async fn timeout_disabled() {
+        let resp = TestClient::new(app()).get("/slow/none").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("done").await;
+    }
+}
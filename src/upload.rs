@@ -0,0 +1,413 @@
+//! Contains helpers for validating uploaded files.
+//!
+//! Files are uploaded using the [`Upload`] type of poem-openapi in a
+//! [`Multipart`](derive@poem_openapi::Multipart) request body, which is
+//! documented as a `multipart/form-data` request with a binary field. The
+//! endpoint then passes the upload to [`UploadPolicy::validate`], which
+//! - rejects files that exceed the maximum size with `413 Payload Too Large`,
+//! - detects the actual type of the file from its first bytes (see
+//!   [`sniff_content_type`]) and rejects files whose type is not allowed or
+//!   does not match the declared content type with
+//!   `415 Unsupported Media Type`,
+//! - rejects empty files with `422 Unprocessable Content` and
+//! - [sanitizes](sanitize_file_name) the file name.
+//!
+//! #### Example
+//! ```
+//! use poem_ext::{
+//!     response,
+//!     responses::UploadRejected,
+//!     upload::UploadPolicy,
+//! };
+//! use poem_openapi::{types::multipart::Upload, Multipart, OpenApi};
+//!
+//! #[derive(Debug, Multipart)]
+//! struct UploadAvatar {
+//!     file: Upload,
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/avatar", method = "put")]
+//!     async fn upload_avatar(
+//!         &self,
+//!         data: UploadAvatar,
+//!     ) -> Result<UploadAvatarResponse::raw::Response, UploadRejected::raw::Response> {
+//!         let file = UploadPolicy::new()
+//!             .with_max_size(1 << 20)
+//!             .with_allowed_types(["image/png", "image/jpeg"])
+//!             .validate(data.file)
+//!             .await?;
+//!         // store file.data
+//!         Ok(UploadAvatarResponse::raw::ok())
+//!     }
+//! }
+//!
+//! response!(UploadAvatarResponse = {
+//!     Ok(200),
+//! });
+//! ```
+
+use poem_openapi::{types::multipart::Upload, Object};
+use tokio::io::AsyncReadExt;
+
+use crate::responses::UploadRejected;
+
+/// Details of a [`FileTooLarge`](UploadRejected::raw::file_too_large)
+/// response.
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct FileTooLargeDetails {
+    /// The maximum size of a file in bytes.
+    pub max_size: u64,
+}
+
+/// Details of an
+/// [`UnsupportedFileType`](UploadRejected::raw::unsupported_file_type)
+/// response.
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct UnsupportedFileTypeDetails {
+    /// The allowed content types.
+    pub allowed: Vec<String>,
+}
+
+/// A validated uploaded file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadedFile {
+    /// The sanitized file name, if the client provided a valid one.
+    pub file_name: Option<String>,
+    /// The detected or (if it could not be detected) declared content type.
+    pub content_type: Option<String>,
+    /// The contents of the file.
+    pub data: Vec<u8>,
+}
+
+/// Rules for validating uploaded files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UploadPolicy {
+    max_size: Option<usize>,
+    allowed_types: Vec<String>,
+}
+
+impl UploadPolicy {
+    /// Create a new UploadPolicy that accepts all non-empty files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum size of a file in bytes.
+    pub fn with_max_size(self, max_size: usize) -> Self {
+        Self {
+            max_size: Some(max_size),
+            ..self
+        }
+    }
+
+    /// Only accept files with one of the given content types.
+    pub fn with_allowed_types<I>(mut self, types: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.allowed_types.extend(types.into_iter().map(Into::into));
+        self
+    }
+
+    /// Read and validate an uploaded file.
+    pub async fn validate(
+        &self,
+        upload: Upload,
+    ) -> Result<UploadedFile, UploadRejected::raw::Response> {
+        let file_name = upload.file_name().and_then(sanitize_file_name);
+        let declared = upload
+            .content_type()
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|essence| essence.trim().to_ascii_lowercase())
+            .filter(|essence| !essence.is_empty() && essence != "application/octet-stream");
+
+        let mut data = Vec::new();
+        let mut reader = upload.into_async_read();
+        let read = match self.max_size {
+            Some(max_size) => {
+                reader
+                    .take(max_size as u64 + 1)
+                    .read_to_end(&mut data)
+                    .await
+            }
+            None => reader.read_to_end(&mut data).await,
+        };
+        #[allow(unused_variables)]
+        if let Err(err) = read {
+            // the upload has already been written to a temporary file, so this
+            // should never happen
+            #[cfg(feature = "tracing")]
+            tracing::error!(error = %err, "failed to read uploaded file");
+            return Err(UploadRejected::raw::empty_file());
+        }
+        self.check(file_name, declared, data)
+    }
+
+    fn check(
+        &self,
+        file_name: Option<String>,
+        declared: Option<String>,
+        data: Vec<u8>,
+    ) -> Result<UploadedFile, UploadRejected::raw::Response> {
+        if let Some(max_size) = self.max_size.filter(|&max_size| data.len() > max_size) {
+            return Err(UploadRejected::raw::file_too_large(FileTooLargeDetails {
+                max_size: max_size as u64,
+            }));
+        }
+        if data.is_empty() {
+            return Err(UploadRejected::raw::empty_file());
+        }
+
+        let unsupported = || {
+            UploadRejected::raw::unsupported_file_type(UnsupportedFileTypeDetails {
+                allowed: self.allowed_types.clone(),
+            })
+        };
+        let content_type = match (sniff_content_type(&data), declared) {
+            (Some(sniffed), Some(declared)) if sniffed != declared => return Err(unsupported()),
+            (Some(sniffed), _) => Some(sniffed.to_owned()),
+            // the file does not have the magic bytes of its declared type
+            (None, Some(declared))
+                if declared == "image/webp"
+                    || SIGNATURES
+                        .iter()
+                        .any(|(_, content_type)| *content_type == declared) =>
+            {
+                return Err(unsupported())
+            }
+            (None, declared) => declared,
+        };
+        if !self.allowed_types.is_empty()
+            && !content_type
+                .as_ref()
+                .is_some_and(|content_type| self.allowed_types.contains(content_type))
+        {
+            return Err(unsupported());
+        }
+
+        Ok(UploadedFile {
+            file_name,
+            content_type,
+            data,
+        })
+    }
+}
+
+/// Magic bytes of the content types that can be detected (in addition to
+/// `image/webp`).
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+];
+
+/// Detect the content type of a file from its first bytes.
+///
+/// Supports common image formats, PDF, ZIP and GZIP files.
+pub fn sniff_content_type(data: &[u8]) -> Option<&'static str> {
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| data.starts_with(signature))
+        .map(|(_, content_type)| *content_type)
+}
+
+/// Sanitize a file name provided by a client.
+///
+/// Directory components, control characters and characters that are not
+/// allowed in file names on common platforms are removed, as well as leading
+/// and trailing dots and whitespace. The result is truncated to 255 bytes.
+/// Returns `None` if nothing remains.
+pub fn sanitize_file_name(file_name: &str) -> Option<String> {
+    let base = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let mut sanitized = base
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        .collect::<String>()
+        .trim_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_owned();
+    if sanitized.len() > 255 {
+        let mut end = 255;
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+    }
+    (!sanitized.is_empty()).then_some(sanitized)
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{
+        http::StatusCode,
+        test::{TestClient, TestForm, TestFormField},
+    };
+    use poem_openapi::{payload::PlainText, Multipart, OpenApi, OpenApiService};
+
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn sanitize() {
+        assert_eq!(sanitize_file_name("test.png").as_deref(), Some("test.png"));
+        assert_eq!(
+            sanitize_file_name("../../etc/passwd").as_deref(),
+            Some("passwd")
+        );
+        assert_eq!(
+            sanitize_file_name("C:\\Users\\foo\\a<b>.txt").as_deref(),
+            Some("ab.txt")
+        );
+        assert_eq!(sanitize_file_name(" .hidden. ").as_deref(), Some("hidden"));
+        assert_eq!(sanitize_file_name("..").as_deref(), None);
+        assert_eq!(sanitize_file_name("a\0b\nc").as_deref(), Some("abc"));
+        assert_eq!(sanitize_file_name(&"ä".repeat(200)).unwrap().len(), 254);
+    }
+
+    #[test]
+    fn sniff() {
+        assert_eq!(sniff_content_type(PNG), Some("image/png"));
+        assert_eq!(sniff_content_type(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(
+            sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_content_type(b"hello"), None);
+    }
+
+    #[test]
+    fn check() {
+        let policy = UploadPolicy::new()
+            .with_max_size(16)
+            .with_allowed_types(["image/png", "text/plain"]);
+        let status = |declared: Option<&str>, data: &[u8]| {
+            use poem::IntoResponse;
+            policy
+                .check(None, declared.map(Into::into), data.into())
+                .map_err(|err| err.into_response().status())
+        };
+
+        assert_eq!(
+            status(None, PNG).unwrap().content_type.as_deref(),
+            Some("image/png")
+        );
+        assert_eq!(
+            status(Some("text/plain"), b"hello")
+                .unwrap()
+                .content_type
+                .as_deref(),
+            Some("text/plain")
+        );
+        assert_eq!(
+            status(None, &[0; 17]).unwrap_err(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            status(Some("image/png"), b"").unwrap_err(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            status(Some("text/plain"), PNG).unwrap_err(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(
+            status(Some("application/pdf"), b"%PDF-1.7").unwrap_err(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(
+            status(None, b"hello").unwrap_err(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(
+            status(Some("image/png"), b"hello").unwrap_err(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+
+    #[derive(Debug, Multipart)]
+    struct Form {
+        file: Upload,
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/upload", method = "post")]
+        async fn upload(
+            &self,
+            form: Form,
+        ) -> Result<PlainText<String>, UploadRejected::raw::Response> {
+            let file = UploadPolicy::new()
+                .with_max_size(32)
+                .with_allowed_types(["image/png"])
+                .validate(form.file)
+                .await?;
+            Ok(PlainText(format!(
+                "{:?} {:?} {}",
+                file.file_name,
+                file.content_type,
+                file.data.len()
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn upload() {
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+
+        let resp = cli
+            .post("/upload")
+            .multipart(
+                TestForm::new().field(
+                    TestFormField::bytes(PNG)
+                        .name("file")
+                        .filename("../avatar.png")
+                        .content_type("image/png"),
+                ),
+            )
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text(r#"Some("avatar.png") Some("image/png") 16"#)
+            .await;
+
+        let resp = cli
+            .post("/upload")
+            .multipart(TestForm::new().field(TestFormField::bytes(vec![0; 64]).name("file")))
+            .send()
+            .await;
+        resp.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+        resp.assert_text(r#"{"details":{"max_size":32},"error":"file_too_large"}"#)
+            .await;
+
+        let resp = cli
+            .post("/upload")
+            .multipart(
+                TestForm::new().field(
+                    TestFormField::bytes(b"hello".to_vec())
+                        .name("file")
+                        .content_type("image/png"),
+                ),
+            )
+            .send()
+            .await;
+        resp.assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        resp.assert_text(
+            r#"{"details":{"allowed":["image/png"]},"error":"unsupported_file_type"}"#,
+        )
+        .await;
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use poem::{http::header::CONTENT_TYPE, Response};
+use serde_json::Value;
+
+/// The value that redacted fields are replaced with.
+pub const REDACTED: &str = "[redacted]";
+
+/// Return the current unix timestamp in seconds.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Check whether `path` is equal to `prefix` or a sub path of it.
+pub(crate) fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix.trim_end_matches('/'))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Read the error code and the error id from a response in the JSON error
+/// format of this crate (e.g. `{"error": "internal_server_error", "error_id":
+/// "..."}`). The body is restored afterwards.
+pub(crate) async fn read_error_body(resp: &mut Response) -> (Option<String>, Option<String>) {
+    let is_json = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.contains("json"));
+    if !is_json {
+        return (None, None);
+    }
+    let Ok(body) = resp.take_body().into_bytes().await else {
+        return (None, None);
+    };
+    let value = serde_json::from_slice::<Value>(&body).ok();
+    resp.set_body(body);
+    let field = |name| value.as_ref()?.get(name)?.as_str().map(ToOwned::to_owned);
+    (field("error"), field("error_id"))
+}
+
+/// Parse a JSON pointer (e.g. `/users/*/token`) into its reference tokens.
+///
+/// # Panics
+/// Panics if the pointer does not start with a `/`.
+pub(crate) fn parse_pointer(pointer: &str) -> Vec<String> {
+    assert!(
+        pointer.starts_with('/'),
+        "invalid json pointer {pointer:?}, expected a leading '/'"
+    );
+    pointer[1..]
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect()
+}
+
+/// Redact and truncate a body for logging.
+pub(crate) fn format_body(body: &[u8], redactions: &[Vec<String>], max_size: usize) -> String {
+    let mut body = match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) if !redactions.is_empty() => {
+            for pointer in redactions {
+                redact(&mut value, pointer);
+            }
+            value.to_string()
+        }
+        _ => String::from_utf8_lossy(body).into_owned(),
+    };
+    if body.len() > max_size {
+        let mut end = max_size;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        let truncated = body.len() - end;
+        body.truncate(end);
+        body.push_str(&format!("... ({truncated} bytes truncated)"));
+    }
+    body
+}
+
+/// Check whether a body with the given content type should be logged.
+pub(crate) fn is_loggable(content_type: Option<&str>) -> bool {
+    let Some(content_type) = content_type else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence.ends_with("json") || essence.starts_with("text/") && essence != "text/event-stream"
+}
+
+pub(crate) fn redact(value: &mut Value, pointer: &[String]) {
+    let Some((token, rest)) = pointer.split_first() else {
+        *value = Value::String(REDACTED.into());
+        return;
+    };
+    match value {
+        Value::Object(map) if token == "*" => map.values_mut().for_each(|v| redact(v, rest)),
+        Value::Object(map) => {
+            if let Some(v) = map.get_mut(token) {
+                redact(v, rest);
+            }
+        }
+        Value::Array(items) if token == "*" => items.iter_mut().for_each(|v| redact(v, rest)),
+        Value::Array(items) => {
+            if let Some(v) = token.parse().ok().and_then(|idx: usize| items.get_mut(idx)) {
+                redact(v, rest);
+            }
+        }
+        _ => {}
+    }
+}
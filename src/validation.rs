@@ -0,0 +1,303 @@
+//! Contains a JSON request body that is validated after deserialization.
+//!
+//! The schema of a request body can only express simple constraints (e.g. the
+//! length of a string or the range of a number). Domain rules like "`end` must
+//! be after `start`" are usually checked at the beginning of each endpoint.
+//! The [`ValidatedJson<T>`] payload runs the rules of the [`Validate`]
+//! implementation of `T` right after the body has been deserialized and
+//! responds with `422 Unprocessable Content` (see [`ValidationFailed`]) if any
+//! of them are violated. The response lists all errors grouped by field:
+//!
+//! ```json
+//! {"error": "validation_failed", "details": {"fields": {"end": ["must be after start"]}}}
+//! ```
+//!
+//! Rules of validation libraries like `garde` or `validator` can be reused by
+//! converting their error reports into [`ValidationErrors`] in the [`Validate`]
+//! implementation.
+//!
+//! #### Example
+//! ```
+//! use poem_ext::{
+//!     add_response_schemas,
+//!     responses::{Response, ValidationFailed},
+//!     validation::{Validate, ValidatedJson, ValidationErrors},
+//! };
+//! use poem_openapi::{payload::Json, Object, OpenApi};
+//!
+//! #[derive(Debug, Object)]
+//! struct Booking {
+//!     start: u64,
+//!     end: u64,
+//! }
+//!
+//! impl Validate for Booking {
+//!     fn validate(&self) -> Result<(), ValidationErrors> {
+//!         let mut errors = ValidationErrors::new();
+//!         if self.end <= self.start {
+//!             errors.add("end", "must be after start");
+//!         }
+//!         errors.into_result()
+//!     }
+//! }
+//!
+//! /// Marker type used to document the 422 response.
+//! struct Validated;
+//! add_response_schemas!(Validated, ValidationFailed::raw::Response);
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/bookings", method = "post")]
+//!     async fn create_booking(&self, booking: ValidatedJson<Booking>) -> Response<Json<u64>, Validated> {
+//!         Ok(Json(booking.end - booking.start).into())
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    ops::{Deref, DerefMut},
+};
+
+use poem::{Request, RequestBody};
+use poem_openapi::{
+    payload::{Json, Payload},
+    registry::{MetaRequest, MetaSchemaRef, Registry},
+    types::{ParseFromJSON, Type},
+    ApiExtractor, ApiExtractorType, ExtractParamOptions, Object,
+};
+
+use crate::responses::ValidationFailed;
+
+/// Trait for types that can be validated after they have been deserialized.
+pub trait Validate {
+    /// Check the rules of this type and return all violations.
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        for (i, item) in self.iter().enumerate() {
+            if let Err(err) = item.validate() {
+                errors.merge(&i.to_string(), err);
+            }
+        }
+        errors.into_result()
+    }
+}
+
+impl<T: Validate> Validate for Option<T> {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        self.as_ref().map_or(Ok(()), Validate::validate)
+    }
+}
+
+/// The violated rules of a value, grouped by field (details of a
+/// [`ValidationFailed`] response).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Object)]
+pub struct ValidationErrors {
+    /// The error messages of each invalid field. Fields of nested objects are
+    /// separated by dots (e.g. `address.zip` or `items.0.name`).
+    pub fields: BTreeMap<String, Vec<String>>,
+}
+
+impl ValidationErrors {
+    /// Create an empty ValidationErrors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an error message for the given field.
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.fields
+            .entry(field.into())
+            .or_default()
+            .push(message.into());
+    }
+
+    /// Add the errors of a nested value with the given field name as prefix.
+    pub fn merge(&mut self, prefix: &str, errors: ValidationErrors) {
+        for (field, messages) in errors.fields {
+            self.fields
+                .entry(format!("{prefix}.{field}"))
+                .or_default()
+                .extend(messages);
+        }
+    }
+
+    /// Return whether no errors have been added.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Return `Ok(())` if no errors have been added, otherwise `Err(self)`.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+/// A JSON request body that is validated using its [`Validate`]
+/// implementation after deserialization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for ValidatedJson<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T: Type> Payload for ValidatedJson<T> {
+    const CONTENT_TYPE: &'static str = Json::<T>::CONTENT_TYPE;
+
+    fn check_content_type(content_type: &str) -> bool {
+        Json::<T>::check_content_type(content_type)
+    }
+
+    fn schema_ref() -> MetaSchemaRef {
+        T::schema_ref()
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+}
+
+#[poem::async_trait]
+impl<'a, T: ParseFromJSON + Validate> ApiExtractor<'a> for ValidatedJson<T> {
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::RequestObject];
+
+    type ParamType = ();
+    type ParamRawType = ();
+
+    fn register(registry: &mut Registry) {
+        <Self as Payload>::register(registry);
+    }
+
+    fn request_meta() -> Option<MetaRequest> {
+        Json::<T>::request_meta()
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        body: &mut RequestBody,
+        param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let Json(value) = Json::<T>::from_request(request, body, param_opts).await?;
+        value
+            .validate()
+            .map_err(ValidationFailed::raw::validation_failed)?;
+        Ok(Self(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::test::TestClient;
+    use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+    use serde_json::json;
+
+    use super::*;
+    use crate::responses::Response;
+
+    #[derive(Debug, Object)]
+    struct Item {
+        name: String,
+    }
+
+    impl Validate for Item {
+        fn validate(&self) -> Result<(), ValidationErrors> {
+            let mut errors = ValidationErrors::new();
+            if self.name.trim().is_empty() {
+                errors.add("name", "must not be blank");
+            }
+            errors.into_result()
+        }
+    }
+
+    #[derive(Debug, Object)]
+    struct Order {
+        quantity: u32,
+        items: Vec<Item>,
+    }
+
+    impl Validate for Order {
+        fn validate(&self) -> Result<(), ValidationErrors> {
+            let mut errors = ValidationErrors::new();
+            if self.quantity == 0 {
+                errors.add("quantity", "must be positive");
+            }
+            if let Err(err) = self.items.validate() {
+                errors.merge("items", err);
+            }
+            errors.into_result()
+        }
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/orders", method = "post")]
+        async fn create(&self, order: ValidatedJson<Order>) -> Response<PlainText<String>> {
+            Ok(PlainText(order.items.len().to_string()).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn validated_json() {
+        let cli = TestClient::new(OpenApiService::new(Api, "test", "1"));
+
+        let resp = cli
+            .post("/orders")
+            .body_json(&json!({"quantity": 1, "items": [{"name": "foo"}]}))
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text("1").await;
+
+        let resp = cli
+            .post("/orders")
+            .body_json(&json!({"quantity": 0, "items": [{"name": "foo"}, {"name": " "}]}))
+            .send()
+            .await;
+        resp.assert_status(poem::http::StatusCode::UNPROCESSABLE_ENTITY);
+        resp.assert_json(json!({
+            "error": "validation_failed",
+            "details": {"fields": {
+                "quantity": ["must be positive"],
+                "items.1.name": ["must not be blank"],
+            }},
+        }))
+        .await;
+
+        // invalid bodies are rejected before validation
+        let resp = cli
+            .post("/orders")
+            .body_json(&json!({"quantity": 0}))
+            .send()
+            .await;
+        resp.assert_status(poem::http::StatusCode::UNPROCESSABLE_ENTITY);
+        resp.json()
+            .await
+            .value()
+            .object()
+            .get("error")
+            .assert_string("unprocessable_content");
+    }
+}
@@ -0,0 +1,472 @@
+//! Contains utilities for serving multiple versions of an api.
+//!
+//! Each version is a separate [`OpenApiService`] with its own OpenAPI
+//! document, which is served at `/{version}/openapi.json`. Response types
+//! created using the [`response!`](crate::response!) macro can simply be
+//! shared between the versions. Requests are routed to a version either by a
+//! path prefix (e.g. `/v1/users`), a custom header (e.g. `X-Api-Version: v1`)
+//! or a vendor media type in the `Accept` header (e.g.
+//! `Accept: application/vnd.example.v1+json`), see [`VersionSelector`].
+//!
+//! Old versions can be marked as [deprecated](ApiVersion::deprecated). All
+//! operations in their OpenAPI document are then marked as deprecated and
+//! their responses include a `Deprecation` header (and optionally `Sunset`
+//! and `Link` headers).
+//!
+//! #### Example
+//! ```
+//! use poem::Route;
+//! use poem_ext::{
+//!     response,
+//!     versioning::{ApiVersion, ApiVersions, VersionSelector},
+//! };
+//! use poem_openapi::{payload::PlainText, OpenApi, OpenApiService};
+//!
+//! // shared between all versions
+//! response!(Hello = {
+//!     Ok(200) => String,
+//! });
+//!
+//! struct ApiV1;
+//!
+//! #[OpenApi]
+//! impl ApiV1 {
+//!     #[oai(path = "/hello", method = "get")]
+//!     async fn hello(&self) -> Hello::Response {
+//!         Hello::ok("Hello World!".into())
+//!     }
+//! }
+//!
+//! struct ApiV2;
+//!
+//! #[OpenApi]
+//! impl ApiV2 {
+//!     #[oai(path = "/hello", method = "get")]
+//!     async fn hello(&self) -> Hello::Response {
+//!         Hello::ok("Hello World, again!".into())
+//!     }
+//! }
+//!
+//! let app = Route::new().nest(
+//!     "/api",
+//!     ApiVersions::new(VersionSelector::PathPrefix)
+//!         .version(
+//!             ApiVersion::new("v1", OpenApiService::new(ApiV1, "Test", "1.0.0"))
+//!                 .deprecated()
+//!                 .with_sunset("Sat, 01 Nov 2025 00:00:00 GMT")
+//!                 .with_successor("/api/v2"),
+//!         )
+//!         .version(ApiVersion::new(
+//!             "v2",
+//!             OpenApiService::new(ApiV2, "Test", "2.0.0"),
+//!         ))
+//!         .into_endpoint(),
+//! );
+//! ```
+
+use std::fmt::Debug;
+
+use poem::{
+    endpoint::{make_sync, BoxEndpoint},
+    http::{header::ACCEPT, HeaderValue},
+    Endpoint, IntoEndpoint, IntoResponse, Request, Response, Route,
+};
+use poem_openapi::{OpenApi, OpenApiService, Webhook};
+use serde_json::Value;
+
+use crate::responses::UnsupportedApiVersion;
+
+/// Determines how requests are routed to a version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionSelector {
+    /// The version is the first segment of the path (e.g. `/v1/users`).
+    PathPrefix,
+    /// The version is the value of the given header (e.g.
+    /// `X-Api-Version: v1`).
+    Header(String),
+    /// The version is part of a vendor media type with the given vendor name
+    /// in the `Accept` header (e.g. `application/vnd.{vendor}.v1+json`).
+    Accept(String),
+}
+
+/// A single version of an api.
+pub struct ApiVersion {
+    name: String,
+    endpoint: BoxEndpoint<'static>,
+    spec: String,
+    deprecated: bool,
+    sunset: Option<String>,
+    successor: Option<String>,
+}
+
+impl Debug for ApiVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiVersion")
+            .field("name", &self.name)
+            .field("deprecated", &self.deprecated)
+            .field("sunset", &self.sunset)
+            .field("successor", &self.successor)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ApiVersion {
+    /// Create a new version with the given name (e.g. `v1`).
+    pub fn new<T, W>(name: impl Into<String>, service: OpenApiService<T, W>) -> Self
+    where
+        T: OpenApi + 'static,
+        W: Webhook + 'static,
+    {
+        Self {
+            name: name.into(),
+            spec: service.spec(),
+            endpoint: service.into_endpoint(),
+            deprecated: false,
+            sunset: None,
+            successor: None,
+        }
+    }
+
+    /// Mark this version as deprecated.
+    pub fn deprecated(self) -> Self {
+        Self {
+            deprecated: true,
+            ..self
+        }
+    }
+
+    /// Set the date (in the HTTP date format, e.g.
+    /// `Sat, 01 Nov 2025 00:00:00 GMT`) after which this version will no
+    /// longer be available. Implies [`deprecated`](Self::deprecated).
+    pub fn with_sunset(self, date: impl Into<String>) -> Self {
+        Self {
+            deprecated: true,
+            sunset: Some(date.into()),
+            ..self
+        }
+    }
+
+    /// Set the url of the version that replaces this one. Implies
+    /// [`deprecated`](Self::deprecated).
+    pub fn with_successor(self, url: impl Into<String>) -> Self {
+        Self {
+            deprecated: true,
+            successor: Some(url.into()),
+            ..self
+        }
+    }
+
+    /// Return the OpenAPI document of this version including the deprecation
+    /// metadata.
+    pub fn spec(&self) -> String {
+        if !self.deprecated {
+            return self.spec.clone();
+        }
+        let Ok(mut spec) = serde_json::from_str::<Value>(&self.spec) else {
+            return self.spec.clone();
+        };
+        if let Some(paths) = spec.get_mut("paths").and_then(Value::as_object_mut) {
+            for operation in paths
+                .values_mut()
+                .filter_map(Value::as_object_mut)
+                .flat_map(|path| path.values_mut())
+                .filter_map(Value::as_object_mut)
+            {
+                operation.insert("deprecated".into(), true.into());
+            }
+        }
+        if let Some(info) = spec.get_mut("info").and_then(Value::as_object_mut) {
+            info.insert("x-deprecated".into(), true.into());
+            if let Some(sunset) = &self.sunset {
+                info.insert("x-sunset".into(), sunset.as_str().into());
+            }
+            if let Some(successor) = &self.successor {
+                info.insert("x-successor".into(), successor.as_str().into());
+            }
+        }
+        spec.to_string()
+    }
+
+    fn add_deprecation_headers(&self, resp: &mut Response) {
+        if !self.deprecated {
+            return;
+        }
+        let headers = resp.headers_mut();
+        headers.insert("deprecation", HeaderValue::from_static("true"));
+        if let Some(sunset) = self.sunset.as_deref().and_then(|x| x.parse().ok()) {
+            headers.insert("sunset", sunset);
+        }
+        if let Some(link) = self
+            .successor
+            .as_deref()
+            .and_then(|x| format!("<{x}>; rel=\"successor-version\"").parse().ok())
+        {
+            headers.append("link", link);
+        }
+    }
+}
+
+/// A collection of api versions.
+#[derive(Debug)]
+pub struct ApiVersions {
+    selector: VersionSelector,
+    versions: Vec<ApiVersion>,
+    default: Option<String>,
+}
+
+impl ApiVersions {
+    /// Create a new empty collection of api versions.
+    pub fn new(selector: VersionSelector) -> Self {
+        Self {
+            selector,
+            versions: Vec::new(),
+            default: None,
+        }
+    }
+
+    /// Add a version.
+    pub fn version(mut self, version: ApiVersion) -> Self {
+        assert!(
+            self.versions.iter().all(|v| v.name != version.name),
+            "duplicate api version {:?}",
+            version.name
+        );
+        self.versions.push(version);
+        self
+    }
+
+    /// Set the version that is used for requests that don't specify a
+    /// version when using a [`VersionSelector::Header`] or
+    /// [`VersionSelector::Accept`] (default: the version that has been added
+    /// last).
+    pub fn with_default(self, name: impl Into<String>) -> Self {
+        Self {
+            default: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Create an endpoint that serves all versions and their OpenAPI
+    /// documents.
+    pub fn into_endpoint(self) -> Route {
+        let mut route = Route::new();
+        for version in &self.versions {
+            let spec = version.spec();
+            route = route.at(
+                format!("/{}/openapi.json", version.name),
+                make_sync(move |_| {
+                    Response::builder()
+                        .content_type("application/json; charset=utf-8")
+                        .body(spec.clone())
+                }),
+            );
+        }
+
+        match self.selector {
+            VersionSelector::PathPrefix => {
+                for version in self.versions {
+                    let name = format!("/{}", version.name);
+                    route = route.nest(name, VersionEndpoint(version));
+                }
+                route
+            }
+            selector => {
+                let default = match &self.default {
+                    Some(default) => self
+                        .versions
+                        .iter()
+                        .position(|v| v.name == *default)
+                        .unwrap_or_else(|| panic!("unknown default api version {default:?}")),
+                    None => self.versions.len().checked_sub(1).expect("no api versions"),
+                };
+                route.nest(
+                    "/",
+                    DispatchEndpoint {
+                        selector,
+                        versions: self.versions.into_iter().map(VersionEndpoint).collect(),
+                        default,
+                    },
+                )
+            }
+        }
+    }
+}
+
+struct VersionEndpoint(ApiVersion);
+
+#[poem::async_trait]
+impl Endpoint for VersionEndpoint {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let mut resp = match self.0.endpoint.call(req).await {
+            Ok(resp) => resp,
+            Err(err) => err.into_response(),
+        };
+        self.0.add_deprecation_headers(&mut resp);
+        Ok(resp)
+    }
+}
+
+struct DispatchEndpoint {
+    selector: VersionSelector,
+    versions: Vec<VersionEndpoint>,
+    default: usize,
+}
+
+impl DispatchEndpoint {
+    /// Return the version requested by the client, if specified.
+    fn requested_version<'a>(&self, req: &'a Request) -> Option<&'a str> {
+        match &self.selector {
+            VersionSelector::PathPrefix => None,
+            VersionSelector::Header(header) => req.header(header).map(str::trim),
+            VersionSelector::Accept(vendor) => {
+                let prefix = format!("application/vnd.{vendor}.");
+                req.headers()
+                    .get_all(ACCEPT)
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .flat_map(|value| value.split(','))
+                    .filter_map(|item| item.split(';').next())
+                    .find_map(|media_type| {
+                        let media_type = media_type.trim();
+                        let version = media_type.get(..prefix.len()).and_then(|p| {
+                            p.eq_ignore_ascii_case(&prefix)
+                                .then(|| &media_type[prefix.len()..])
+                        })?;
+                        Some(version.strip_suffix("+json").unwrap_or(version))
+                    })
+            }
+        }
+    }
+}
+
+#[poem::async_trait]
+impl Endpoint for DispatchEndpoint {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let version = match self.requested_version(&req) {
+            Some(name) => match self.versions.iter().find(|v| v.0.name == name) {
+                Some(version) => version,
+                None => return Ok(UnsupportedApiVersion::raw::not_acceptable().into_response()),
+            },
+            None => &self.versions[self.default],
+        };
+        version.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::test::TestClient;
+    use poem_openapi::payload::PlainText;
+
+    use super::*;
+
+    struct ApiV1;
+
+    #[poem_openapi::OpenApi]
+    impl ApiV1 {
+        #[oai(path = "/hello", method = "get")]
+        async fn hello(&self) -> PlainText<&'static str> {
+            PlainText("v1")
+        }
+    }
+
+    struct ApiV2;
+
+    #[poem_openapi::OpenApi]
+    impl ApiV2 {
+        #[oai(path = "/hello", method = "get")]
+        async fn hello(&self) -> PlainText<&'static str> {
+            PlainText("v2")
+        }
+    }
+
+    fn versions(selector: VersionSelector) -> ApiVersions {
+        ApiVersions::new(selector)
+            .version(
+                ApiVersion::new("v1", OpenApiService::new(ApiV1, "test", "1"))
+                    .with_sunset("Sat, 01 Nov 2025 00:00:00 GMT")
+                    .with_successor("/v2"),
+            )
+            .version(ApiVersion::new(
+                "v2",
+                OpenApiService::new(ApiV2, "test", "2"),
+            ))
+    }
+
+    #[tokio::test]
+    async fn path_prefix() {
+        let cli = TestClient::new(versions(VersionSelector::PathPrefix).into_endpoint());
+
+        let resp = cli.get("/v1/hello").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_header("deprecation", "true");
+        resp.assert_header("sunset", "Sat, 01 Nov 2025 00:00:00 GMT");
+        resp.assert_header("link", "</v2>; rel=\"successor-version\"");
+        resp.assert_text("v1").await;
+
+        let resp = cli.get("/v2/hello").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_header_is_not_exist("deprecation");
+        resp.assert_text("v2").await;
+
+        cli.get("/v3/hello")
+            .send()
+            .await
+            .assert_status(poem::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn header() {
+        let cli = TestClient::new(
+            versions(VersionSelector::Header("x-api-version".into()))
+                .with_default("v1")
+                .into_endpoint(),
+        );
+
+        let resp = cli.get("/hello").header("x-api-version", "v2").send().await;
+        resp.assert_text("v2").await;
+
+        let resp = cli.get("/hello").send().await;
+        resp.assert_text("v1").await;
+
+        let resp = cli.get("/hello").header("x-api-version", "v3").send().await;
+        resp.assert_status(poem::http::StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[tokio::test]
+    async fn accept() {
+        let cli = TestClient::new(versions(VersionSelector::Accept("test".into())).into_endpoint());
+
+        let resp = cli
+            .get("/hello")
+            .header(ACCEPT, "text/plain, application/vnd.test.v1+json")
+            .send()
+            .await;
+        resp.assert_text("v1").await;
+
+        let resp = cli.get("/hello").send().await;
+        resp.assert_text("v2").await;
+    }
+
+    #[tokio::test]
+    async fn spec() {
+        let cli = TestClient::new(versions(VersionSelector::PathPrefix).into_endpoint());
+
+        let resp = cli.get("/v1/openapi.json").send().await;
+        resp.assert_status_is_ok();
+        let spec: Value =
+            serde_json::from_str(&resp.0.into_body().into_string().await.unwrap()).unwrap();
+        assert_eq!(spec["paths"]["/hello"]["get"]["deprecated"], true);
+        assert_eq!(spec["info"]["x-sunset"], "Sat, 01 Nov 2025 00:00:00 GMT");
+
+        let resp = cli.get("/v2/openapi.json").send().await;
+        let spec: Value =
+            serde_json::from_str(&resp.0.into_body().into_string().await.unwrap()).unwrap();
+        assert_eq!(spec["info"]["version"], "2");
+        assert!(spec["paths"]["/hello"]["get"].get("deprecated").is_none());
+    }
+}
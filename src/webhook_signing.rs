@@ -0,0 +1,365 @@
+//! Contains utilities for signing outgoing webhooks and verifying the
+//! signatures of incoming webhooks.
+//!
+//! A webhook is signed by computing an HMAC-SHA256 over the unix timestamp
+//! and the body of the request (`{timestamp}.{body}`) using a shared secret.
+//! The timestamp is sent in the `Webhook-Timestamp` header, the base64
+//! encoded signature prefixed with its version in the `Webhook-Signature`
+//! header (e.g. `v1=K5oZfzN95Z9UVu1EsfQmfVNQhnkZ2pj9o9NDN/H/pI4=`). Multiple
+//! space separated signatures are accepted, which allows rotating secrets.
+//!
+//! Outgoing webhooks can be signed using a [`WebhookSigner`]. Incoming
+//! webhooks are verified by the [`SignedWebhook`] extractor using the
+//! [`WebhookVerifier`] from the application data. Requests with missing or
+//! malformed signature headers are rejected with `400 Bad Request`, requests
+//! with an invalid signature or a timestamp outside of the tolerance window
+//! with `401 Unauthorized` (see [`InvalidWebhookSignature`]).
+//!
+//! #### Example
+//! ```
+//! use std::time::Duration;
+//!
+//! use poem::{EndpointExt, Route};
+//! use poem_ext::{
+//!     responses::InvalidWebhookSignature,
+//!     webhook_signing::{SignedWebhook, WebhookSigner, WebhookVerifier},
+//! };
+//! use poem_openapi::{payload::Json, OpenApi, OpenApiService};
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/webhook", method = "post")]
+//!     async fn webhook(
+//!         &self,
+//!         data: SignedWebhook<Json<String>>,
+//!     ) -> Result<Json<String>, InvalidWebhookSignature::raw::Response> {
+//!         Ok(data.0)
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .data(WebhookVerifier::new(b"secret").with_tolerance(Duration::from_secs(60)));
+//!
+//! // sign an outgoing webhook and add these headers to the request
+//! let headers = WebhookSigner::new(b"secret")
+//!     .sign(br#""Hello World!""#)
+//!     .headers();
+//! ```
+
+use std::{fmt::Debug, ops::Deref, sync::Arc, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use poem::{http::StatusCode, Body, Request, RequestBody};
+use poem_openapi::{
+    registry::{MetaRequest, Registry},
+    ApiExtractor, ApiExtractorType, ExtractParamOptions,
+};
+use sha2::Sha256;
+
+use crate::{responses::InvalidWebhookSignature, utils::unix_now};
+
+/// Name of the header that contains the timestamp of the webhook.
+pub const TIMESTAMP_HEADER: &str = "webhook-timestamp";
+
+/// Name of the header that contains the signature of the webhook.
+pub const SIGNATURE_HEADER: &str = "webhook-signature";
+
+/// Default maximum difference between the timestamp of a webhook and the
+/// current time.
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_secs(300);
+
+const VERSION: &str = "v1";
+
+/// Signs outgoing webhooks.
+#[derive(Clone)]
+pub struct WebhookSigner {
+    secret: Arc<[u8]>,
+}
+
+impl Debug for WebhookSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookSigner").finish_non_exhaustive()
+    }
+}
+
+impl WebhookSigner {
+    /// Create a new WebhookSigner that uses the given secret.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            secret: secret.as_ref().into(),
+        }
+    }
+
+    /// Sign a webhook with the given body using the current time.
+    pub fn sign(&self, body: &[u8]) -> WebhookSignature {
+        self.sign_at(body, unix_now())
+    }
+
+    /// Sign a webhook with the given body and unix timestamp.
+    pub fn sign_at(&self, body: &[u8], timestamp: u64) -> WebhookSignature {
+        let signature = mac(&self.secret, timestamp, body).finalize().into_bytes();
+        WebhookSignature {
+            timestamp,
+            signature: format!("{VERSION}={}", STANDARD.encode(signature)),
+        }
+    }
+}
+
+/// The signature of a webhook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookSignature {
+    /// The unix timestamp at which the webhook has been signed.
+    pub timestamp: u64,
+    /// The versioned signature (e.g. `v1=...`).
+    pub signature: String,
+}
+
+impl WebhookSignature {
+    /// Return the names and values of the headers that have to be added to
+    /// the webhook request.
+    pub fn headers(&self) -> [(&'static str, String); 2] {
+        [
+            (TIMESTAMP_HEADER, self.timestamp.to_string()),
+            (SIGNATURE_HEADER, self.signature.clone()),
+        ]
+    }
+}
+
+/// Verifies the signatures of incoming webhooks.
+///
+/// Has to be attached to the endpoint using
+/// [`data`](poem::EndpointExt::data) to be used by the [`SignedWebhook`]
+/// extractor.
+#[derive(Clone)]
+pub struct WebhookVerifier {
+    secret: Arc<[u8]>,
+    tolerance: Duration,
+}
+
+impl Debug for WebhookVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookVerifier")
+            .field("tolerance", &self.tolerance)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WebhookVerifier {
+    /// Create a new WebhookVerifier that uses the given secret.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            secret: secret.as_ref().into(),
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+
+    /// Set the maximum difference between the timestamp of a webhook and the
+    /// current time (default: [`DEFAULT_TOLERANCE`]).
+    pub fn with_tolerance(self, tolerance: Duration) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    /// Verify the signature of a webhook request with the given body.
+    pub fn verify(
+        &self,
+        req: &Request,
+        body: &[u8],
+    ) -> Result<(), InvalidWebhookSignature::raw::Response> {
+        self.verify_at(
+            req.header(TIMESTAMP_HEADER),
+            req.header(SIGNATURE_HEADER),
+            body,
+            unix_now(),
+        )
+    }
+
+    fn verify_at(
+        &self,
+        timestamp: Option<&str>,
+        signature: Option<&str>,
+        body: &[u8],
+        now: u64,
+    ) -> Result<(), InvalidWebhookSignature::raw::Response> {
+        let (Some(timestamp), Some(signature)) = (
+            timestamp.and_then(|x| x.trim().parse::<u64>().ok()),
+            signature,
+        ) else {
+            return Err(InvalidWebhookSignature::raw::malformed_signature());
+        };
+        if now.abs_diff(timestamp) > self.tolerance.as_secs() {
+            return Err(InvalidWebhookSignature::raw::invalid_signature());
+        }
+        let signatures = signature
+            .split_whitespace()
+            .filter_map(|signature| signature.strip_prefix(VERSION)?.strip_prefix('='))
+            .map(|signature| STANDARD.decode(signature))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| InvalidWebhookSignature::raw::malformed_signature())?;
+        if signatures.is_empty() {
+            return Err(InvalidWebhookSignature::raw::malformed_signature());
+        }
+        let mac = mac(&self.secret, timestamp, body);
+        signatures
+            .iter()
+            .any(|signature| mac.clone().verify_slice(signature).is_ok())
+            .then_some(())
+            .ok_or_else(InvalidWebhookSignature::raw::invalid_signature)
+    }
+}
+
+/// Extractor for the body `T` (e.g. [`Json`](poem_openapi::payload::Json)) of
+/// a webhook request with a valid signature.
+///
+/// Requires a [`WebhookVerifier`] to be attached to the endpoint, otherwise
+/// the request fails with `500 Internal Server Error`.
+#[derive(Debug)]
+pub struct SignedWebhook<T>(pub T);
+
+impl<T> Deref for SignedWebhook<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[poem::async_trait]
+impl<'a, T: ApiExtractor<'a>> ApiExtractor<'a> for SignedWebhook<T> {
+    const TYPES: &'static [ApiExtractorType] = T::TYPES;
+
+    type ParamType = T::ParamType;
+    type ParamRawType = T::ParamRawType;
+
+    fn register(registry: &mut Registry) {
+        T::register(registry);
+    }
+
+    fn request_meta() -> Option<MetaRequest> {
+        T::request_meta()
+    }
+
+    async fn from_request(
+        request: &'a Request,
+        body: &mut RequestBody,
+        param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let verifier = request.data::<WebhookVerifier>().ok_or_else(|| {
+            poem::Error::from_string(
+                "webhook verifier has not been configured",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+        let data = body.take()?.into_bytes().await?;
+        verifier.verify(request, &data)?;
+        *body = RequestBody::new(Body::from_bytes(data));
+        T::from_request(request, body, param_opts).await.map(Self)
+    }
+}
+
+fn mac(secret: &[u8], timestamp: u64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any size");
+    mac.update(format!("{timestamp}.").as_bytes());
+    mac.update(body);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, EndpointExt};
+    use poem_openapi::{payload::Json, OpenApi, OpenApiService};
+
+    use super::*;
+
+    #[test]
+    fn verify() {
+        let signer = WebhookSigner::new(b"secret");
+        let verifier = WebhookVerifier::new(b"secret").with_tolerance(Duration::from_secs(10));
+        let status = |timestamp: Option<&str>, signature: Option<&str>, body: &[u8]| {
+            verifier
+                .verify_at(timestamp, signature, body, 1000)
+                .map_err(|err| poem::Error::from(err).status().as_u16())
+        };
+
+        let WebhookSignature {
+            timestamp,
+            signature,
+        } = signer.sign_at(b"foo", 995);
+        let timestamp = timestamp.to_string();
+        let timestamp = Some(timestamp.as_str());
+        assert!(signature.starts_with("v1="));
+        assert_eq!(status(timestamp, Some(&signature), b"foo"), Ok(()));
+        assert_eq!(
+            status(timestamp, Some(&format!("v0=foo {signature}")), b"foo"),
+            Ok(())
+        );
+        assert_eq!(status(timestamp, Some(&signature), b"bar"), Err(401));
+        assert_eq!(status(Some("994"), Some(&signature), b"foo"), Err(401));
+        assert_eq!(status(None, Some(&signature), b"foo"), Err(400));
+        assert_eq!(status(Some("foo"), Some(&signature), b"foo"), Err(400));
+        assert_eq!(status(timestamp, None, b"foo"), Err(400));
+        assert_eq!(status(timestamp, Some("v1=%%%"), b"foo"), Err(400));
+        assert_eq!(status(timestamp, Some("v2=foo"), b"foo"), Err(400));
+
+        let signature = signer.sign_at(b"foo", 1011);
+        let timestamp = signature.timestamp.to_string();
+        assert_eq!(
+            status(Some(&timestamp), Some(&signature.signature), b"foo"),
+            Err(401)
+        );
+
+        let signature = WebhookSigner::new(b"other").sign_at(b"foo", 1000);
+        assert_eq!(
+            status(Some("1000"), Some(&signature.signature), b"foo"),
+            Err(401)
+        );
+    }
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/webhook", method = "post")]
+        async fn webhook(&self, data: SignedWebhook<Json<Vec<u32>>>) -> Json<u32> {
+            Json(data.iter().sum())
+        }
+    }
+
+    #[tokio::test]
+    async fn extractor() {
+        let cli = TestClient::new(
+            OpenApiService::new(Api, "test", "1").data(WebhookVerifier::new(b"secret")),
+        );
+        let send = |body: &'static str, signer: WebhookSigner| {
+            let mut req = cli
+                .post("/webhook")
+                .content_type("application/json")
+                .body(body);
+            for (name, value) in signer.sign(body.as_bytes()).headers() {
+                req = req.header(name, value);
+            }
+            req.send()
+        };
+
+        let resp = send("[1, 2, 3]", WebhookSigner::new(b"secret")).await;
+        resp.assert_status_is_ok();
+        resp.assert_text("6").await;
+
+        let resp = send("[1, 2, 3]", WebhookSigner::new(b"foo")).await;
+        resp.assert_status(StatusCode::UNAUTHORIZED);
+        resp.assert_text(r#"{"error":"invalid_signature"}"#).await;
+
+        let resp = cli
+            .post("/webhook")
+            .content_type("application/json")
+            .body("[1, 2, 3]")
+            .send()
+            .await;
+        resp.assert_status(StatusCode::BAD_REQUEST);
+        resp.assert_text(r#"{"error":"malformed_signature"}"#).await;
+    }
+}
@@ -0,0 +1,292 @@
+//! Contains utilities for WebSocket upgrade endpoints.
+//!
+//! Browsers cannot set the `Authorization` header when opening a WebSocket
+//! connection. The [`WebSocketAuth`] extractor therefore runs the checker of
+//! an authorization dependency defined using the
+//! [`custom_auth!`](crate::custom_auth) macro with the bearer token from the
+//! `Authorization` header or, if the header is missing, from the
+//! `access_token` query parameter (see [`ACCESS_TOKEN_QUERY`]). Because
+//! extractors run before the endpoint is called, unauthorized requests are
+//! rejected before the connection is upgraded.
+//!
+//! OpenAPI has no notion of WebSockets. [`websocket_spec`] marks all
+//! operations that respond with `101 Switching Protocols` using an
+//! `x-websocket` extension, [`spec_endpoint`] serves the resulting document.
+//!
+//! #### Example
+//! ```
+//! use futures_util::{future::BoxFuture, FutureExt, SinkExt, StreamExt};
+//! use poem::{
+//!     web::websocket::{WebSocket, WebSocketStream, WebSocketUpgraded},
+//!     Request, Route,
+//! };
+//! use poem_ext::{custom_auth, response, websocket::WebSocketAuth};
+//! use poem_openapi::{auth::Bearer, OpenApi, OpenApiService};
+//!
+//! struct UserAuth(String);
+//!
+//! response!(AuthResult = {
+//!     /// The user is unauthenticated.
+//!     Unauthorized(401, error),
+//! });
+//!
+//! async fn user_auth_check(
+//!     _req: &Request,
+//!     token: Option<Bearer>,
+//! ) -> Result<String, AuthResult::raw::Response> {
+//!     match token {
+//!         Some(Bearer { token }) if token == "secret_token" => Ok("user".into()),
+//!         _ => Err(AuthResult::raw::unauthorized()),
+//!     }
+//! }
+//!
+//! custom_auth!(UserAuth, user_auth_check);
+//!
+//! type Handler = fn(WebSocketStream) -> BoxFuture<'static, ()>;
+//!
+//! fn echo(mut socket: WebSocketStream) -> BoxFuture<'static, ()> {
+//!     async move {
+//!         while let Some(Ok(msg)) = socket.next().await {
+//!             if socket.send(msg).await.is_err() {
+//!                 break;
+//!             }
+//!         }
+//!     }
+//!     .boxed()
+//! }
+//!
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     /// Echo all messages (`/echo?access_token=secret_token`).
+//!     #[oai(path = "/echo", method = "get")]
+//!     async fn echo(
+//!         &self,
+//!         _auth: WebSocketAuth<UserAuth>,
+//!         ws: WebSocket,
+//!     ) -> WebSocketUpgraded<Handler> {
+//!         ws.on_upgrade(echo)
+//!     }
+//! }
+//!
+//! let api_service = OpenApiService::new(Api, "Test", "0.1.0");
+//! let spec = poem_ext::websocket::spec_endpoint(&api_service);
+//! let app = Route::new()
+//!     .nest("/", api_service)
+//!     .at("/openapi.json", spec);
+//! ```
+
+use std::{collections::HashMap, ops::Deref};
+
+use poem::{endpoint::make_sync, Endpoint, Request, RequestBody, Response};
+use poem_openapi::{
+    auth::{Bearer, BearerAuthorization},
+    registry::Registry,
+    ApiExtractor, ApiExtractorType, ExtractParamOptions, OpenApi, OpenApiService, Webhook,
+};
+use serde_json::Value;
+
+use crate::__private::CustomAuth;
+
+/// Name of the query parameter that is used if the `Authorization` header is
+/// missing.
+pub const ACCESS_TOKEN_QUERY: &str = "access_token";
+
+/// Name of the extension that marks WebSocket operations in the OpenAPI
+/// document.
+pub const WEBSOCKET_EXTENSION: &str = "x-websocket";
+
+/// Extractor that authorizes WebSocket upgrade requests using an
+/// authorization dependency `A` defined using the
+/// [`custom_auth!`](crate::custom_auth) macro.
+///
+/// The bearer token is taken from the `Authorization` header or, if it is
+/// missing, from the [`access_token`](ACCESS_TOKEN_QUERY) query parameter.
+/// The endpoint is documented with the security scheme of `A`.
+#[derive(Debug)]
+pub struct WebSocketAuth<A>(pub A);
+
+impl<A> Deref for WebSocketAuth<A> {
+    type Target = A;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[poem::async_trait]
+impl<'a, A> ApiExtractor<'a> for WebSocketAuth<A>
+where
+    A: CustomAuth + for<'b> ApiExtractor<'b> + Send,
+{
+    const TYPES: &'static [ApiExtractorType] = &[ApiExtractorType::SecurityScheme];
+
+    type ParamType = ();
+    type ParamRawType = ();
+
+    async fn from_request(
+        request: &'a Request,
+        _body: &mut RequestBody,
+        _param_opts: ExtractParamOptions<Self::ParamType>,
+    ) -> poem::Result<Self> {
+        let token = Bearer::from_request(request).ok().or_else(|| {
+            request
+                .params::<HashMap<String, String>>()
+                .ok()?
+                .remove(ACCESS_TOKEN_QUERY)
+                .map(|token| Bearer { token })
+        });
+        A::authenticate(request, token).await.map(Self)
+    }
+
+    fn register(registry: &mut Registry) {
+        A::register(registry);
+    }
+
+    fn security_schemes() -> Vec<&'static str> {
+        A::security_schemes()
+    }
+}
+
+/// Add the `x-websocket` extension to all operations of an OpenAPI document
+/// that respond with `101 Switching Protocols`.
+pub fn websocket_spec(spec: &str) -> String {
+    let Ok(mut spec) = serde_json::from_str::<Value>(spec) else {
+        return spec.into();
+    };
+    if let Some(paths) = spec.get_mut("paths").and_then(Value::as_object_mut) {
+        for operation in paths
+            .values_mut()
+            .filter_map(Value::as_object_mut)
+            .flat_map(|path| path.values_mut())
+            .filter_map(Value::as_object_mut)
+            .filter(|operation| {
+                operation
+                    .get("responses")
+                    .and_then(|responses| responses.get("101"))
+                    .is_some()
+            })
+        {
+            operation.insert(WEBSOCKET_EXTENSION.into(), true.into());
+        }
+    }
+    spec.to_string()
+}
+
+/// Create an endpoint that serves the OpenAPI document of the given service
+/// in JSON format including the `x-websocket` extensions (see
+/// [`websocket_spec`]).
+pub fn spec_endpoint<T, W>(service: &OpenApiService<T, W>) -> impl Endpoint<Output = Response>
+where
+    T: OpenApi,
+    W: Webhook,
+{
+    let spec = websocket_spec(&service.spec());
+    make_sync(move |_| {
+        Response::builder()
+            .content_type("application/json; charset=utf-8")
+            .body(spec.clone())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{
+        http::StatusCode,
+        test::TestClient,
+        web::websocket::{WebSocket, WebSocketStream, WebSocketUpgraded},
+        Route,
+    };
+    use poem_openapi::payload::PlainText;
+
+    use super::*;
+    use crate::{custom_auth, response};
+
+    #[derive(Debug)]
+    struct UserAuth(String);
+
+    response!(AuthResult = {
+        Unauthorized(401, error),
+    });
+
+    async fn user_auth_check(
+        _req: &Request,
+        token: Option<Bearer>,
+    ) -> Result<String, AuthResult::raw::Response> {
+        match token {
+            Some(Bearer { token }) if token == "secret_token" => Ok("user".into()),
+            _ => Err(AuthResult::raw::unauthorized()),
+        }
+    }
+
+    custom_auth!(UserAuth, user_auth_check);
+
+    struct Api;
+
+    #[OpenApi]
+    impl Api {
+        #[oai(path = "/ws", method = "get")]
+        async fn ws(
+            &self,
+            _auth: WebSocketAuth<UserAuth>,
+            ws: WebSocket,
+        ) -> WebSocketUpgraded<fn(WebSocketStream) -> std::future::Ready<()>> {
+            ws.on_upgrade(|_| std::future::ready(()))
+        }
+
+        #[oai(path = "/test", method = "get")]
+        async fn test(&self, auth: WebSocketAuth<UserAuth>) -> PlainText<String> {
+            PlainText(auth.0 .0)
+        }
+    }
+
+    #[tokio::test]
+    async fn auth() {
+        let cli = TestClient::new(Route::new().nest("/", OpenApiService::new(Api, "test", "1")));
+
+        let resp = cli
+            .get("/test")
+            .header("authorization", "Bearer secret_token")
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.assert_text("user").await;
+
+        let resp = cli.get("/test?access_token=secret_token").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_text("user").await;
+
+        // the header takes precedence over the query parameter
+        let resp = cli
+            .get("/test?access_token=secret_token")
+            .header("authorization", "Bearer foo")
+            .send()
+            .await;
+        resp.assert_status(StatusCode::UNAUTHORIZED);
+
+        let resp = cli.get("/test?access_token=foo").send().await;
+        resp.assert_status(StatusCode::UNAUTHORIZED);
+
+        let resp = cli.get("/test").send().await;
+        resp.assert_status(StatusCode::UNAUTHORIZED);
+        resp.assert_text(r#"{"error":"unauthorized"}"#).await;
+    }
+
+    #[tokio::test]
+    async fn spec() {
+        let service = OpenApiService::new(Api, "test", "1");
+        let cli = TestClient::new(spec_endpoint(&service));
+        let resp = cli.get("/").send().await;
+        resp.assert_status_is_ok();
+        let spec: Value =
+            serde_json::from_str(&resp.0.into_body().into_string().await.unwrap()).unwrap();
+
+        let ws = &spec["paths"]["/ws"]["get"];
+        assert_eq!(ws[WEBSOCKET_EXTENSION], true);
+        assert_eq!(ws["security"][0]["UserAuth"], Value::Array(vec![]));
+        assert!(spec["paths"]["/test"]["get"]
+            .get(WEBSOCKET_EXTENSION)
+            .is_none());
+    }
+}